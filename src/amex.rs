@@ -0,0 +1,365 @@
+// American Express
+//
+// Transactions of Amex card exports, either the CSV download ("Date",
+// "Description", "Amount", ... and, when selected, "Extended Details",
+// "Reference", "Category", ...) or the QFX (Quicken, OFX) download.
+//
+// The CSV export lists charges as positive and payments/refunds as negative
+// amounts, they are inverted for YNAB. QFX amounts are signed like in YNAB.
+// Purchases in another currency carry the foreign amount, the commission and
+// the exchange rate (in the extended details of the CSV export or in
+// `<ORIGCURRENCY>` of the QFX export), they end up in the memo.
+
+use crate::camt::unescape;
+use crate::parse::{amount_detect, milliunits, AmountFormat};
+use crate::source::{BankSource, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use csv::ReaderBuilder;
+use failure::ResultExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+
+/// Placeholders available in `--memo-template` for Amex transactions.
+pub const MEMO_FIELDS: &[&str] = &[
+    "description",
+    "extended_details",
+    "category",
+    "reference",
+    "foreign_amount",
+    "exchange_rate",
+    "commission",
+    "foreign",
+];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{foreign}";
+
+// Labels of the foreign transaction details, eg. "Foreign Spend Amount: 45.00
+// Euros Commission Amount: 1.24 Currency Exchange Rate: 1.0867".
+const FOREIGN_AMOUNT_LABEL: &str = "foreign spend amount:";
+const COMMISSION_LABEL: &str = "commission amount:";
+const EXCHANGE_RATE_LABEL: &str = "currency exchange rate:";
+const FOREIGN_LABELS: &[&str] = &[FOREIGN_AMOUNT_LABEL, COMMISSION_LABEL, EXCHANGE_RATE_LABEL];
+
+pub struct Amex {
+    pub transactions: Vec<SourceTransaction>,
+    pub days_to_sync: i64,
+}
+
+impl Amex {
+    /// Parse CSV or QFX exports of the same card, the format is told from
+    /// the content. Transactions in more than one export are only kept once.
+    /// Dates of CSV exports are read as `DD/MM/YYYY` with `day_first` (eg.
+    /// exports of Amex UK), as `MM/DD/YYYY` otherwise.
+    pub fn new(amex_files: &[String], day_first: bool) -> Result<Self> {
+        let mut transactions: Vec<SourceTransaction> = vec![];
+        let mut ids = HashSet::new();
+        for amex_file in amex_files {
+            let content = read_to_string(amex_file)
+                .with_context(|_| ErrorKind::AmexFileCanNotRead(amex_file.to_string()))?;
+            let parsed = if is_qfx(&content) {
+                parse_qfx(amex_file, &content)?
+            } else {
+                parse_csv(amex_file, &content, day_first)?
+            };
+            for transaction in parsed {
+                if ids.insert(transaction.id.clone()) {
+                    transactions.push(transaction);
+                }
+            }
+        }
+
+        let today = Utc::today().naive_local();
+        let days_to_sync = transactions
+            .iter()
+            .map(|x| (today - x.date).num_days())
+            .max()
+            .unwrap_or(0);
+
+        Ok(Amex {
+            transactions,
+            days_to_sync,
+        })
+    }
+}
+
+impl BankSource for Amex {
+    fn name(&self) -> &'static str {
+        "Amex"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Transactions of the exports posted in the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::today().naive_local() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.date >= since)
+            .cloned()
+            .collect())
+    }
+}
+
+fn is_qfx(content: &str) -> bool {
+    let start = content.trim_start_matches('\u{feff}').trim_start();
+    start.starts_with("OFXHEADER") || start.starts_with("<?xml") || content.contains("<OFX>")
+}
+
+/// Details of a purchase in another currency.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Foreign {
+    /// eg. "45.00 Euros"
+    amount: Option<String>,
+    /// eg. "1.0867"
+    exchange_rate: Option<String>,
+    /// Commission in the card's currency, as a positive amount.
+    commission: Option<i32>,
+}
+
+impl Foreign {
+    /// Details in the extended details of the CSV export (or in the memo of
+    /// the QFX export).
+    fn parse(text: &str) -> Self {
+        // ASCII lowercasing keeps the byte offsets of `text`
+        let lowercase = text.to_ascii_lowercase();
+        let value = |label: &str| -> Option<String> {
+            let start = lowercase.find(label)? + label.len();
+            let end = FOREIGN_LABELS
+                .iter()
+                .filter_map(|x| lowercase[start..].find(x))
+                .min()
+                .map(|x| start + x)
+                .unwrap_or(text.len());
+            Some(text[start..end].trim().to_string()).filter(|x| !x.is_empty())
+        };
+        Foreign {
+            amount: value(FOREIGN_AMOUNT_LABEL),
+            exchange_rate: value(EXCHANGE_RATE_LABEL),
+            commission: value(COMMISSION_LABEL)
+                .and_then(|x| amount_detect(&x, &AmountFormat::US).ok())
+                .map(i32::abs)
+                .filter(|x| *x != 0),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.amount.is_none() && self.exchange_rate.is_none()
+    }
+
+    /// eg. "(45.00 Euros @ 1.0867)"
+    fn summary(&self) -> Option<String> {
+        match (&self.amount, &self.exchange_rate) {
+            (Some(amount), Some(rate)) => Some(format!("({} @ {})", amount, rate)),
+            (Some(amount), None) => Some(format!("({})", amount)),
+            (None, Some(rate)) => Some(format!("(@ {})", rate)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Transaction of either export.
+struct Entry {
+    date: NaiveDate,
+    amount: i32,
+    description: Option<String>,
+    extended_details: Option<String>,
+    category: Option<String>,
+    reference: Option<String>,
+    foreign: Foreign,
+}
+
+impl Entry {
+    fn into_transaction(self, occurrences: &mut HashMap<String, usize>) -> SourceTransaction {
+        // identical transactions without a reference (eg. two equal
+        // purchases on the same day) get different import ids
+        let key = match &self.reference {
+            Some(x) => x.clone(),
+            None => format!(
+                "{}|{}|{}",
+                self.date,
+                self.amount,
+                self.description.clone().unwrap_or_default()
+            ),
+        };
+        let occurrence = occurrences.entry(key.clone()).or_insert(0);
+        *occurrence += 1;
+        let mut import_id_sha = Sha1::new();
+        import_id_sha.input_str(&key);
+        import_id_sha.input_str(&format!("{}", occurrence));
+        let id = import_id_sha.result_str()[..36].to_string();
+
+        let foreign = self.foreign;
+        let mut memo_values = HashMap::new();
+        memo_values.insert("description", self.description.clone());
+        memo_values.insert("extended_details", self.extended_details);
+        memo_values.insert("category", self.category);
+        memo_values.insert("reference", self.reference);
+        memo_values.insert("foreign_amount", foreign.amount.clone());
+        memo_values.insert("exchange_rate", foreign.exchange_rate.clone());
+        memo_values.insert(
+            "commission",
+            foreign
+                .commission
+                .map(|x| format!("{:.2}", x as f64 / 1000.0)),
+        );
+        memo_values.insert("foreign", foreign.summary());
+
+        SourceTransaction {
+            id,
+            date: self.date,
+            amount: self.amount,
+            payee: self.description,
+            memo_values,
+            is_cash_withdrawal: false,
+            // the commission is included in the amount of the purchase
+            fee: foreign.commission.map(|x| -x),
+            pending: false,
+            category: None,
+        }
+    }
+}
+
+fn parse_csv(amex_file: &str, content: &str, day_first: bool) -> Result<Vec<SourceTransaction>> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+    let headers = reader
+        .headers()
+        .with_context(|_| ErrorKind::AmexFileParse(amex_file.to_string()))?
+        .clone();
+    let index = |name: &str| headers.iter().position(|x| x.trim() == name);
+    let date_index =
+        index("Date").ok_or_else(|| ErrorKind::AmexFileParse(amex_file.to_string()))?;
+    let amount_index =
+        index("Amount").ok_or_else(|| ErrorKind::AmexFileParse(amex_file.to_string()))?;
+    let description_index = index("Description");
+    let extended_details_index = index("Extended Details");
+    let category_index = index("Category");
+    let reference_index = index("Reference");
+    let date_format = if day_first { "%d/%m/%Y" } else { "%m/%d/%Y" };
+
+    let mut transactions = vec![];
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for result in reader.records() {
+        let record = result.with_context(|_| ErrorKind::AmexFileParse(amex_file.to_string()))?;
+        let row = || record.iter().collect::<Vec<_>>().join(",");
+        let value = |index: Option<usize>| -> Option<String> {
+            index
+                .and_then(|x| record.get(x))
+                // eg. "'320193160123456789'", quoted to keep spreadsheets
+                // from turning it into a number
+                .map(|x| x.trim().trim_matches('\'').trim().to_string())
+                .filter(|x| !x.is_empty())
+        };
+
+        let date_value = match value(Some(date_index)) {
+            Some(x) => x,
+            None => continue,
+        };
+        let date = NaiveDate::parse_from_str(&date_value, date_format)
+            .with_context(|_| ErrorKind::AmexTransactionParse(amex_file.to_string(), row()))?;
+        // charges are positive in the export
+        let amount = amount_detect(
+            &value(Some(amount_index)).unwrap_or_default(),
+            &AmountFormat::US,
+        )
+        .with_context(|_| ErrorKind::AmexTransactionParse(amex_file.to_string(), row()))?;
+        let amount = -amount;
+
+        let description = value(description_index);
+        let extended_details = value(extended_details_index)
+            // the details are split over several lines
+            .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "));
+        let reference = value(reference_index);
+        let foreign = extended_details
+            .as_deref()
+            .map(Foreign::parse)
+            .unwrap_or_default();
+
+        let entry = Entry {
+            date,
+            amount,
+            description,
+            extended_details,
+            category: value(category_index),
+            reference,
+            foreign,
+        };
+        transactions.push(entry.into_transaction(&mut occurrences));
+    }
+    Ok(transactions)
+}
+
+fn parse_qfx(amex_file: &str, content: &str) -> Result<Vec<SourceTransaction>> {
+    let mut transactions = vec![];
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<STMTTRN>") {
+        rest = &rest[start + "<STMTTRN>".len()..];
+        let end = rest
+            .find("</STMTTRN>")
+            .ok_or_else(|| ErrorKind::AmexFileParse(amex_file.to_string()))?;
+        let entry = &rest[..end];
+        rest = &rest[end..];
+
+        let date = qfx_value(entry, "DTPOSTED")
+            .and_then(|x| x.get(..8).map(|x| x.to_string()))
+            .and_then(|x| NaiveDate::parse_from_str(&x, "%Y%m%d").ok())
+            .ok_or_else(|| {
+                ErrorKind::AmexTransactionParse(amex_file.to_string(), entry.trim().to_string())
+            })?;
+        let amount = qfx_value(entry, "TRNAMT")
+            .and_then(|x| x.parse::<f64>().ok())
+            .map(milliunits)
+            .ok_or_else(|| {
+                ErrorKind::AmexTransactionParse(amex_file.to_string(), entry.trim().to_string())
+            })?;
+
+        let description = qfx_value(entry, "NAME");
+        let memo = qfx_value(entry, "MEMO");
+        let reference = qfx_value(entry, "FITID");
+        let mut foreign = memo.as_deref().map(Foreign::parse).unwrap_or_default();
+        // <ORIGCURRENCY><CURRATE>1.0867<CURSYM>EUR</ORIGCURRENCY>
+        if let Some(currency) = qfx_value(entry, "CURSYM") {
+            if foreign.is_empty() {
+                foreign.amount = Some(currency);
+            }
+            foreign.exchange_rate = foreign
+                .exchange_rate
+                .or_else(|| qfx_value(entry, "CURRATE"));
+        }
+
+        let entry = Entry {
+            date,
+            amount,
+            description,
+            extended_details: memo,
+            category: None,
+            reference,
+            foreign,
+        };
+        transactions.push(entry.into_transaction(&mut occurrences));
+    }
+    Ok(transactions)
+}
+
+/// Value of an element of a QFX transaction, either SGML (`<NAME>Value`,
+/// without a closing tag) or XML (`<NAME>Value</NAME>`).
+fn qfx_value(entry: &str, name: &str) -> Option<String> {
+    let tag = format!("<{}>", name);
+    let start = entry.find(&tag)? + tag.len();
+    let value = &entry[start..];
+    let value = value[..value.find('<').unwrap_or(value.len())].trim();
+    // SGML exports don't always escape `&` (eg. "AT&T")
+    Some(unescape(value).unwrap_or_else(|| value.to_string())).filter(|x| !x.is_empty())
+}
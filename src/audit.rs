@@ -0,0 +1,340 @@
+// Audit log
+//
+// Every transaction created, updated or deleted in YNAB is appended as a JSON
+// line to `audit.jsonl` in the state directory (see `state_dir`), so it's
+// always possible to tell what the tool changed and when. Entries are never
+// rewritten or removed.
+//
+// Payee names and memos (which may contain IBANs) are personal data, by
+// default only their hashes are written (see `--audit-pii`). They are keyed
+// (HMAC-SHA256) with a random secret created once per install in
+// `audit.key` next to the log, since plain hashes of short texts like payee
+// names are easily reversed by hashing guesses. Ids, import ids,
+// dates, amounts and categories are always kept, which is enough to find and
+// undo a change.
+//
+// With `--encrypt-state` every line is encrypted on its own (see
+// `encryption`), so the log can still be appended to.
+
+use crate::ynab::{SubTransaction, Transaction};
+use crate::{encryption, http};
+use crate::{state_dir, ErrorKind, Result};
+use chrono::{DateTime, Utc};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use failure::ResultExt;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{hard_link, read_to_string, remove_file, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::result;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use structopt::StructOpt;
+
+const AUDIT_FILE: &str = "audit.jsonl";
+const KEY_FILE: &str = "audit.key";
+const KEY_LEN: usize = 32;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "audit-pii",
+        value_name = "MODE",
+        default_value = "hash",
+        possible_values = &["plain", "hash", "omit"],
+        env = "YNAB_SYNC_AUDIT_PII",
+        help = "How payee names and memos are written to the audit log: as they are, as keyed SHA-256 hashes (still comparable between entries) or not at all."
+    )]
+    pub audit_pii: PiiMode,
+}
+
+impl Cli {
+    /// The options as environment variables of another ynab-sync process.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("YNAB_SYNC_AUDIT_PII", self.audit_pii.to_string())]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PiiMode {
+    Plain,
+    Hash,
+    Omit,
+}
+
+impl fmt::Display for PiiMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                PiiMode::Plain => "plain",
+                PiiMode::Hash => "hash",
+                PiiMode::Omit => "omit",
+            },
+        )
+    }
+}
+
+impl FromStr for PiiMode {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(PiiMode::Plain),
+            "hash" => Ok(PiiMode::Hash),
+            "omit" => Ok(PiiMode::Omit),
+            _ => Err(ErrorKind::ArgParse(format!("--audit-pii {}", s))),
+        }
+    }
+}
+
+impl PiiMode {
+    /// `value` as written to the audit log, `key` is the secret of
+    /// `PiiMode::Hash`.
+    fn apply(&self, key: &[u8], value: &Option<String>) -> Option<String> {
+        match self {
+            PiiMode::Plain => value.clone(),
+            PiiMode::Hash => value.as_ref().map(|x| {
+                let mut hmac = Hmac::new(Sha256::new(), key);
+                hmac.input(x.as_bytes());
+                let hash: String = hmac
+                    .result()
+                    .code()
+                    .iter()
+                    .map(|x| format!("{:02x}", x))
+                    .collect();
+                format!("hmac-sha256:{}", hash)
+            }),
+            PiiMode::Omit => None,
+        }
+    }
+
+    fn transaction(&self, key: &[u8], transaction: &Transaction) -> Transaction {
+        Transaction {
+            payee_name: self.apply(key, &transaction.payee_name),
+            memo: self.apply(key, &transaction.memo),
+            subtransactions: transaction
+                .subtransactions
+                .iter()
+                .map(|x| SubTransaction {
+                    payee_name: self.apply(key, &x.payee_name),
+                    memo: self.apply(key, &x.memo),
+                    ..x.clone()
+                })
+                .collect(),
+            ..transaction.clone()
+        }
+    }
+}
+
+static PII_MODE: OnceLock<PiiMode> = OnceLock::new();
+static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Configure how personal data is written to the audit log, without it
+/// `PiiMode::Hash` is used.
+pub fn setup(cli: &Cli) {
+    // the first configuration wins
+    let _ = PII_MODE.set(cli.audit_pii.clone());
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub action: Action,
+    pub budget_id: String,
+    pub account_id: String,
+    pub import_id: Option<String>,
+    pub transaction_id: Option<String>,
+    /// YNAB's version of the transaction before the change.
+    pub before: Option<Transaction>,
+    /// Transaction as sent to YNAB, `None` for deletions.
+    pub after: Option<Transaction>,
+}
+
+impl Entry {
+    /// Entries for `transactions` saved with `action`, with the previous
+    /// versions looked up by import id in `existing_transactions`.
+    pub fn saved(
+        action: Action,
+        budget_id: &str,
+        transactions: &[Transaction],
+        existing_transactions: &HashMap<String, Transaction>,
+    ) -> Vec<Self> {
+        let timestamp = Utc::now();
+        transactions
+            .iter()
+            .map(|transaction| {
+                let before = transaction
+                    .import_id
+                    .as_ref()
+                    .and_then(|x| existing_transactions.get(x))
+                    .cloned();
+                Entry {
+                    timestamp,
+                    action: action.clone(),
+                    budget_id: budget_id.to_string(),
+                    account_id: transaction.account_id.clone(),
+                    import_id: transaction.import_id.clone(),
+                    transaction_id: before.as_ref().and_then(|x| x.id.clone()),
+                    before,
+                    after: Some(transaction.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Entry for the YNAB transaction `before` updated to `transaction`.
+    pub fn updated(budget_id: &str, before: &Transaction, transaction: &Transaction) -> Self {
+        Entry {
+            timestamp: Utc::now(),
+            action: Action::Update,
+            budget_id: budget_id.to_string(),
+            account_id: transaction.account_id.clone(),
+            import_id: transaction.import_id.clone(),
+            transaction_id: before.id.clone(),
+            before: Some(before.clone()),
+            after: Some(transaction.clone()),
+        }
+    }
+
+    pub fn deleted(budget_id: &str, transaction: &Transaction) -> Self {
+        Entry {
+            timestamp: Utc::now(),
+            action: Action::Delete,
+            budget_id: budget_id.to_string(),
+            account_id: transaction.account_id.clone(),
+            import_id: transaction.import_id.clone(),
+            transaction_id: transaction.id.clone(),
+            before: Some(transaction.clone()),
+            after: None,
+        }
+    }
+}
+
+/// Secret the hashes of `PiiMode::Hash` are keyed with, created on first use
+/// and kept in the state directory (encrypted with `--encrypt-state`).
+fn key() -> Result<Vec<u8>> {
+    if let Some(key) = KEY.get() {
+        return Ok(key.clone());
+    }
+    let key = read_or_create_key(&state_dir()?.join(KEY_FILE))?;
+    Ok(KEY.get_or_init(|| key).clone())
+}
+
+/// Key in `path`, a new one when there's none yet.
+fn read_or_create_key(path: &Path) -> Result<Vec<u8>> {
+    let error = || ErrorKind::AuditLogCanNotWrite(path.to_string_lossy().into());
+    let decode =
+        |content: String| -> Result<Vec<u8>> {
+            Ok(base64::decode(encryption::decrypt(content.trim())?.trim())
+                .with_context(|_| error())?)
+        };
+    match read_to_string(path) {
+        Ok(content) => decode(content),
+        Err(_) => {
+            let mut key = vec![0u8; KEY_LEN];
+            OsRng.try_fill_bytes(&mut key).with_context(|_| error())?;
+            // written to a temporary file first and linked into place, so
+            // processes creating it at the same time (eg. `sync --jobs`)
+            // never read half of it and all end up with the same key
+            let tmp =
+                path.with_extension(format!("{}-{}.tmp", std::process::id(), OsRng.next_u32()));
+            let mut options = OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(&tmp).with_context(|_| error())?;
+            let content = encryption::encrypt(&base64::encode(&key))?;
+            file.write_all(content.as_bytes())
+                .with_context(|_| error())?;
+            let linked = hard_link(&tmp, path);
+            let _ = remove_file(&tmp);
+            match linked {
+                Ok(()) => Ok(key),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    decode(read_to_string(path).with_context(|_| error())?)
+                }
+                Err(e) => Err(e).with_context(|_| error())?,
+            }
+        }
+    }
+}
+
+/// Append `entries` to the audit log. Nothing is written when replaying
+/// recorded HTTP sessions, since nothing was changed in YNAB.
+pub fn record(entries: &[Entry]) -> Result<()> {
+    if entries.is_empty() || http::is_replaying() {
+        return Ok(());
+    }
+    let pii_mode = PII_MODE.get().unwrap_or(&PiiMode::Hash);
+    let key = match pii_mode {
+        PiiMode::Hash => key()?,
+        _ => vec![],
+    };
+    let path = state_dir()?.join(AUDIT_FILE);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|_| ErrorKind::AuditLogCanNotWrite(path.to_string_lossy().into()))?;
+    let mut content = String::new();
+    for entry in entries {
+        let entry = Entry {
+            before: entry.before.as_ref().map(|x| pii_mode.transaction(&key, x)),
+            after: entry.after.as_ref().map(|x| pii_mode.transaction(&key, x)),
+            ..entry.clone()
+        };
+        let line = serde_json::to_string(&entry)
+            .with_context(|_| ErrorKind::AuditLogCanNotWrite(path.to_string_lossy().into()))?;
+        let line = encryption::encrypt(&line)
+            .with_context(|_| ErrorKind::AuditLogCanNotWrite(path.to_string_lossy().into()))?;
+        content.push_str(&line);
+        content.push('\n');
+    }
+    file.write_all(content.as_bytes())
+        .with_context(|_| ErrorKind::AuditLogCanNotWrite(path.to_string_lossy().into()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::thread;
+
+    #[test]
+    fn key_created_at_the_same_time_is_shared() {
+        let path = temp_dir().join(format!("ynab-sync-audit-{}.key", std::process::id()));
+        let _ = remove_file(&path);
+        let keys: Vec<Vec<u8>> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || read_or_create_key(&path).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect();
+        assert_eq!(keys[0].len(), KEY_LEN);
+        assert!(keys.iter().all(|x| *x == keys[0]));
+        assert_eq!(read_or_create_key(&path).unwrap(), keys[0]);
+        remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+// Balance guard
+//
+// Before saving, the cleared balance the YNAB account would have after the
+// sync is compared with the balance the bank reports (eg. the "Saldo" of an
+// ING-DiBa export). A difference means transactions are missing or doubled
+// in YNAB, which is easier to fix before more transactions are added.
+
+use crate::notify::{self, Notification};
+use crate::parse::milliunits;
+use crate::{ErrorKind, Result};
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "balance-guard",
+        value_name = "MODE",
+        default_value = "off",
+        possible_values = &["off", "warn", "abort"],
+        env = "YNAB_BALANCE_GUARD",
+        help = "Before saving, compare the cleared balance the YNAB account would have after the sync with the balance reported by the bank: warn or abort the sync when they differ by more than --balance-tolerance."
+    )]
+    pub balance_guard: BalanceGuardMode,
+    #[structopt(
+        long = "balance-tolerance",
+        value_name = "AMOUNT",
+        default_value = "0.00",
+        env = "YNAB_BALANCE_TOLERANCE",
+        help = "Difference between the balances accepted by --balance-guard (eg. 0.01)."
+    )]
+    pub balance_tolerance: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BalanceGuardMode {
+    Off,
+    Warn,
+    Abort,
+}
+
+impl fmt::Display for BalanceGuardMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                BalanceGuardMode::Off => "off",
+                BalanceGuardMode::Warn => "warn",
+                BalanceGuardMode::Abort => "abort",
+            },
+        )
+    }
+}
+
+impl FromStr for BalanceGuardMode {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(BalanceGuardMode::Off),
+            "warn" => Ok(BalanceGuardMode::Warn),
+            "abort" => Ok(BalanceGuardMode::Abort),
+            _ => Err(ErrorKind::ArgParse(format!("--balance-guard {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BalanceGuard {
+    mode: BalanceGuardMode,
+    /// Accepted difference in milliunits.
+    tolerance: i64,
+}
+
+impl BalanceGuard {
+    pub fn new(cli: &Cli) -> Self {
+        BalanceGuard {
+            mode: cli.balance_guard,
+            tolerance: i64::from(milliunits(cli.balance_tolerance.abs())),
+        }
+    }
+
+    /// Guard of syncs without --balance-guard.
+    pub fn off() -> Self {
+        BalanceGuard {
+            mode: BalanceGuardMode::Off,
+            tolerance: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != BalanceGuardMode::Off
+    }
+
+    /// Compare the `expected` cleared balance of the YNAB account after the
+    /// sync with the `bank_balance` (both in milliunits). A difference is
+    /// printed and notified, with `abort` it fails the sync.
+    pub fn check(&self, expected: i64, bank_balance: i64) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let difference = expected - bank_balance;
+        if difference.abs() <= self.tolerance {
+            println!(
+                " => Balance after the sync matches the bank: {:.2} EUR",
+                bank_balance as f64 / 1000.0
+            );
+            return Ok(());
+        }
+        let message = format!(
+            "YNAB cleared balance after the sync would be {:.2} EUR, the bank reports {:.2} EUR ({:+.2} EUR)",
+            expected as f64 / 1000.0,
+            bank_balance as f64 / 1000.0,
+            difference as f64 / 1000.0
+        );
+        if self.mode == BalanceGuardMode::Abort {
+            Err(ErrorKind::BalanceMismatch(message))?
+        } else {
+            println!(" => {}", message);
+            notify::send(&Notification::attention("Balance mismatch", &message));
+        }
+        Ok(())
+    }
+}
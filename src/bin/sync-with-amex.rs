@@ -0,0 +1,102 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::amex::{self, Amex};
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::Result;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::Cli as YNABCli;
+
+#[derive(StructOpt, Debug)]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
+    ynab: YNABCli,
+    #[structopt(
+        long = "amex",
+        required = true,
+        value_name = "FILE",
+        number_of_values = 1,
+        help = "CSV or QFX file which you exported from American Express. Can be used multiple times, transactions in overlapping exports are only synced once."
+    )]
+    amex_files: Vec<String>,
+    #[structopt(
+        long = "amex-day-first",
+        help = "Dates of the CSV export are DD/MM/YYYY (eg. exports of Amex UK) instead of MM/DD/YYYY."
+    )]
+    day_first: bool,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = amex::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {description}, {extended_details}, {category}, {reference}, {foreign_amount}, {exchange_rate}, {commission} and {foreign} (eg. \"(45.00 Euros @ 1.0867)\", empty for purchases in the card's currency). Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-amex")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+
+    let memo_template = MemoTemplate::parse(&cli.memo_template, amex::MEMO_FIELDS)?;
+
+    println!("[1/6] Parsing --amex files");
+    let mut amex = Amex::new(&cli.amex_files, cli.day_first)?;
+    let days_to_sync = amex.days_to_sync;
+
+    sync_source(
+        &mut amex,
+        Window::Days(days_to_sync),
+        cli.ynab,
+        &memo_template,
+        &BalanceGuard::off(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::camt::{self, Camt};
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::Result;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::Cli as YNABCli;
+
+#[derive(StructOpt, Debug)]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
+    ynab: YNABCli,
+    #[structopt(
+        long = "camt",
+        required = true,
+        value_name = "FILE",
+        number_of_values = 1,
+        help = "camt.053, camt.052 or camt.054 (ISO 20022) XML statement which you exported from your bank. Can be used multiple times, entries in overlapping statements are only synced once."
+    )]
+    camt_files: Vec<String>,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = camt::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {payee}, {remittance}, {end_to_end_id}, {iban}, {info}. Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-camt")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+
+    let memo_template = MemoTemplate::parse(&cli.memo_template, camt::MEMO_FIELDS)?;
+
+    println!("[1/6] Parsing --camt files");
+    let mut camt = Camt::new(&cli.camt_files)?;
+    let days_to_sync = camt.days_to_sync;
+
+    sync_source(
+        &mut camt,
+        Window::Days(days_to_sync),
+        cli.ynab,
+        &memo_template,
+        &BalanceGuard::off(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::csv_import::{self, CsvImport, CsvMapping};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::Result;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::source::Cli as DateRangeCli;
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::Cli as YNABCli;
+
+#[derive(StructOpt, Debug)]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
+    ynab: YNABCli,
+    #[structopt(flatten)]
+    dates: DateRangeCli,
+    #[structopt(
+        long = "csv",
+        required = true,
+        value_name = "FILE",
+        help = "CSV file which you exported from your bank."
+    )]
+    csv_file: String,
+    #[structopt(
+        long = "csv-mapping",
+        required = true,
+        value_name = "FILE",
+        help = "JSON file which describes the columns of the --csv file (delimiter, encoding, date, amount, payee, memo, ...)."
+    )]
+    csv_mapping_file: String,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = csv_import::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {payee}, {memo}, {iban}. Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-csv")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+
+    let mapping = CsvMapping::from_file(&cli.csv_mapping_file)?;
+    let memo_template = MemoTemplate::parse(&cli.memo_template, csv_import::MEMO_FIELDS)?;
+
+    println!("[1/6] Parsing --csv file");
+    let csv_import = CsvImport::new(&cli.csv_file, &mapping)?;
+    let total = csv_import.transactions.len();
+    let mut csv_import = csv_import.filter_dates(&cli.dates);
+    if csv_import.transactions.len() < total {
+        println!(
+            " => Skipping {} transactions outside of --from-date/--to-date",
+            total - csv_import.transactions.len()
+        );
+    }
+    let days_to_sync = csv_import.days_to_sync;
+
+    sync_source(
+        &mut csv_import,
+        Window::Days(days_to_sync),
+        cli.ynab,
+        &memo_template,
+        &BalanceGuard::off(),
+    )?;
+
+    Ok(())
+}
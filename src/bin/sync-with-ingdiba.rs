@@ -1,238 +1,670 @@
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
-use failure::ResultExt;
-use serde::{Deserialize, Serialize};
-use std::fmt;
-use std::fs::read_to_string;
+use chrono::{Duration, Utc};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Confirmation;
+use exitfailure::ExitFailure;
+use log::warn;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::result;
-use std::str::FromStr;
+use std::thread::sleep;
 use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::{BalanceGuard, Cli as BalanceCli};
+use ynab_sync::cache::Cache;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
 use ynab_sync::error::{ErrorKind, Result};
-use ynab_sync::ingdiba::{IngDiBa, Transaction as IngDiBaTransaction};
+use ynab_sync::explain::Trace;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::ingdiba::{self, ExportKind, IngDiBa};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::report::SyncReport;
+use ynab_sync::rules::{
+    self, Cli as RulesCli, MatchMode, RuleActions, RuleMatches, RuleTarget, Rules, TargetPart,
+    TransactionField, UnknownCategories,
+};
+use ynab_sync::source::{BankSource, Cli as DateRangeCli, SourceCategory, SourceTransaction};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::totals::{finished_body, Totals};
+use ynab_sync::watch::Watcher;
 use ynab_sync::ynab::{
-    Category, Cli as YNABCli, Transaction as YNABTransaction, TransactionCleared, YNAB,
+    BudgetCategories, Category, Cli as YNABCli, Transaction as YNABTransaction, TransactionCleared,
+    YNAB,
 };
 
-#[derive(StructOpt, Debug)]
+#[derive(Clone, StructOpt, Debug)]
 struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
     #[structopt(flatten)]
     ynab: YNABCli,
+    #[structopt(flatten)]
+    balance: BalanceCli,
+    #[structopt(flatten)]
+    dates: DateRangeCli,
     #[structopt(
         long = "category-rules",
         required = true,
         value_name = "FILE",
-        help = "JSON file which represents mapping rules between IngDiba and YNAB categories."
+        help = "JSON (or .toml, .yaml) file which represents mapping rules between IngDiba and YNAB categories."
     )]
     category_rules_file: String,
+    #[structopt(flatten)]
+    rules: RulesCli,
+    #[structopt(
+        long = "unknown-categories",
+        value_name = "MODE",
+        default_value = "ask",
+        possible_values = &["ask", "strict"],
+        env = "YNAB_UNKNOWN_CATEGORIES",
+        help = "What to do when --category-rules refer to a category which doesn't exist in the budget: ask whether to use the most similar one, or fail (strict)."
+    )]
+    unknown_categories: UnknownCategories,
+    #[structopt(
+        long = "interactive-categorize",
+        conflicts_with = "watch_dir",
+        help = "Search a category for each transaction no rule matched, optionally adding a rule for its entity to --category-rules."
+    )]
+    interactive_categorize: bool,
     #[structopt(
         long = "csv",
-        required = true,
+        required_unless = "watch_dir",
+        value_name = "FILE",
+        number_of_values = 1,
+        help = "CSV file which you exported from Ing-DiBa. Can be used multiple times, rows in overlapping exports are only synced once."
+    )]
+    csv_files: Vec<String>,
+    #[structopt(
+        long = "extra-konto-csv",
         value_name = "FILE",
-        help = "CSV file which you exported from Ing-DiBa."
+        number_of_values = 1,
+        requires = "extra_konto_account",
+        conflicts_with = "watch_dir",
+        help = "CSV file which you exported from your Ing-DiBa Extra-Konto (savings account). Can be used multiple times."
+    )]
+    extra_konto_csv_files: Vec<String>,
+    #[structopt(
+        long = "ynab-extra-konto-account",
+        value_name = "NAME|ID",
+        requires = "extra_konto_csv_files",
+        help = "YNAB (savings) account to sync --extra-konto-csv into."
     )]
-    csv_file: String,
+    extra_konto_account: Option<String>,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = ingdiba::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {merchant}, {reference}, {type}. Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+    #[structopt(
+        long = "watch",
+        value_name = "DIR",
+        help = "Instead of syncing --csv files, watch this directory (eg. ~/Downloads) for new exports, sync them and move them to --archive-dir."
+    )]
+    watch_dir: Option<String>,
+    #[structopt(
+        long = "watch-pattern",
+        value_name = "PATTERN",
+        default_value = "Umsatzanzeige_*.csv",
+        help = "Name of the exports in the --watch directory, * matches any text."
+    )]
+    watch_pattern: String,
+    #[structopt(
+        long = "watch-interval",
+        value_name = "SECONDS",
+        default_value = "10",
+        help = "Look for new exports in the --watch directory this often."
+    )]
+    watch_interval: i64,
+    #[structopt(
+        long = "archive-dir",
+        value_name = "DIR",
+        help = "Directory synced exports are moved to. Defaults to the synced directory in the --watch directory."
+    )]
+    archive_dir: Option<String>,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-ingdiba")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-#[serde(tag = "rule")]
-enum Rules {
-    Contains {
-        value: String,
-        #[serde(with = "serde_str")]
-        field: TransactionField,
-        category: String,
-    },
-    StartsWith {
-        value: String,
-        #[serde(with = "serde_str")]
-        field: TransactionField,
-        category: String,
-    },
-    EndsWith {
-        value: String,
-        #[serde(with = "serde_str")]
-        field: TransactionField,
-        category: String,
-    },
+/// An ING-DiBa export as `BankSource`, categorized by --category-rules.
+struct RulesSource {
+    statement: IngDiBa,
+    rules: Vec<Rules>,
+    rules_file: String,
+    match_mode: MatchMode,
+    unknown_categories: UnknownCategories,
+    assume_yes: bool,
+    interactive_categorize: bool,
+    /// Unknown categories of rules the user kept, by rule and part.
+    declined: Vec<(usize, TargetPart)>,
+    /// Rules added with --interactive-categorize and their category.
+    learned_rules: Vec<(Rules, Category)>,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum TransactionField {
-    Memo,
-    Entity,
+/// Memo ("Verwendungszweck") and entity of the row, the texts rules match.
+fn rule_texts(transaction: &SourceTransaction) -> (&str, &str) {
+    let value = |field| {
+        transaction
+            .memo_values
+            .get(field)
+            .and_then(|x: &Option<String>| x.as_deref())
+            .unwrap_or_default()
+    };
+    (value("reference"), value("merchant"))
 }
 
-impl fmt::Display for TransactionField {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match *self {
-                TransactionField::Memo => "memo",
-                TransactionField::Entity => "entity",
-            },
-        )
+/// `RuleTarget` resolved against the budget.
+fn resolve_target(target: &RuleTarget, categories: &BudgetCategories) -> Option<SourceCategory> {
+    match target {
+        RuleTarget::Category { category } => categories
+            .categories
+            .get(category)
+            .cloned()
+            .map(SourceCategory::Category),
+        RuleTarget::CategoryGroup {
+            category_group,
+            default,
+        } => {
+            let group = categories.groups.get(category_group)?;
+            match default {
+                Some(default) => group
+                    .category(default)
+                    .cloned()
+                    .map(SourceCategory::Category),
+                None => Some(SourceCategory::CategoryGroup(group.clone())),
+            }
+        }
     }
 }
 
-impl FromStr for TransactionField {
-    type Err = ErrorKind;
+impl RulesSource {
+    fn matches(&self, transaction: &SourceTransaction) -> RuleMatches<'_> {
+        let (memo, entity) = rule_texts(transaction);
+        rules::apply(&self.rules, self.match_mode, memo, entity)
+    }
+}
+
+impl BankSource for RulesSource {
+    fn name(&self) -> &'static str {
+        self.statement.name()
+    }
 
-    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
-        match s {
-            "memo" => Ok(TransactionField::Memo),
-            "entity" => Ok(TransactionField::Entity),
-            _ => Err(ErrorKind::YNABAccountTypeParse),
+    fn memo_fields(&self) -> &'static [&'static str] {
+        self.statement.memo_fields()
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        self.statement.default_memo_template()
+    }
+
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        self.statement.transactions(days)
+    }
+
+    fn balance(&self) -> Result<Option<i32>> {
+        Ok(self.statement.balance())
+    }
+
+    /// Deal with rules referring to categories which don't exist in the
+    /// budget (see `UnknownCategories`). Without a similar category, or when
+    /// the user declines it, the rule is kept and its transactions stay
+    /// uncategorized.
+    fn prepare(&mut self, categories: &BudgetCategories) -> Result<()> {
+        // a fixed category group can reveal an unknown default category, so
+        // look again until everything is either fixed or declined
+        loop {
+            let unknown: Vec<_> =
+                rules::unknown_categories(&self.rules, &categories.categories, &categories.groups)
+                    .into_iter()
+                    .filter(|x| !self.declined.contains(&(x.rule, x.part)))
+                    .collect();
+            if unknown.is_empty() {
+                return Ok(());
+            }
+            for unknown in unknown {
+                let message = match &unknown.suggestion {
+                    Some(x) => format!("{}, did you mean \"{}\"?", unknown, x),
+                    None => unknown.to_string(),
+                };
+                if self.unknown_categories == UnknownCategories::Strict {
+                    Err(ErrorKind::RulesUnknownCategory(message.clone()))?
+                }
+                let confirmed = unknown.suggestion.is_some()
+                    && !self.assume_yes
+                    && Confirmation::with_theme(&ColorfulTheme::default())
+                        .with_text(&message)
+                        .default(true)
+                        .interact()
+                        .unwrap();
+                if confirmed {
+                    unknown.fix(&mut self.rules);
+                } else {
+                    println!(" => {}", message);
+                    self.declined.push((unknown.rule, unknown.part));
+                }
+            }
         }
     }
-}
 
-fn main() -> Result<()> {
-    let cli = Cli::from_args();
+    fn skip_reason(&self, transaction: &SourceTransaction) -> Option<String> {
+        if self.matches(transaction).actions().skip {
+            Some("by a rule".to_string())
+        } else {
+            None
+        }
+    }
 
-    // check if --category-rules file exists and that it is of JSON format
-    if !PathBuf::from(cli.category_rules_file.clone()).exists() {
-        Err(ErrorKind::ArgParseCategoryRulesCanNotRead(
-            cli.csv_file.clone(),
-        ))?
+    /// The winning rule sets the category (see `rules::apply`).
+    fn category(
+        &self,
+        transaction: &SourceTransaction,
+        categories: &BudgetCategories,
+        trace: &mut Trace,
+    ) -> Option<SourceCategory> {
+        let matches = self.matches(transaction);
+        let result = matches
+            .rule
+            .and_then(|(_, rule)| rule.target())
+            .and_then(|x| resolve_target(x, categories));
+
+        // rules matching a transaction with different categories, the
+        // winning one might not be the intended one
+        if let Some((i, rule)) = matches.rule {
+            for (conflict, _) in &matches.conflicts {
+                println!(
+                    " => Rules #{} and #{} match {} | {} | {:>+10.2} EUR differently, using rule #{} ({})",
+                    i + 1,
+                    conflict + 1,
+                    transaction.date,
+                    transaction.payee.clone().unwrap_or_default(),
+                    (transaction.amount as f32 / 1000.0),
+                    i + 1,
+                    rule.outcome()
+                );
+            }
+        }
+
+        let (memo, entity) = rule_texts(transaction);
+        for i in rules::evaluation_order(&self.rules, self.match_mode) {
+            let rule = &self.rules[i];
+            if matches.not_evaluated.contains(&i) {
+                continue;
+            }
+            let text = match rule.field() {
+                TransactionField::Memo => memo,
+                TransactionField::Entity => entity,
+            };
+            let applied = matches.applied.iter().any(|x| x.0 == i);
+            trace.add(|| match (rule.target(), &result) {
+                _ if !rule.matches(text) => {
+                    format!("rule #{} {}: no match ({:?})", i + 1, rule, text)
+                }
+                _ if !applied => format!(
+                    "rule #{} {}: matched, but rule #{} wins",
+                    i + 1,
+                    rule,
+                    matches.rule.map(|x| x.0 + 1).unwrap_or_default()
+                ),
+                (Some(target), None) => format!(
+                    "rule #{} {}: matched, but {} doesn't exist in the budget",
+                    i + 1,
+                    rule,
+                    target
+                ),
+                _ => format!("rule #{} {}: matched => {}", i + 1, rule, rule.outcome()),
+            });
+        }
+        if !matches.not_evaluated.is_empty() {
+            trace.add(|| {
+                let rules: Vec<String> = matches
+                    .not_evaluated
+                    .iter()
+                    .map(|x| format!("#{}", x + 1))
+                    .collect();
+                format!("rules {}: not evaluated", rules.join(", "))
+            });
+        }
+        result
     }
-    let category_rules_string =
-        read_to_string(cli.category_rules_file.to_string()).with_context(|_| {
-            ErrorKind::ArgParseCategoryRulesCanNotRead(cli.category_rules_file.clone())
-        })?;
-    let rules: Vec<Rules> = serde_json::from_str(category_rules_string.as_str()).context(
-        ErrorKind::ArgParseCategoryRulesCanNotParse(cli.category_rules_file.clone()),
-    )?;
 
-    println!("[1/7] Parsing --csv file");
-    let ingdiba = IngDiBa::new(cli.csv_file)?;
+    /// The matching rules add their actions.
+    fn amend(
+        &self,
+        transaction: &SourceTransaction,
+        converted: YNABTransaction,
+    ) -> YNABTransaction {
+        self.matches(transaction).actions().apply(converted)
+    }
 
-    // YNAB client
-    let ynab = YNAB {
-        token: cli.ynab.token.clone(),
-    };
+    /// With --interactive-categorize the user searches the category of
+    /// transactions no rule matched, rules added on the way apply to the
+    /// following transactions.
+    fn categorize_uncategorized(
+        &mut self,
+        ynab: &YNAB,
+        transaction: &YNABTransaction,
+        source: &SourceTransaction,
+        categories: &BudgetCategories,
+    ) -> Result<Option<Category>> {
+        if !self.interactive_categorize {
+            return Ok(None);
+        }
+        let (memo, entity) = rule_texts(source);
+        if let Some((_, category)) = self
+            .learned_rules
+            .iter()
+            .find(|x| x.0.matches_transaction(memo, entity))
+        {
+            return Ok(Some(category.clone()));
+        }
+        let category = match ynab.search_category(transaction, &categories.group_list) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        if entity.trim().is_empty() {
+            return Ok(Some(category));
+        }
+
+        // qualified by the group when the name is ambiguous
+        let name = match categories
+            .group_list
+            .iter()
+            .find(|x| x.id == category.category_group_id)
+        {
+            Some(group) if !categories.categories.contains_key(&category.name) => {
+                format!("{}:{}", group.name, category.name)
+            }
+            _ => category.name.clone(),
+        };
+        let rule = Rules::Contains {
+            value: entity.to_string(),
+            field: TransactionField::Entity,
+            target: Some(RuleTarget::Category { category: name }),
+            actions: RuleActions::default(),
+            priority: 0,
+        };
+        let add = Confirmation::with_theme(&ColorfulTheme::default())
+            .with_text(&format!(
+                "Add rule {} => {} to {}?",
+                rule,
+                rule.outcome(),
+                self.rules_file
+            ))
+            .default(true)
+            .interact()
+            .unwrap();
+        if add {
+            Rules::append_to_file(&self.rules_file, &rule)?;
+            self.learned_rules.push((rule, category.clone()));
+        }
+        Ok(Some(category))
+    }
+}
 
-    // validate ynab cli options
-    ynab.validate_cli(cli.ynab.clone(), 1, 7)?;
+/// Parse and merge exports of the same account, reporting rows that were in
+/// more than one of them.
+fn parse_csv_files(csv_files: &[String], option: &str, dates: &DateRangeCli) -> Result<IngDiBa> {
+    let exports = csv_files
+        .iter()
+        .map(|x| IngDiBa::new(x.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    let (ingdiba, merged) = IngDiBa::merge(exports);
+    if merged > 0 {
+        println!(
+            " => Merged {} rows present in more than one {} file",
+            merged, option
+        );
+    }
+    let total = ingdiba.transactions.len();
+    let ingdiba = ingdiba.filter_dates(dates);
+    if ingdiba.transactions.len() < total {
+        println!(
+            " => Skipping {} rows of {} outside of --from-date/--to-date",
+            total - ingdiba.transactions.len(),
+            option
+        );
+    }
+    Ok(ingdiba)
+}
 
-    // Fetch YNAB categories
-    println!("[4/7] Fetching YNAB categories");
-    let ynab_categories = ynab.get_categories(cli.ynab.budget_id.clone())?;
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
 
-    // Fetch ynab transactions
-    println!(
-        "[5/7] Fetching YNAB transactions for the last {} days",
-        ingdiba.days_to_sync
-    );
-    let ynab_transactions = ynab.get_transactions(
-        cli.ynab.budget_id.clone(),
-        cli.ynab.account_id.clone(),
-        ingdiba.days_to_sync,
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
     )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
 
-    let apply_rules = |transaction: &IngDiBaTransaction| -> Option<Category> {
-        for rule in &rules {
-            match rule {
-                Rules::Contains {
-                    value,
-                    field,
-                    category,
-                } => {
-                    let text = match field {
-                        TransactionField::Memo => &transaction.memo,
-                        TransactionField::Entity => &transaction.entity,
-                    };
-                    if text.to_lowercase().contains(&value.to_lowercase()) {
-                        return ynab_categories.get(category).cloned();
-                    }
-                }
-                Rules::StartsWith {
-                    value,
-                    field,
-                    category,
-                } => {
-                    let text = match field {
-                        TransactionField::Memo => &transaction.memo,
-                        TransactionField::Entity => &transaction.entity,
-                    };
-                    if text.to_lowercase().starts_with(&value.to_lowercase()) {
-                        return ynab_categories.get(category).cloned();
-                    }
+    match cli.watch_dir.clone() {
+        Some(watch_dir) => watch(cli, PathBuf::from(watch_dir)),
+        None => sync(cli),
+    }
+}
+
+/// Sync every export appearing in `watch_dir` until stopped. Synced exports
+/// are archived, failing ones are kept and tried again once they change.
+fn watch(cli: Cli, watch_dir: PathBuf) -> Result<()> {
+    let archive_dir = cli
+        .archive_dir
+        .clone()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| watch_dir.join("synced"));
+    let mut watcher = Watcher::new(&watch_dir, &cli.watch_pattern, &archive_dir);
+    println!(
+        "Watching {} for {}",
+        watch_dir.to_string_lossy(),
+        cli.watch_pattern
+    );
+    loop {
+        for path in watcher.poll()? {
+            println!("Syncing {}", path.to_string_lossy());
+            let cli = Cli {
+                csv_files: vec![path.to_string_lossy().into()],
+                ..cli.clone()
+            };
+            match sync(cli) {
+                Ok(()) => {
+                    let archived = watcher.archive(&path)?;
+                    println!(" => Moved to {}", archived.to_string_lossy());
                 }
-                Rules::EndsWith {
-                    value,
-                    field,
-                    category,
-                } => {
-                    let text = match field {
-                        TransactionField::Memo => &transaction.memo,
-                        TransactionField::Entity => &transaction.entity,
-                    };
-                    if text.to_lowercase().ends_with(&value.to_lowercase()) {
-                        return ynab_categories.get(category).cloned();
-                    }
+                Err(e) => {
+                    warn!("Syncing {} failed: {}", path.to_string_lossy(), e);
+                    println!(" => {}", e);
+                    notify::send(&Notification::attention("Sync failed", &e.to_string()));
                 }
             }
         }
+        sleep(
+            Duration::seconds(cli.watch_interval.max(1))
+                .to_std()
+                .unwrap_or_default(),
+        );
+    }
+}
+
+fn sync(cli: Cli) -> Result<()> {
+    let rules = Rules::from_file(&cli.category_rules_file)?;
+
+    let memo_template = MemoTemplate::parse(&cli.memo_template, ingdiba::MEMO_FIELDS)?;
+
+    println!("[1/6] Parsing --csv files");
+    let ingdiba = parse_csv_files(&cli.csv_files, "--csv", &cli.dates)?;
+    let extra_konto = if cli.extra_konto_csv_files.is_empty() {
         None
+    } else {
+        Some(parse_csv_files(
+            &cli.extra_konto_csv_files,
+            "--extra-konto-csv",
+            &cli.dates,
+        )?)
     };
+    if ingdiba.kind == ExportKind::ExtraKonto {
+        println!(" => --csv looks like an Extra-Konto export, syncing it into --ynab-account");
+    }
+    if extra_konto
+        .as_ref()
+        .is_some_and(|x| x.kind != ExportKind::ExtraKonto)
+    {
+        Err(ErrorKind::ArgParse(
+            "--extra-konto-csv is not an Extra-Konto export".to_string(),
+        ))?
+    }
 
-    let convert_transaction =
-        |account_id: &str, transaction: &IngDiBaTransaction| -> YNABTransaction {
-            // apply category rules
-            let category: Option<String> = apply_rules(transaction).map(|x| x.id);
-
-            // when we can not figure out category we mark transaction as not approved
-            let approved = category.is_some();
-
-            // XXX: we can probably find more idiomatic way of doing this
-            let memo = format!(
-                "{} :: {}",
-                transaction.entity.clone(),
-                transaction.memo.clone()
-            );
-
-            let date = transaction.ts.format("%Y-%m-%d").to_string();
-
-            let mut import_id_sha = Sha1::new();
-            import_id_sha.input_str(&date);
-            import_id_sha.input_str(&format!("{}", transaction.amount));
-            import_id_sha.input_str(&memo);
-            let import_id = import_id_sha.result_str()[..36].to_string();
-
-            YNABTransaction {
-                account_id: account_id.to_string(),
-                date,
-                amount: transaction.amount,
-                // TODO: we would need to have payee_mapping
-                payee_id: None,
-                payee_name: None,
-                category_id: category,
-                memo: Some(memo),
-                cleared: TransactionCleared::Cleared,
-                approved,
-                flag_color: None,
-                import_id: Some(import_id),
-            }
-        };
+    // a Depot has no transactions, its value is synced instead
+    if ingdiba.kind == ExportKind::Depot {
+        return sync_depot(&cli, &ingdiba);
+    }
 
-    println!("[6/7] Convert IngDiBa transactions to YNAB transactions");
-    let account_id = cli.ynab.account_id.as_str();
-    let transactions: Vec<YNABTransaction> = ingdiba
-        .transactions
-        .into_iter()
-        .map(|t| convert_transaction(account_id, &t))
-        .collect();
-
-    ynab.sync(
-        transactions,
-        ynab_transactions,
-        cli.ynab.budget_id,
-        cli.ynab.force_update,
-        6,
-        7,
+    let balance_guard = BalanceGuard::new(&cli.balance);
+    // YNAB has the transactions after --to-date as well
+    let balance_guard = if balance_guard.is_enabled() && cli.dates.to_date.is_some() {
+        println!(" => Not checking the balance with --to-date");
+        BalanceGuard::off()
+    } else {
+        balance_guard
+    };
+    let days_to_sync = ingdiba.days_to_sync;
+    let mut source = RulesSource {
+        statement: ingdiba,
+        rules,
+        rules_file: cli.category_rules_file.clone(),
+        match_mode: cli.rules.match_mode,
+        unknown_categories: cli.unknown_categories,
+        assume_yes: cli.ynab.assume_yes(),
+        interactive_categorize: cli.interactive_categorize,
+        declined: vec![],
+        learned_rules: vec![],
+    };
+    sync_source(
+        &mut source,
+        Window::Days(days_to_sync),
+        cli.ynab.clone(),
+        &memo_template,
+        &balance_guard,
     )?;
 
+    // the Extra-Konto is synced with the same rules, without splitting or
+    // mirroring its transactions
+    if let (Some(extra_konto), Some(account)) = (extra_konto, &cli.extra_konto_account) {
+        println!("Syncing --extra-konto-csv into {}", account);
+        let days_to_sync = extra_konto.days_to_sync;
+        source.statement = extra_konto;
+        let ynab_cli = YNABCli {
+            account_id: account.clone(),
+            split_config_file: None,
+            mirror_to: vec![],
+            ..cli.ynab.clone()
+        };
+        sync_source(
+            &mut source,
+            Window::Days(days_to_sync),
+            ynab_cli,
+            &memo_template,
+            &BalanceGuard::off(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Sync the market value of the positions of a Depot export: a transaction
+/// for the difference between it and the balance of the YNAB (tracking)
+/// account, like the market value adjustments YNAB suggests itself.
+fn sync_depot(cli: &Cli, depot: &IngDiBa) -> Result<()> {
+    let ynab = YNAB::new(cli.ynab.token.clone())
+        .with_cache(Cache::new(
+            Duration::hours(cli.ynab.cache_ttl),
+            cli.ynab.refresh,
+        )?)
+        .with_assume_yes(cli.ynab.assume_yes())
+        .with_batch_size(cli.ynab.batch_size)
+        .with_dry_run(cli.ynab.dry_run);
+    let ynab_cli = ynab.validate_cli(cli.ynab.clone(), 1, 6)?;
+
+    let value = depot.depot_value();
+    println!(
+        "[5/6] Depot with {} positions worth {:.2} EUR",
+        depot.positions.len(),
+        value as f64 / 1000.0
+    );
+    let account = ynab.get_account(&ynab_cli.budget_id, &ynab_cli.account_id)?;
+    let difference = value - account.balance;
+    let date = depot
+        .positions_date
+        .unwrap_or_else(|| Utc::today().naive_local());
+    let transaction = YNABTransaction {
+        id: None,
+        account_id: ynab_cli.account_id.clone(),
+        date: date.format("%Y-%m-%d").to_string(),
+        amount: difference as i32,
+        payee_id: None,
+        payee_name: Some(ingdiba::DEPOT_PAYEE.to_string()),
+        category_id: None,
+        memo: Some(format!("Depot value {:.2} EUR", value as f64 / 1000.0)),
+        cleared: TransactionCleared::Cleared,
+        approved: true,
+        flag_color: None,
+        import_id: Some(format!("DEPOT:{}:{}", date, value)),
+        subtransactions: vec![],
+    };
+    let report = if difference == 0 {
+        println!("[6/6] No transactions to update.");
+        SyncReport {
+            synced: true,
+            unchanged: 1,
+            ..SyncReport::default()
+        }
+    } else {
+        ynab.sync(
+            vec![transaction],
+            HashMap::new(),
+            ynab_cli.budget_id.clone(),
+            ynab_cli.force_update,
+            5,
+            6,
+        )?
+    };
+
+    report.print();
+    notify::send(&Notification::new(
+        "ING-DiBa sync finished",
+        &finished_body(report.synced, &Totals::new(&[], &HashMap::new()), 0),
+    ));
+
     Ok(())
 }
@@ -1,23 +1,56 @@
-use chrono::{NaiveDate, Utc};
-use clap_verbosity_flag;
+use chrono::{Duration, NaiveDate};
+use exitfailure::ExitFailure;
 use failure::ResultExt;
-use serde_json;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::PathBuf;
+use std::result;
 use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::{BalanceGuard, Cli as BalanceCli};
+use ynab_sync::cache::Cache;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
 use ynab_sync::error::{ErrorKind, Result};
-use ynab_sync::logging::setup_logging;
-use ynab_sync::n26::{Cli as N26Cli, Transaction as N26Transaction, N26};
-use ynab_sync::ynab::{Cli as YNABCli, Transaction as YNABTransaction, TransactionCleared, YNAB};
+use ynab_sync::explain::Trace;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::n26::{self, Cli as N26Cli, PendingMode, N26};
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::source::{BankSource, SourceCategory, SourceTransaction};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::{BudgetCategories, Cli as YNABCli, YNAB};
 
 #[derive(Debug, StructOpt)]
 struct Cli {
     #[structopt(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
     #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
     ynab: YNABCli,
     #[structopt(flatten)]
     n26: N26Cli,
+    #[structopt(flatten)]
+    balance: BalanceCli,
     #[structopt(
         long = "n26-category-mapping",
         required = true,
@@ -27,144 +60,241 @@ struct Cli {
     category_mapping_file: String,
     #[structopt(
         long = "sync-from",
-        required = true,
         value_name = "YYYY-MM-DD",
-        help = "Date (including) when to sync from."
+        help = "Date (including) when to sync from. Defaults to the date of the last synced transaction minus --sync-overlap days."
+    )]
+    sync_from: Option<String>,
+    #[structopt(
+        long = "sync-overlap",
+        value_name = "DAYS",
+        default_value = "3",
+        help = "Days before the last synced transaction to sync again, to pick up late (pending) transactions."
+    )]
+    sync_overlap: i64,
+    #[structopt(
+        long = "n26-pending",
+        value_name = "MODE",
+        default_value = "uncleared",
+        possible_values = &["uncleared", "cleared", "skip"],
+        env = "N26_PENDING",
+        help = "How to sync pending transactions (card authorizations): uncleared, updated to cleared with the settled amount by the next sync; cleared, like settled ones; skip, only once settled."
     )]
-    sync_from: String,
+    pending: PendingMode,
+    #[structopt(
+        long = "n26-check-balance",
+        help = "After syncing compare the N26 balance with the cleared balance of the YNAB account and warn when they differ."
+    )]
+    check_balance: bool,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = n26::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {type} (eg. card payment, direct debit), {reference}, {merchant}, {city}, {partner}, {iban}, {original_amount}, {exchange_rate} and {original} (eg. \"(12.34 USD @ 0.92)\", empty for transactions in account currency). Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-n26")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+/// N26 as `BankSource`, with its categories mapped to YNAB categories by
+/// --n26-category-mapping and pending transactions synced as --n26-pending
+/// says.
+struct N26Source {
+    n26: N26,
+    /// N26 category names by id.
+    categories: HashMap<String, String>,
+    category_mapping: Map<String, Value>,
+    pending: PendingMode,
+}
+
+impl BankSource for N26Source {
+    fn name(&self) -> &'static str {
+        self.n26.name()
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        self.n26.memo_fields()
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        self.n26.default_memo_template()
+    }
+
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let mut transactions = self.n26.transactions(days)?;
+        match self.pending {
+            PendingMode::Uncleared => (),
+            PendingMode::Cleared => {
+                for transaction in transactions.iter_mut() {
+                    transaction.pending = false;
+                }
+            }
+            PendingMode::Skip => {
+                let pending = transactions.iter().filter(|x| x.pending).count();
+                if pending > 0 {
+                    println!(" => Skipping {} pending transactions", pending);
+                    transactions.retain(|x| !x.pending);
+                }
+            }
+        }
+        Ok(transactions)
+    }
+
+    fn balance(&self) -> Result<Option<i32>> {
+        Ok(self.n26.get_balance()?.cleared(self.pending))
+    }
+
+    fn category(
+        &self,
+        transaction: &SourceTransaction,
+        categories: &BudgetCategories,
+        trace: &mut Trace,
+    ) -> Option<SourceCategory> {
+        let n26_category_id = transaction.category.as_deref().unwrap_or_default();
+        // select category from transaction
+        let n26_category = self.categories.get(n26_category_id);
+        // find category in category_mapping
+        let mapping_entry = n26_category
+            .and_then(|x| self.category_mapping.get(x))
+            .and_then(|x| x.as_str());
+        // find id of the category
+        let mapped_category = mapping_entry.and_then(|x| categories.categories.get(x));
+        trace.add(|| match (n26_category, mapping_entry, mapped_category) {
+            (None, _, _) => format!("N26 category {:?} is unknown", n26_category_id),
+            (Some(n26_category), None, _) => format!(
+                "category mapping: no entry for N26 category {:?}",
+                n26_category
+            ),
+            (Some(n26_category), Some(entry), None) => format!(
+                "category mapping: {:?} => {:?}, which doesn't exist in the budget",
+                n26_category, entry
+            ),
+            (Some(n26_category), Some(entry), Some(_)) => {
+                format!("category mapping: {:?} => {:?}", n26_category, entry)
+            }
+        });
+        mapped_category.cloned().map(SourceCategory::Category)
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::from_args();
+fn run(cli: Cli) -> Result<()> {
     let app = Cli::clap();
 
-    setup_logging(app.get_name().to_string(), cli.verbose.log_level())?;
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+    n26::setup(&cli.n26);
+
+    let sync_from = match &cli.sync_from {
+        Some(sync_from) => Some(NaiveDate::parse_from_str(sync_from, "%Y-%m-%d")?),
+        None => None,
+    };
 
-    println!("[ 1/10] Parsing --sync-from");
-    let sync_from = NaiveDate::parse_from_str(&cli.sync_from, "%Y-%m-%d")?;
-    let days_to_sync = Utc::now()
-        .naive_utc()
-        .date()
-        .signed_duration_since(sync_from)
-        .num_days()
-        + 1;
+    let memo_template = MemoTemplate::parse(&cli.memo_template, n26::MEMO_FIELDS)?;
 
     //
     // Validate that category_mapping_file file exists and that it is of JSON format
     //
-    println!("[ 2/10] Parsing --category-mapping-file");
-
     if !PathBuf::from(cli.category_mapping_file.clone()).exists() {
         Err(ErrorKind::ArgParseCategoryMappingCanNotRead(
             cli.category_mapping_file.clone(),
         ))?
     }
 
-    let category_mapping_string = read_to_string(cli.category_mapping_file.to_string())
-        .with_context(|_| {
+    let category_mapping_string =
+        read_to_string(&cli.category_mapping_file).with_context(|_| {
             ErrorKind::ArgParseCategoryMappingCanNotRead(cli.category_mapping_file.clone())
         })?;
-    let category_mapping_value: serde_json::Value =
-        serde_json::from_str(category_mapping_string.as_str()).context(
-            ErrorKind::ArgParseCategoryMappingCanNotParse(cli.category_mapping_file.clone()),
-        )?;
-
-    let category_mapping = match category_mapping_value.as_object() {
-        Some(x) => x,
-        None => Err(ErrorKind::ArgParseCategoryMappingCanNotParse(
+    let category_mapping_value: Value = serde_json::from_str(category_mapping_string.as_str())
+        .context(ErrorKind::ArgParseCategoryMappingCanNotParse(
+            cli.category_mapping_file.clone(),
+        ))?;
+
+    let category_mapping = match category_mapping_value {
+        Value::Object(x) => x,
+        _ => Err(ErrorKind::ArgParseCategoryMappingCanNotParse(
             cli.category_mapping_file.clone(),
         ))?,
     };
 
-    // YNAB client
-    let ynab = YNAB {
-        token: cli.ynab.token.clone(),
+    // N26 client and categories
+    println!("[1/6] Fetching N26 token and categories");
+    let n26 = N26::new(cli.n26.username.clone(), cli.n26.password.clone())?;
+    let mut source = N26Source {
+        categories: n26.get_categories()?,
+        n26,
+        category_mapping,
+        pending: cli.pending,
     };
 
-    // validate ynab cli options
-    ynab.validate_cli(cli.ynab.clone(), 2, 10)?;
-
-    // Fetch YNAB categories
-    println!("[ 5/10] Fetching YNAB categories");
-    let ynab_categories = ynab.get_categories(cli.ynab.budget_id.clone())?;
-
-    // Fetch ynab transactions
-    println!(
-        "[ 6/10] Fetching YNAB transactions for the last {} days",
-        days_to_sync
-    );
-    let ynab_transactions = ynab.get_transactions(
-        cli.ynab.budget_id.clone(),
-        cli.ynab.account_id.clone(),
-        days_to_sync,
+    // without --sync-from continue from the last synced transaction
+    sync_source(
+        &mut source,
+        Window::Since {
+            date: sync_from,
+            overlap: cli.sync_overlap,
+        },
+        cli.ynab.clone(),
+        &memo_template,
+        &BalanceGuard::new(&cli.balance),
     )?;
+    if cli.check_balance && !cli.ynab.dry_run {
+        check_balance(&source.n26, &cli)?;
+    }
 
-    // N26 client
-    println!("[ 7/10] Fetching N26 token");
-    let n26 = N26::new(cli.n26.username.clone(), cli.n26.password.clone())?;
+    Ok(())
+}
 
-    // Fetch n26 categories
-    println!("[ 8/10] Fetching N26 categories");
-    let n26_categories = n26.get_categories()?;
-
-    let convert_transaction = |transaction: &N26Transaction| -> YNABTransaction {
-        let category: Option<String> = n26_categories
-            // select category from transaction
-            .get(&transaction.category)
-            // find category in category_mapping
-            .and_then(|x| category_mapping.get(x))
-            .and_then(|x| x.as_str())
-            .map(String::from)
-            // find id of the category
-            .and_then(|x| ynab_categories.get(&x))
-            .map(|x| x.clone().id);
-
-        // when we can not figure out category we mark transaction as not approved
-        let approved = category.is_some();
-
-        // XXX: we can probably find more idiomatic way of doing this
-        let memo = match &transaction.reference_text {
-            Some(reference_text) => Some(reference_text.to_string()),
-            None => match &transaction.merchant_name {
-                Some(merchant_name) => match &transaction.merchant_city {
-                    Some(merchant_city) => Some(format!("{} {}", merchant_name, merchant_city)),
-                    None => Some(merchant_name.to_string()),
-                },
-                None => None,
-            },
-        };
-
-        YNABTransaction {
-            account_id: cli.ynab.account_id.clone().to_string(),
-            date: transaction.visible_ts.format("%Y-%m-%d").to_string(),
-            amount: transaction.amount,
-            // TODO: we would need to have payee_mapping
-            payee_id: None,
-            payee_name: None,
-            category_id: category,
-            memo,
-            cleared: TransactionCleared::Cleared,
-            approved,
-            flag_color: None,
-            import_id: Some(transaction.id.clone()),
-        }
+/// Compare the N26 balance with the balance of the YNAB account. Pending
+/// transactions are only cleared in YNAB with `--n26-pending cleared`,
+/// otherwise the balance of the settled transactions is compared with the
+/// cleared balance. When N26 doesn't tell that balance, the balance
+/// including pending transactions is compared with the whole YNAB balance.
+fn check_balance(n26: &N26, cli: &Cli) -> Result<()> {
+    let ynab = YNAB::new(cli.ynab.token.clone()).with_cache(Cache::new(
+        Duration::hours(cli.ynab.cache_ttl),
+        cli.ynab.refresh,
+    )?);
+    let budget_id = ynab.resolve_budget_id(&cli.ynab.budget_id)?;
+    let account_id = ynab.resolve_account_id(&budget_id, &cli.ynab.account_id)?;
+    let balance = n26.get_balance()?;
+    let account = ynab.get_account(&budget_id, &account_id)?;
+    let (n26_balance, ynab_balance, kind) = match balance.cleared(cli.pending) {
+        Some(cleared) => (cleared, account.cleared_balance, "cleared "),
+        None => (balance.available_balance, account.balance, ""),
     };
-
-    println!("[ 9/10] Fetching N26 transaction and converting them to YNAB transactions");
-    let transactions: Vec<YNABTransaction> = n26
-        .get_transactions(days_to_sync, 100_000_000)? // XXX: for now we set limit to 1mio
-        .into_iter()
-        .map(|t| convert_transaction(&t))
-        .collect();
-
-    ynab.sync(
-        transactions,
-        ynab_transactions,
-        cli.ynab.budget_id.clone(),
-        cli.ynab.force_update,
-        9,
-        10,
-    )?;
-
+    let difference = ynab_balance - i64::from(n26_balance);
+    if difference == 0 {
+        println!(
+            " => YNAB {}balance matches N26: {:.2} EUR",
+            kind,
+            n26_balance as f64 / 1000.0
+        );
+        return Ok(());
+    }
+    let message = format!(
+        "YNAB {}balance {:.2} EUR differs from N26 balance {:.2} EUR by {:+.2} EUR",
+        kind,
+        ynab_balance as f64 / 1000.0,
+        n26_balance as f64 / 1000.0,
+        difference as f64 / 1000.0
+    );
+    println!(" => {}", message);
+    notify::send(&Notification::attention("N26 balance mismatch", &message));
     Ok(())
 }
@@ -0,0 +1,122 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plaid::{self, Cli as PlaidCli, Plaid};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::Cli as YNABCli;
+
+#[derive(StructOpt, Debug)]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
+    ynab: YNABCli,
+    #[structopt(flatten)]
+    plaid: PlaidCli,
+    #[structopt(
+        long = "sync-days",
+        value_name = "DAYS",
+        default_value = "30",
+        help = "Sync transactions of the last DAYS days."
+    )]
+    sync_days: i64,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = plaid::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {name}, {merchant}, {category}, {channel}. Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-plaid")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+
+    let memo_template = MemoTemplate::parse(&cli.memo_template, plaid::MEMO_FIELDS)?;
+
+    println!("[1/6] Fetching Plaid transactions");
+    let mut client = Plaid::new(&cli.plaid)?;
+    let (added, modified, removed) = client.sync_transactions()?;
+    println!(
+        " => {} added, {} modified and {} removed since the last sync",
+        added, modified, removed
+    );
+    let accounts = client.get_accounts()?;
+    for account in &accounts {
+        println!(
+            " - | {} | {} | {} |",
+            account.account_id,
+            account.name,
+            account.mask.clone().unwrap_or_default()
+        );
+    }
+    let account = match &cli.plaid.account {
+        Some(account) => accounts
+            .into_iter()
+            .find(|x| {
+                x.account_id == *account || x.name == *account || x.mask.as_ref() == Some(account)
+            })
+            .ok_or_else(|| ErrorKind::PlaidAccountNotFound(account.clone()))?,
+        None if accounts.len() == 1 => accounts.into_iter().next().unwrap(),
+        None => Err(ErrorKind::PlaidAccountAmbiguous)?,
+    };
+    let mut account = client.account(account);
+
+    sync_source(
+        &mut account,
+        Window::Days(cli.sync_days),
+        cli.ynab,
+        &memo_template,
+        &BalanceGuard::off(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::config::{self, Cli as ConfigCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::Result;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::notify::{self, Cli as NotifyCli, Notification};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+use ynab_sync::plan::{self, Cli as PlanCli};
+use ynab_sync::revolut::{self, ExchangeRates, Revolut};
+use ynab_sync::sync::{sync_source, Window};
+use ynab_sync::ynab::{Cli as YNABCli, YNAB};
+
+#[derive(StructOpt, Debug)]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(flatten)]
+    notify: NotifyCli,
+    #[structopt(flatten)]
+    plan: PlanCli,
+    #[structopt(flatten)]
+    config: ConfigCli,
+    #[structopt(flatten)]
+    ynab: YNABCli,
+    #[structopt(
+        long = "csv",
+        required = true,
+        value_name = "FILE",
+        number_of_values = 1,
+        help = "CSV file (account statement) which you exported from Revolut. Can be used multiple times, rows in overlapping exports are only synced once."
+    )]
+    csv_files: Vec<String>,
+    #[structopt(
+        long = "revolut-product",
+        value_name = "NAME",
+        default_value = "Current",
+        number_of_values = 1,
+        help = "Only sync rows of this product (eg. Current or Savings). Can be repeated."
+    )]
+    products: Vec<String>,
+    #[structopt(
+        long = "revolut-pocket",
+        value_name = "CURRENCY",
+        number_of_values = 1,
+        help = "Only sync rows of the pocket in this currency, all pockets are synced without it. Can be repeated."
+    )]
+    pockets: Vec<String>,
+    #[structopt(
+        long = "exchange-rate",
+        value_name = "CURRENCY=RATE",
+        number_of_values = 1,
+        help = "Convert amounts in CURRENCY with a fixed RATE (eg. USD=0.92) instead of the ECB reference rate of the day. Can be repeated."
+    )]
+    exchange_rates: Vec<String>,
+    #[structopt(
+        long = "memo-template",
+        value_name = "TEMPLATE",
+        default_value = revolut::DEFAULT_MEMO_TEMPLATE,
+        help = "Template of the YNAB memo. Placeholders: {description}, {type}, {product}, {original_amount}, {exchange_rate} and {original} (eg. \"(12.34 USD @ 0.9200)\", empty for rows in the budget currency). Use {a|b} to fall back to b when a is empty."
+    )]
+    memo_template: String,
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_iter(config::args("sync-with-revolut")?);
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| {
+        notify::send(&Notification::attention("Sync failed", &e.to_string()));
+        report_error(&output, e)
+    })
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+    notify::setup(&cli.notify);
+    plan::setup(&cli.plan)?;
+    config::setup(&cli.config);
+
+    let memo_template = MemoTemplate::parse(&cli.memo_template, revolut::MEMO_FIELDS)?;
+    let exchange_rates = ExchangeRates::new(&cli.exchange_rates)?;
+
+    println!("[1/6] Parsing --csv files");
+    let mut revolut = Revolut::new(&cli.csv_files, &cli.products, &cli.pockets)?;
+
+    // amounts of other pockets are converted into the budget currency
+    let budget = YNAB::new(cli.ynab.token.clone()).get_budget(&cli.ynab.budget_id)?;
+    let currency = budget.currency_format.iso_code;
+    let converted = revolut.convert(&currency, &exchange_rates)?;
+    if converted > 0 {
+        println!(" => Converted {} rows into {}", converted, currency);
+    }
+    let days_to_sync = revolut.days_to_sync;
+
+    sync_source(
+        &mut revolut,
+        Window::Days(days_to_sync),
+        cli.ynab,
+        &memo_template,
+        &BalanceGuard::off(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use chrono::{NaiveDate, Utc};
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+use structopt::StructOpt;
+use ynab_sync::error::Result;
+use ynab_sync::plan::{Change, Plan};
+use ynab_sync::ynab::{Transaction, YNAB};
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(long = "yes", help = "Don't ask for confirmation.")]
+    yes: bool,
+    #[structopt(value_name = "FILE", help = "Plan written by a sync with --plan FILE.")]
+    plan_file: String,
+}
+
+/// Save the transactions of a plan. Every account is compared with YNAB
+/// again, so transactions which were synced in the meantime are skipped.
+pub fn run(cli: Cli) -> Result<()> {
+    let plan = Plan::load(&cli.plan_file)?;
+    println!(
+        "Applying plan {} created {}",
+        cli.plan_file,
+        plan.created.format("%Y-%m-%d %H:%M")
+    );
+    let ynab = YNAB::new(cli.token.clone()).with_assume_yes(cli.yes);
+
+    let steps = plan.changes.len() as i32;
+    if steps == 0 {
+        println!("Plan is empty.");
+    }
+    for (step, change) in plan.changes.into_iter().enumerate() {
+        apply(&ynab, change, step as i32 + 1, steps)?;
+    }
+
+    Ok(())
+}
+
+fn apply(ynab: &YNAB, change: Change, step: i32, steps: i32) -> Result<()> {
+    let mut by_account: BTreeMap<String, Vec<Transaction>> = BTreeMap::new();
+    for transaction in change.create.into_iter().chain(change.update) {
+        by_account
+            .entry(transaction.account_id.clone())
+            .or_default()
+            .push(transaction);
+    }
+    let before: HashMap<&String, &Transaction> = change
+        .before
+        .iter()
+        .filter_map(|x| x.import_id.as_ref().map(|id| (id, x)))
+        .collect();
+
+    for (account_id, transactions) in by_account {
+        println!(
+            "[ {}/{}] Comparing plan with account {}",
+            step, steps, account_id
+        );
+        let today = Utc::now().naive_utc().date();
+        let since = transactions
+            .iter()
+            .filter_map(|x| NaiveDate::parse_from_str(&x.date, "%Y-%m-%d").ok())
+            .min()
+            .unwrap_or(today);
+        let existing_transactions = ynab.get_transactions(
+            change.budget_id.clone(),
+            account_id.clone(),
+            (today - since).num_days() + 1,
+        )?;
+
+        for (import_id, planned) in before.iter().filter(|x| x.1.account_id == account_id) {
+            let current = existing_transactions.get(*import_id);
+            if current.map(|x| serde_json::to_value(x).ok())
+                != Some(serde_json::to_value(planned).ok())
+            {
+                warn!("Transaction {} changed in YNAB after planning", import_id);
+                println!(
+                    " => Transaction {} changed in YNAB after the plan was made",
+                    import_id
+                );
+            }
+        }
+
+        ynab.sync(
+            transactions,
+            existing_transactions,
+            change.budget_id.clone(),
+            change.force_update,
+            step,
+            steps,
+        )?;
+    }
+
+    Ok(())
+}
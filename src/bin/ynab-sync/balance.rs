@@ -0,0 +1,187 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use std::convert::TryFrom;
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+use structopt::StructOpt;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::parse::{amount_detect, AmountFormat};
+use ynab_sync::ynab::{Transaction, TransactionCleared, YNAB};
+
+// Import ids of balance adjustments start with this, followed by the first
+// day of the period, so there is one adjustment per period which is updated
+// when the balance is synced again.
+const IMPORT_ID_PREFIX: &str = "ynab-sync-balance";
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id)."
+    )]
+    budget_id: String,
+    #[structopt(
+        long = "ynab-account",
+        required = true,
+        value_name = "NAME|ID",
+        help = "Tracking account (name or id) of the portfolio, eg. Trade Republic, IBKR or a crypto wallet."
+    )]
+    account_id: String,
+    #[structopt(
+        long = "balance",
+        required = true,
+        value_name = "AMOUNT",
+        help = "Current value of the portfolio, eg. 12345.67 or 12.345,67."
+    )]
+    balance: String,
+    #[structopt(
+        long = "period",
+        value_name = "PERIOD",
+        default_value = "day",
+        possible_values = &["day", "week", "month"],
+        help = "Post one adjustment per period. Syncing again within the same period updates its adjustment."
+    )]
+    period: Period,
+    #[structopt(
+        long = "payee",
+        value_name = "TEXT",
+        default_value = "Balance Adjustment",
+        help = "Payee of the adjustment transactions."
+    )]
+    payee: String,
+    #[structopt(long = "yes", help = "Don't ask for confirmation.")]
+    yes: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    /// First day of the period `date` is in.
+    fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => date - Duration::days(i64::from(date.weekday().num_days_from_monday())),
+            Period::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Period::Day => "day",
+                Period::Week => "week",
+                Period::Month => "month",
+            },
+        )
+    }
+}
+
+impl FromStr for Period {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            _ => Err(ErrorKind::ArgParse(format!("--period {}", s))),
+        }
+    }
+}
+
+/// Instead of importing every trade of an investment account, post a
+/// balance adjustment so that the balance of the YNAB tracking account (and
+/// with it the net worth) follows the value of the portfolio.
+pub fn run(cli: Cli) -> Result<()> {
+    let target = i64::from(amount_detect(&cli.balance, &AmountFormat::PLAIN)?);
+
+    println!("[1/3] Resolving budget and account");
+    let ynab = YNAB::new(cli.token.clone()).with_assume_yes(cli.yes);
+    let budget_id = ynab.resolve_budget_id(&cli.budget_id)?;
+    let account_id = ynab.resolve_account_id(&budget_id, &cli.account_id)?;
+    let account = ynab
+        .get_accounts(budget_id.clone())?
+        .into_iter()
+        .find(|x| x.id == account_id)
+        .ok_or_else(|| ErrorKind::WrongAccountId(cli.account_id.clone()))?;
+    if account.on_budget {
+        println!(
+            " => \"{}\" is a budget account, balance adjustments will show up in Ready to Assign",
+            account.name
+        );
+    }
+
+    println!("[2/3] Calculating balance adjustment");
+    let today = Utc::now().naive_utc().date();
+    let period_start = cli.period.start(today);
+    let import_id = format!("{}:{}", IMPORT_ID_PREFIX, period_start.format("%Y-%m-%d"));
+    let existing_transactions = ynab.get_transactions(
+        budget_id.clone(),
+        account_id.clone(),
+        (today - period_start).num_days() + 1,
+    )?;
+    // the adjustment of this period is replaced, so it doesn't count
+    let balance = account.balance
+        - existing_transactions
+            .get(&import_id)
+            .map(|x| i64::from(x.amount))
+            .unwrap_or(0);
+    let adjustment = target - balance;
+    println!(
+        " => Portfolio {:.2} EUR, YNAB {:.2} EUR, adjustment {:+.2} EUR",
+        target as f64 / 1000.0,
+        balance as f64 / 1000.0,
+        adjustment as f64 / 1000.0
+    );
+    if adjustment == 0 && !existing_transactions.contains_key(&import_id) {
+        println!("[3/3] Balance is up to date.");
+        return Ok(());
+    }
+
+    let transaction = Transaction {
+        id: None,
+        account_id: account_id.clone(),
+        date: today.format("%Y-%m-%d").to_string(),
+        amount: i32::try_from(adjustment)
+            .map_err(|_| ErrorKind::AmountParse(cli.balance.clone()))?,
+        payee_id: None,
+        payee_name: Some(cli.payee.clone()),
+        category_id: None,
+        memo: Some(format!("Portfolio value {:.2} EUR", target as f64 / 1000.0)),
+        cleared: TransactionCleared::Cleared,
+        approved: true,
+        flag_color: None,
+        import_id: Some(import_id),
+        subtransactions: vec![],
+    };
+    ynab.sync(
+        vec![transaction],
+        existing_transactions,
+        budget_id,
+        false,
+        3,
+        3,
+    )?;
+
+    Ok(())
+}
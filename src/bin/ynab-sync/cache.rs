@@ -0,0 +1,180 @@
+use chrono::{Duration, TimeZone, Utc};
+use failure::ResultExt;
+use std::fs::remove_file;
+use structopt::StructOpt;
+use ynab_sync::cache::Cache;
+use ynab_sync::delta::Deltas;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::n26::{self, N26};
+use ynab_sync::state::State;
+
+#[derive(Debug, StructOpt)]
+pub enum Cli {
+    #[structopt(
+        name = "show",
+        about = "Show cached YNAB responses, YNAB snapshots, the N26 token and the sync state."
+    )]
+    Show,
+    #[structopt(name = "prune", about = "Remove expired YNAB cache entries.")]
+    Prune {
+        #[structopt(
+            long = "cache-ttl",
+            value_name = "HOURS",
+            default_value = "24",
+            help = "Remove entries older than this."
+        )]
+        cache_ttl: i64,
+    },
+    #[structopt(
+        name = "clear",
+        about = "Remove cached data. Without flags the YNAB cache and snapshots and the N26 token are removed."
+    )]
+    Clear {
+        #[structopt(
+            long = "ynab",
+            help = "Remove cached YNAB responses and snapshots (the next sync downloads everything)."
+        )]
+        ynab: bool,
+        #[structopt(
+            long = "n26-token",
+            help = "Remove the N26 token (the next sync needs a login approval)."
+        )]
+        n26_token: bool,
+        #[structopt(
+            long = "state",
+            help = "Remove the sync state (--sync-from is needed for the next N26 sync)."
+        )]
+        state: bool,
+    },
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    match cli {
+        Cli::Show => show(),
+        Cli::Prune { cache_ttl } => {
+            let removed = Cache::new(Duration::hours(cache_ttl), false)?.prune()?;
+            println!("Removed {} expired YNAB cache entries.", removed);
+            Ok(())
+        }
+        Cli::Clear {
+            ynab,
+            n26_token,
+            state,
+        } => {
+            let all = !ynab && !n26_token && !state;
+            if ynab || all {
+                let removed = Cache::new(Duration::zero(), false)?.clear()?;
+                println!("Removed {} YNAB cache entries.", removed);
+                let removed = Deltas::new(false)?.clear()?;
+                println!("Removed {} YNAB snapshots.", removed);
+            }
+            if n26_token || all {
+                let token_files = n26::token_files()?;
+                for token_file in &token_files {
+                    remove_file(token_file).with_context(|_| {
+                        ErrorKind::CacheCanNotWrite(token_file.to_string_lossy().into())
+                    })?;
+                }
+                println!("Removed {} N26 tokens.", token_files.len());
+            }
+            if state {
+                let state = State::load()?;
+                if state.path().exists() {
+                    remove_file(state.path()).with_context(|_| {
+                        ErrorKind::StateCanNotWrite(state.path().to_string_lossy().into())
+                    })?;
+                }
+                println!("Removed sync state.");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn show() -> Result<()> {
+    let cache = Cache::new(Duration::zero(), false)?;
+    let entries = cache.entries()?;
+    println!(
+        "YNAB cache ({}): {} entries, {} bytes",
+        cache.dir().to_string_lossy(),
+        entries.len(),
+        entries.iter().map(|x| x.size).sum::<u64>()
+    );
+    let now = Utc::now();
+    for entry in &entries {
+        println!(
+            " - {} ({} hours old)",
+            entry
+                .path
+                .file_stem()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            (now - entry.modified).num_hours()
+        );
+    }
+
+    let deltas = Deltas::new(false)?;
+    let snapshots = deltas.entries()?;
+    println!(
+        "YNAB snapshots ({}): {} entries",
+        deltas.dir().to_string_lossy(),
+        snapshots.len()
+    );
+    for path in &snapshots {
+        println!(
+            " - {}",
+            path.file_stem()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        );
+    }
+
+    let token_files = n26::token_files()?;
+    println!("N26 tokens: {} accounts", token_files.len());
+    for token_file in &token_files {
+        match N26::load(token_file) {
+            Ok(n26) => println!(
+                " - {} ({}): {} {}",
+                if n26.username.is_empty() {
+                    "unknown account"
+                } else {
+                    &n26.username
+                },
+                token_file.to_string_lossy(),
+                if n26.is_valid() {
+                    "valid until"
+                } else {
+                    "expired at"
+                },
+                Utc.timestamp_opt(n26.expiration_time, 0)
+                    .single()
+                    .map(|x| x.to_rfc3339())
+                    .unwrap_or_default()
+            ),
+            Err(e) => println!(" - {}: {}", token_file.to_string_lossy(), e),
+        }
+    }
+
+    let state = State::load()?;
+    let mut last_synced: Vec<_> = state.all_last_synced().iter().collect();
+    last_synced.sort();
+    println!(
+        "Sync state ({}): {} accounts",
+        state.path().to_string_lossy(),
+        last_synced.len()
+    );
+    for (account_id, date) in last_synced {
+        println!(
+            " - {} last synced up to {} ({} synced transactions remembered{})",
+            account_id,
+            date,
+            state.synced_count(account_id),
+            state
+                .last_sync_time(account_id)
+                .map(|x| format!(", last sync at {}", x.to_rfc3339()))
+                .unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
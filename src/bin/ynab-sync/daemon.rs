@@ -0,0 +1,140 @@
+use crate::sync;
+use chrono::{DateTime, Duration, Local, NaiveTime};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::thread::sleep;
+use structopt::StructOpt;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::n26::{self, N26};
+
+// N26 tokens are refreshed this long before they expire.
+const TOKEN_REFRESH_MARGIN_SECONDS: i64 = 60;
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "config",
+        value_name = "FILE",
+        env = "YNAB_SYNC_CONFIG",
+        help = "TOML file with the profiles. Defaults to ~/.config/ynab-sync/config.toml."
+    )]
+    config_file: Option<String>,
+    #[structopt(
+        long = "profile",
+        value_name = "NAME",
+        number_of_values = 1,
+        help = "Only sync this profile. Can be repeated."
+    )]
+    profiles: Vec<String>,
+    #[structopt(
+        long = "interval",
+        value_name = "MINUTES",
+        default_value = "60",
+        env = "YNAB_SYNC_INTERVAL",
+        help = "Sync every this many minutes."
+    )]
+    interval: i64,
+    #[structopt(
+        long = "at",
+        value_name = "HH:MM",
+        number_of_values = 1,
+        parse(try_from_str = parse_time),
+        help = "Sync every day at this (local) time instead of every --interval. Can be repeated."
+    )]
+    at: Vec<NaiveTime>,
+    #[structopt(
+        long = "jobs",
+        value_name = "NUMBER",
+        default_value = "1",
+        env = "YNAB_SYNC_JOBS",
+        help = "Sync up to this many profiles at the same time, see `ynab-sync sync --jobs`."
+    )]
+    jobs: usize,
+}
+
+/// Sync the profiles of the config file (like `ynab-sync sync --yes
+/// --jobs`) on a schedule until stopped. In between, stored N26 tokens are refreshed
+/// before they expire, so syncs don't need a login approval. Failing syncs
+/// are logged and retried at the next scheduled time.
+pub fn run(cli: Cli, inherited: &sync::Inherited) -> Result<()> {
+    loop {
+        info!("Syncing profiles");
+        let sync_cli = sync::Cli {
+            config_file: cli.config_file.clone(),
+            profiles: cli.profiles.clone(),
+            yes: true,
+            jobs: cli.jobs,
+        };
+        match sync::run(sync_cli, inherited) {
+            Ok(()) => info!("Synced profiles"),
+            Err(e) => warn!("Syncing profiles failed: {}", e),
+        }
+
+        let next_sync = next_sync(&cli);
+        println!(" => Next sync at {}", next_sync.format("%Y-%m-%d %H:%M"));
+        // token files which failed to refresh, the next sync logs in again
+        let mut failed = HashSet::new();
+        loop {
+            let now = Local::now();
+            if now >= next_sync {
+                break;
+            }
+            let token_expirations: Vec<(PathBuf, i64)> = n26::token_files()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|x| !failed.contains(x))
+                .filter_map(|x| {
+                    let n26 = N26::load(&x).ok()?;
+                    Some((x, n26.expiration_time - TOKEN_REFRESH_MARGIN_SECONDS))
+                })
+                .collect();
+            let wake_up = token_expirations
+                .iter()
+                .map(|x| x.1)
+                .chain(Some(next_sync.timestamp()))
+                .min()
+                .unwrap_or_default();
+            sleep(
+                Duration::seconds(wake_up - now.timestamp())
+                    .to_std()
+                    .unwrap_or_default(),
+            );
+            let now = Local::now().timestamp();
+            for (token_file, _) in token_expirations.iter().filter(|x| x.1 <= now) {
+                match N26::refresh_cached(token_file) {
+                    Ok(_) => info!("Refreshed N26 token {}", token_file.to_string_lossy()),
+                    Err(e) => {
+                        warn!(
+                            "Refreshing N26 token {} failed: {}",
+                            token_file.to_string_lossy(),
+                            e
+                        );
+                        failed.insert(token_file.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime> {
+    Ok(NaiveTime::parse_from_str(s, "%H:%M")
+        .map_err(|_| ErrorKind::ArgParse(format!("--at {}", s)))?)
+}
+
+/// Time of the next sync, the next of the --at times or --interval from now.
+fn next_sync(cli: &Cli) -> DateTime<Local> {
+    let now = Local::now();
+    if cli.at.is_empty() {
+        return now + Duration::minutes(cli.interval.max(1));
+    }
+    (0..2)
+        .flat_map(|days| {
+            let date = (now + Duration::days(days)).date();
+            cli.at.iter().filter_map(move |x| date.and_time(*x))
+        })
+        .filter(|x| *x > now)
+        .min()
+        .unwrap_or_else(|| now + Duration::days(1))
+}
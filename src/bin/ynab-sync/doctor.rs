@@ -0,0 +1,473 @@
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{read_to_string, remove_file, write};
+use std::path::Path;
+use structopt::StructOpt;
+use ynab_sync::cache::Cache;
+use ynab_sync::error::{find_kinds, ErrorKind, Result};
+use ynab_sync::n26::{self, N26};
+use ynab_sync::payees::PayeeMapping;
+use ynab_sync::rules::{self, RuleTarget, RulesFormat, RULE_HELP};
+use ynab_sync::split::SplitConfig;
+use ynab_sync::state_dir;
+use ynab_sync::ynab::{index_categories, index_category_groups, Category, CategoryGroup, YNAB};
+
+// Warn when less than this share of YNAB's hourly rate limit is left.
+const RATE_LIMIT_WARNING: f64 = 0.1;
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        alias = "ynab-budget-id",
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id) to check."
+    )]
+    budget_id: Option<String>,
+    #[structopt(
+        long = "ynab-account",
+        alias = "ynab-account-id",
+        value_name = "NAME|ID",
+        env = "YNAB_ACCOUNT_ID",
+        requires = "budget_id",
+        help = "YNAB account (name or id) to check."
+    )]
+    account_id: Option<String>,
+    #[structopt(
+        long = "n26-category-mapping",
+        value_name = "FILE",
+        help = "N26 category mapping file to check."
+    )]
+    category_mapping_file: Option<String>,
+    #[structopt(
+        long = "category-rules",
+        value_name = "FILE",
+        help = "IngDiba category rules file to check."
+    )]
+    category_rules_file: Option<String>,
+    #[structopt(
+        long = "payee-mapping",
+        value_name = "FILE",
+        help = "Payee mapping file to check."
+    )]
+    payee_mapping_file: Option<String>,
+    #[structopt(
+        long = "split-config",
+        value_name = "FILE",
+        help = "Split config file to check."
+    )]
+    split_config_file: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub failures: usize,
+}
+
+impl Report {
+    pub fn ok(&self, check: &str) {
+        println!(" [ok]   {}", check);
+    }
+
+    pub fn warn(&self, check: &str, fix: &str) {
+        println!(" [warn] {}\n    => {}", check, fix);
+    }
+
+    pub fn fail(&mut self, check: &str, fix: &str) {
+        self.failures += 1;
+        println!(" [fail] {}\n    => {}", check, fix);
+    }
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    let mut report = Report::default();
+    let ynab = YNAB::new(cli.token.clone());
+
+    println!("[1/4] Checking YNAB");
+    let category_groups = check_ynab(&cli, &ynab, &mut report);
+    let categories = category_groups.clone().map(index_categories);
+    let category_groups = category_groups.map(index_category_groups);
+
+    println!("[2/4] Checking N26 token cache");
+    check_n26_token(&mut report);
+
+    println!("[3/4] Checking rule and mapping files");
+    if let Some(file) = &cli.category_mapping_file {
+        check_category_mapping(file, categories.as_ref(), &mut report);
+    }
+    if let Some(file) = &cli.category_rules_file {
+        check_category_rules(
+            file,
+            categories.as_ref(),
+            category_groups.as_ref(),
+            &mut report,
+        );
+    }
+    if let Some(file) = &cli.payee_mapping_file {
+        match PayeeMapping::from_file(file) {
+            Ok(_) => report.ok(&format!("payee mapping {} is valid", file)),
+            Err(e) => report.fail(
+                &format!("payee mapping: {}", e),
+                "The file must be a JSON object of source names to YNAB payee names or ids.",
+            ),
+        }
+    }
+    if let Some(file) = &cli.split_config_file {
+        match SplitConfig::from_file(file) {
+            Ok(_) => report.ok(&format!("split config {} is valid", file)),
+            Err(e) => report.fail(
+                &format!("split config: {}", e),
+                "The file needs \"share\" (0-100) and either \"category\" or \"budget\" and \"account\".",
+            ),
+        }
+    }
+
+    println!("[4/4] Checking cache and state directories");
+    match Cache::new(Duration::zero(), false) {
+        Ok(cache) => check_writable("cache directory", cache.dir(), &mut report),
+        Err(e) => report.fail(
+            &format!("cache directory: {}", e),
+            "Make sure $XDG_CACHE_HOME (or ~/.cache) is writable.",
+        ),
+    }
+    match state_dir() {
+        Ok(dir) => check_writable("state directory", &dir, &mut report),
+        Err(e) => report.fail(
+            &format!("state directory: {}", e),
+            "Make sure $XDG_DATA_HOME (or ~/.local/share) is writable.",
+        ),
+    }
+
+    if report.failures > 0 {
+        Err(ErrorKind::DoctorFailed(report.failures))?
+    }
+    println!("Everything looks fine.");
+    Ok(())
+}
+
+// Token, rate limit, budget and account. Returns the budget's category groups
+// when the budget could be checked.
+fn check_ynab(cli: &Cli, ynab: &YNAB, report: &mut Report) -> Option<Vec<CategoryGroup>> {
+    match ynab.rate_limit() {
+        Ok(rate_limit) => {
+            report.ok("YNAB token is valid");
+            let usage = rate_limit.as_ref().and_then(|x| {
+                let mut parts = x.splitn(2, '/');
+                let used: f64 = parts.next()?.parse().ok()?;
+                let limit: f64 = parts.next()?.parse().ok()?;
+                Some((used, limit))
+            });
+            match usage {
+                Some((used, limit)) if limit - used < limit * RATE_LIMIT_WARNING => report.warn(
+                    &format!("YNAB rate limit almost used up ({} requests)", used),
+                    "YNAB allows a limited number of requests per hour, wait before syncing again.",
+                ),
+                Some((used, limit)) => report.ok(&format!(
+                    "YNAB rate limit: {} of {} requests used this hour",
+                    used, limit
+                )),
+                None => (),
+            }
+        }
+        Err(e) => {
+            let kinds = find_kinds(&e);
+            let fix = if kinds
+                .iter()
+                .any(|x| matches!(x, ErrorKind::YNABUnauthorized(_)))
+            {
+                "Create a new personal access token under \"Developer Settings\" in YNAB and pass it via --ynab-token."
+            } else if kinds.iter().any(|x| x.http_status().is_some()) {
+                "YNAB answered with an error, try again later."
+            } else {
+                "Check your network connection, the YNAB API could not be reached."
+            };
+            report.fail(&format!("YNAB token: {}", e), fix);
+            return None;
+        }
+    }
+
+    let budget = cli.budget_id.as_ref()?;
+    let budget_id = match ynab.resolve_budget_id(budget) {
+        Ok(x) => {
+            report.ok(&format!("budget {} is reachable", budget));
+            x
+        }
+        Err(e) => {
+            let budgets = ynab
+                .get_budgets()
+                .map(|x| x.into_iter().map(|x| x.name).collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            report.fail(
+                &format!("budget: {}", e),
+                &format!("Pass one of these budgets via --ynab-budget: {}", budgets),
+            );
+            return None;
+        }
+    };
+
+    if let Some(account) = &cli.account_id {
+        match ynab.resolve_account_id(&budget_id, account) {
+            Ok(_) => report.ok(&format!("account {} is reachable", account)),
+            Err(e) => {
+                let accounts = ynab
+                    .get_accounts(budget_id.clone())
+                    .map(|x| {
+                        x.into_iter()
+                            .filter(|x| !x.closed && !x.deleted)
+                            .map(|x| x.name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .unwrap_or_default();
+                report.fail(
+                    &format!("account: {}", e),
+                    &format!(
+                        "Pass one of these accounts via --ynab-account: {}",
+                        accounts
+                    ),
+                );
+            }
+        }
+    }
+
+    match ynab.get_category_groups(budget_id) {
+        Ok(category_groups) => Some(category_groups),
+        Err(e) => {
+            report.fail(
+                &format!("categories: {}", e),
+                "Check that the token has access to the budget.",
+            );
+            None
+        }
+    }
+}
+
+fn check_n26_token(report: &mut Report) {
+    let token_files = match n26::token_files() {
+        Ok(x) => x,
+        Err(e) => {
+            return report.fail(
+                &format!("N26 token cache: {}", e),
+                "Check the cache directory.",
+            )
+        }
+    };
+    if token_files.is_empty() {
+        report.warn(
+            "no N26 token cached yet",
+            "The next N26 sync needs the login to be approved in the N26 app.",
+        );
+    }
+    for token_file in &token_files {
+        let account = |n26: &N26| {
+            if n26.username.is_empty() {
+                token_file.to_string_lossy().into_owned()
+            } else {
+                n26.username.clone()
+            }
+        };
+        match N26::load(token_file) {
+            Ok(n26) if n26.is_valid() => report.ok(&format!(
+                "N26 token of {} is valid until {}",
+                account(&n26),
+                Utc.timestamp_opt(n26.expiration_time, 0)
+                    .single()
+                    .map(|x| x.to_rfc3339())
+                    .unwrap_or_default()
+            )),
+            Ok(n26) => report.warn(
+                &format!("N26 token of {} expired", account(&n26)),
+                "It is refreshed on the next sync, if that fails you need to approve the login in the N26 app.",
+            ),
+            Err(e) => report.fail(
+                &format!("N26 token cache: {}", e),
+                &format!("Remove {} to log in again.", token_file.to_string_lossy()),
+            ),
+        }
+    }
+}
+
+fn read_json(file: &str, report: &mut Report) -> Option<Value> {
+    let content = match read_to_string(file) {
+        Ok(x) => x,
+        Err(e) => {
+            report.fail(
+                &format!("{} can not be read: {}", file, e),
+                "Check the path and the permissions of the file.",
+            );
+            return None;
+        }
+    };
+    match serde_json::from_str(&content) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            report.fail(
+                &format!("{} is not valid JSON: {}", file, e),
+                "Fix the JSON syntax at the given line and column.",
+            );
+            None
+        }
+    }
+}
+
+fn check_categories_exist(
+    file: &str,
+    names: Vec<&str>,
+    categories: Option<&HashMap<String, Category>>,
+    report: &mut Report,
+) {
+    let categories = match categories {
+        Some(x) => x,
+        None => return,
+    };
+    let mut missing: Vec<&str> = names
+        .into_iter()
+        .filter(|x| !categories.contains_key(*x))
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if missing.is_empty() {
+        report.ok(&format!("categories used in {} exist in the budget", file));
+    } else {
+        report.fail(
+            &format!(
+                "{} uses categories which don't exist in the budget: {}",
+                file,
+                missing.join(", ")
+            ),
+            "Use the category name, \"<Group>:<Category>\" or the category id.",
+        );
+    }
+}
+
+pub fn check_category_mapping(
+    file: &str,
+    categories: Option<&HashMap<String, Category>>,
+    report: &mut Report,
+) {
+    let value = match read_json(file, report) {
+        Some(x) => x,
+        None => return,
+    };
+    let mapping = match value.as_object() {
+        Some(x) => x,
+        None => {
+            report.fail(
+                &format!("{} is not a JSON object", file),
+                "Generate a mapping with `ynab-sync generate-mapping`.",
+            );
+            return;
+        }
+    };
+    let names: Vec<&str> = mapping.values().filter_map(|x| x.as_str()).collect();
+    if names.len() != mapping.len() {
+        report.fail(
+            &format!("{} has non-string values", file),
+            "Every N26 category must map to the name of a YNAB category.",
+        );
+        return;
+    }
+    report.ok(&format!("category mapping {} is valid", file));
+    check_categories_exist(file, names, categories, report);
+}
+
+fn check_category_rules(
+    file: &str,
+    categories: Option<&HashMap<String, Category>>,
+    category_groups: Option<&HashMap<String, CategoryGroup>>,
+    report: &mut Report,
+) {
+    let content = match read_to_string(file) {
+        Ok(x) => x,
+        Err(e) => {
+            return report.fail(
+                &format!("{} can not be read: {}", file, e),
+                "Check the path and the permissions of the file.",
+            )
+        }
+    };
+    let rules = match rules::parse(&content, RulesFormat::of(file)) {
+        Ok(x) => x,
+        Err(e) => return report.fail(&format!("{} is invalid: {}", file, e), RULE_HELP),
+    };
+    let mut names = vec![];
+    let mut groups = vec![];
+    for rule in &rules {
+        match rule.target() {
+            Some(RuleTarget::Category { category }) => names.push(category.as_str()),
+            Some(RuleTarget::CategoryGroup {
+                category_group,
+                default,
+            }) => groups.push((category_group.as_str(), default.as_deref())),
+            None => (),
+        }
+    }
+    report.ok(&format!("category rules {} are valid", file));
+    check_categories_exist(file, names, categories, report);
+    check_category_groups_exist(file, groups, category_groups, report);
+}
+
+fn check_category_groups_exist(
+    file: &str,
+    groups: Vec<(&str, Option<&str>)>,
+    category_groups: Option<&HashMap<String, CategoryGroup>>,
+    report: &mut Report,
+) {
+    let category_groups = match category_groups {
+        Some(x) => x,
+        None => return,
+    };
+    if groups.is_empty() {
+        return;
+    }
+    let mut missing: Vec<String> = groups
+        .into_iter()
+        .filter_map(
+            |(group, default)| match (category_groups.get(group), default) {
+                (None, _) => Some(group.to_string()),
+                (Some(x), Some(default)) if x.category(default).is_none() => {
+                    Some(format!("{}:{}", group, default))
+                }
+                _ => None,
+            },
+        )
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if missing.is_empty() {
+        report.ok(&format!(
+            "category groups used in {} exist in the budget",
+            file
+        ));
+    } else {
+        report.fail(
+            &format!(
+                "{} uses category groups (or defaults) which don't exist in the budget: {}",
+                file,
+                missing.join(", ")
+            ),
+            "Use the group name or id, \"default\" must be a category of the group.",
+        );
+    }
+}
+
+fn check_writable(name: &str, dir: &Path, report: &mut Report) {
+    let probe = dir.join(".ynab-sync-doctor");
+    match write(&probe, "").and_then(|_| remove_file(&probe)) {
+        Ok(_) => report.ok(&format!("{} {} is writable", name, dir.to_string_lossy())),
+        Err(e) => report.fail(
+            &format!("{} {} is not writable: {}", name, dir.to_string_lossy(), e),
+            &format!("Fix the permissions of {}.", dir.to_string_lossy()),
+        ),
+    }
+}
@@ -0,0 +1,119 @@
+use failure::ResultExt;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::write;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::fuzzy::best_match;
+use ynab_sync::n26::{self, Cli as N26Cli, N26};
+use ynab_sync::ynab::YNAB;
+
+// Guesses scoring at least this much are written as is, weaker guesses are
+// marked with a TODO so they don't silently end up in the mapping.
+const CONFIDENT_MATCH: f64 = 0.9;
+const POSSIBLE_MATCH: f64 = 0.7;
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        alias = "ynab-budget-id",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id) whose categories should be used."
+    )]
+    budget_id: String,
+    #[structopt(flatten)]
+    n26: N26Cli,
+    #[structopt(
+        long = "out",
+        value_name = "FILE",
+        parse(from_os_str),
+        help = "Where to write the mapping file (default: stdout)."
+    )]
+    out: Option<PathBuf>,
+    #[structopt(long = "force", help = "Overwrite --out file if it already exists.")]
+    force: bool,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    if let Some(out) = &cli.out {
+        if out.exists() && !cli.force {
+            Err(ErrorKind::OutputFileExists(out.to_string_lossy().into()))?
+        }
+    }
+
+    eprintln!("[1/3] Fetching YNAB categories");
+    let ynab = YNAB::new(cli.token.clone());
+    let budget_id = ynab.resolve_budget_id(&cli.budget_id)?;
+    let category_groups = ynab.get_category_groups(budget_id)?;
+
+    // refer to categories by bare name, unless the name is used in more
+    // than one group
+    let mut name_count: HashMap<String, usize> = HashMap::new();
+    for category in category_groups.iter().flat_map(|x| &x.categories) {
+        *name_count.entry(category.name.clone()).or_insert(0) += 1;
+    }
+    let ynab_categories: Vec<String> = category_groups
+        .iter()
+        .filter(|x| !x.hidden && !x.deleted)
+        .flat_map(|group| {
+            group
+                .categories
+                .iter()
+                .filter(|x| !x.hidden && !x.deleted)
+                .map(|x| {
+                    if name_count.get(&x.name).cloned().unwrap_or(0) > 1 {
+                        format!("{}:{}", group.name, x.name)
+                    } else {
+                        x.name.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    eprintln!("[2/3] Fetching N26 categories");
+    n26::setup(&cli.n26);
+    let n26 = N26::new(cli.n26.username.clone(), cli.n26.password.clone())?;
+    let mut n26_categories: Vec<String> = n26.get_categories()?.into_iter().map(|x| x.1).collect();
+    n26_categories.sort();
+
+    eprintln!("[3/3] Guessing category mapping");
+    let mut mapping = Map::new();
+    for n26_category in n26_categories {
+        let guess = best_match(&n26_category, ynab_categories.iter().map(|x| x.as_str()));
+        let value = match guess {
+            Some((name, score)) if score >= CONFIDENT_MATCH => name.to_string(),
+            Some((name, score)) if score >= POSSIBLE_MATCH => format!("TODO: {}?", name),
+            _ => "TODO".to_string(),
+        };
+        mapping.insert(n26_category, Value::String(value));
+    }
+
+    let content = serde_json::to_string_pretty(&Value::Object(mapping))
+        .context(ErrorKind::GenerateMapping)?;
+    match &cli.out {
+        Some(out) => {
+            write(out, content + "\n")
+                .with_context(|_| ErrorKind::OutputFileCanNotWrite(out.to_string_lossy().into()))?;
+            eprintln!(
+                "Mapping written to {}, replace the TODO entries before using it.",
+                out.to_string_lossy()
+            );
+        }
+        None => println!("{}", content),
+    }
+
+    Ok(())
+}
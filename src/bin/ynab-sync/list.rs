@@ -0,0 +1,136 @@
+use structopt::StructOpt;
+use ynab_sync::error::Result;
+use ynab_sync::ynab::YNAB;
+
+#[derive(Debug, StructOpt)]
+pub enum Cli {
+    #[structopt(name = "budgets", about = "List budgets with their ids.")]
+    Budgets(TokenCli),
+    #[structopt(
+        name = "accounts",
+        about = "List accounts of a budget with their ids and balances."
+    )]
+    Accounts(BudgetCli),
+    #[structopt(
+        name = "categories",
+        about = "List category groups and categories of a budget with their ids."
+    )]
+    Categories(BudgetCli),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TokenCli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct BudgetCli {
+    #[structopt(flatten)]
+    token: TokenCli,
+    #[structopt(
+        long = "ynab-budget",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id)."
+    )]
+    budget_id: String,
+    #[structopt(long = "all", help = "Include closed accounts and hidden categories.")]
+    all: bool,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    match cli {
+        Cli::Budgets(cli) => budgets(&YNAB::new(cli.token)),
+        Cli::Accounts(cli) => accounts(&YNAB::new(cli.token.token), &cli.budget_id, cli.all),
+        Cli::Categories(cli) => categories(&YNAB::new(cli.token.token), &cli.budget_id, cli.all),
+    }
+}
+
+fn budgets(ynab: &YNAB) -> Result<()> {
+    let budgets = ynab.get_budgets()?;
+    let width = budgets
+        .iter()
+        .map(|x| x.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for budget in &budgets {
+        println!(
+            "{:<width$} | {} | last modified {}",
+            budget.name,
+            budget.id,
+            budget.last_modified_on,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+fn accounts(ynab: &YNAB, budget: &str, all: bool) -> Result<()> {
+    let budget = ynab.get_budget(budget)?;
+    let currency = budget.currency_format.iso_code;
+    let accounts: Vec<_> = ynab
+        .get_accounts(budget.id)?
+        .into_iter()
+        .filter(|x| !x.deleted && (all || !x.closed))
+        .collect();
+    let width = accounts
+        .iter()
+        .map(|x| x.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for account in &accounts {
+        println!(
+            "{:<width$} | {} | {:>+12.2} {} | {:?}{}{}",
+            account.name,
+            account.id,
+            account.balance as f64 / 1000.0,
+            currency,
+            account.type_,
+            if account.on_budget { "" } else { ", tracking" },
+            if account.closed { ", closed" } else { "" },
+            width = width
+        );
+    }
+    Ok(())
+}
+
+fn categories(ynab: &YNAB, budget: &str, all: bool) -> Result<()> {
+    let budget_id = ynab.resolve_budget_id(budget)?;
+    let groups: Vec<_> = ynab
+        .get_category_groups(budget_id)?
+        .into_iter()
+        .filter(|x| !x.deleted && (all || !x.hidden))
+        .collect();
+    let width = groups
+        .iter()
+        .flat_map(|x| {
+            let categories = x.categories.iter().map(|x| x.name.chars().count() + 2);
+            categories.chain(Some(x.name.chars().count()))
+        })
+        .max()
+        .unwrap_or(2);
+    for group in &groups {
+        println!("{:<width$} | {}", group.name, group.id, width = width);
+        for category in group
+            .categories
+            .iter()
+            .filter(|x| !x.deleted && (all || !x.hidden))
+        {
+            println!(
+                "  {:<width$} | {}",
+                category.name,
+                category.id,
+                width = width - 2
+            );
+        }
+    }
+    Ok(())
+}
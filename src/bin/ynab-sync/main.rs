@@ -0,0 +1,153 @@
+use exitfailure::ExitFailure;
+use std::result;
+use structopt::StructOpt;
+use ynab_sync::audit::{self, Cli as AuditCli};
+use ynab_sync::encryption::{self, Cli as EncryptionCli};
+use ynab_sync::error::Result;
+use ynab_sync::http::{self, Cli as HttpCli};
+use ynab_sync::logging::{setup_logging, Cli as LoggingCli};
+use ynab_sync::output::{report_error, Cli as OutputCli};
+
+mod apply;
+mod balance;
+mod cache;
+mod daemon;
+mod doctor;
+mod generate_mapping;
+mod list;
+mod payees;
+mod rules;
+mod selftest;
+mod setup;
+mod sync;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "ynab-sync")]
+struct Cli {
+    #[structopt(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    #[structopt(flatten)]
+    logging: LoggingCli,
+    #[structopt(flatten)]
+    output: OutputCli,
+    #[structopt(flatten)]
+    http: HttpCli,
+    #[structopt(flatten)]
+    audit: AuditCli,
+    #[structopt(flatten)]
+    encryption: EncryptionCli,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(
+        name = "generate-mapping",
+        about = "Generate a skeleton N26 to YNAB category mapping file."
+    )]
+    GenerateMapping(generate_mapping::Cli),
+    #[structopt(
+        name = "selftest",
+        about = "Check syncing against the live YNAB API using a scratch budget."
+    )]
+    Selftest(selftest::Cli),
+    #[structopt(
+        name = "doctor",
+        about = "Check token, budget, files and directories and suggest fixes."
+    )]
+    Doctor(doctor::Cli),
+    #[structopt(
+        name = "cache",
+        about = "Inspect and clear the YNAB cache, the N26 token and the sync state."
+    )]
+    Cache(cache::Cli),
+    #[structopt(
+        name = "balance",
+        about = "Post a balance adjustment so a tracking account follows the value of a portfolio."
+    )]
+    Balance(balance::Cli),
+    #[structopt(name = "payees", about = "Clean up YNAB payees.")]
+    Payees(payees::Cli),
+    #[structopt(
+        name = "apply",
+        about = "Sync the transactions of a plan written with --plan FILE."
+    )]
+    Apply(apply::Cli),
+    #[structopt(
+        name = "sync",
+        about = "Sync the profiles of the config file, one after another or --jobs at a time."
+    )]
+    Sync(sync::Cli),
+    #[structopt(
+        name = "setup",
+        about = "Pick a budget, an account and a bank and add a profile for them to the config file."
+    )]
+    Setup(setup::Cli),
+    #[structopt(
+        name = "list",
+        about = "List YNAB budgets, accounts or categories with their ids."
+    )]
+    List(list::Cli),
+    #[structopt(
+        name = "daemon",
+        about = "Sync the profiles of the config file on a schedule, keeping the N26 token refreshed."
+    )]
+    Daemon(daemon::Cli),
+    #[structopt(
+        name = "rules",
+        about = "Check category rules and mapping files against the YNAB budget."
+    )]
+    Rules(rules::Cli),
+}
+
+fn main() -> result::Result<(), ExitFailure> {
+    let cli = Cli::from_args();
+    let output = cli.output.output.clone();
+    run(cli).or_else(|e| report_error(&output, e))
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let app = Cli::clap();
+
+    setup_logging(
+        app.get_name().to_string(),
+        cli.verbose.log_level(),
+        &cli.logging,
+    )?;
+    http::setup(&cli.http)?;
+    audit::setup(&cli.audit);
+    encryption::setup(&cli.encryption);
+
+    match cli.command {
+        Command::GenerateMapping(cli) => generate_mapping::run(cli),
+        Command::Selftest(cli) => selftest::run(cli),
+        Command::Doctor(cli) => doctor::run(cli),
+        Command::Cache(cli) => cache::run(cli),
+        Command::Balance(cli) => balance::run(cli),
+        Command::Payees(cli) => payees::run(cli),
+        Command::Apply(cli) => apply::run(cli),
+        Command::Sync(sync_cli) => {
+            sync::run(sync_cli, &inherited(&cli.output, &cli.http, &cli.audit)?)
+        }
+        Command::Setup(cli) => setup::run(cli),
+        Command::List(cli) => list::run(cli),
+        Command::Daemon(daemon_cli) => {
+            daemon::run(daemon_cli, &inherited(&cli.output, &cli.http, &cli.audit)?)
+        }
+        Command::Rules(cli) => rules::run(cli),
+    }
+}
+
+/// Global options passed on to the profiles synced by `sync` and `daemon`.
+fn inherited(output: &OutputCli, http: &HttpCli, audit: &AuditCli) -> Result<sync::Inherited> {
+    let mut env = output.env();
+    env.extend(http.env());
+    env.extend(audit.env());
+    // prompted for once here, profiles synced with --jobs have no stdin
+    env.extend(encryption::env()?);
+    Ok(sync::Inherited {
+        env,
+        http_headers: http.headers.clone(),
+    })
+}
@@ -0,0 +1,203 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use reqwest::Method;
+use std::collections::HashMap;
+use structopt::StructOpt;
+use ynab_sync::error::Result;
+use ynab_sync::payees::payee_key;
+use ynab_sync::ynab::{Payee, Transaction, YNAB};
+
+#[derive(Debug, StructOpt)]
+pub enum Cli {
+    #[structopt(
+        name = "cleanup",
+        about = "Find near-duplicate payees of an account and merge them after confirmation."
+    )]
+    Cleanup(CleanupCli),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CleanupCli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id)."
+    )]
+    budget_id: String,
+    #[structopt(
+        long = "ynab-account",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_ACCOUNT_ID",
+        help = "YNAB account (name or id) whose payees are cleaned up."
+    )]
+    account_id: String,
+    #[structopt(
+        long = "days",
+        value_name = "DAYS",
+        default_value = "365",
+        help = "Look at payees of the transactions of this many days."
+    )]
+    days: i64,
+}
+
+/// Near-duplicate payees, the one most transactions use first.
+struct Group<'a> {
+    payees: Vec<(&'a Payee, Vec<&'a Transaction>)>,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    match cli {
+        Cli::Cleanup(cli) => cleanup(cli),
+    }
+}
+
+fn cleanup(cli: CleanupCli) -> Result<()> {
+    println!("[1/3] Resolving budget and account");
+    let ynab = YNAB::new(cli.token.clone());
+    let budget_id = ynab.resolve_budget_id(&cli.budget_id)?;
+    let account_id = ynab.resolve_account_id(&budget_id, &cli.account_id)?;
+
+    println!("[2/3] Finding near-duplicate payees");
+    let payees = ynab.get_payees(&budget_id)?;
+    let transactions =
+        ynab.get_account_transactions(budget_id.clone(), account_id.clone(), cli.days)?;
+    let groups = groups(&payees, &transactions);
+    if groups.is_empty() {
+        println!("[3/3] No near-duplicate payees found.");
+        return Ok(());
+    }
+    println!(" => Found {} groups of near-duplicate payees", groups.len());
+
+    println!("[3/3] Merging payees");
+    for group in groups {
+        merge(&ynab, &budget_id, &group)?;
+    }
+
+    Ok(())
+}
+
+/// Payees used by `transactions`, grouped by `payee_key`. Transfer payees
+/// are left alone.
+fn groups<'a>(payees: &'a [Payee], transactions: &'a [Transaction]) -> Vec<Group<'a>> {
+    let mut used: HashMap<&str, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions {
+        if let Some(payee_id) = &transaction.payee_id {
+            used.entry(payee_id).or_default().push(transaction);
+        }
+    }
+
+    let mut by_key: HashMap<String, Vec<(&Payee, Vec<&Transaction>)>> = HashMap::new();
+    for payee in payees {
+        if payee.deleted || payee.transfer_account_id.is_some() {
+            continue;
+        }
+        let key = payee_key(&payee.name);
+        if key.is_empty() {
+            continue;
+        }
+        if let Some(transactions) = used.remove(payee.id.as_str()) {
+            by_key.entry(key).or_default().push((payee, transactions));
+        }
+    }
+
+    let mut groups: Vec<Group> = by_key
+        .into_values()
+        .filter(|x| x.len() > 1)
+        .map(|mut payees| {
+            payees.sort_by(|a, b| {
+                b.1.len()
+                    .cmp(&a.1.len())
+                    .then(a.0.name.len().cmp(&b.0.name.len()))
+                    .then(a.0.name.cmp(&b.0.name))
+            });
+            Group { payees }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.payees[0].0.name.cmp(&b.payees[0].0.name));
+    groups
+}
+
+/// Ask how to merge `group` and move the transactions of the other payees to
+/// the chosen one, which is renamed first when a new name is entered.
+fn merge(ynab: &YNAB, budget_id: &str, group: &Group) -> Result<()> {
+    println!("Near-duplicate payees:");
+    for (payee, transactions) in &group.payees {
+        println!(" - | {} | {} transactions", payee.name, transactions.len());
+    }
+
+    let mut items: Vec<String> = group
+        .payees
+        .iter()
+        .map(|(payee, _)| format!("Merge into \"{}\"", payee.name))
+        .collect();
+    items.push("Merge and rename to ...".to_string());
+    items.push("Skip".to_string());
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Merge these payees?")
+        .default(0)
+        .items(&items)
+        .interact()
+        .unwrap();
+
+    let (target, _) = match group.payees.get(selection) {
+        Some(x) => x,
+        None if selection == group.payees.len() => {
+            let name: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("New payee name")
+                .default(group.payees[0].0.name.clone())
+                .interact()
+                .unwrap();
+            let target = &group.payees[0];
+            if name != target.0.name {
+                ynab.rename_payee(budget_id, &target.0.id, &name)?;
+                println!(" => Renamed \"{}\" to \"{}\"", target.0.name, name);
+            }
+            target
+        }
+        None => return Ok(()),
+    };
+
+    let moved: Vec<Transaction> = group
+        .payees
+        .iter()
+        .filter(|(payee, _)| payee.id != target.id)
+        .flat_map(|(_, transactions)| transactions.iter())
+        .map(|transaction| Transaction {
+            payee_id: Some(target.id.clone()),
+            payee_name: None,
+            // subtransactions of existing splits can not be updated
+            subtransactions: vec![],
+            ..(*transaction).clone()
+        })
+        .collect();
+    if moved.is_empty() {
+        return Ok(());
+    }
+    let existing_transactions: HashMap<String, Transaction> = group
+        .payees
+        .iter()
+        .flat_map(|(_, transactions)| transactions.iter())
+        .filter_map(|x| x.import_id.clone().map(|id| (id, (*x).clone())))
+        .collect();
+    let count = moved.len();
+    ynab.save_transactions(
+        moved,
+        &existing_transactions,
+        budget_id.to_string(),
+        Method::PATCH,
+    )?;
+    println!(" => Moved {} transactions to \"{}\"", count, target.name);
+
+    Ok(())
+}
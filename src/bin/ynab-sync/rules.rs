@@ -0,0 +1,624 @@
+use crate::doctor::{self, Report};
+use failure::ResultExt;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::{read_to_string, write};
+use std::mem::discriminant;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::ingdiba::IngDiBa;
+use ynab_sync::n26::{self, Cli as N26Cli, N26};
+use ynab_sync::rules::{
+    self, evaluation_order, rule_lines, shadows, Cli as RulesCli, MatchMode, RuleActions,
+    RuleTarget, Rules, RulesFormat, TransactionField, RULE_HELP,
+};
+use ynab_sync::ynab::{index_categories, index_category_groups, Category, CategoryGroup, YNAB};
+
+#[derive(Debug, StructOpt)]
+pub enum Cli {
+    #[structopt(
+        name = "lint",
+        about = "Check category rules and mapping files for syntax errors, unknown categories and rules which never match."
+    )]
+    Lint(LintCli),
+    #[structopt(
+        name = "test",
+        about = "Show which rule matches each transaction of an export and the category it would get, without touching YNAB."
+    )]
+    Test(TestCli),
+    #[structopt(
+        name = "suggest",
+        about = "Generate draft category rules from the categorized transactions of a YNAB account."
+    )]
+    Suggest(SuggestCli),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LintCli {
+    #[structopt(
+        long = "ynab-token",
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        hide_env_values = true,
+        help = "YNAB token."
+    )]
+    token: Option<String>,
+    #[structopt(
+        long = "ynab-budget",
+        alias = "ynab-budget-id",
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        requires = "token",
+        help = "YNAB budget (name or id) whose categories the files must use. Without it the categories are not checked."
+    )]
+    budget_id: Option<String>,
+    #[structopt(
+        long = "category-rules",
+        value_name = "FILE",
+        required_unless = "category_mapping_file",
+        help = "IngDiba category rules file to check."
+    )]
+    category_rules_file: Option<String>,
+    #[structopt(
+        long = "n26-category-mapping",
+        value_name = "FILE",
+        help = "N26 category mapping file to check."
+    )]
+    category_mapping_file: Option<String>,
+    #[structopt(flatten)]
+    rules: RulesCli,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TestCli {
+    #[structopt(
+        name = "ingdiba",
+        about = "Test IngDiba category rules on CSV exports."
+    )]
+    IngDiBa(IngDiBaTestCli),
+    #[structopt(
+        name = "n26",
+        about = "Test an N26 category mapping on the latest N26 transactions."
+    )]
+    N26(N26TestCli),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct IngDiBaTestCli {
+    #[structopt(
+        long = "category-rules",
+        required = true,
+        value_name = "FILE",
+        help = "IngDiba category rules file to test."
+    )]
+    category_rules_file: String,
+    #[structopt(
+        long = "csv",
+        required = true,
+        value_name = "FILE",
+        number_of_values = 1,
+        help = "IngDiba CSV export to test the rules on. Can be repeated."
+    )]
+    csv_files: Vec<String>,
+    #[structopt(flatten)]
+    rules: RulesCli,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct N26TestCli {
+    #[structopt(
+        long = "n26-category-mapping",
+        required = true,
+        value_name = "FILE",
+        help = "N26 category mapping file to test."
+    )]
+    category_mapping_file: String,
+    #[structopt(flatten)]
+    n26: N26Cli,
+    #[structopt(
+        long = "days",
+        value_name = "NUMBER",
+        default_value = "30",
+        help = "Test the mapping on the N26 transactions of this many days."
+    )]
+    days: i64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SuggestCli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        hide_env_values = true,
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        alias = "ynab-budget-id",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_BUDGET_ID",
+        help = "YNAB budget (name or id)."
+    )]
+    budget_id: String,
+    #[structopt(
+        long = "ynab-account",
+        alias = "ynab-account-id",
+        required = true,
+        value_name = "NAME|ID",
+        env = "YNAB_ACCOUNT_ID",
+        help = "YNAB account (name or id) whose transactions the rules are learned from."
+    )]
+    account_id: String,
+    #[structopt(
+        long = "days",
+        value_name = "NUMBER",
+        default_value = "365",
+        help = "Learn from the transactions of this many days."
+    )]
+    days: i64,
+    #[structopt(
+        long = "min-count",
+        value_name = "NUMBER",
+        default_value = "3",
+        help = "Only suggest rules for payees with at least this many transactions in the same category."
+    )]
+    min_count: usize,
+    #[structopt(
+        long = "max-rules",
+        value_name = "NUMBER",
+        default_value = "50",
+        help = "Suggest rules for at most this many payees, the most frequent ones."
+    )]
+    max_rules: usize,
+    #[structopt(
+        long = "out",
+        value_name = "FILE",
+        parse(from_os_str),
+        help = "Where to write the rules file (default: stdout), as TOML when it ends with .toml."
+    )]
+    out: Option<PathBuf>,
+    #[structopt(long = "force", help = "Overwrite --out file if it already exists.")]
+    force: bool,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    match cli {
+        Cli::Lint(cli) => lint(&cli),
+        Cli::Test(TestCli::IngDiBa(cli)) => test_ingdiba(&cli),
+        Cli::Test(TestCli::N26(cli)) => test_n26(&cli),
+        Cli::Suggest(cli) => suggest(&cli),
+    }
+}
+
+/// Check the files like `ynab-sync doctor` does and additionally every rule
+/// on its own: whether its category exists and whether an earlier rule
+/// always matches first.
+fn lint(cli: &LintCli) -> Result<()> {
+    let mut report = Report::default();
+
+    println!("[1/2] Fetching YNAB categories");
+    let category_groups = match (&cli.token, &cli.budget_id) {
+        (Some(token), Some(budget)) => {
+            let ynab = YNAB::new(token.clone());
+            let budget_id = ynab.resolve_budget_id(budget)?;
+            Some(ynab.get_category_groups(budget_id)?)
+        }
+        _ => {
+            println!(" => No --ynab-budget given, categories are not checked");
+            None
+        }
+    };
+    let categories = category_groups.clone().map(index_categories);
+    let category_groups = category_groups.map(index_category_groups);
+
+    println!("[2/2] Checking files");
+    if let Some(file) = &cli.category_mapping_file {
+        doctor::check_category_mapping(file, categories.as_ref(), &mut report);
+    }
+    if let Some(file) = &cli.category_rules_file {
+        lint_category_rules(
+            file,
+            cli.rules.match_mode,
+            categories.as_ref(),
+            category_groups.as_ref(),
+            &mut report,
+        );
+    }
+
+    if report.failures > 0 {
+        Err(ErrorKind::RulesLintFailed(report.failures))?
+    }
+    println!("No problems found.");
+    Ok(())
+}
+
+fn lint_category_rules(
+    file: &str,
+    match_mode: MatchMode,
+    categories: Option<&HashMap<String, Category>>,
+    category_groups: Option<&HashMap<String, CategoryGroup>>,
+    report: &mut Report,
+) {
+    let content = match read_to_string(file) {
+        Ok(x) => x,
+        Err(e) => {
+            return report.fail(
+                &format!("{} can not be read: {}", file, e),
+                "Check the path and the permissions of the file.",
+            )
+        }
+    };
+    let format = RulesFormat::of(file);
+    let rules = match rules::parse(&content, format) {
+        Ok(x) => x,
+        Err(e) => return report.fail(&format!("{} is invalid: {}", file, e), RULE_HELP),
+    };
+    report.ok(&format!("category rules {} are valid", file));
+
+    let lines = rule_lines(&content, format);
+    let name = |i: usize| {
+        format!(
+            "rule #{} (line {}) {}",
+            i + 1,
+            lines.get(i).copied().unwrap_or_default(),
+            rules[i]
+        )
+    };
+    let evaluation_order = evaluation_order(&rules, match_mode);
+    let failures = report.failures;
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.target().is_none() && rule.actions().is_empty() {
+            report.fail(
+                &format!("{} does nothing", name(i)),
+                "Add a \"category\", a \"category_group\" or an action to it.",
+            );
+            continue;
+        }
+        let earlier = match evaluation_order
+            .iter()
+            .copied()
+            .take_while(|x| *x != i)
+            .find(|x| shadows(&rules, *x, i, match_mode))
+        {
+            Some(x) => x,
+            None => continue,
+        };
+        let duplicate = discriminant(&rules[earlier]) == discriminant(rule)
+            && rules[earlier].value().to_lowercase() == rule.value().to_lowercase();
+        if duplicate {
+            report.fail(
+                &format!("{} repeats {}", name(i), name(earlier)),
+                "Remove one of them, only the first one is ever used.",
+            );
+        } else {
+            report.fail(
+                &format!(
+                    "{} is never reached, {} wins over it",
+                    name(i),
+                    name(earlier)
+                ),
+                &format!(
+                    "Move it above rule #{}, give it a higher \"priority\" or remove it.",
+                    earlier + 1
+                ),
+            );
+        }
+    }
+    if report.failures == failures {
+        report.ok(&format!("every rule in {} can match", file));
+    }
+
+    let (categories, category_groups) = match (categories, category_groups) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return,
+    };
+    let failures = report.failures;
+    for unknown in rules::unknown_categories(&rules, categories, category_groups) {
+        let fix = match &unknown.suggestion {
+            Some(x) => format!("Did you mean \"{}\"?", x),
+            None => "Use the category name, \"<Group>:<Category>\" or the category id, \"default\" must be a category of the group.".to_string(),
+        };
+        let target = rules[unknown.rule].target().map(|x| x.to_string());
+        report.fail(
+            &format!(
+                "{}: {} doesn't exist in the budget",
+                name(unknown.rule),
+                target.unwrap_or_default()
+            ),
+            &fix,
+        );
+    }
+    if report.failures == failures {
+        report.ok(&format!("categories used in {} exist in the budget", file));
+    }
+}
+
+fn test_ingdiba(cli: &IngDiBaTestCli) -> Result<()> {
+    println!("[1/3] Parsing --category-rules");
+    let rules = Rules::from_file(&cli.category_rules_file)?;
+
+    println!("[2/3] Parsing --csv files");
+    let mut transactions = vec![];
+    for csv_file in &cli.csv_files {
+        transactions.extend(IngDiBa::new(csv_file.clone())?.transactions);
+    }
+    transactions.sort_by_key(|x| x.ts);
+
+    println!("[3/3] Matching rules");
+    let mut used = vec![false; rules.len()];
+    let mut unmatched = 0;
+    for transaction in &transactions {
+        let matches = rules::apply(
+            &rules,
+            cli.rules.match_mode,
+            &transaction.memo,
+            &transaction.payee(),
+        );
+        if matches.rule.is_none() {
+            unmatched += 1;
+        }
+        let result = if matches.applied.is_empty() {
+            "no rule matched".to_string()
+        } else {
+            let applied = matches.applied.iter().map(|(i, rule)| {
+                used[*i] = true;
+                format!("rule #{} {} => {}", i + 1, rule, rule.outcome())
+            });
+            let conflicts = matches.conflicts.iter().map(|(i, rule)| {
+                format!(
+                    "rule #{} {} => {} matches too, but loses",
+                    i + 1,
+                    rule,
+                    rule.outcome()
+                )
+            });
+            applied
+                .chain(conflicts)
+                .collect::<Vec<_>>()
+                .join("\n    => ")
+        };
+        print_match(
+            &transaction.ts.to_string(),
+            transaction.amount,
+            &transaction.payee(),
+            &transaction.memo,
+            &result,
+        );
+    }
+
+    print_summary(transactions.len(), unmatched);
+    if used.contains(&false) {
+        println!("Rules which applied to nothing:");
+        for (i, rule) in rules.iter().enumerate().filter(|(i, _)| !used[*i]) {
+            println!(" - rule #{} {} => {}", i + 1, rule, rule.outcome());
+        }
+    }
+    Ok(())
+}
+
+fn test_n26(cli: &N26TestCli) -> Result<()> {
+    println!("[1/4] Parsing --n26-category-mapping");
+    let file = &cli.category_mapping_file;
+    let content = read_to_string(file)
+        .with_context(|_| ErrorKind::ArgParseCategoryMappingCanNotRead(file.clone()))?;
+    let mapping: Map<String, Value> = serde_json::from_str(&content)
+        .with_context(|_| ErrorKind::ArgParseCategoryMappingCanNotParse(file.clone()))?;
+
+    println!("[2/4] Fetching N26 token");
+    n26::setup(&cli.n26);
+    let n26 = N26::new(cli.n26.username.clone(), cli.n26.password.clone())?;
+
+    println!("[3/4] Fetching N26 categories");
+    let n26_categories = n26.get_categories()?;
+
+    println!(
+        "[4/4] Fetching N26 transactions for the last {} days",
+        cli.days
+    );
+    let mut transactions = n26.get_transactions(cli.days)?;
+    transactions.sort_by_key(|x| x.visible_ts);
+
+    let mut unmatched = 0;
+    for transaction in &transactions {
+        let n26_category = n26_categories.get(&transaction.category);
+        let entry = n26_category
+            .and_then(|x| mapping.get(x))
+            .and_then(|x| x.as_str());
+        let result = match (n26_category, entry) {
+            (Some(n26_category), Some(entry)) => {
+                format!(
+                    "category mapping: {:?} => category {:?}",
+                    n26_category, entry
+                )
+            }
+            (Some(n26_category), None) => {
+                unmatched += 1;
+                format!("no entry for N26 category {:?}", n26_category)
+            }
+            (None, _) => {
+                unmatched += 1;
+                format!("N26 category {:?} is unknown", transaction.category)
+            }
+        };
+        print_match(
+            &transaction.date().format("%Y-%m-%d").to_string(),
+            transaction.amount,
+            transaction.payee().map(|x| x.as_str()).unwrap_or_default(),
+            transaction.reference_text.as_deref().unwrap_or_default(),
+            &result,
+        );
+    }
+
+    print_summary(transactions.len(), unmatched);
+    Ok(())
+}
+
+fn print_match(date: &str, amount: i32, payee: &str, memo: &str, result: &str) {
+    println!(
+        " - {} | {:>+10.2} | {} | {}\n    => {}",
+        date,
+        amount as f64 / 1000.0,
+        payee,
+        memo,
+        result
+    );
+}
+
+fn print_summary(transactions: usize, unmatched: usize) {
+    println!("Summary:");
+    println!(
+        " - | {:<14} | {:>10} |",
+        "matched",
+        transactions - unmatched
+    );
+    println!(" - | {:<14} | {:>10} |", "unmatched", unmatched);
+}
+
+/// Draft rules from YNAB's history: payees (without the parts which vary,
+/// like numbers) are matched by a `Contains` rule on the entity assigning the
+/// category they were categorized with most often.
+fn suggest(cli: &SuggestCli) -> Result<()> {
+    if let Some(out) = &cli.out {
+        if out.exists() && !cli.force {
+            Err(ErrorKind::OutputFileExists(out.to_string_lossy().into()))?
+        }
+    }
+
+    eprintln!("[1/3] Fetching YNAB categories");
+    let ynab = YNAB::new(cli.token.clone());
+    let budget_id = ynab.resolve_budget_id(&cli.budget_id)?;
+    let account_id = ynab.resolve_account_id(&budget_id, &cli.account_id)?;
+    let category_names = category_names(&ynab.get_category_groups(budget_id.clone())?);
+
+    eprintln!(
+        "[2/3] Fetching YNAB transactions for the last {} days",
+        cli.days
+    );
+    let transactions = ynab.get_account_transactions(budget_id, account_id, cli.days)?;
+
+    eprintln!("[3/3] Grouping transactions by payee");
+    // pattern => (pattern as first seen, category id => count)
+    let mut payees: HashMap<String, (String, HashMap<String, usize>)> = HashMap::new();
+    for transaction in &transactions {
+        let (payee, category_id) = match (&transaction.payee_name, &transaction.category_id) {
+            (Some(payee), Some(category_id)) if transaction.subtransactions.is_empty() => {
+                (payee, category_id)
+            }
+            _ => continue,
+        };
+        let pattern = match payee_pattern(payee) {
+            Some(x) => x,
+            None => continue,
+        };
+        let entry = payees
+            .entry(pattern.to_lowercase())
+            .or_insert_with(|| (pattern, HashMap::new()));
+        *entry.1.entry(category_id.clone()).or_insert(0) += 1;
+    }
+
+    let mut suggestions: Vec<(String, String, usize, usize)> = payees
+        .into_iter()
+        .filter_map(|(_, (pattern, counts))| {
+            let total = counts.values().sum();
+            let (category_id, count) = counts.into_iter().max_by_key(|x| (x.1, x.0.clone()))?;
+            let category = category_names.get(&category_id)?.clone();
+            Some((pattern, category, count, total))
+        })
+        .filter(|x| x.2 >= cli.min_count)
+        .collect();
+    suggestions.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+    let mut rules: Vec<Rules> = vec![];
+    for (pattern, category, count, total) in suggestions {
+        let rule = Rules::Contains {
+            value: pattern,
+            field: TransactionField::Entity,
+            target: Some(RuleTarget::Category { category }),
+            actions: RuleActions::default(),
+            priority: 0,
+        };
+        // a more general payee which comes first matches it already
+        rules.push(rule);
+        let last = rules.len() - 1;
+        if last >= cli.max_rules || (0..last).any(|x| shadows(&rules, x, last, MatchMode::First)) {
+            rules.pop();
+            continue;
+        }
+        let rule = &rules[last];
+        eprintln!(
+            " => {} => {} ({} of {} transactions)",
+            rule,
+            rule.outcome(),
+            count,
+            total
+        );
+    }
+
+    let format = cli
+        .out
+        .as_ref()
+        .map(|x| RulesFormat::of(&x.to_string_lossy()))
+        .unwrap_or(RulesFormat::Json);
+    let content = rules::serialize(&rules, format).context(ErrorKind::SuggestRules)?;
+    match &cli.out {
+        Some(out) => {
+            write(out, content)
+                .with_context(|_| ErrorKind::OutputFileCanNotWrite(out.to_string_lossy().into()))?;
+            eprintln!(
+                "Rules written to {}, review them and check them with `ynab-sync rules test`.",
+                out.to_string_lossy()
+            );
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+/// Names of the categories by id, qualified with the group when the name is
+/// used in more than one group.
+fn category_names(category_groups: &[CategoryGroup]) -> HashMap<String, String> {
+    let mut name_count: HashMap<&str, usize> = HashMap::new();
+    for category in category_groups.iter().flat_map(|x| &x.categories) {
+        *name_count.entry(&category.name).or_insert(0) += 1;
+    }
+    category_groups
+        .iter()
+        .filter(|x| !x.deleted)
+        .flat_map(|group| {
+            group
+                .categories
+                .iter()
+                .filter(|x| !x.deleted)
+                .map(|x| {
+                    let name = if name_count.get(x.name.as_str()).cloned().unwrap_or(0) > 1 {
+                        format!("{}:{}", group.name, x.name)
+                    } else {
+                        x.name.clone()
+                    };
+                    (x.id.clone(), name)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Payee name without the words which differ between transactions of the
+/// same payee, like store numbers or references.
+fn payee_pattern(payee: &str) -> Option<String> {
+    let pattern = payee
+        .split_whitespace()
+        .filter(|x| !x.chars().any(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let pattern = pattern.trim_matches(|c: char| !c.is_alphanumeric());
+    if pattern.is_empty() {
+        None
+    } else {
+        Some(pattern.to_string())
+    }
+}
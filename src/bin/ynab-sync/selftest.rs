@@ -0,0 +1,218 @@
+use chrono::{Duration, Utc};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use failure::ResultExt;
+use reqwest::Method;
+use serde::Deserialize;
+use std::collections::HashMap;
+use structopt::StructOpt;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::ynab::{changed_transactions, Transaction, TransactionCleared, YNAB};
+
+// Transactions created by the self test, relative to today.
+const FIXTURES: &str = include_str!("selftest.json");
+
+// Import ids of test transactions start with this, followed by the id of the
+// run, so every run creates fresh transactions.
+const IMPORT_ID_PREFIX: &str = "ynab-sync-selftest";
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        required = true,
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        help = "YNAB token."
+    )]
+    token: String,
+    #[structopt(
+        long = "ynab-budget",
+        required = true,
+        value_name = "NAME|ID",
+        help = "Scratch YNAB budget (name or id) in which test transactions are created and deleted again."
+    )]
+    budget_id: String,
+    #[structopt(
+        long = "ynab-account",
+        required = true,
+        value_name = "NAME|ID",
+        help = "Account of the scratch budget to use."
+    )]
+    account_id: String,
+    #[structopt(long = "yes", help = "Don't ask for confirmation.")]
+    yes: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    days_ago: i64,
+    amount: i32,
+    payee: String,
+    memo: String,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    println!("[1/6] Resolving scratch budget and account");
+    let ynab = YNAB::new(cli.token.clone());
+    let budget_id = ynab.resolve_budget_id(&cli.budget_id)?;
+    let account_id = ynab.resolve_account_id(&budget_id, &cli.account_id)?;
+
+    if !cli.yes {
+        let prompt = format!(
+            "Test transactions will be created in and deleted from \"{}\" of budget \"{}\". Continue?",
+            cli.account_id, cli.budget_id
+        );
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(&prompt)
+            .default(1)
+            .items(&["Yes", "No"])
+            .interact()
+            .unwrap();
+        if selection != 0 {
+            return Ok(());
+        }
+    }
+
+    let fixtures: Vec<Fixture> = serde_json::from_str(FIXTURES).context(
+        ErrorKind::SelftestFailed("bundled fixtures are invalid".into()),
+    )?;
+    let run_id = Utc::now().timestamp();
+    let today = Utc::now().naive_utc().date();
+    let transactions: Vec<Transaction> = fixtures
+        .iter()
+        .enumerate()
+        .map(|(i, x)| Transaction {
+            id: None,
+            account_id: account_id.clone(),
+            date: (today - Duration::days(x.days_ago))
+                .format("%Y-%m-%d")
+                .to_string(),
+            amount: x.amount,
+            payee_id: None,
+            payee_name: Some(x.payee.clone()),
+            category_id: None,
+            memo: Some(x.memo.clone()),
+            cleared: TransactionCleared::Uncleared,
+            approved: false,
+            flag_color: None,
+            import_id: Some(format!("{}:{}:{}", IMPORT_ID_PREFIX, run_id, i)),
+            subtransactions: vec![],
+        })
+        .collect();
+    let days = fixtures.iter().map(|x| x.days_ago).max().unwrap_or(0) + 1;
+
+    // always clean up, also after a failed check
+    let result = check(&ynab, &budget_id, &account_id, &transactions, days);
+    println!("[6/6] Deleting test transactions");
+    cleanup(&ynab, &budget_id, &account_id, &transactions, days)?;
+    result?;
+
+    println!("Self test passed.");
+    Ok(())
+}
+
+fn check(
+    ynab: &YNAB,
+    budget_id: &str,
+    account_id: &str,
+    transactions: &[Transaction],
+    days: i64,
+) -> Result<()> {
+    let fetch = || ynab.get_transactions(budget_id.to_string(), account_id.to_string(), days);
+
+    println!("[2/6] Creating test transactions");
+    let existing = fetch()?;
+    let (new, update) = changed_transactions(transactions, &existing, false);
+    expect(
+        new.len() == transactions.len() && update.is_empty(),
+        "test transactions already exist",
+    )?;
+    ynab.save_transactions(new, &existing, budget_id.to_string(), Method::POST)?;
+
+    println!("[3/6] Checking that synced transactions are not created again");
+    let existing = fetch()?;
+    let (new, update) = changed_transactions(transactions, &existing, false);
+    expect(
+        new.is_empty() && update.is_empty(),
+        "created transactions are not recognized as synced",
+    )?;
+
+    println!("[4/6] Checking that changed transactions are updated");
+    let mut changed = transactions.to_vec();
+    changed[0].amount -= 1000;
+    let (new, update) = changed_transactions(&changed, &existing, false);
+    expect(
+        new.is_empty() && update.len() == 1,
+        "changed amount is not detected",
+    )?;
+    ynab.save_transactions(update, &existing, budget_id.to_string(), Method::PATCH)?;
+    let existing = fetch()?;
+    expect(
+        imported(&existing, &changed[0]).map(|x| x.amount) == Some(changed[0].amount),
+        "amount was not updated",
+    )?;
+
+    println!("[5/6] Checking that --force-update updates categories");
+    let category = ynab
+        .get_categories(budget_id.to_string())?
+        .values()
+        .find(|x| !x.hidden && !x.deleted)
+        .cloned();
+    let category = match category {
+        Some(x) => x,
+        None => Err(ErrorKind::SelftestFailed(
+            "scratch budget has no categories".into(),
+        ))?,
+    };
+    changed[1].category_id = Some(category.id.clone());
+    let (new, update) = changed_transactions(&changed, &existing, false);
+    expect(
+        new.is_empty() && update.is_empty(),
+        "changed category is updated without --force-update",
+    )?;
+    let (new, update) = changed_transactions(&changed, &existing, true);
+    expect(
+        new.is_empty() && update.len() == 1,
+        "changed category is not detected with --force-update",
+    )?;
+    ynab.save_transactions(update, &existing, budget_id.to_string(), Method::PATCH)?;
+    let existing = fetch()?;
+    expect(
+        imported(&existing, &changed[1]).and_then(|x| x.category_id.clone()) == Some(category.id),
+        "category was not updated",
+    )?;
+
+    Ok(())
+}
+
+fn cleanup(
+    ynab: &YNAB,
+    budget_id: &str,
+    account_id: &str,
+    transactions: &[Transaction],
+    days: i64,
+) -> Result<()> {
+    let existing = ynab.get_transactions(budget_id.to_string(), account_id.to_string(), days)?;
+    for transaction in transactions {
+        if let Some(transaction) = imported(&existing, transaction) {
+            ynab.delete_transaction(budget_id, transaction)?;
+        }
+    }
+    Ok(())
+}
+
+// YNAB's copy of `transaction`, found by import id.
+fn imported<'a>(
+    existing: &'a HashMap<String, Transaction>,
+    transaction: &Transaction,
+) -> Option<&'a Transaction> {
+    transaction.import_id.as_ref().and_then(|x| existing.get(x))
+}
+
+fn expect(ok: bool, message: &str) -> Result<()> {
+    if !ok {
+        Err(ErrorKind::SelftestFailed(message.to_string()))?
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, PasswordInput, Select};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use toml::Value;
+use ynab_sync::config;
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::ynab::YNAB;
+use ynab_sync::N26;
+
+// Binaries a profile can run, with the sources they sync.
+const COMMANDS: &[(&str, &str)] = &[
+    ("sync-with-n26", "N26 (API)"),
+    ("sync-with-ingdiba", "ING-DiBa (CSV export)"),
+    ("sync-with-csv", "Any bank (CSV export)"),
+    ("sync-with-camt", "Any bank (CAMT.053 statement)"),
+    ("sync-with-revolut", "Revolut (CSV export)"),
+    ("sync-with-amex", "American Express (CSV/QFX export)"),
+    ("sync-with-nordigen", "Any bank (Nordigen/GoCardless)"),
+    ("sync-with-plaid", "Any bank (Plaid)"),
+];
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "ynab-token",
+        value_name = "TEXT",
+        env = "YNAB_TOKEN",
+        hide_env_values = true,
+        help = "YNAB token, prompted for when not given."
+    )]
+    token: Option<String>,
+    #[structopt(
+        long = "config",
+        value_name = "FILE",
+        env = "YNAB_SYNC_CONFIG",
+        help = "TOML file the profile is added to. Defaults to ~/.config/ynab-sync/config.toml."
+    )]
+    config_file: Option<String>,
+}
+
+/// Pick a budget, an account and a bank, test the bank login (for N26) and
+/// add a profile for them to the config file.
+pub fn run(cli: Cli) -> Result<()> {
+    let config_file = match cli.config_file.clone().map(PathBuf::from) {
+        Some(x) => x,
+        None => config::default_path()
+            .ok_or_else(|| ErrorKind::ConfigCanNotWrite("~/.config/ynab-sync".to_string()))?,
+    };
+    let mut options: Vec<(&str, Value)> = vec![];
+
+    println!("[1/5] YNAB token");
+    let token = match &cli.token {
+        Some(token) => token.clone(),
+        None => password("YNAB token (Account Settings => Developer Settings)"),
+    };
+    let ynab = YNAB::new(token.clone());
+    let storage = select(
+        "Where should the token be kept?",
+        &[
+            "In the YNAB_TOKEN environment variable (nothing is written)",
+            "In the config file",
+            "In a password manager, read with a command",
+        ],
+    );
+    match storage {
+        1 => options.push(("ynab-token", Value::String(token))),
+        2 => options.push((
+            "ynab-token-command",
+            Value::String(input(
+                "Command printing the token (eg. pass show ynab)",
+                None,
+            )),
+        )),
+        _ => (),
+    }
+
+    println!("[2/5] Fetching YNAB budgets");
+    let budgets = ynab.get_budgets()?;
+    if budgets.is_empty() {
+        Err(ErrorKind::WrongBudgetId(String::new()))?;
+    }
+    let names: Vec<&str> = budgets.iter().map(|x| x.name.as_str()).collect();
+    let budget = &budgets[select("Budget", &names)];
+    options.push(("ynab-budget", Value::String(budget.id.clone())));
+
+    println!("[3/5] Fetching YNAB accounts");
+    let accounts: Vec<_> = ynab
+        .get_accounts(budget.id.clone())?
+        .into_iter()
+        .filter(|x| !x.deleted && !x.closed)
+        .collect();
+    if accounts.is_empty() {
+        Err(ErrorKind::WrongAccountId(String::new()))?;
+    }
+    let names: Vec<String> = accounts
+        .iter()
+        .map(|x| format!("{} ({:.2})", x.name, x.balance as f64 / 1000.0))
+        .collect();
+    let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+    let account = &accounts[select("Account to sync into", &names)];
+    options.push(("ynab-account", Value::String(account.id.clone())));
+
+    println!("[4/5] Bank");
+    let sources: Vec<&str> = COMMANDS.iter().map(|x| x.1).collect();
+    let command = COMMANDS[select("Bank", &sources)].0;
+    options.insert(0, ("command", Value::String(command.to_string())));
+    if command == "sync-with-n26" {
+        let username = input("N26 username", None);
+        let password = password("N26 password");
+        println!(" => Logging in to N26, confirm the login in the N26 app");
+        N26::new(username.clone(), password.clone())?;
+        println!(" => Logged in");
+        options.push(("n26-username", Value::String(username)));
+        match select(
+            "Where should the N26 password be kept?",
+            &[
+                "In the N26_PASSWORD environment variable (nothing is written)",
+                "In the config file",
+                "In a password manager, read with a command",
+            ],
+        ) {
+            1 => options.push(("n26-password", Value::String(password))),
+            2 => options.push((
+                "n26-password-command",
+                Value::String(input("Command printing the password", None)),
+            )),
+            _ => (),
+        }
+    } else {
+        println!(
+            " => No login to test, see `{} --help` for the options to add to the profile (eg. files)",
+            command
+        );
+    }
+
+    println!("[5/5] Writing {}", config_file.to_string_lossy());
+    let default_name = account.name.to_lowercase().replace(' ', "-");
+    let name = input("Profile name", Some(default_name));
+    config::add_profile(&config_file, &name, &options)?;
+    println!(
+        " => Added profile {}, sync it with `{} --profile {}` or `ynab-sync sync`",
+        name, command, name
+    );
+    Ok(())
+}
+
+fn select(prompt: &str, items: &[&str]) -> usize {
+    Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(0)
+        .items(items)
+        .interact()
+        .unwrap()
+}
+
+fn input(prompt: &str, default: Option<String>) -> String {
+    let theme = ColorfulTheme::default();
+    let mut input = Input::with_theme(&theme);
+    input.with_prompt(prompt);
+    if let Some(default) = default {
+        input.default(default);
+    }
+    input.interact().unwrap()
+}
+
+fn password(prompt: &str) -> String {
+    PasswordInput::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .interact()
+        .unwrap()
+}
@@ -0,0 +1,326 @@
+use failure::ResultExt;
+use std::env::current_exe;
+use std::process::Command;
+use std::sync::mpsc::channel;
+use std::thread;
+use structopt::StructOpt;
+use url::Url;
+use ynab_sync::config::{self, Profile};
+use ynab_sync::error::{ErrorKind, Result};
+use ynab_sync::http;
+use ynab_sync::ynab::API_URL;
+
+// YNAB requests a profile's sync is expected to need at most, profiles are
+// only synced at the same time while YNAB's rate limit has room for all of
+// them.
+const YNAB_REQUESTS_PER_PROFILE: u64 = 25;
+
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(
+        long = "config",
+        value_name = "FILE",
+        env = "YNAB_SYNC_CONFIG",
+        help = "TOML file with the profiles. Defaults to ~/.config/ynab-sync/config.toml."
+    )]
+    pub config_file: Option<String>,
+    #[structopt(
+        long = "profile",
+        value_name = "NAME",
+        number_of_values = 1,
+        help = "Only sync this profile. Can be repeated."
+    )]
+    pub profiles: Vec<String>,
+    #[structopt(long = "yes", help = "Sync without asking for confirmation.")]
+    pub yes: bool,
+    #[structopt(
+        long = "jobs",
+        value_name = "NUMBER",
+        default_value = "1",
+        env = "YNAB_SYNC_JOBS",
+        help = "Sync up to this many profiles at the same time (as long as YNAB's rate limit has room for them), their output is printed once they are done."
+    )]
+    pub jobs: usize,
+}
+
+/// Options of ynab-sync itself (--output, --http-*, ...) passed on to the
+/// sync binary of every profile. They are set as environment variables, so
+/// options in the profile's config still win.
+#[derive(Clone, Debug, Default)]
+pub struct Inherited {
+    pub env: Vec<(&'static str, String)>,
+    /// --http-header has no environment variable and is passed as argument.
+    pub http_headers: Vec<String>,
+}
+
+/// Run the sync binary of every profile of the config file, `--jobs` at a
+/// time, and report how each of them went. A failing profile doesn't stop
+/// the others.
+pub fn run(cli: Cli, inherited: &Inherited) -> Result<()> {
+    // clap's `requires` would count the default of --jobs as given
+    if cli.jobs > 1 && !cli.yes {
+        Err(ErrorKind::ProfilesJobsWithoutYes(cli.jobs))?;
+    }
+    let mut profiles = config::profiles(cli.config_file.as_deref())?;
+    for name in &cli.profiles {
+        if !profiles.iter().any(|x| x.name == *name) {
+            Err(ErrorKind::ConfigProfileNotFound(name.clone()))?;
+        }
+    }
+    if !cli.profiles.is_empty() {
+        profiles.retain(|x| cli.profiles.contains(&x.name));
+    }
+    if profiles.is_empty() {
+        println!("No profiles configured.");
+        return Ok(());
+    }
+
+    let header = |i: usize, profile: &Profile| {
+        println!(
+            "[{: >2}/{}] Syncing profile {} ({})",
+            i + 1,
+            profiles.len(),
+            profile.name,
+            profile.command
+        )
+    };
+    let mut results = vec![];
+    if cli.jobs <= 1 {
+        for (i, profile) in profiles.iter().enumerate() {
+            header(i, profile);
+            let result = sync(&cli, inherited, profile, false).and_then(|(_, status)| status);
+            if let Err(e) = &result {
+                println!(" => {}", e);
+            }
+            results.push(result);
+        }
+    } else {
+        results = sync_concurrently(&cli, inherited, &profiles, header);
+    }
+
+    println!("Profiles:");
+    for (profile, result) in profiles.iter().zip(&results) {
+        println!(
+            " - | {} | {}",
+            profile.name,
+            match result {
+                Ok(()) => "synced".to_string(),
+                Err(e) => e.to_string(),
+            }
+        );
+    }
+
+    let failed = results.iter().filter(|x| x.is_err()).count();
+    if failed > 0 {
+        Err(ErrorKind::ProfilesFailed(failed))?;
+    }
+    Ok(())
+}
+
+/// Sync up to `--jobs` profiles at the same time, starting another one only
+/// while the YNAB rate limit left (as last reported to any process, see
+/// `http::rate_limits`) covers all running ones. Outputs are printed, after
+/// `header`, once a profile is done.
+fn sync_concurrently(
+    cli: &Cli,
+    inherited: &Inherited,
+    profiles: &[Profile],
+    header: impl Fn(usize, &Profile),
+) -> Vec<Result<()>> {
+    let ynab_host = Url::parse(API_URL)
+        .ok()
+        .and_then(|x| x.host_str().map(String::from))
+        .unwrap_or_default();
+    let has_room = |running: usize| {
+        http::rate_limits()
+            .get(&ynab_host)
+            .is_none_or(|x| x.remaining() >= YNAB_REQUESTS_PER_PROFILE * (running as u64 + 1))
+    };
+
+    let mut results: Vec<Option<Result<()>>> = profiles.iter().map(|_| None).collect();
+    let (sender, receiver) = channel();
+    thread::scope(|scope| {
+        let mut next = 0;
+        let mut running = 0;
+        while next < profiles.len() || running > 0 {
+            // one profile always runs, YNAB rejecting requests is handled
+            // by its retries
+            if next < profiles.len() && running < cli.jobs && (running == 0 || has_room(running)) {
+                let (i, sender) = (next, sender.clone());
+                scope.spawn(move || {
+                    let _ = sender.send((i, sync(cli, inherited, &profiles[i], true)));
+                });
+                next += 1;
+                running += 1;
+                continue;
+            }
+            let (i, result) = match receiver.recv() {
+                Ok(x) => x,
+                Err(_) => break,
+            };
+            running -= 1;
+            header(i, &profiles[i]);
+            let result = result.and_then(|(output, status)| {
+                print!("{}", output);
+                status
+            });
+            if let Err(e) = &result {
+                println!(" => {}", e);
+            }
+            results[i] = Some(result);
+        }
+    });
+    results
+        .into_iter()
+        .zip(profiles)
+        .map(|(result, profile)| {
+            result.unwrap_or_else(|| Err(ErrorKind::ProfileFailed(profile.name.clone()).into()))
+        })
+        .collect()
+}
+
+/// Run the sync binary of `profile`. With `capture` its output is returned
+/// instead of printed, together with whether the sync succeeded.
+fn sync(
+    cli: &Cli,
+    inherited: &Inherited,
+    profile: &Profile,
+    capture: bool,
+) -> Result<(String, Result<()>)> {
+    let mut command = command(cli, inherited, profile);
+    let (output, status) = if capture {
+        let output = command
+            .output()
+            .with_context(|_| ErrorKind::ProfileFailed(profile.name.clone()))?;
+        let text = String::from_utf8_lossy(&output.stdout).to_string()
+            + &String::from_utf8_lossy(&output.stderr);
+        (text, output.status)
+    } else {
+        let status = command
+            .status()
+            .with_context(|_| ErrorKind::ProfileFailed(profile.name.clone()))?;
+        (String::new(), status)
+    };
+    if !status.success() {
+        return Ok((
+            output,
+            Err(ErrorKind::ProfileFailed(profile.name.clone()).into()),
+        ));
+    }
+    Ok((output, Ok(())))
+}
+
+/// Command running the sync binary of `profile`.
+fn command(cli: &Cli, inherited: &Inherited, profile: &Profile) -> Command {
+    // binaries are installed next to each other
+    let command = current_exe()
+        .ok()
+        .and_then(|x| x.parent().map(|x| x.join(&profile.command)))
+        .filter(|x| x.exists())
+        .map(|x| x.into_os_string())
+        .unwrap_or_else(|| profile.command.clone().into());
+
+    let mut command = Command::new(command);
+    command.arg("--profile").arg(&profile.name);
+    if let Some(config_file) = &cli.config_file {
+        command.arg("--config").arg(config_file);
+    }
+    if cli.yes {
+        command.arg("--yes");
+    }
+    for header in &inherited.http_headers {
+        command.arg("--http-header").arg(header);
+    }
+    command.envs(inherited.env.iter().cloned());
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inherited, Cli as MainCli, Command as MainCommand};
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use ynab_sync::{encryption, ynab};
+
+    #[test]
+    fn global_options_are_passed_on_to_profiles() {
+        let cli = MainCli::from_iter(&[
+            "ynab-sync",
+            "--output=json",
+            "--http-timeout=5",
+            "--http-proxy=http://localhost:3128",
+            "--http-header=X-Test: 1",
+            "--audit-pii=omit",
+            "--state-passphrase=secret",
+            "sync",
+            "--yes",
+            "--jobs=2",
+        ]);
+        encryption::setup(&cli.encryption);
+        let inherited = inherited(&cli.output, &cli.http, &cli.audit).unwrap();
+        let sync_cli = match cli.command {
+            MainCommand::Sync(x) => x,
+            _ => unreachable!(),
+        };
+        let profile = Profile {
+            name: "n26".to_string(),
+            command: "sync-with-n26".to_string(),
+        };
+
+        let command = command(&sync_cli, &inherited, &profile);
+        let args: Vec<&OsStr> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["--profile", "n26", "--yes", "--http-header", "X-Test: 1"]
+        );
+        let env: HashMap<&OsStr, Option<&OsStr>> = command.get_envs().collect();
+        for (key, value) in &[
+            ("YNAB_SYNC_OUTPUT", "json"),
+            ("YNAB_SYNC_HTTP_TIMEOUT", "5"),
+            ("YNAB_SYNC_HTTP_CONNECT_TIMEOUT", "30"),
+            ("YNAB_SYNC_HTTP_PROXY", "http://localhost:3128"),
+            ("YNAB_SYNC_AUDIT_PII", "omit"),
+            // children synced with --jobs can't prompt for it
+            ("YNAB_SYNC_STATE_PASSPHRASE", "secret"),
+        ] {
+            assert_eq!(env[OsStr::new(key)], Some(OsStr::new(value)), "{}", key);
+        }
+    }
+
+    #[test]
+    fn profiles_accept_yes_as_last_argument() {
+        let cli = ynab::Cli::from_iter_safe(&[
+            "sync-with-n26",
+            "--ynab-token=token",
+            "--ynab-budget=budget",
+            "--ynab-account=account",
+            "--yes",
+        ])
+        .unwrap();
+        assert!(cli.assume_yes());
+    }
+
+    #[test]
+    fn encrypt_state_is_a_flag() {
+        let cli =
+            MainCli::from_iter_safe(&["ynab-sync", "--encrypt-state", "sync", "--yes"]).unwrap();
+        assert!(cli.encryption.encrypt_state);
+        assert!(matches!(cli.command, MainCommand::Sync(_)));
+    }
+
+    #[test]
+    fn jobs_need_yes_only_above_one() {
+        let sync_cli = |args: &[&str]| match MainCli::from_iter(args).command {
+            MainCommand::Sync(x) => x,
+            _ => unreachable!(),
+        };
+        let inherited = Inherited::default();
+        let cli = sync_cli(&["ynab-sync", "sync", "--jobs=2"]);
+        assert!(run(cli, &inherited).is_err());
+
+        let cli = sync_cli(&["ynab-sync", "sync"]);
+        assert_eq!(cli.jobs, 1);
+        assert!(!cli.yes);
+    }
+}
@@ -0,0 +1,140 @@
+// Disk cache for rarely changing API data (eg. YNAB categories and accounts)
+//
+// Entries are JSON files in `~/.cache/ynab-sync` and expire based on their
+// modification time.
+
+use crate::{http, ErrorKind, Result};
+use chrono::{DateTime, Duration, Utc};
+use dirs::cache_dir;
+use failure::ResultExt;
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env::current_dir;
+use std::fs::{create_dir_all, metadata, read_dir, read_to_string, remove_file, write};
+use std::path::{Path, PathBuf};
+
+/// A stored entry, see `Cache::entries`.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub modified: DateTime<Utc>,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    refresh: bool,
+}
+
+impl Cache {
+    /// Entries older than `ttl` are refetched, with `refresh` every entry is
+    /// refetched (and stored again).
+    pub fn new(ttl: Duration, refresh: bool) -> Result<Self> {
+        let mut dir = cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
+        dir.push("ynab-sync");
+        create_dir_all(&dir)
+            .with_context(|_| ErrorKind::CacheCanNotWrite(dir.to_string_lossy().into()))?;
+        Ok(Cache { dir, ttl, refresh })
+    }
+
+    /// Directory in which entries are stored.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Recording/replaying sessions need every request to hit the HTTP layer.
+    fn enabled(&self) -> bool {
+        self.ttl > Duration::zero() && http::is_live()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        if !self.enabled() || self.refresh {
+            return None;
+        }
+        let path = self.path(key);
+        let modified: DateTime<Utc> = metadata(&path).and_then(|x| x.modified()).ok()?.into();
+        if Utc::now() - modified > self.ttl {
+            debug!("Cache entry {} expired", path.to_string_lossy());
+            return None;
+        }
+        let content = read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(value) => {
+                debug!("Using cache entry {}", path.to_string_lossy());
+                Some(value)
+            }
+            Err(e) => {
+                warn!("Ignoring cache entry {}: {}", path.to_string_lossy(), e);
+                None
+            }
+        }
+    }
+
+    pub fn put<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let path = self.path(key);
+        let content = serde_json::to_string(value)
+            .with_context(|_| ErrorKind::CacheCanNotWrite(path.to_string_lossy().into()))?;
+        write(&path, content)
+            .with_context(|_| ErrorKind::CacheCanNotWrite(path.to_string_lossy().into()))?;
+        Ok(())
+    }
+
+    /// All stored entries, oldest first.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let dir = self.dir.to_string_lossy().to_string();
+        let mut entries = vec![];
+        for file in read_dir(&self.dir).with_context(|_| ErrorKind::CacheCanNotRead(dir.clone()))? {
+            let path = file
+                .with_context(|_| ErrorKind::CacheCanNotRead(dir.clone()))?
+                .path();
+            if path.extension().and_then(|x| x.to_str()) != Some("json") {
+                continue;
+            }
+            let metadata =
+                metadata(&path).with_context(|_| ErrorKind::CacheCanNotRead(dir.clone()))?;
+            let modified = metadata
+                .modified()
+                .with_context(|_| ErrorKind::CacheCanNotRead(dir.clone()))?;
+            entries.push(Entry {
+                path,
+                modified: modified.into(),
+                size: metadata.len(),
+            });
+        }
+        entries.sort_by_key(|x| x.modified);
+        Ok(entries)
+    }
+
+    /// Remove entries older than the ttl, returns the number of removed
+    /// entries.
+    pub fn prune(&self) -> Result<usize> {
+        let now = Utc::now();
+        self.remove(|x| now - x.modified > self.ttl)
+    }
+
+    /// Remove all entries, returns the number of removed entries.
+    pub fn clear(&self) -> Result<usize> {
+        self.remove(|_| true)
+    }
+
+    fn remove(&self, filter: impl Fn(&Entry) -> bool) -> Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries()?.into_iter().filter(|x| filter(x)) {
+            remove_file(&entry.path).with_context(|_| {
+                ErrorKind::CacheCanNotWrite(entry.path.to_string_lossy().into())
+            })?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
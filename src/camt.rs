@@ -0,0 +1,375 @@
+// ISO 20022 bank statements
+//
+// Many European banks export statements as camt.053 (end of day statement),
+// camt.052 (intraday report) or camt.054 (notification) XML files. Each entry
+// (`Ntry`) of the statements becomes a transaction, with the creditor's (for
+// outflows) or debtor's (for inflows) name as payee and the remittance
+// information as memo. Pending entries of intraday reports are skipped, they
+// are synced once they are booked.
+
+use crate::parse::{amount, AmountFormat};
+use crate::source::{BankSource, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use failure::ResultExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+
+/// Placeholders available in `--memo-template` for camt transactions.
+pub const MEMO_FIELDS: &[&str] = &["payee", "remittance", "end_to_end_id", "iban", "info"];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{remittance|info}";
+
+// Bank transaction code of ATM withdrawals (customer card transactions, cash
+// withdrawal).
+const CASH_WITHDRAWAL_FAMILY: &str = "CCRD";
+const CASH_WITHDRAWAL_SUB_FAMILY: &str = "CWDL";
+
+// End-to-end id of payments where the payer didn't set one.
+const NOT_PROVIDED: &str = "NOTPROVIDED";
+
+pub struct Camt {
+    pub transactions: Vec<SourceTransaction>,
+    pub days_to_sync: i64,
+}
+
+impl Camt {
+    /// Parse statements of the same account. Entries in more than one of
+    /// them (eg. overlapping intraday reports) are only kept once.
+    pub fn new(camt_files: &[String]) -> Result<Self> {
+        let mut transactions: Vec<SourceTransaction> = vec![];
+        let mut ids = HashSet::new();
+        for camt_file in camt_files {
+            for transaction in parse_file(camt_file)? {
+                if ids.insert(transaction.id.clone()) {
+                    transactions.push(transaction);
+                }
+            }
+        }
+
+        let today = Utc::today().naive_local();
+        let days_to_sync = transactions
+            .iter()
+            .map(|x| (today - x.date).num_days())
+            .max()
+            .unwrap_or(0);
+
+        Ok(Camt {
+            transactions,
+            days_to_sync,
+        })
+    }
+}
+
+impl BankSource for Camt {
+    fn name(&self) -> &'static str {
+        "camt"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Entries of the statements booked in the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::today().naive_local() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.date >= since)
+            .cloned()
+            .collect())
+    }
+}
+
+fn parse_file(camt_file: &str) -> Result<Vec<SourceTransaction>> {
+    let content = read_to_string(camt_file)
+        .with_context(|_| ErrorKind::CamtFileCanNotRead(camt_file.to_string()))?;
+    let document =
+        Element::parse(&content).ok_or_else(|| ErrorKind::CamtFileParse(camt_file.to_string()))?;
+
+    // Document > BkToCstmrStmt/BkToCstmrAcctRpt/BkToCstmrDbtCdtNtfctn >
+    // Stmt/Rpt/Ntfctn > Ntry
+    let entries = document
+        .children
+        .iter()
+        .flat_map(|x| x.children.iter())
+        .filter(|x| ["Stmt", "Rpt", "Ntfctn"].contains(&x.name.as_str()))
+        .flat_map(|x| x.children("Ntry"));
+
+    let mut transactions = vec![];
+    // entries without references (eg. two equal card payments on the same
+    // day) get different import ids
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let transaction = parse_entry(entry, &mut occurrences).with_context(|_| {
+            ErrorKind::CamtEntryParse(camt_file.to_string(), entry.to_string())
+        })?;
+        if let Some(transaction) = transaction {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+/// Transaction of a booked entry, `None` for pending ones.
+fn parse_entry(
+    entry: &Element,
+    occurrences: &mut HashMap<String, usize>,
+) -> Result<Option<SourceTransaction>> {
+    // `<Sts>BOOK</Sts>` or, since version 8, `<Sts><Cd>BOOK</Cd></Sts>`
+    let status = entry
+        .text(&["Sts", "Cd"])
+        .or_else(|| entry.text(&["Sts"]))
+        .unwrap_or_default();
+    if status == "PDNG" {
+        return Ok(None);
+    }
+
+    let amount_value = entry.text(&["Amt"]).unwrap_or_default();
+    let mut amount = amount(&amount_value, &AmountFormat::PLAIN)?;
+    let is_debit = entry.text(&["CdtDbtInd"]).as_deref() == Some("DBIT");
+    if is_debit {
+        amount = -amount;
+    }
+    // reversals are booked on the other side of the original entry
+    if entry.text(&["RvslInd"]).as_deref() == Some("true") {
+        amount = -amount;
+    }
+
+    let date_value = entry
+        .text(&["BookgDt", "Dt"])
+        .or_else(|| entry.text(&["BookgDt", "DtTm"]))
+        .or_else(|| entry.text(&["ValDt", "Dt"]))
+        .or_else(|| entry.text(&["ValDt", "DtTm"]))
+        .unwrap_or_default();
+    let date = date_value
+        .get(..10)
+        .and_then(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").ok())
+        .ok_or_else(|| ErrorKind::DateParse(date_value.clone()))?;
+
+    // only the first transaction of batch bookings is used, the amount is
+    // the entry's total
+    let details = entry.find(&["NtryDtls", "TxDtls"]);
+    let detail = |path: &[&str]| details.and_then(|x| x.text(path));
+
+    // the other party: the creditor of outflows and the debtor of inflows
+    let (party, party_account) = if is_debit {
+        ("Cdtr", "CdtrAcct")
+    } else {
+        ("Dbtr", "DbtrAcct")
+    };
+    let payee =
+        detail(&["RltdPties", party, "Nm"]).or_else(|| detail(&["RltdPties", party, "Pty", "Nm"]));
+    let iban = detail(&["RltdPties", party_account, "Id", "IBAN"]);
+    let end_to_end_id = detail(&["Refs", "EndToEndId"]).filter(|x| x != NOT_PROVIDED);
+    let remittance = details
+        .and_then(|x| x.find(&["RmtInf"]))
+        .map(|x| {
+            x.children("Ustrd")
+                .map(|x| x.text.trim())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|x| !x.is_empty())
+        .or_else(|| detail(&["RmtInf", "Strd", "CdtrRefInf", "Ref"]));
+    let info = entry.text(&["AddtlNtryInf"]);
+
+    let is_cash_withdrawal = entry.text(&["BkTxCd", "Domn", "Fmly", "Cd"]).as_deref()
+        == Some(CASH_WITHDRAWAL_FAMILY)
+        && entry
+            .text(&["BkTxCd", "Domn", "Fmly", "SubFmlyCd"])
+            .as_deref()
+            == Some(CASH_WITHDRAWAL_SUB_FAMILY);
+
+    // the bank's reference, which isn't required to be unique over time, so
+    // the import id also depends on the date and amount
+    let reference = end_to_end_id
+        .clone()
+        .or_else(|| entry.text(&["AcctSvcrRef"]))
+        .or_else(|| detail(&["Refs", "AcctSvcrRef"]))
+        .unwrap_or_else(|| {
+            format!(
+                "{}|{}|{}",
+                payee.clone().unwrap_or_default(),
+                remittance.clone().unwrap_or_default(),
+                info.clone().unwrap_or_default()
+            )
+        });
+    let key = format!("{}|{}|{}", date, amount, reference);
+    let occurrence = occurrences.entry(key.clone()).or_insert(0);
+    *occurrence += 1;
+    let mut import_id_sha = Sha1::new();
+    import_id_sha.input_str(&key);
+    import_id_sha.input_str(&format!("{}", occurrence));
+    let id = import_id_sha.result_str()[..36].to_string();
+
+    let mut memo_values = HashMap::new();
+    memo_values.insert("payee", payee.clone());
+    memo_values.insert("remittance", remittance);
+    memo_values.insert("end_to_end_id", end_to_end_id);
+    memo_values.insert("iban", iban);
+    memo_values.insert("info", info.clone());
+
+    Ok(Some(SourceTransaction {
+        id,
+        date,
+        amount,
+        // card payments often only name the merchant in the entry's info
+        payee: payee.or(info),
+        memo_values,
+        is_cash_withdrawal,
+        fee: None,
+        pending: false,
+        category: None,
+    }))
+}
+
+/// XML element, just enough of XML to read camt statements. Namespace
+/// prefixes are dropped from names.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Element {
+    name: String,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    /// The root element of `xml`, `None` if it isn't well-formed.
+    fn parse(xml: &str) -> Option<Element> {
+        let mut stack: Vec<Element> = vec![Element::default()];
+        let mut rest = xml;
+        while !rest.is_empty() {
+            if let Some(x) = rest.strip_prefix("<?") {
+                rest = &x[x.find("?>")? + 2..];
+            } else if let Some(x) = rest.strip_prefix("<!--") {
+                rest = &x[x.find("-->")? + 3..];
+            } else if let Some(x) = rest.strip_prefix("<![CDATA[") {
+                let end = x.find("]]>")?;
+                stack.last_mut()?.text.push_str(&x[..end]);
+                rest = &x[end + 3..];
+            } else if let Some(x) = rest.strip_prefix("<!") {
+                rest = &x[x.find('>')? + 1..];
+            } else if let Some(x) = rest.strip_prefix("</") {
+                let end = x.find('>')?;
+                let element = stack.pop()?;
+                if element.name != local_name(x[..end].trim()) {
+                    return None;
+                }
+                stack.last_mut()?.children.push(element);
+                rest = &x[end + 1..];
+            } else if let Some(x) = rest.strip_prefix('<') {
+                let end = tag_end(x)?;
+                let tag = &x[..end];
+                let (tag, empty) = match tag.strip_suffix('/') {
+                    Some(tag) => (tag, true),
+                    None => (tag, false),
+                };
+                let name = tag.split_whitespace().next()?;
+                let element = Element {
+                    name: local_name(name).to_string(),
+                    ..Element::default()
+                };
+                if empty {
+                    stack.last_mut()?.children.push(element);
+                } else {
+                    stack.push(element);
+                }
+                rest = &x[end + 1..];
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                stack.last_mut()?.text.push_str(&unescape(&rest[..end])?);
+                rest = &rest[end..];
+            }
+        }
+        let mut document = stack.pop()?;
+        if !stack.is_empty() || document.children.len() != 1 {
+            return None;
+        }
+        document.children.pop()
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |x| x.name == name)
+    }
+
+    /// First descendant along `path`, eg. `["BookgDt", "Dt"]`.
+    fn find(&self, path: &[&str]) -> Option<&Element> {
+        path.iter().try_fold(self, |element, name| {
+            element.children.iter().find(|x| x.name == *name)
+        })
+    }
+
+    /// Trimmed text of the first descendant along `path`, `None` if it's
+    /// missing or empty.
+    fn text(&self, path: &[&str]) -> Option<String> {
+        self.find(path)
+            .map(|x| x.text.trim().to_string())
+            .filter(|x| !x.is_empty())
+    }
+}
+
+impl std::fmt::Display for Element {
+    /// The element's texts, eg. for errors about an entry.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = self.text.trim();
+        if !text.is_empty() {
+            write!(f, "{}={} ", self.name, text)?;
+        }
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Position of the `>` closing a tag, skipping the ones in quoted attribute
+/// values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Replace XML entities (`&amp;`, `&#228;`, ...), `None` for malformed ones.
+pub(crate) fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let end = rest.find(';')?;
+        let c = match &rest[..end] {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            x => match x.strip_prefix("#x").or_else(|| x.strip_prefix("#X")) {
+                Some(hex) => std::char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                None => std::char::from_u32(x.strip_prefix('#')?.parse().ok()?)?,
+            },
+        };
+        unescaped.push(c);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Some(unescaped)
+}
@@ -0,0 +1,119 @@
+// Categorizing by payee
+//
+// With `--classify` transactions which no rule, mapping or payee history
+// matched get the category a naive Bayes classifier finds most likely. It
+// learns how often the words of payee names (eg. "rewe" of "REWE Markt
+// GmbH") occur per category in the account's categorized transactions, so
+// new payees sharing words with known ones are categorized too. Guesses are
+// synced unapproved to be reviewed in YNAB.
+
+use crate::ynab::Transaction;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Default)]
+struct CategoryCounts {
+    /// Number of transactions with the category.
+    transactions: usize,
+    /// How often each word occurs in their payee names.
+    words: HashMap<String, usize>,
+    /// Sum of `words`.
+    total: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Classifier {
+    /// Word counts by category id.
+    categories: HashMap<String, CategoryCounts>,
+    vocabulary: HashSet<String>,
+    transactions: usize,
+    min_probability: f64,
+}
+
+impl Classifier {
+    /// Learn from the categorized transactions, guesses less likely than
+    /// `min_probability` are dropped.
+    pub fn from_transactions(transactions: &[Transaction], min_probability: f64) -> Self {
+        let mut classifier = Classifier {
+            min_probability,
+            ..Classifier::default()
+        };
+        for transaction in transactions {
+            let (category_id, payee_name) =
+                match (&transaction.category_id, &transaction.payee_name) {
+                    // split transactions have a placeholder category
+                    (Some(x), Some(y)) if transaction.subtransactions.is_empty() => (x, y),
+                    _ => continue,
+                };
+            let words = words(payee_name);
+            if words.is_empty() {
+                continue;
+            }
+            let counts = classifier
+                .categories
+                .entry(category_id.clone())
+                .or_default();
+            counts.transactions += 1;
+            for word in words {
+                *counts.words.entry(word.clone()).or_insert(0) += 1;
+                counts.total += 1;
+                classifier.vocabulary.insert(word);
+            }
+            classifier.transactions += 1;
+        }
+        classifier
+    }
+
+    /// Most likely category id for a transaction with `payee` and its
+    /// probability. `None` when none of the payee's words are known or the
+    /// guess is less likely than the minimum probability.
+    pub fn classify(&self, payee: &str) -> Option<(String, f64)> {
+        let words: Vec<String> = words(payee)
+            .into_iter()
+            .filter(|x| self.vocabulary.contains(x))
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+
+        // log probabilities with add-one smoothing
+        let vocabulary = self.vocabulary.len() as f64;
+        let scores: Vec<(&String, f64)> = self
+            .categories
+            .iter()
+            .map(|(category_id, counts)| {
+                let prior = (counts.transactions as f64 / self.transactions as f64).ln();
+                let likelihood: f64 = words
+                    .iter()
+                    .map(|x| {
+                        let count = counts.words.get(x).cloned().unwrap_or(0) as f64;
+                        ((count + 1.0) / (counts.total as f64 + vocabulary)).ln()
+                    })
+                    .sum();
+                (category_id, prior + likelihood)
+            })
+            .collect();
+        let best = scores
+            .iter()
+            .fold(None, |best: Option<&(&String, f64)>, x| match best {
+                Some(y) if y.1 >= x.1 => best,
+                _ => Some(x),
+            })?;
+        let total: f64 = scores.iter().map(|x| (x.1 - best.1).exp()).sum();
+        let probability = 1.0 / total;
+        if probability < self.min_probability {
+            return None;
+        }
+        Some((best.0.clone(), probability))
+    }
+}
+
+/// Lowercased words of a payee name, without ones containing digits (eg.
+/// store numbers or dates) and single characters.
+fn words(payee: &str) -> Vec<String> {
+    payee
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| x.chars().count() > 1 && !x.chars().any(|c| c.is_numeric()))
+        .map(String::from)
+        .collect()
+}
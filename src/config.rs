@@ -0,0 +1,274 @@
+// Config file
+//
+// Instead of long command lines, options can be kept in a TOML file, by
+// default `~/.config/ynab-sync/config.toml` (see `--config`). Keys are the
+// names of the long options, values are strings, numbers, booleans (for
+// flags) or arrays (for options which can be repeated). Keys at the top level
+// apply to both sync binaries, keys in a table named after one only to it:
+//
+// ```toml
+// ynab-budget = "My Budget"
+// totals = 5
+//
+// [sync-with-n26]
+// ynab-account = "N26"
+// n26-username = "me@example.com"
+// n26-password-command = "pass show n26"
+// n26-category-mapping = "/home/me/n26_category_mapping.json"
+// ```
+//
+// Profiles sync one source into one YNAB account each, eg. two N26 accounts
+// and an ING-DiBa export. Their options are kept in tables under `profiles`,
+// with the binary to run as `command`, and win over the other tables:
+//
+// ```toml
+// [profiles.n26-joint]
+// command = "sync-with-n26"
+// ynab-account = "N26 Joint"
+// n26-username = "joint@example.com"
+// ```
+//
+// `sync-with-n26 --profile n26-joint` uses the options of one profile and
+// `ynab-sync sync` runs all of them, one after another or `--jobs` at a time.
+//
+// A key ending in `-command` runs the command with `sh -c` and uses its
+// output as the value of the option, so credentials can be read from a
+// password manager instead of being stored in the file.
+//
+// Options given on the command line override the ones from the file, which
+// override environment variables.
+
+use crate::{ErrorKind, Result};
+use dirs::config_dir;
+use failure::ResultExt;
+use log::info;
+use std::env::{args_os, var_os};
+use std::ffi::OsString;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use structopt::StructOpt;
+use toml::Value;
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "config",
+        value_name = "FILE",
+        env = "YNAB_SYNC_CONFIG",
+        help = "TOML file with options, used for options not given on the command line. Defaults to ~/.config/ynab-sync/config.toml when it exists."
+    )]
+    pub config_file: Option<String>,
+    #[structopt(
+        long = "profile",
+        value_name = "NAME",
+        env = "YNAB_SYNC_PROFILE",
+        help = "Use the options of this profile of the config file."
+    )]
+    pub profile: Option<String>,
+}
+
+/// Sync profile of the config file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Binary which syncs the profile, eg. `sync-with-n26`.
+    pub command: String,
+}
+
+/// Log which config file was used, its options were already added by `args`.
+pub fn setup(cli: &Cli) {
+    let config_file = cli
+        .config_file
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(default_config_file);
+    if let Some(config_file) = config_file {
+        info!("Using options from {}", config_file.to_string_lossy());
+    }
+    if let Some(profile) = &cli.profile {
+        info!("Using options of profile {}", profile);
+    }
+}
+
+/// Command line arguments of the binary `name` with the options of the
+/// config file added.
+pub fn args(name: &str) -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = args_os().collect();
+    let config_file = match config_file(&args) {
+        Some(x) => x,
+        None => return Ok(args),
+    };
+    let config = load(&config_file)?;
+
+    // options of the profile win over the binary's table, which wins over
+    // the top level
+    let mut options = vec![];
+    if let Some(profile) = given(&args, "--profile").or_else(|| var_os("YNAB_SYNC_PROFILE")) {
+        let profile = profile.to_string_lossy().to_string();
+        match config.get("profiles").and_then(|x| x.get(&profile)) {
+            Some(Value::Table(table)) => {
+                options.extend(table.iter().filter(|x| x.0 != "command"));
+            }
+            _ => Err(ErrorKind::ConfigProfileNotFound(profile))?,
+        }
+    }
+    if let Some(Value::Table(table)) = config.get(name) {
+        options.extend(table.iter());
+    }
+    if let Value::Table(table) = &config {
+        options.extend(table.iter().filter(|x| !x.1.is_table()));
+    }
+
+    let mut added: Vec<OsString> = vec![];
+    for (key, value) in options {
+        let command = key.strip_suffix("-command");
+        let option = format!("--{}", command.unwrap_or(key));
+        if is_given(&args, &option) || is_given(&added, &option) {
+            continue;
+        }
+        let value = match command {
+            Some(_) => Value::String(run_command(key, value)?),
+            None => value.clone(),
+        };
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => added.push(option.clone().into()),
+                Value::Boolean(false) => (),
+                Value::String(x) => added.push(format!("{}={}", option, x).into()),
+                x => added.push(format!("{}={}", option, x).into()),
+            }
+        }
+    }
+    args.extend(added);
+    Ok(args)
+}
+
+/// Profiles of the config file given with `config_file`, YNAB_SYNC_CONFIG
+/// or the default file, sorted by name.
+pub fn profiles(config_file: Option<&str>) -> Result<Vec<Profile>> {
+    let config_file = match config_file
+        .map(PathBuf::from)
+        .or_else(|| var_os("YNAB_SYNC_CONFIG").map(PathBuf::from))
+        .or_else(default_config_file)
+    {
+        Some(x) => x,
+        None => return Ok(vec![]),
+    };
+    let config = load(&config_file)?;
+    let profiles = match config.get("profiles") {
+        Some(Value::Table(table)) => table,
+        _ => return Ok(vec![]),
+    };
+    profiles
+        .iter()
+        .map(|(name, profile)| match profile.get("command") {
+            Some(Value::String(command)) => Ok(Profile {
+                name: name.clone(),
+                command: command.clone(),
+            }),
+            _ => Err(ErrorKind::ConfigProfileWithoutCommand(name.clone()))?,
+        })
+        .collect()
+}
+
+/// Add profile `name` with `options` (keys are long options) to the end of
+/// `config_file`, which is created when missing. The rest of the file
+/// (including comments) stays as it is.
+pub fn add_profile(config_file: &Path, name: &str, options: &[(&str, Value)]) -> Result<()> {
+    let path = config_file.to_string_lossy().to_string();
+    let mut content = String::new();
+    if config_file.exists() {
+        let config = load(config_file)?;
+        if config.get("profiles").and_then(|x| x.get(name)).is_some() {
+            Err(ErrorKind::ConfigProfileExists(name.to_string()))?;
+        }
+        content = read_to_string(config_file)
+            .with_context(|_| ErrorKind::ConfigCanNotRead(path.clone()))?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    } else if let Some(dir) = config_file.parent() {
+        create_dir_all(dir).with_context(|_| ErrorKind::ConfigCanNotWrite(path.clone()))?;
+    }
+
+    content.push_str(&format!("[profiles.{}]\n", Value::String(name.to_string())));
+    for (key, value) in options {
+        content.push_str(&format!("{} = {}\n", key, value));
+    }
+    write(config_file, content).with_context(|_| ErrorKind::ConfigCanNotWrite(path))?;
+    Ok(())
+}
+
+/// Path of the default config file, whether it exists or not.
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|x| x.join("ynab-sync").join(CONFIG_FILE))
+}
+
+fn load(config_file: &Path) -> Result<Value> {
+    let content = read_to_string(config_file)
+        .with_context(|_| ErrorKind::ConfigCanNotRead(config_file.to_string_lossy().into()))?;
+    let config = content
+        .parse::<Value>()
+        .with_context(|_| ErrorKind::ConfigCanNotParse(config_file.to_string_lossy().into()))?;
+    Ok(config)
+}
+
+/// File given with --config, YNAB_SYNC_CONFIG or the default file if it
+/// exists.
+fn config_file(args: &[OsString]) -> Option<PathBuf> {
+    given(args, "--config")
+        .or_else(|| var_os("YNAB_SYNC_CONFIG"))
+        .map(PathBuf::from)
+        .or_else(default_config_file)
+}
+
+/// Value of `option` in `args`, given as `--option VALUE` or
+/// `--option=VALUE`.
+fn given(args: &[OsString], option: &str) -> Option<OsString> {
+    let with_value = format!("{}=", option);
+    args.iter()
+        .zip(args.iter().skip(1))
+        .find(|x| x.0 == option)
+        .map(|x| x.1.clone())
+        .or_else(|| {
+            args.iter()
+                .filter_map(|x| x.to_str())
+                .find_map(|x| x.strip_prefix(&with_value))
+                .map(OsString::from)
+        })
+}
+
+fn default_config_file() -> Option<PathBuf> {
+    default_path().filter(|x| x.exists())
+}
+
+fn is_given(args: &[OsString], option: &str) -> bool {
+    let with_value = format!("{}=", option);
+    args.iter()
+        .filter_map(|x| x.to_str())
+        .any(|x| x == option || x.starts_with(&with_value))
+}
+
+fn run_command(key: &str, command: &Value) -> Result<String> {
+    let command = match command {
+        Value::String(x) => x,
+        _ => Err(ErrorKind::ConfigCommandFailed(key.to_string()))?,
+    };
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .with_context(|_| ErrorKind::ConfigCommandFailed(key.to_string()))?;
+    if !output.status.success() {
+        Err(ErrorKind::ConfigCommandFailed(key.to_string()))?;
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
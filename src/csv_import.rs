@@ -0,0 +1,315 @@
+use crate::parse::{amount_detect, date, Locale};
+use crate::source::{BankSource, Cli as DateRangeCli, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use csv::{ReaderBuilder, StringRecord};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{read_to_string, File};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+/// Placeholders available in `--memo-template` for CSV transactions.
+pub const MEMO_FIELDS: &[&str] = &["payee", "memo", "iban"];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{memo}";
+
+/// Columns of a bank's CSV export, read from the file provided via
+/// `--csv-mapping`:
+///
+/// ```json
+/// {
+///   "delimiter": ";",
+///   "encoding": "windows-1252",
+///   "skip_lines": 4,
+///   "locale": "de",
+///   "date": "Buchungstag",
+///   "amount": "Betrag",
+///   "payee": "Beguenstigter/Zahlungspflichtiger",
+///   "memo": ["Buchungstext", "Verwendungszweck"],
+///   "iban": "Kontonummer/IBAN"
+/// }
+/// ```
+///
+/// Columns are given by their header or, for exports without a header
+/// (`"header": false`), by their index starting at 0. Banks which export
+/// outflows and inflows in separate columns use `"outflow"` and `"inflow"`
+/// instead of `"amount"`. Amounts and dates are read according to `locale`
+/// (`de`, `us`, `ch`, `fr` or `iso`), `date_format` (eg. `%d.%m.%y`)
+/// overrides its date format.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CsvMapping {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    /// Lines before the header (eg. account details).
+    #[serde(default)]
+    pub skip_lines: usize,
+    #[serde(default = "default_header")]
+    pub header: bool,
+    #[serde(default = "default_locale", with = "serde_str")]
+    pub locale: Locale,
+    pub date_format: Option<String>,
+    pub date: Column,
+    pub amount: Option<Column>,
+    pub outflow: Option<Column>,
+    pub inflow: Option<Column>,
+    pub payee: Option<Column>,
+    #[serde(default)]
+    pub memo: Vec<Column>,
+    pub iban: Option<Column>,
+    /// Column with a unique id of the transaction, used as YNAB import id.
+    /// Without it the import id is a hash of the row's values.
+    pub id: Option<Column>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_encoding() -> String {
+    "utf-8".to_string()
+}
+
+fn default_header() -> bool {
+    true
+}
+
+fn default_locale() -> Locale {
+    Locale::ISO
+}
+
+impl CsvMapping {
+    pub fn from_file(csv_mapping_file: &str) -> Result<Self> {
+        if !PathBuf::from(csv_mapping_file).exists() {
+            Err(ErrorKind::ArgParseCsvMappingCanNotRead(
+                csv_mapping_file.to_string(),
+            ))?
+        }
+        let csv_mapping_string = read_to_string(csv_mapping_file).with_context(|_| {
+            ErrorKind::ArgParseCsvMappingCanNotRead(csv_mapping_file.to_string())
+        })?;
+        let mapping: CsvMapping = serde_json::from_str(&csv_mapping_string).context(
+            ErrorKind::ArgParseCsvMappingCanNotParse(csv_mapping_file.to_string()),
+        )?;
+
+        let has_amount = mapping.amount.is_some();
+        let has_outflow_inflow = mapping.outflow.is_some() || mapping.inflow.is_some();
+        if has_amount == has_outflow_inflow
+            || !mapping.delimiter.is_ascii()
+            || Encoding::for_label(mapping.encoding.as_bytes()).is_none()
+        {
+            Err(ErrorKind::ArgParseCsvMappingCanNotParse(
+                csv_mapping_file.to_string(),
+            ))?
+        }
+
+        Ok(mapping)
+    }
+
+    fn date_format(&self) -> String {
+        self.date_format
+            .clone()
+            .unwrap_or_else(|| self.locale.date_format())
+    }
+}
+
+pub struct CsvImport {
+    pub transactions: Vec<SourceTransaction>,
+    pub days_to_sync: i64,
+}
+
+impl CsvImport {
+    pub fn new(csv_file: &str, mapping: &CsvMapping) -> Result<Self> {
+        let encoding = Encoding::for_label(mapping.encoding.as_bytes());
+        let reader = BufReader::new(
+            DecodeReaderBytesBuilder::new().encoding(encoding).build(
+                File::open(csv_file)
+                    .with_context(|_| ErrorKind::CsvFileCanNotOpen(csv_file.to_string()))?,
+            ),
+        );
+        let mut lines = vec![];
+        for line in reader.lines().skip(mapping.skip_lines) {
+            lines.push(line.with_context(|_| ErrorKind::CsvFileParse(csv_file.to_string()))?);
+        }
+        let csv_data = lines.join("\n");
+
+        let mut reader = ReaderBuilder::new()
+            .delimiter(mapping.delimiter as u8)
+            .has_headers(mapping.header)
+            .flexible(true)
+            .from_reader(csv_data.as_bytes());
+        let headers = if mapping.header {
+            reader
+                .headers()
+                .with_context(|_| ErrorKind::CsvFileParse(csv_file.to_string()))?
+                .clone()
+        } else {
+            StringRecord::new()
+        };
+
+        let index = |column: &Column| -> Result<usize> {
+            match column {
+                Column::Index(x) => Ok(*x),
+                Column::Name(name) => Ok(headers
+                    .iter()
+                    .position(|x| x.trim() == name)
+                    .ok_or_else(|| ErrorKind::CsvColumnNotFound(name.clone()))?),
+            }
+        };
+        let optional_index = |column: &Option<Column>| -> Result<Option<usize>> {
+            column.as_ref().map(index).transpose()
+        };
+        let date_index = index(&mapping.date)?;
+        let amount_index = optional_index(&mapping.amount)?;
+        let outflow_index = optional_index(&mapping.outflow)?;
+        let inflow_index = optional_index(&mapping.inflow)?;
+        let payee_index = optional_index(&mapping.payee)?;
+        let iban_index = optional_index(&mapping.iban)?;
+        let id_index = optional_index(&mapping.id)?;
+        let memo_indexes = mapping.memo.iter().map(index).collect::<Result<Vec<_>>>()?;
+        let date_format = mapping.date_format();
+
+        let mut transactions = vec![];
+        // identical rows (eg. two equal purchases on the same day) get
+        // different import ids
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        for result in reader.records() {
+            let record = result.with_context(|_| ErrorKind::CsvFileParse(csv_file.to_string()))?;
+            let row = || {
+                record
+                    .iter()
+                    .collect::<Vec<_>>()
+                    .join(&mapping.delimiter.to_string())
+            };
+            let value = |index: Option<usize>| -> Option<String> {
+                index
+                    .and_then(|x| record.get(x))
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+            };
+            let amount = |index: Option<usize>| -> Result<i32> {
+                match value(index) {
+                    Some(x) => amount_detect(&x, &mapping.locale.amount),
+                    None => Ok(0),
+                }
+            };
+            // empty rows, eg. a summary at the end of the export
+            let date_value = match value(Some(date_index)) {
+                Some(x) => x,
+                None => continue,
+            };
+            let transaction_date = NaiveDate::parse_from_str(&date_value, &date_format)
+                .or_else(|_| date(&date_value, &mapping.locale))
+                .with_context(|_| ErrorKind::CsvTransactionParse(csv_file.to_string(), row()))?;
+            let transaction_amount = match amount_index {
+                Some(_) => amount(amount_index),
+                None => amount(inflow_index)
+                    .and_then(|inflow| Ok(inflow - amount(outflow_index)?.abs())),
+            }
+            .with_context(|_| ErrorKind::CsvTransactionParse(csv_file.to_string(), row()))?;
+
+            let payee = value(payee_index);
+            let memo = memo_indexes
+                .iter()
+                .filter_map(|x| value(Some(*x)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let iban = value(iban_index);
+
+            let id = match value(id_index) {
+                Some(x) => x,
+                None => {
+                    let key = row();
+                    let occurrence = occurrences.entry(key.clone()).or_insert(0);
+                    *occurrence += 1;
+                    let mut import_id_sha = Sha1::new();
+                    import_id_sha.input_str(&key);
+                    import_id_sha.input_str(&format!("{}", occurrence));
+                    import_id_sha.result_str()[..36].to_string()
+                }
+            };
+
+            let mut memo_values = HashMap::new();
+            memo_values.insert("payee", payee.clone());
+            memo_values.insert("memo", Some(memo));
+            memo_values.insert("iban", iban);
+            transactions.push(SourceTransaction {
+                id,
+                date: transaction_date,
+                amount: transaction_amount,
+                payee,
+                memo_values,
+                is_cash_withdrawal: false,
+                fee: None,
+                pending: false,
+                category: None,
+            });
+        }
+
+        Ok(CsvImport::from_transactions(transactions))
+    }
+
+    /// Only the transactions booked within --from-date and --to-date, with
+    /// `days_to_sync` reaching back to the oldest of them.
+    pub fn filter_dates(self, dates: &DateRangeCli) -> Self {
+        CsvImport::from_transactions(
+            self.transactions
+                .into_iter()
+                .filter(|x| dates.contains(x.date))
+                .collect(),
+        )
+    }
+
+    fn from_transactions(transactions: Vec<SourceTransaction>) -> Self {
+        let today = Utc::today().naive_local();
+        let days_to_sync = transactions
+            .iter()
+            .map(|x| (today - x.date).num_days())
+            .max()
+            .unwrap_or(0);
+
+        CsvImport {
+            transactions,
+            days_to_sync,
+        }
+    }
+}
+
+impl BankSource for CsvImport {
+    fn name(&self) -> &'static str {
+        "CSV"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Transactions of the export booked in the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::today().naive_local() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.date >= since)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,165 @@
+// Delta requests
+//
+// Every YNAB list (categories, accounts, transactions) comes with a
+// `server_knowledge`. Passed back as `last_knowledge_of_server`, YNAB only
+// returns what changed since then, including deleted entities. A `Snapshot`
+// keeps the last known list together with its server knowledge in the state
+// directory (`~/.local/share/ynab-sync/deltas`), the changes are merged into
+// it so repeated syncs only download deltas.
+//
+// Snapshots contain transactions, they are encrypted with --encrypt-state.
+
+use crate::{encryption, http, state_dir, ErrorKind, Result};
+use chrono::NaiveDate;
+use failure::ResultExt;
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write};
+use std::path::{Path, PathBuf};
+
+const DELTAS_DIR: &str = "deltas";
+
+/// A list of entities as known at `server_knowledge`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    pub server_knowledge: i64,
+    /// Oldest date the list covers, for lists fetched with a `since_date`.
+    #[serde(default)]
+    pub since_date: Option<NaiveDate>,
+    pub items: Vec<T>,
+}
+
+impl<T> Snapshot<T> {
+    /// Merge the changes since the snapshot's server knowledge, see `merge`.
+    pub fn merge(
+        &mut self,
+        changed: Vec<T>,
+        server_knowledge: i64,
+        id: impl Fn(&T) -> &str,
+        deleted: impl Fn(&T) -> bool,
+    ) {
+        merge(&mut self.items, changed, id, deleted);
+        self.server_knowledge = server_knowledge;
+    }
+}
+
+/// Replace changed items and add new ones (both by `id`), deleted ones are
+/// removed.
+pub fn merge<T>(
+    items: &mut Vec<T>,
+    changed: Vec<T>,
+    id: impl Fn(&T) -> &str,
+    deleted: impl Fn(&T) -> bool,
+) {
+    for item in changed {
+        let position = items.iter().position(|x| id(x) == id(&item));
+        match (position, deleted(&item)) {
+            (Some(i), true) => {
+                items.remove(i);
+            }
+            (Some(i), false) => items[i] = item,
+            (None, true) => (),
+            (None, false) => items.push(item),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Deltas {
+    dir: PathBuf,
+    refresh: bool,
+}
+
+impl Deltas {
+    /// With `refresh` every list is downloaded completely (and stored
+    /// again).
+    pub fn new(refresh: bool) -> Result<Self> {
+        let dir = state_dir()?.join(DELTAS_DIR);
+        create_dir_all(&dir)
+            .with_context(|_| ErrorKind::StateCanNotWrite(dir.to_string_lossy().into()))?;
+        Ok(Deltas { dir, refresh })
+    }
+
+    /// Directory in which snapshots are stored.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Recording/replaying sessions need every request to be the same.
+    fn enabled(&self) -> bool {
+        http::is_live()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Stored snapshot, `None` when there is none (or it can't be read) and
+    /// the list has to be downloaded completely.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<Snapshot<T>> {
+        if !self.enabled() || self.refresh {
+            return None;
+        }
+        let path = self.path(key);
+        let content = read_to_string(&path).ok()?;
+        let snapshot = encryption::decrypt(&content)
+            .map_err(|e| e.to_string())
+            .and_then(|x| serde_json::from_str(&x).map_err(|e| e.to_string()));
+        match snapshot {
+            Ok(snapshot) => {
+                debug!("Using snapshot {}", path.to_string_lossy());
+                Some(snapshot)
+            }
+            Err(e) => {
+                warn!("Ignoring snapshot {}: {}", path.to_string_lossy(), e);
+                None
+            }
+        }
+    }
+
+    pub fn put<T: Serialize>(&self, key: &str, snapshot: &Snapshot<T>) -> Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let path = self.path(key);
+        let content = serde_json::to_string(snapshot)
+            .with_context(|_| ErrorKind::StateCanNotWrite(path.to_string_lossy().into()))?;
+        let content = encryption::encrypt(&content)
+            .with_context(|_| ErrorKind::StateCanNotWrite(path.to_string_lossy().into()))?;
+        // written to a temporary file first, so syncs running at the same
+        // time (eg. `sync --jobs`) never read half of it
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        write(&tmp, content)
+            .with_context(|_| ErrorKind::StateCanNotWrite(path.to_string_lossy().into()))?;
+        rename(&tmp, &path)
+            .with_context(|_| ErrorKind::StateCanNotWrite(path.to_string_lossy().into()))?;
+        Ok(())
+    }
+
+    /// Paths of all stored snapshots.
+    pub fn entries(&self) -> Result<Vec<PathBuf>> {
+        let dir = self.dir.to_string_lossy().to_string();
+        let mut entries = vec![];
+        for file in read_dir(&self.dir).with_context(|_| ErrorKind::StateCanNotRead(dir.clone()))? {
+            let path = file
+                .with_context(|_| ErrorKind::StateCanNotRead(dir.clone()))?
+                .path();
+            if path.extension().and_then(|x| x.to_str()) == Some("json") {
+                entries.push(path);
+            }
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    /// Remove all snapshots, returns the number of removed snapshots.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for path in &entries {
+            remove_file(path)
+                .with_context(|_| ErrorKind::StateCanNotWrite(path.to_string_lossy().into()))?;
+        }
+        Ok(entries.len())
+    }
+}
@@ -0,0 +1,223 @@
+// Encryption at rest
+//
+// The sync state, the audit log and the N26 tokens can be encrypted with a
+// passphrase (`--encrypt-state`), for users keeping years of transaction
+// history on shared machines. The passphrase comes from
+// `YNAB_SYNC_STATE_PASSPHRASE` or is prompted for once per run.
+//
+// Encrypted content is a single line of text:
+//
+//     ynab-sync:v1:<base64(salt | nonce | tag | ciphertext)>
+//
+// so the audit log stays a file of independent lines. The key is derived from
+// the passphrase with scrypt and the content sealed with ChaCha20-Poly1305.
+// One salt is used for everything written in a run, which keeps it to a
+// single key derivation; every line gets its own nonce.
+//
+// Content that isn't encrypted is read as it is, so existing files are
+// encrypted the next time they are written.
+
+use crate::{env_flag, state_dir, ErrorKind, Result};
+use crypto::aead::{AeadDecryptor, AeadEncryptor};
+use crypto::chacha20poly1305::ChaCha20Poly1305;
+use crypto::scrypt::{scrypt, ScryptParams};
+use dialoguer::PasswordInput;
+use failure::ResultExt;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::sync::{Mutex, OnceLock};
+use structopt::StructOpt;
+
+const PREFIX: &str = "ynab-sync:v1:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 8;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+// 2^15 * 8 * 128 bytes = 32MB of memory per key derivation
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "encrypt-state",
+        help = "Encrypt the sync state, the audit log and the N26 tokens with a passphrase. [env: YNAB_SYNC_ENCRYPT_STATE]"
+    )]
+    pub encrypt_state: bool,
+    #[structopt(
+        long = "state-passphrase",
+        value_name = "TEXT",
+        env = "YNAB_SYNC_STATE_PASSPHRASE",
+        hide_env_values = true,
+        help = "Passphrase of the encrypted state, prompted for when needed and not provided."
+    )]
+    pub state_passphrase: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Config {
+    enabled: bool,
+    passphrase: Option<String>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+static WRITE_SALT: OnceLock<[u8; SALT_LEN]> = OnceLock::new();
+static KEYS: OnceLock<Mutex<HashMap<Vec<u8>, Vec<u8>>>> = OnceLock::new();
+
+/// Enable encryption with `--encrypt-state` or `--state-passphrase`, without
+/// them only already encrypted content is decrypted (and stays encrypted).
+pub fn setup(cli: &Cli) {
+    // the first configuration wins
+    let _ = CONFIG.set(Config {
+        enabled: cli.encrypt_state
+            || env_flag("YNAB_SYNC_ENCRYPT_STATE")
+            || cli.state_passphrase.is_some(),
+        passphrase: cli.state_passphrase.clone(),
+    });
+}
+
+/// The passphrase as environment variable of another ynab-sync process, which
+/// might not be able to prompt for it (eg. profiles synced with `--jobs`).
+/// It's prompted for now when encryption is enabled or the state encrypted.
+pub fn env() -> Result<Vec<(&'static str, String)>> {
+    if !is_enabled() && !has_encrypted_state()? {
+        return Ok(vec![]);
+    }
+    Ok(vec![("YNAB_SYNC_STATE_PASSPHRASE", passphrase()?)])
+}
+
+fn config() -> Config {
+    CONFIG.get().cloned().unwrap_or_default()
+}
+
+/// Whether content written now is encrypted, either because it was asked for
+/// or because encrypted content was already read in this run.
+pub fn is_enabled() -> bool {
+    config().enabled || PASSPHRASE.get().is_some()
+}
+
+pub fn is_encrypted(content: &str) -> bool {
+    content.trim_start().starts_with(PREFIX)
+}
+
+/// Encrypt `content` when encryption is enabled, otherwise return it as it
+/// is.
+pub fn encrypt(content: &str) -> Result<String> {
+    if !is_enabled() {
+        return Ok(content.to_string());
+    }
+
+    let salt = write_salt()?;
+    let key = key(&salt)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    random(&mut nonce)?;
+
+    let mut ciphertext = vec![0u8; content.len()];
+    let mut tag = [0u8; TAG_LEN];
+    ChaCha20Poly1305::new(&key, &nonce, PREFIX.as_bytes()).encrypt(
+        content.as_bytes(),
+        &mut ciphertext,
+        &mut tag,
+    );
+
+    let mut data = Vec::with_capacity(SALT_LEN + NONCE_LEN + TAG_LEN + ciphertext.len());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&tag);
+    data.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", PREFIX, base64::encode(&data)))
+}
+
+/// Decrypt `content` if it's encrypted, otherwise return it as it is.
+pub fn decrypt(content: &str) -> Result<String> {
+    if !is_encrypted(content) {
+        return Ok(content.to_string());
+    }
+
+    let data = base64::decode(content.trim().trim_start_matches(PREFIX))
+        .context(ErrorKind::StateCanNotDecrypt)?;
+    if data.len() < SALT_LEN + NONCE_LEN + TAG_LEN {
+        Err(ErrorKind::StateCanNotDecrypt)?
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, rest) = rest.split_at(NONCE_LEN);
+    let (tag, ciphertext) = rest.split_at(TAG_LEN);
+
+    let key = key(salt)?;
+    let mut plaintext = vec![0u8; ciphertext.len()];
+    if !ChaCha20Poly1305::new(&key, nonce, PREFIX.as_bytes()).decrypt(
+        ciphertext,
+        &mut plaintext,
+        tag,
+    ) {
+        Err(ErrorKind::StateCanNotDecrypt)?
+    }
+    Ok(String::from_utf8(plaintext).context(ErrorKind::StateCanNotDecrypt)?)
+}
+
+fn random(buf: &mut [u8]) -> Result<()> {
+    OsRng
+        .try_fill_bytes(buf)
+        .context(ErrorKind::StateCanNotEncrypt)?;
+    Ok(())
+}
+
+fn write_salt() -> Result<[u8; SALT_LEN]> {
+    if let Some(salt) = WRITE_SALT.get() {
+        return Ok(*salt);
+    }
+    let mut salt = [0u8; SALT_LEN];
+    random(&mut salt)?;
+    Ok(*WRITE_SALT.get_or_init(|| salt))
+}
+
+fn key(salt: &[u8]) -> Result<Vec<u8>> {
+    let keys = KEYS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(key) = keys.lock().unwrap().get(salt) {
+        return Ok(key.clone());
+    }
+
+    let passphrase = passphrase()?;
+    let mut key = vec![0u8; KEY_LEN];
+    scrypt(
+        passphrase.as_bytes(),
+        salt,
+        &ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P),
+        &mut key,
+    );
+    keys.lock().unwrap().insert(salt.to_vec(), key.clone());
+    Ok(key)
+}
+
+fn passphrase() -> Result<String> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase.clone());
+    }
+
+    let passphrase = match config().passphrase {
+        Some(passphrase) => passphrase,
+        None => {
+            let mut input = PasswordInput::new();
+            input.with_prompt("State passphrase");
+            // a mistyped passphrase would lock away the first encrypted state
+            if !has_encrypted_state()? {
+                input.with_confirmation("Repeat passphrase", "Passphrases do not match");
+            }
+            input
+                .interact()
+                .context(ErrorKind::StatePassphraseCanNotRead)?
+        }
+    };
+    Ok(PASSPHRASE.get_or_init(|| passphrase).clone())
+}
+
+fn has_encrypted_state() -> Result<bool> {
+    let path = state_dir()?.join(crate::state::STATE_FILE);
+    Ok(read_to_string(path)
+        .map(|x| is_encrypted(&x))
+        .unwrap_or(false))
+}
@@ -7,28 +7,129 @@
 //
 // More: https://github.com/rust-lang-nursery/failure/blob/master/book/src/error-errorkind.md
 
-use exitfailure::ExitFailure;
+// `failure_derive` expands `Fail` into non-local impls.
+#![allow(non_local_definitions)]
+
 use failure::{Backtrace, Context, Fail};
 use std::convert::From;
 use std::fmt::{self, Display};
 use std::result;
 
-pub type Result<T> = result::Result<T, ExitFailure>;
+pub type Result<T> = result::Result<T, failure::Error>;
 
 #[derive(Debug, Fail, PartialEq, Clone)]
 pub enum ErrorKind {
     #[fail(display = "failed to setup logging")]
     LoggingSetupFailed,
 
+    #[fail(display = "failed to open log file: {}", _0)]
+    LoggingFileCanNotOpen(String),
+
+    #[fail(display = "failed to rotate log file: {}", _0)]
+    LoggingFileCanNotRotate(String),
+
     #[fail(display = "failed to retrieve current directory")]
     CurrentDir,
 
+    #[fail(display = "failed to create state directory: {}", _0)]
+    StateDirCanNotCreate(String),
+
+    #[fail(display = "failed to write cache entry: {}", _0)]
+    CacheCanNotWrite(String),
+
+    #[fail(display = "failed to read cache directory: {}", _0)]
+    CacheCanNotRead(String),
+
+    #[fail(display = "failed to write audit log: {}", _0)]
+    AuditLogCanNotWrite(String),
+
+    #[fail(display = "failed to read config file: {}", _0)]
+    ConfigCanNotRead(String),
+
+    #[fail(display = "failed to parse config file: {}", _0)]
+    ConfigCanNotParse(String),
+
+    #[fail(display = "failed to run command of config option: {}", _0)]
+    ConfigCommandFailed(String),
+
+    #[fail(display = "failed to find profile in config file: {}", _0)]
+    ConfigProfileNotFound(String),
+
+    #[fail(display = "failed to find command of profile: {}", _0)]
+    ConfigProfileWithoutCommand(String),
+
+    #[fail(display = "failed to write config file: {}", _0)]
+    ConfigCanNotWrite(String),
+
+    #[fail(display = "profile already exists in config file: {}", _0)]
+    ConfigProfileExists(String),
+
+    #[fail(display = "failed to read watched directory: {}", _0)]
+    WatchDirCanNotRead(String),
+
+    #[fail(display = "failed to archive synced file: {}", _0)]
+    WatchCanNotArchive(String),
+
+    #[fail(display = "balance check failed, not syncing: {}", _0)]
+    BalanceMismatch(String),
+
+    #[fail(display = "failed to read plan file: {}", _0)]
+    PlanCanNotRead(String),
+
+    #[fail(display = "failed to parse plan file: {}", _0)]
+    PlanCanNotParse(String),
+
+    #[fail(display = "failed to write plan file: {}", _0)]
+    PlanCanNotWrite(String),
+
+    #[fail(display = "failed to read state file: {}", _0)]
+    StateCanNotRead(String),
+
+    #[fail(display = "failed to write state file: {}", _0)]
+    StateCanNotWrite(String),
+
+    #[fail(display = "failed to read state passphrase")]
+    StatePassphraseCanNotRead,
+
+    #[fail(display = "failed to decrypt state (wrong passphrase or damaged file)")]
+    StateCanNotDecrypt,
+
+    #[fail(display = "failed to encrypt state")]
+    StateCanNotEncrypt,
+
+    #[fail(
+        display = "no --sync-from provided and no previous sync of account {} found",
+        _0
+    )]
+    SyncFromUnknown(String),
+
     #[fail(display = "failed to write to token file")]
     N26WritingToTokenFile,
 
+    #[fail(display = "failed to record HTTP interaction to: {}", _0)]
+    HttpRecordCanNotWrite(String),
+
+    #[fail(display = "failed to read recorded HTTP interaction from: {}", _0)]
+    HttpReplayCanNotRead(String),
+
+    #[fail(display = "no recorded HTTP interaction left for: {}", _0)]
+    HttpReplayMissing(String),
+
+    #[fail(
+        display = "recorded HTTP interaction ({}) does not match request ({})",
+        _0, _1
+    )]
+    HttpReplayMismatch(String, String),
+
     #[fail(display = "failed to parse option {}", _0)]
     ArgParse(String),
 
+    #[fail(display = "failed to parse amount: {}", _0)]
+    AmountParse(String),
+
+    #[fail(display = "failed to parse date: {}", _0)]
+    DateParse(String),
+
     #[fail(
         display = "failed to parse --days-to-sync option {} \n    => {}",
         _0, _1
@@ -42,29 +143,131 @@ pub enum ErrorKind {
     ArgParseCategoryMappingCanNotRead(String),
 
     #[fail(
-        display = "failed to parse file as JSON provided via --category-rules option: {}",
+        display = "failed to read file provided via --category-rules option: {}",
         _0
     )]
     ArgParseCategoryRulesCanNotRead(String),
 
     #[fail(
-        display = "failed to read file provided via --category-rules option: {}",
+        display = "failed to parse file provided via --category-rules option: {}",
         _0
     )]
     ArgParseCategoryRulesCanNotParse(String),
 
+    #[fail(
+        display = "failed to write file provided via --category-rules option: {}",
+        _0
+    )]
+    RulesCanNotWrite(String),
+
+    #[fail(display = "{}", _0)]
+    RulesSyntax(String),
+
+    #[fail(display = "unknown category in --category-rules, {}", _0)]
+    RulesUnknownCategory(String),
+
     #[fail(
         display = "failed to parse file as JSON provided via --category-mapping option: {}",
         _0
     )]
     ArgParseCategoryMappingCanNotParse(String),
 
+    #[fail(display = "file {} already exists, use --force to overwrite it", _0)]
+    OutputFileExists(String),
+
+    #[fail(display = "failed to write to file: {}", _0)]
+    OutputFileCanNotWrite(String),
+
+    #[fail(display = "failed to generate category mapping")]
+    GenerateMapping,
+
+    #[fail(display = "failed to generate category rules")]
+    SuggestRules,
+
+    #[fail(
+        display = "failed to read file provided via --payee-mapping option: {}",
+        _0
+    )]
+    ArgParsePayeeMappingCanNotRead(String),
+
+    #[fail(
+        display = "failed to parse file as JSON provided via --payee-mapping option: {}",
+        _0
+    )]
+    ArgParsePayeeMappingCanNotParse(String),
+
+    #[fail(
+        display = "failed to read file provided via --split-config option: {}",
+        _0
+    )]
+    ArgParseSplitConfigCanNotRead(String),
+
+    #[fail(
+        display = "failed to parse file provided via --split-config option: {} (expected a share between 0 and 100 and either a category or a budget and account)",
+        _0
+    )]
+    ArgParseSplitConfigCanNotParse(String),
+
+    #[fail(display = "failed to find the \"Inflow: Ready to Assign\" category in the budget.")]
+    YNABInflowCategoryMissing,
+
+    #[fail(display = "fee category ({}) does not exist in the budget.", _0)]
+    WrongFeeCategory(String),
+
+    #[fail(display = "split category ({}) does not exist in the budget.", _0)]
+    WrongSplitCategory(String),
+
+    #[fail(display = "failed to parse --memo-template {}\n    => {}", _0, _1)]
+    ArgParseMemoTemplate(String, String),
+
+    #[fail(
+        display = "YNAB token is invalid, expired or revoked ({}).\n    => Create a new personal access token under \"Developer Settings\" in YNAB and pass it via --ynab-token.",
+        _0
+    )]
+    YNABUnauthorized(String),
+
+    #[fail(
+        display = "YNAB subscription has lapsed ({}).\n    => The API only accepts requests for accounts with an active subscription.",
+        _0
+    )]
+    YNABSubscriptionLapsed(String),
+
+    #[fail(
+        display = "YNAB could not find the requested resource ({}).\n    => Check --ynab-budget and --ynab-account, the budget or account might have been deleted.",
+        _0
+    )]
+    YNABNotFound(String),
+
+    #[fail(
+        display = "YNAB rate limit reached ({}).\n    => YNAB allows 200 requests per hour per token, try again later.",
+        _0
+    )]
+    YNABRateLimited(String),
+
     #[fail(display = "budget ({}) does not exists. ", _0)]
     WrongBudgetId(String),
 
     #[fail(display = "account ({}) does not exists. ", _0)]
     WrongAccountId(String),
 
+    #[fail(
+        display = "more than one budget is named \"{}\", use its id instead.",
+        _0
+    )]
+    AmbiguousBudget(String),
+
+    #[fail(
+        display = "more than one account is named \"{}\", use its id instead.",
+        _0
+    )]
+    AmbiguousAccount(String),
+
+    #[fail(
+        display = "category ({}) provided via --default-category does not exists. ",
+        _0
+    )]
+    WrongDefaultCategory(String),
+
     #[fail(display = "failed to parse type goal_type from YNAB category")]
     YNABCategoryGoalTypeParse,
 
@@ -104,6 +307,12 @@ pub enum ErrorKind {
     #[fail(display = "failed to parse budgets fetched from YNAB: {}", _0)]
     YNABGetBudgetsParse(String),
 
+    #[fail(display = "failed to fetch user from YNAB")]
+    YNABGetUser,
+
+    #[fail(display = "failed to fetch user from YNAB: {} {}", _0, _1)]
+    YNABGetUserHttp(u16, String),
+
     #[fail(display = "failed to fetch transactions from YNAB")]
     YNABGetTransactions,
 
@@ -113,12 +322,147 @@ pub enum ErrorKind {
     #[fail(display = "failed to parse transactions fetched from YNAB: {}", _0)]
     YNABGetTransactionsParse(String),
 
+    #[fail(display = "failed to fetch budget month from YNAB")]
+    YNABGetMonth,
+
+    #[fail(display = "failed to fetch budget month from YNAB: {} {}", _0, _1)]
+    YNABGetMonthHttp(u16, String),
+
+    #[fail(display = "failed to parse budget month fetched from YNAB: {}", _0)]
+    YNABGetMonthParse(String),
+
+    #[fail(display = "failed to fetch payees from YNAB")]
+    YNABGetPayees,
+
+    #[fail(display = "failed to fetch payees from YNAB: {} {}", _0, _1)]
+    YNABGetPayeesHttp(u16, String),
+
+    #[fail(display = "failed to parse payees fetched from YNAB: {}", _0)]
+    YNABGetPayeesParse(String),
+
+    #[fail(display = "failed to save payee to YNAB")]
+    YNABSavePayee,
+
+    #[fail(display = "failed to save payee to YNAB: {} {}", _0, _1)]
+    YNABSavePayeeHttp(u16, String),
+
     #[fail(display = "failed to save transactions to YNAB")]
     YNABSaveTransactions,
 
     #[fail(display = "failed to save transactions to YNAB: {} {}", _0, _1)]
     YNABSaveTransactionsHttp(u16, String),
 
+    #[fail(
+        display = "failed to parse response of saving transactions to YNAB: {}",
+        _0
+    )]
+    YNABSaveTransactionsParse(String),
+
+    #[fail(
+        display = "saved {} of {} transactions to YNAB before failing, syncing again saves the rest",
+        _0, _1
+    )]
+    YNABSaveTransactionsPartial(usize, usize),
+
+    #[fail(display = "failed to delete transaction from YNAB")]
+    YNABDeleteTransaction,
+
+    #[fail(display = "failed to delete transaction from YNAB: {} {}", _0, _1)]
+    YNABDeleteTransactionHttp(u16, String),
+
+    #[fail(display = "failed to get account from YNAB")]
+    YNABGetAccount,
+
+    #[fail(display = "failed to get account from YNAB: {} {}", _0, _1)]
+    YNABGetAccountHttp(u16, String),
+
+    #[fail(display = "failed to parse response to get account from YNAB: {}", _0)]
+    YNABGetAccountParse(String),
+
+    #[fail(display = "failed to update budgeted amount in YNAB")]
+    YNABUpdateMonthCategory,
+
+    #[fail(display = "failed to update budgeted amount in YNAB: {} {}", _0, _1)]
+    YNABUpdateMonthCategoryHttp(u16, String),
+
+    #[fail(display = "failed to parse category updated in YNAB: {}", _0)]
+    YNABUpdateMonthCategoryParse(String),
+
+    #[fail(display = "failed to get transaction from YNAB")]
+    YNABGetTransaction,
+
+    #[fail(display = "failed to get transaction from YNAB: {} {}", _0, _1)]
+    YNABGetTransactionHttp(u16, String),
+
+    #[fail(display = "failed to parse transaction fetched from YNAB: {}", _0)]
+    YNABGetTransactionParse(String),
+
+    #[fail(display = "failed to update transaction in YNAB")]
+    YNABUpdateTransaction,
+
+    #[fail(display = "failed to update transaction in YNAB: {} {}", _0, _1)]
+    YNABUpdateTransactionHttp(u16, String),
+
+    #[fail(display = "failed to parse transaction updated in YNAB: {}", _0)]
+    YNABUpdateTransactionParse(String),
+
+    #[fail(display = "failed to get scheduled transactions from YNAB")]
+    YNABGetScheduledTransactions,
+
+    #[fail(
+        display = "failed to get scheduled transactions from YNAB: {} {}",
+        _0, _1
+    )]
+    YNABGetScheduledTransactionsHttp(u16, String),
+
+    #[fail(
+        display = "failed to parse scheduled transactions fetched from YNAB: {}",
+        _0
+    )]
+    YNABGetScheduledTransactionsParse(String),
+
+    #[fail(display = "failed to create scheduled transaction in YNAB")]
+    YNABCreateScheduledTransaction,
+
+    #[fail(
+        display = "failed to create scheduled transaction in YNAB: {} {}",
+        _0, _1
+    )]
+    YNABCreateScheduledTransactionHttp(u16, String),
+
+    #[fail(
+        display = "failed to parse scheduled transaction created in YNAB: {}",
+        _0
+    )]
+    YNABCreateScheduledTransactionParse(String),
+
+    #[fail(display = "failed to send Matrix message via: {}", _0)]
+    MatrixSendMessage(String),
+
+    #[fail(display = "failed to send Matrix message: {} {}", _0, _1)]
+    MatrixSendMessageHttp(u16, String),
+
+    #[fail(display = "self test failed: {}", _0)]
+    SelftestFailed(String),
+
+    #[fail(display = "{} doctor check(s) failed", _0)]
+    DoctorFailed(usize),
+
+    #[fail(display = "{} problem(s) found in the rule files", _0)]
+    RulesLintFailed(usize),
+
+    #[fail(display = "failed to sync profile: {}", _0)]
+    ProfileFailed(String),
+
+    #[fail(display = "{} profile(s) failed to sync", _0)]
+    ProfilesFailed(usize),
+
+    #[fail(
+        display = "--jobs {} needs --yes, profiles synced at the same time can't ask for confirmation",
+        _0
+    )]
+    ProfilesJobsWithoutYes(usize),
+
     #[fail(display = "failed to open N26 token data file")]
     N26TokenDataFileCanNotRead,
 
@@ -149,6 +493,14 @@ pub enum ErrorKind {
     #[fail(display = "failed to authenticate against N26")]
     N26Authenticate,
 
+    #[fail(
+        display = "N26 login needs an approval in the app, not waited for with --n26-mfa-timeout 0"
+    )]
+    N26MfaRequired,
+
+    #[fail(display = "N26 login wasn't approved in the app within {} seconds", _0)]
+    N26MfaTimeout(u64),
+
     #[fail(display = "failed to get categories from N26")]
     N26GetCategories,
 
@@ -158,6 +510,15 @@ pub enum ErrorKind {
     #[fail(display = "failed to get categories from N26: {}, {}", _0, _1)]
     N26GetCategoriesHttp(u16, String),
 
+    #[fail(display = "failed to get balance from N26")]
+    N26GetBalance,
+
+    #[fail(display = "failed to parse balance from N26: {}", _0)]
+    N26GetBalanceParse(String),
+
+    #[fail(display = "failed to get balance from N26: {}, {}", _0, _1)]
+    N26GetBalanceHttp(u16, String),
+
     #[fail(display = "failed to get transactions from N26")]
     N26GetTransactions,
 
@@ -172,6 +533,473 @@ pub enum ErrorKind {
 
     #[fail(display = "failed to parse transaction from: {}", _0)]
     IngDiBaCsvFileParse(String),
+
+    #[fail(display = "failed to parse transaction from: {}\n    => {}", _0, _1)]
+    IngDiBaCsvTransactionParse(String, String),
+
+    #[fail(
+        display = "balances in {} don't add up, rows are missing or changed\n    => {}",
+        _0, _1
+    )]
+    IngDiBaCsvBalanceMismatch(String, String),
+
+    #[fail(
+        display = "failed to read file provided via --csv-mapping option: {}",
+        _0
+    )]
+    ArgParseCsvMappingCanNotRead(String),
+
+    #[fail(
+        display = "failed to parse file as JSON provided via --csv-mapping option: {}",
+        _0
+    )]
+    ArgParseCsvMappingCanNotParse(String),
+
+    #[fail(
+        display = "column of --csv-mapping not found in the CSV header: {}",
+        _0
+    )]
+    CsvColumnNotFound(String),
+
+    #[fail(display = "failed to open a file provided via --csv option: {}", _0)]
+    CsvFileCanNotOpen(String),
+
+    #[fail(display = "failed to parse transactions from: {}", _0)]
+    CsvFileParse(String),
+
+    #[fail(display = "failed to parse transaction from: {}\n    => {}", _0, _1)]
+    CsvTransactionParse(String, String),
+
+    #[fail(display = "failed to read a file provided via --camt option: {}", _0)]
+    CamtFileCanNotRead(String),
+
+    #[fail(display = "failed to parse camt statement (XML) from: {}", _0)]
+    CamtFileParse(String),
+
+    #[fail(display = "failed to parse entry from: {}\n    => {}", _0, _1)]
+    CamtEntryParse(String, String),
+
+    #[fail(display = "failed to open Nordigen token data file")]
+    NordigenTokenDataFileCanNotRead,
+
+    #[fail(display = "failed to parse Nordigen token data file")]
+    NordigenTokenDataFileCanNotParse,
+
+    #[fail(display = "failed to write Nordigen token data file")]
+    NordigenWritingToTokenFile,
+
+    #[fail(display = "failed to authenticate against Nordigen")]
+    NordigenAuthenticate,
+
+    #[fail(display = "failed to authenticate against Nordigen: {}, {}", _0, _1)]
+    NordigenAuthenticateHttp(u16, String),
+
+    #[fail(display = "failed to parse token from Nordigen: {}", _0)]
+    NordigenAuthenticateParse(String),
+
+    #[fail(display = "failed to refresh token at Nordigen")]
+    NordigenRefreshToken,
+
+    #[fail(display = "failed to refresh token at Nordigen: {}, {}", _0, _1)]
+    NordigenRefreshTokenHttp(u16, String),
+
+    #[fail(display = "failed to parse refreshed token from Nordigen: {}", _0)]
+    NordigenRefreshTokenParse(String),
+
+    #[fail(display = "failed to get requisition from Nordigen")]
+    NordigenGetRequisition,
+
+    #[fail(display = "failed to get requisition from Nordigen: {}, {}", _0, _1)]
+    NordigenGetRequisitionHttp(u16, String),
+
+    #[fail(display = "failed to parse requisition from Nordigen: {}", _0)]
+    NordigenGetRequisitionParse(String),
+
+    #[fail(display = "failed to create requisition at Nordigen")]
+    NordigenCreateRequisition,
+
+    #[fail(display = "failed to create requisition at Nordigen: {}, {}", _0, _1)]
+    NordigenCreateRequisitionHttp(u16, String),
+
+    #[fail(display = "failed to parse created requisition from Nordigen: {}", _0)]
+    NordigenCreateRequisitionParse(String),
+
+    #[fail(display = "access to the bank was not given, try again at: {}", _0)]
+    NordigenRequisitionNotLinked(String),
+
+    #[fail(display = "failed to get account from Nordigen")]
+    NordigenGetAccount,
+
+    #[fail(display = "failed to get account from Nordigen: {}, {}", _0, _1)]
+    NordigenGetAccountHttp(u16, String),
+
+    #[fail(display = "failed to parse account from Nordigen: {}", _0)]
+    NordigenGetAccountParse(String),
+
+    #[fail(display = "failed to get transactions from Nordigen")]
+    NordigenGetTransactions,
+
+    #[fail(display = "failed to get transactions from Nordigen: {}, {}", _0, _1)]
+    NordigenGetTransactionsHttp(u16, String),
+
+    #[fail(display = "failed to parse transactions from Nordigen: {}", _0)]
+    NordigenGetTransactionsParse(String),
+
+    #[fail(display = "account provided via --nordigen-account not found: {}", _0)]
+    NordigenAccountNotFound(String),
+
+    #[fail(
+        display = "access to more than one account was given, pick one with --nordigen-account"
+    )]
+    NordigenAccountAmbiguous,
+
+    #[fail(display = "failed to open Plaid token data file")]
+    PlaidTokenDataFileCanNotRead,
+
+    #[fail(display = "failed to parse Plaid token data file")]
+    PlaidTokenDataFileCanNotParse,
+
+    #[fail(display = "failed to write Plaid token data file")]
+    PlaidWritingToTokenFile,
+
+    #[fail(display = "failed to create link token at Plaid")]
+    PlaidCreateLinkToken,
+
+    #[fail(display = "failed to create link token at Plaid: {}, {}", _0, _1)]
+    PlaidCreateLinkTokenHttp(u16, String),
+
+    #[fail(display = "failed to parse link token from Plaid: {}", _0)]
+    PlaidCreateLinkTokenParse(String),
+
+    #[fail(display = "failed to get link token from Plaid")]
+    PlaidGetLinkToken,
+
+    #[fail(display = "failed to get link token from Plaid: {}, {}", _0, _1)]
+    PlaidGetLinkTokenHttp(u16, String),
+
+    #[fail(display = "failed to parse link token from Plaid: {}", _0)]
+    PlaidGetLinkTokenParse(String),
+
+    #[fail(display = "failed to exchange public token at Plaid")]
+    PlaidExchangePublicToken,
+
+    #[fail(display = "failed to exchange public token at Plaid: {}, {}", _0, _1)]
+    PlaidExchangePublicTokenHttp(u16, String),
+
+    #[fail(display = "failed to parse access token from Plaid: {}", _0)]
+    PlaidExchangePublicTokenParse(String),
+
+    #[fail(display = "failed to get accounts from Plaid")]
+    PlaidGetAccounts,
+
+    #[fail(display = "failed to get accounts from Plaid: {}, {}", _0, _1)]
+    PlaidGetAccountsHttp(u16, String),
+
+    #[fail(display = "failed to parse accounts from Plaid: {}", _0)]
+    PlaidGetAccountsParse(String),
+
+    #[fail(display = "failed to sync transactions from Plaid")]
+    PlaidSyncTransactions,
+
+    #[fail(display = "failed to sync transactions from Plaid: {}, {}", _0, _1)]
+    PlaidSyncTransactionsHttp(u16, String),
+
+    #[fail(display = "failed to parse transactions from Plaid: {}", _0)]
+    PlaidSyncTransactionsParse(String),
+
+    #[fail(display = "linking the bank was not completed, try again at: {}", _0)]
+    PlaidLinkNotCompleted(String),
+
+    #[fail(display = "account provided via --plaid-account not found: {}", _0)]
+    PlaidAccountNotFound(String),
+
+    #[fail(display = "access to more than one account was given, pick one with --plaid-account")]
+    PlaidAccountAmbiguous,
+
+    #[fail(display = "failed to open a file provided via --csv option: {}", _0)]
+    RevolutCsvFileCanNotOpen(String),
+
+    #[fail(display = "failed to parse transactions from: {}", _0)]
+    RevolutCsvFileParse(String),
+
+    #[fail(display = "failed to parse transaction from: {}\n    => {}", _0, _1)]
+    RevolutCsvTransactionParse(String, String),
+
+    #[fail(display = "failed to read a file provided via --amex option: {}", _0)]
+    AmexFileCanNotRead(String),
+
+    #[fail(display = "failed to parse transactions (CSV or QFX) from: {}", _0)]
+    AmexFileParse(String),
+
+    #[fail(display = "failed to parse transaction from: {}\n    => {}", _0, _1)]
+    AmexTransactionParse(String, String),
+
+    #[fail(display = "failed to get exchange rate")]
+    ExchangeRateGet,
+
+    #[fail(display = "failed to get exchange rate: {}, {}", _0, _1)]
+    ExchangeRateGetHttp(u16, String),
+
+    #[fail(display = "failed to parse exchange rate: {}", _0)]
+    ExchangeRateGetParse(String),
+}
+
+impl ErrorKind {
+    /// Name of the variant (eg. `YNABGetCategoriesHttp`), stable enough to be
+    /// matched on by scripts.
+    pub fn name(&self) -> String {
+        let debug = format!("{:?}", self);
+        debug.split(['(', ' ']).next().unwrap_or("").to_string()
+    }
+
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            ErrorKind::YNABGetCategoriesHttp(status, _)
+            | ErrorKind::YNABGetAccountsHttp(status, _)
+            | ErrorKind::YNABGetBudgetsHttp(status, _)
+            | ErrorKind::YNABGetUserHttp(status, _)
+            | ErrorKind::YNABGetTransactionsHttp(status, _)
+            | ErrorKind::YNABGetMonthHttp(status, _)
+            | ErrorKind::YNABGetPayeesHttp(status, _)
+            | ErrorKind::YNABSavePayeeHttp(status, _)
+            | ErrorKind::YNABSaveTransactionsHttp(status, _)
+            | ErrorKind::YNABDeleteTransactionHttp(status, _)
+            | ErrorKind::YNABGetAccountHttp(status, _)
+            | ErrorKind::YNABUpdateMonthCategoryHttp(status, _)
+            | ErrorKind::YNABGetTransactionHttp(status, _)
+            | ErrorKind::YNABUpdateTransactionHttp(status, _)
+            | ErrorKind::YNABGetScheduledTransactionsHttp(status, _)
+            | ErrorKind::YNABCreateScheduledTransactionHttp(status, _)
+            | ErrorKind::MatrixSendMessageHttp(status, _)
+            | ErrorKind::N26GetCategoriesHttp(status, _)
+            | ErrorKind::N26GetBalanceHttp(status, _)
+            | ErrorKind::N26GetTransactionsHttp(status, _)
+            | ErrorKind::NordigenAuthenticateHttp(status, _)
+            | ErrorKind::NordigenRefreshTokenHttp(status, _)
+            | ErrorKind::NordigenGetRequisitionHttp(status, _)
+            | ErrorKind::NordigenCreateRequisitionHttp(status, _)
+            | ErrorKind::NordigenGetAccountHttp(status, _)
+            | ErrorKind::NordigenGetTransactionsHttp(status, _)
+            | ErrorKind::PlaidCreateLinkTokenHttp(status, _)
+            | ErrorKind::PlaidGetLinkTokenHttp(status, _)
+            | ErrorKind::PlaidExchangePublicTokenHttp(status, _)
+            | ErrorKind::PlaidGetAccountsHttp(status, _)
+            | ErrorKind::PlaidSyncTransactionsHttp(status, _)
+            | ErrorKind::ExchangeRateGetHttp(status, _) => Some(*status),
+            ErrorKind::YNABUnauthorized(_) => Some(401),
+            ErrorKind::YNABSubscriptionLapsed(_) => Some(403),
+            ErrorKind::YNABNotFound(_) => Some(404),
+            ErrorKind::YNABRateLimited(_) => Some(429),
+            _ => None,
+        }
+    }
+
+    pub fn http_body(&self) -> Option<&str> {
+        match self {
+            ErrorKind::YNABGetCategoriesHttp(_, body)
+            | ErrorKind::YNABGetAccountsHttp(_, body)
+            | ErrorKind::YNABGetBudgetsHttp(_, body)
+            | ErrorKind::YNABGetUserHttp(_, body)
+            | ErrorKind::YNABGetTransactionsHttp(_, body)
+            | ErrorKind::YNABGetMonthHttp(_, body)
+            | ErrorKind::YNABGetPayeesHttp(_, body)
+            | ErrorKind::YNABSavePayeeHttp(_, body)
+            | ErrorKind::YNABSaveTransactionsHttp(_, body)
+            | ErrorKind::YNABDeleteTransactionHttp(_, body)
+            | ErrorKind::YNABGetAccountHttp(_, body)
+            | ErrorKind::YNABUpdateMonthCategoryHttp(_, body)
+            | ErrorKind::YNABGetTransactionHttp(_, body)
+            | ErrorKind::YNABUpdateTransactionHttp(_, body)
+            | ErrorKind::YNABGetScheduledTransactionsHttp(_, body)
+            | ErrorKind::YNABCreateScheduledTransactionHttp(_, body)
+            | ErrorKind::MatrixSendMessageHttp(_, body)
+            | ErrorKind::N26GetCategoriesHttp(_, body)
+            | ErrorKind::N26GetBalanceHttp(_, body)
+            | ErrorKind::N26GetTransactionsHttp(_, body)
+            | ErrorKind::NordigenAuthenticateHttp(_, body)
+            | ErrorKind::NordigenRefreshTokenHttp(_, body)
+            | ErrorKind::NordigenGetRequisitionHttp(_, body)
+            | ErrorKind::NordigenCreateRequisitionHttp(_, body)
+            | ErrorKind::NordigenGetAccountHttp(_, body)
+            | ErrorKind::NordigenGetTransactionsHttp(_, body)
+            | ErrorKind::PlaidCreateLinkTokenHttp(_, body)
+            | ErrorKind::PlaidGetLinkTokenHttp(_, body)
+            | ErrorKind::PlaidExchangePublicTokenHttp(_, body)
+            | ErrorKind::PlaidGetAccountsHttp(_, body)
+            | ErrorKind::PlaidSyncTransactionsHttp(_, body)
+            | ErrorKind::ExchangeRateGetHttp(_, body) => Some(body),
+            _ => None,
+        }
+    }
+
+    /// Source transaction which caused the error, if known.
+    pub fn transaction(&self) -> Option<&str> {
+        match self {
+            ErrorKind::IngDiBaCsvTransactionParse(_, transaction)
+            | ErrorKind::CsvTransactionParse(_, transaction)
+            | ErrorKind::CamtEntryParse(_, transaction)
+            | ErrorKind::RevolutCsvTransactionParse(_, transaction)
+            | ErrorKind::AmexTransactionParse(_, transaction) => Some(transaction),
+            _ => None,
+        }
+    }
+
+    /// Exit code for errors a caller (eg. a cron job) may want to handle
+    /// differently, other errors exit with 1.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            // EX_TEMPFAIL, retrying later (with an approval) may work
+            ErrorKind::N26MfaRequired | ErrorKind::N26MfaTimeout(_) => Some(75),
+            _ => None,
+        }
+    }
+
+    /// API endpoint which was being called when the error happened.
+    pub fn endpoint(&self) -> Option<&'static str> {
+        match self {
+            ErrorKind::YNABGetCategories
+            | ErrorKind::YNABGetCategoriesHttp(_, _)
+            | ErrorKind::YNABGetCategoriesParse(_) => Some("GET /budgets/{budget_id}/categories"),
+            ErrorKind::YNABGetAccounts
+            | ErrorKind::YNABGetAccountsHttp(_, _)
+            | ErrorKind::YNABGetAccountsParse(_) => Some("GET /budgets/{budget_id}/accounts"),
+            ErrorKind::YNABGetBudgets
+            | ErrorKind::YNABGetBudgetsHttp(_, _)
+            | ErrorKind::YNABGetBudgetsParse(_) => Some("GET /budgets"),
+            ErrorKind::YNABGetUser | ErrorKind::YNABGetUserHttp(_, _) => Some("GET /user"),
+            ErrorKind::YNABGetTransactions
+            | ErrorKind::YNABGetTransactionsHttp(_, _)
+            | ErrorKind::YNABGetTransactionsParse(_) => {
+                Some("GET /budgets/{budget_id}/accounts/{account_id}/transactions")
+            }
+            ErrorKind::YNABGetMonth
+            | ErrorKind::YNABGetMonthHttp(_, _)
+            | ErrorKind::YNABGetMonthParse(_) => Some("GET /budgets/{budget_id}/months/{month}"),
+            ErrorKind::YNABGetPayees
+            | ErrorKind::YNABGetPayeesHttp(_, _)
+            | ErrorKind::YNABGetPayeesParse(_) => Some("GET /budgets/{budget_id}/payees"),
+            ErrorKind::YNABSavePayee | ErrorKind::YNABSavePayeeHttp(_, _) => {
+                Some("PATCH /budgets/{budget_id}/payees/{payee_id}")
+            }
+            ErrorKind::YNABSaveTransactions
+            | ErrorKind::YNABSaveTransactionsHttp(_, _)
+            | ErrorKind::YNABSaveTransactionsParse(_)
+            | ErrorKind::YNABSaveTransactionsPartial(_, _) => {
+                Some("POST|PATCH /budgets/{budget_id}/transactions")
+            }
+            ErrorKind::YNABDeleteTransaction | ErrorKind::YNABDeleteTransactionHttp(_, _) => {
+                Some("DELETE /budgets/{budget_id}/transactions/{transaction_id}")
+            }
+            ErrorKind::YNABCreateScheduledTransaction
+            | ErrorKind::YNABCreateScheduledTransactionHttp(_, _)
+            | ErrorKind::YNABCreateScheduledTransactionParse(_) => {
+                Some("POST /budgets/{budget_id}/scheduled_transactions")
+            }
+            ErrorKind::YNABGetScheduledTransactions
+            | ErrorKind::YNABGetScheduledTransactionsHttp(_, _)
+            | ErrorKind::YNABGetScheduledTransactionsParse(_) => {
+                Some("GET /budgets/{budget_id}/scheduled_transactions")
+            }
+            ErrorKind::YNABUpdateTransaction
+            | ErrorKind::YNABUpdateTransactionHttp(_, _)
+            | ErrorKind::YNABUpdateTransactionParse(_) => {
+                Some("PUT /budgets/{budget_id}/transactions/{transaction_id}")
+            }
+            ErrorKind::YNABGetTransaction
+            | ErrorKind::YNABGetTransactionHttp(_, _)
+            | ErrorKind::YNABGetTransactionParse(_) => {
+                Some("GET /budgets/{budget_id}/transactions/{transaction_id}")
+            }
+            ErrorKind::YNABUpdateMonthCategory
+            | ErrorKind::YNABUpdateMonthCategoryHttp(_, _)
+            | ErrorKind::YNABUpdateMonthCategoryParse(_) => {
+                Some("PATCH /budgets/{budget_id}/months/{month}/categories/{category_id}")
+            }
+            ErrorKind::YNABGetAccount
+            | ErrorKind::YNABGetAccountHttp(_, _)
+            | ErrorKind::YNABGetAccountParse(_) => {
+                Some("GET /budgets/{budget_id}/accounts/{account_id}")
+            }
+            ErrorKind::N26AuthenticateNew | ErrorKind::N26AuthenticateNewParse(_) => {
+                Some("POST /oauth2/token")
+            }
+            ErrorKind::N26AuthenticateMfaApproval => Some("POST /api/mfa/challenge"),
+            ErrorKind::N26AuthenticateCompleteMFA
+            | ErrorKind::N26AuthenticateCompleteMFAParse(_)
+            | ErrorKind::N26AuthenticateRefreshToken
+            | ErrorKind::N26AuthenticateRefreshTokenParse(_) => Some("POST /oauth/token"),
+            ErrorKind::N26GetCategories
+            | ErrorKind::N26GetCategoriesHttp(_, _)
+            | ErrorKind::N26GetCategoriesParse(_) => Some("GET /api/smrt/categories"),
+            ErrorKind::N26GetBalance
+            | ErrorKind::N26GetBalanceHttp(_, _)
+            | ErrorKind::N26GetBalanceParse(_) => Some("GET /api/accounts"),
+            ErrorKind::N26GetTransactions
+            | ErrorKind::N26GetTransactionsHttp(_, _)
+            | ErrorKind::N26GetTransactionsParse(_) => Some("GET /api/smrt/transactions"),
+            ErrorKind::NordigenAuthenticate
+            | ErrorKind::NordigenAuthenticateHttp(_, _)
+            | ErrorKind::NordigenAuthenticateParse(_) => Some("POST /token/new/"),
+            ErrorKind::NordigenRefreshToken
+            | ErrorKind::NordigenRefreshTokenHttp(_, _)
+            | ErrorKind::NordigenRefreshTokenParse(_) => Some("POST /token/refresh/"),
+            ErrorKind::NordigenGetRequisition
+            | ErrorKind::NordigenGetRequisitionHttp(_, _)
+            | ErrorKind::NordigenGetRequisitionParse(_) => {
+                Some("GET /requisitions/{requisition_id}/")
+            }
+            ErrorKind::NordigenCreateRequisition
+            | ErrorKind::NordigenCreateRequisitionHttp(_, _)
+            | ErrorKind::NordigenCreateRequisitionParse(_) => Some("POST /requisitions/"),
+            ErrorKind::NordigenGetAccount
+            | ErrorKind::NordigenGetAccountHttp(_, _)
+            | ErrorKind::NordigenGetAccountParse(_) => Some("GET /accounts/{account_id}/details/"),
+            ErrorKind::NordigenGetTransactions
+            | ErrorKind::NordigenGetTransactionsHttp(_, _)
+            | ErrorKind::NordigenGetTransactionsParse(_) => {
+                Some("GET /accounts/{account_id}/transactions/")
+            }
+            ErrorKind::PlaidCreateLinkToken
+            | ErrorKind::PlaidCreateLinkTokenHttp(_, _)
+            | ErrorKind::PlaidCreateLinkTokenParse(_) => Some("POST /link/token/create"),
+            ErrorKind::PlaidGetLinkToken
+            | ErrorKind::PlaidGetLinkTokenHttp(_, _)
+            | ErrorKind::PlaidGetLinkTokenParse(_) => Some("POST /link/token/get"),
+            ErrorKind::PlaidExchangePublicToken
+            | ErrorKind::PlaidExchangePublicTokenHttp(_, _)
+            | ErrorKind::PlaidExchangePublicTokenParse(_) => {
+                Some("POST /item/public_token/exchange")
+            }
+            ErrorKind::PlaidGetAccounts
+            | ErrorKind::PlaidGetAccountsHttp(_, _)
+            | ErrorKind::PlaidGetAccountsParse(_) => Some("POST /accounts/get"),
+            ErrorKind::PlaidSyncTransactions
+            | ErrorKind::PlaidSyncTransactionsHttp(_, _)
+            | ErrorKind::PlaidSyncTransactionsParse(_) => Some("POST /transactions/sync"),
+            ErrorKind::ExchangeRateGet
+            | ErrorKind::ExchangeRateGetHttp(_, _)
+            | ErrorKind::ExchangeRateGetParse(_) => Some("GET /{date}"),
+            _ => None,
+        }
+    }
+}
+
+/// Find the first `ErrorKind` in the chain of causes of `error`.
+pub fn find_kind(error: &failure::Error) -> Option<&ErrorKind> {
+    find_kinds(error).into_iter().next()
+}
+
+/// All `ErrorKind`s in the chain of `error`, outermost first.
+pub fn find_kinds(error: &failure::Error) -> Vec<&ErrorKind> {
+    error
+        .iter_chain()
+        .filter_map(|fail| {
+            fail.downcast_ref::<ErrorKind>().or_else(|| {
+                fail.downcast_ref::<Context<ErrorKind>>()
+                    .map(|x| x.get_context())
+            })
+        })
+        .collect()
 }
 
 #[derive(Debug)]
@@ -181,7 +1009,7 @@ pub struct Error {
 
 impl Error {
     pub fn kind(&self) -> &ErrorKind {
-        &*self.inner.get_context()
+        self.inner.get_context()
     }
 }
 
@@ -0,0 +1,71 @@
+// Explain mode
+//
+// With `--explain` every converted transaction is printed together with the
+// decisions which led to its category: the rules or mapping entries that
+// were evaluated and why they did or didn't apply, and the fallbacks (payee
+// history, --default-category, income, transfers) that were used.
+
+use crate::ynab::{Category, Transaction};
+use std::collections::HashMap;
+
+/// Decisions taken while converting one transaction.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    enabled: bool,
+    steps: Vec<String>,
+}
+
+impl Trace {
+    pub fn new(enabled: bool) -> Self {
+        Trace {
+            enabled,
+            steps: vec![],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record a step, `step` is only called with `--explain`.
+    pub fn add<F: FnOnce() -> String>(&mut self, step: F) {
+        if self.enabled {
+            self.steps.push(step());
+        }
+    }
+
+    /// Print `transaction` with the recorded steps and the resulting
+    /// category, looked up by id in `categories`.
+    pub fn print(&self, transaction: &Transaction, categories: &HashMap<String, Category>) {
+        if !self.enabled {
+            return;
+        }
+        println!(
+            " - | {} | {} | {:>+10.2} EUR | {}",
+            transaction.date,
+            transaction.payee_name.clone().unwrap_or_default(),
+            (transaction.amount as f32 / 1000.0),
+            transaction.memo.clone().unwrap_or_default(),
+        );
+        for step in &self.steps {
+            println!("     {}", step);
+        }
+        let category = match &transaction.category_id {
+            Some(id) => categories
+                .get(id)
+                .map(|x| x.name.clone())
+                .unwrap_or_else(|| id.clone()),
+            None if transaction.subtransactions.is_empty() => "none".to_string(),
+            None => "split".to_string(),
+        };
+        println!(
+            "     => category: {}{}",
+            category,
+            if transaction.approved {
+                ""
+            } else {
+                " (needs review)"
+            }
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use strsim::jaro_winkler;
+
+/// Similarity of two names in the range `0.0..=1.0`.
+///
+/// Combines Jaro-Winkler similarity of the whole strings with word overlap,
+/// so that eg. `Food & Groceries` and `Groceries` are considered close.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+
+    let words = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|x| x.len() > 2)
+            .map(String::from)
+            .collect()
+    };
+    let a_words = words(&a);
+    let b_words = words(&b);
+    let shared = a_words.iter().filter(|x| b_words.contains(x)).count();
+    let overlap = if a_words.is_empty() || b_words.is_empty() {
+        0.0
+    } else {
+        shared as f64 / a_words.len().min(b_words.len()) as f64
+    };
+
+    jaro_winkler(&a, &b).max(overlap)
+}
+
+/// Return the most similar candidate to `name` together with its score.
+pub fn best_match<'a, I>(name: &str, candidates: I) -> Option<(&'a str, f64)>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .map(|x| (x, similarity(name, x)))
+        .fold(None, |best, (x, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((x, score)),
+        })
+}
@@ -0,0 +1,865 @@
+// Shared HTTP layer
+//
+// All requests (to YNAB, N26, Nordigen, Plaid, ...) go through
+// `Request::send` which takes care of logging request/response bodies (see
+// `logging::HTTP_TARGET`), of recording/replaying HTTP sessions
+// (`--record`/`--replay`) and of timeouts, proxy, user agent and extra
+// headers (`--http-*`). Requests share one client (and its connections)
+// unless they're given their own, see `Client`.
+//
+// Transient failures are retried (`--http-retries`) with exponential backoff
+// and jitter: rate limited (429) and unavailable (503) responses always, other
+// server errors and failed connections only for idempotent requests, so
+// nothing is created twice. A `Retry-After` of the API is waited for instead,
+// unless it's longer than `--http-max-retry-delay` (eg. YNAB's hourly limit of
+// 200 requests), then the request fails right away.
+//
+// The layer is blocking, independent requests are sent concurrently from
+// threads with `join`. With the `async` feature requests can also be sent
+// with `Request::send_async` on tokio and reqwest's async client, which the
+// `async fn` variants of the `YNAB` and `N26` getters use. Both ways share
+// everything but the client: options, retries, recording and replaying.
+//
+// The last rate limit usage an API reported is kept in the state directory
+// (`rate_limits`), so processes syncing at the same time (eg. profiles of
+// `ynab-sync sync --jobs`) share one budget.
+
+use crate::logging::HTTP_TARGET;
+use crate::{state_dir, ErrorKind, Result};
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use log::{debug, warn};
+use rand::Rng;
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE, USER_AGENT};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string, rename, write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread::{self, sleep};
+use std::time::Duration;
+use structopt::StructOpt;
+use url::form_urlencoded;
+use url::Url;
+
+// Keys whose values are replaced before an interaction is written to disk.
+const SENSITIVE_KEYS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "mfaToken",
+    "username",
+    "password",
+    "iban",
+    "partnerIban",
+    "partnerBic",
+    "secret_id",
+    "secret_key",
+    "access",
+    "refresh",
+    "client_id",
+    "secret",
+    "public_token",
+    "link_token",
+];
+const REDACTED: &str = "REDACTED";
+
+const DEFAULT_USER_AGENT: &str = concat!("ynab-sync/", env!("CARGO_PKG_VERSION"));
+
+// Delay before the first retry, doubled with every further one.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+// Share of requests of a rate limit (eg. YNAB's `X-Rate-Limit: 190/200`)
+// after which a warning is logged.
+const RATE_LIMIT_WARNING: f64 = 0.9;
+
+const RATE_LIMIT_FILE: &str = "rate-limits.json";
+// Usage reported longer ago than this has been reset (YNAB counts requests
+// of the last hour).
+const RATE_LIMIT_WINDOW_MINUTES: i64 = 60;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "record",
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with = "replay",
+        help = "Record (sanitized) HTTP interactions into this directory."
+    )]
+    pub record: Option<PathBuf>,
+    #[structopt(
+        long = "replay",
+        value_name = "DIR",
+        parse(from_os_str),
+        help = "Replay HTTP interactions recorded with --record instead of calling the APIs."
+    )]
+    pub replay: Option<PathBuf>,
+    #[structopt(
+        long = "http-connect-timeout",
+        value_name = "SECONDS",
+        default_value = "30",
+        env = "YNAB_SYNC_HTTP_CONNECT_TIMEOUT",
+        help = "Give up connecting to an API after this many seconds."
+    )]
+    pub connect_timeout: u64,
+    #[structopt(
+        long = "http-timeout",
+        value_name = "SECONDS",
+        default_value = "120",
+        env = "YNAB_SYNC_HTTP_TIMEOUT",
+        help = "Give up on a request (including reading the response) after this many seconds."
+    )]
+    pub timeout: u64,
+    #[structopt(
+        long = "http-user-agent",
+        value_name = "TEXT",
+        env = "YNAB_SYNC_HTTP_USER_AGENT",
+        help = "User agent sent with requests which don't need a specific one (default: ynab-sync/VERSION)."
+    )]
+    pub user_agent: Option<String>,
+    #[structopt(
+        long = "http-header",
+        value_name = "NAME:VALUE",
+        number_of_values = 1,
+        help = "Extra header sent with every request (eg. for proxies). Can be used multiple times."
+    )]
+    pub headers: Vec<String>,
+    #[structopt(
+        long = "http-proxy",
+        value_name = "URL",
+        env = "YNAB_SYNC_HTTP_PROXY",
+        help = "Send all requests through this proxy (eg. http://localhost:3128)."
+    )]
+    pub proxy: Option<String>,
+    #[structopt(
+        long = "http-retries",
+        value_name = "NUMBER",
+        default_value = "3",
+        env = "YNAB_SYNC_HTTP_RETRIES",
+        help = "Retry rate limited requests and transient failures this many times. 0 disables retries."
+    )]
+    pub retries: u32,
+    #[structopt(
+        long = "http-max-retry-delay",
+        value_name = "SECONDS",
+        default_value = "60",
+        env = "YNAB_SYNC_HTTP_MAX_RETRY_DELAY",
+        help = "Fail instead of retrying when an API asks to wait longer than this many seconds."
+    )]
+    pub max_retry_delay: u64,
+}
+
+impl Cli {
+    /// The options as environment variables of another ynab-sync process,
+    /// except for --record, --replay and --http-header which have none.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            (
+                "YNAB_SYNC_HTTP_CONNECT_TIMEOUT",
+                self.connect_timeout.to_string(),
+            ),
+            ("YNAB_SYNC_HTTP_TIMEOUT", self.timeout.to_string()),
+            ("YNAB_SYNC_HTTP_RETRIES", self.retries.to_string()),
+            (
+                "YNAB_SYNC_HTTP_MAX_RETRY_DELAY",
+                self.max_retry_delay.to_string(),
+            ),
+        ];
+        if let Some(user_agent) = &self.user_agent {
+            env.push(("YNAB_SYNC_HTTP_USER_AGENT", user_agent.clone()));
+        }
+        if let Some(proxy) = &self.proxy {
+            env.push(("YNAB_SYNC_HTTP_PROXY", proxy.clone()));
+        }
+        env
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ClientConfig {
+    connect_timeout: Duration,
+    timeout: Duration,
+    user_agent: String,
+    headers: Vec<(HeaderName, String)>,
+    proxy: Option<String>,
+    retries: u32,
+    max_retry_delay: Duration,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            connect_timeout: Duration::from_secs(30),
+            timeout: Duration::from_secs(120),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: vec![],
+            proxy: None,
+            retries: 3,
+            max_retry_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+static CLIENT_CONFIG: OnceLock<ClientConfig> = OnceLock::new();
+static SHARED_CLIENT: OnceLock<Client> = OnceLock::new();
+#[cfg(feature = "async")]
+static ASYNC_CLIENT: OnceLock<reqwest_async::Client> = OnceLock::new();
+static INTERACTION: AtomicUsize = AtomicUsize::new(0);
+
+/// Configure recording/replaying of HTTP interactions, must be called before
+/// the first request is sent.
+pub fn setup(cli: &Cli) -> Result<()> {
+    let mode = match (&cli.record, &cli.replay) {
+        (Some(dir), _) => {
+            create_dir_all(dir)
+                .with_context(|_| ErrorKind::HttpRecordCanNotWrite(dir.to_string_lossy().into()))?;
+            Mode::Record(dir.clone())
+        }
+        (None, Some(dir)) => {
+            if !dir.is_dir() {
+                Err(ErrorKind::HttpReplayCanNotRead(
+                    dir.to_string_lossy().into(),
+                ))?
+            }
+            Mode::Replay(dir.clone())
+        }
+        (None, None) => Mode::Live,
+    };
+
+    let mut headers = vec![];
+    for header in &cli.headers {
+        headers.push(parse_header(header)?);
+    }
+    if let Some(user_agent) = &cli.user_agent {
+        HeaderValue::from_str(user_agent)
+            .map_err(|_| ErrorKind::ArgParse(format!("--http-user-agent {}", user_agent)))?;
+    }
+    if let Some(proxy) = &cli.proxy {
+        reqwest::Proxy::all(proxy.as_str())
+            .map_err(|_| ErrorKind::ArgParse(format!("--http-proxy {}", proxy)))?;
+    }
+    let client_config = ClientConfig {
+        connect_timeout: Duration::from_secs(cli.connect_timeout),
+        timeout: Duration::from_secs(cli.timeout),
+        user_agent: cli
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+        headers,
+        proxy: cli.proxy.clone(),
+        retries: cli.retries,
+        max_retry_delay: Duration::from_secs(cli.max_retry_delay),
+    };
+
+    // the first configuration wins
+    let _ = MODE.set(mode);
+    let _ = CLIENT_CONFIG.set(client_config);
+    Ok(())
+}
+
+// "Name: value" as passed to --http-header.
+fn parse_header(header: &str) -> Result<(HeaderName, String)> {
+    let error = || ErrorKind::ArgParse(format!("--http-header {}", header));
+    let mut parts = header.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    let value = parts.next().ok_or_else(error)?.trim();
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| error())?;
+    HeaderValue::from_str(value).map_err(|_| error())?;
+    Ok((name, value.to_string()))
+}
+
+/// Whether requests reach the real APIs without being recorded.
+pub fn is_live() -> bool {
+    matches!(MODE.get().unwrap_or(&Mode::Live), Mode::Live)
+}
+
+/// Whether HTTP interactions are replayed from disk (ie. nothing reaches the
+/// real APIs and tokens in responses are not real).
+pub fn is_replaying() -> bool {
+    matches!(MODE.get(), Some(Mode::Replay(_)))
+}
+
+/// Run `a` and `b` at the same time on two threads, eg. to fetch from two
+/// endpoints concurrently with the blocking API (the `async` feature has
+/// `YNAB::get_account_data_async` for that). Recorded sessions need their
+/// requests in a fixed order, so while recording or replaying `b` runs after
+/// `a`.
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA,
+    B: FnOnce() -> RB + Send,
+    RB: Send,
+{
+    if !is_live() {
+        let ra = a();
+        return (ra, b());
+    }
+    thread::scope(|scope| {
+        let handle = scope.spawn(b);
+        let ra = a();
+        match handle.join() {
+            Ok(rb) => (ra, rb),
+            Err(e) => panic::resume_unwind(e),
+        }
+    })
+}
+
+/// HTTP client requests are sent with. Clones share their connections.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: reqwest::Client,
+}
+
+impl Client {
+    /// Client configured with the `--http-*` options (see `setup`).
+    pub fn new() -> Result<Self> {
+        let default_config = ClientConfig::default();
+        let config = CLIENT_CONFIG.get().unwrap_or(&default_config);
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+        Ok(Client {
+            inner: builder.build()?,
+        })
+    }
+
+    /// Use a client configured by the caller (eg. with its own TLS
+    /// settings), its timeouts and proxy apply instead of `--http-*`. User
+    /// agent and extra headers of `--http-*` are still sent.
+    pub fn from_reqwest(client: reqwest::Client) -> Self {
+        Client { inner: client }
+    }
+
+    /// Client of requests which weren't given one, created with the first
+    /// of them.
+    pub fn shared() -> Result<Self> {
+        if let Some(client) = SHARED_CLIENT.get() {
+            return Ok(client.clone());
+        }
+        let client = Client::new()?;
+        Ok(SHARED_CLIENT.get_or_init(|| client).clone())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    request_body: Option<String>,
+    status: u16,
+    response_headers: Vec<(String, String)>,
+    response_body: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    method: Method,
+    url: String,
+    headers: Vec<(HeaderName, String)>,
+    body: Option<String>,
+    client: Option<Client>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    pub fn new(method: Method, url: &str) -> Self {
+        Request {
+            method,
+            url: url.to_string(),
+            headers: vec![],
+            body: None,
+            client: None,
+        }
+    }
+
+    pub fn get(url: &str) -> Self {
+        Request::new(Method::GET, url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        Request::new(Method::POST, url)
+    }
+
+    /// Send the request with `client` instead of the shared one (when
+    /// given).
+    pub fn client(mut self, client: Option<&Client>) -> Self {
+        self.client = client.cloned();
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn json<T: Serialize>(self, data: &T) -> Self {
+        let body = serde_json::to_string(data).unwrap_or_default();
+        self.header(CONTENT_TYPE, "application/json").body(body)
+    }
+
+    pub fn form<I, K, V>(self, data: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(data)
+            .finish();
+        self.header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    pub fn send(self) -> Result<Response> {
+        let response = match MODE.get().unwrap_or(&Mode::Live) {
+            Mode::Live => self.send_retrying()?,
+            Mode::Record(dir) => {
+                let response = self.send_retrying()?;
+                self.record(dir, &response)?;
+                response
+            }
+            Mode::Replay(dir) => self.replay(dir)?,
+        };
+        self.finish(&response);
+        Ok(response)
+    }
+
+    /// `send` with reqwest's async client, which is shared by all requests
+    /// (ones given their own `Client` are sent with it as well).
+    #[cfg(feature = "async")]
+    pub async fn send_async(self) -> Result<Response> {
+        let response = match MODE.get().unwrap_or(&Mode::Live) {
+            Mode::Live => self.send_retrying_async().await?,
+            Mode::Record(dir) => {
+                let response = self.send_retrying_async().await?;
+                self.record(dir, &response)?;
+                response
+            }
+            Mode::Replay(dir) => self.replay(dir)?,
+        };
+        self.finish(&response);
+        Ok(response)
+    }
+
+    // Log the interaction and keep the rate limit usage of the response.
+    fn finish(&self, response: &Response) {
+        debug!(
+            target: HTTP_TARGET,
+            "{} {}\n{}\n-> {}\n{}",
+            self.method,
+            self.url,
+            self.body.clone().unwrap_or_default(),
+            response.status,
+            response.body
+        );
+
+        if let Some(rate_limit) = RateLimit::of(response) {
+            rate_limit.warn();
+            if !is_replaying() {
+                rate_limit.save(&self.url);
+            }
+        }
+    }
+
+    // Requests which can be repeated without changing the result.
+    fn is_idempotent(&self) -> bool {
+        [
+            Method::GET,
+            Method::HEAD,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ]
+        .contains(&self.method)
+    }
+
+    fn is_retryable(&self, result: &Result<Response>) -> bool {
+        match result {
+            Ok(response) => match response.status {
+                // the request was refused, not processed
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+                StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::GATEWAY_TIMEOUT => self.is_idempotent(),
+                _ => false,
+            },
+            Err(_) => self.is_idempotent(),
+        }
+    }
+
+    fn send_retrying(&self) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let result = self.send_live();
+            match self.retry_delay(&result, attempt) {
+                Some(delay) => sleep(delay),
+                None => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_retrying_async(&self) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let result = self.send_live_async().await;
+            match self.retry_delay(&result, attempt) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    // How long to wait before retrying after the `attempt`th retry (0 for
+    // the first try) ended with `result`, `None` when it's not retried.
+    fn retry_delay(&self, result: &Result<Response>, attempt: u32) -> Option<Duration> {
+        let default_config = ClientConfig::default();
+        let config = CLIENT_CONFIG.get().unwrap_or(&default_config);
+        if attempt >= config.retries || !self.is_retryable(result) {
+            return None;
+        }
+
+        // half of the backoff is random, so parallel runs don't retry in
+        // lockstep
+        let backoff = RETRY_BASE_DELAY * 2u32.pow(attempt);
+        let jitter = rand::thread_rng().gen_range(0, backoff.as_millis() as u64 / 2 + 1);
+        let delay = match result.as_ref().ok().and_then(|x| x.retry_after()) {
+            Some(retry_after) => retry_after,
+            None => backoff / 2 + Duration::from_millis(jitter),
+        };
+        if delay > config.max_retry_delay {
+            return None;
+        }
+
+        let failure = match result {
+            Ok(response) => response.status.to_string(),
+            Err(e) => e.to_string(),
+        };
+        warn!(
+            "{} {} failed ({}), retrying in {:.1}s ({}/{})",
+            self.method,
+            path(&self.url),
+            failure,
+            delay.as_secs_f64(),
+            attempt + 1,
+            config.retries
+        );
+        Some(delay)
+    }
+
+    fn send_live(&self) -> Result<Response> {
+        let default_config = ClientConfig::default();
+        let config = CLIENT_CONFIG.get().unwrap_or(&default_config);
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => Client::shared()?,
+        };
+        let mut request = client.inner.request(self.method.clone(), &self.url);
+        // headers of the request itself (eg. N26's user agent) win
+        let has_header = |name: &HeaderName| self.headers.iter().any(|(x, _)| x == name);
+        if !has_header(&USER_AGENT) {
+            request = request.header(USER_AGENT, config.user_agent.as_str());
+        }
+        for (name, value) in &config.headers {
+            if !has_header(name) {
+                request = request.header(name.clone(), value.as_str());
+            }
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name.clone(), value.as_str());
+        }
+        if let Some(body) = &self.body {
+            request = request.body(body.clone());
+        }
+
+        let mut res = request.send()?;
+        let body = res.text()?;
+        let headers = res
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+            .collect();
+
+        Ok(Response {
+            status: res.status(),
+            headers,
+            body,
+        })
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_live_async(&self) -> Result<Response> {
+        let default_config = ClientConfig::default();
+        let config = CLIENT_CONFIG.get().unwrap_or(&default_config);
+        let client = match ASYNC_CLIENT.get() {
+            Some(client) => client.clone(),
+            None => {
+                let mut builder = reqwest_async::Client::builder()
+                    .connect_timeout(config.connect_timeout)
+                    .timeout(config.timeout);
+                if let Some(proxy) = &config.proxy {
+                    builder = builder.proxy(reqwest_async::Proxy::all(proxy.as_str())?);
+                }
+                let client = builder.build()?;
+                ASYNC_CLIENT.get_or_init(|| client).clone()
+            }
+        };
+        // reqwest's async client uses a newer version of the `http` types
+        let method = reqwest_async::Method::from_bytes(self.method.as_str().as_bytes())?;
+        let mut request = client.request(method, &self.url);
+        // headers of the request itself (eg. N26's user agent) win
+        let has_header = |name: &HeaderName| self.headers.iter().any(|(x, _)| x == name);
+        if !has_header(&USER_AGENT) {
+            request = request.header(USER_AGENT.as_str(), config.user_agent.as_str());
+        }
+        for (name, value) in &config.headers {
+            if !has_header(name) {
+                request = request.header(name.as_str(), value.as_str());
+            }
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = &self.body {
+            request = request.body(body.clone());
+        }
+
+        let res = request.send().await?;
+        let status = StatusCode::from_u16(res.status().as_u16())?;
+        let headers = res
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+            .collect();
+        let body = res.text().await?;
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn record(&self, dir: &Path, response: &Response) -> Result<()> {
+        let interaction = Interaction {
+            method: self.method.to_string(),
+            url: self.url.clone(),
+            request_body: self.body.as_ref().map(|x| sanitize(x)),
+            status: response.status.as_u16(),
+            response_headers: response.headers.clone(),
+            response_body: sanitize(&response.body),
+        };
+        let file = dir.join(format!(
+            "{:04}.json",
+            INTERACTION.fetch_add(1, Ordering::SeqCst)
+        ));
+        let content = serde_json::to_string_pretty(&interaction)
+            .with_context(|_| ErrorKind::HttpRecordCanNotWrite(file.to_string_lossy().into()))?;
+        write(&file, content)
+            .with_context(|_| ErrorKind::HttpRecordCanNotWrite(file.to_string_lossy().into()))?;
+        Ok(())
+    }
+
+    // Interactions are replayed in the order they were recorded. Only method
+    // and path are compared since query strings contain timestamps.
+    fn replay(&self, dir: &Path) -> Result<Response> {
+        let file = dir.join(format!(
+            "{:04}.json",
+            INTERACTION.fetch_add(1, Ordering::SeqCst)
+        ));
+        let request = format!("{} {}", self.method, path(&self.url));
+        let content = read_to_string(&file)
+            .with_context(|_| ErrorKind::HttpReplayMissing(request.clone()))?;
+        let interaction: Interaction = serde_json::from_str(&content)
+            .with_context(|_| ErrorKind::HttpReplayCanNotRead(file.to_string_lossy().into()))?;
+
+        let recorded = format!("{} {}", interaction.method, path(&interaction.url));
+        if recorded != request {
+            Err(ErrorKind::HttpReplayMismatch(recorded, request))?
+        }
+
+        Ok(Response {
+            status: StatusCode::from_u16(interaction.status)
+                .with_context(|_| ErrorKind::HttpReplayCanNotRead(file.to_string_lossy().into()))?,
+            headers: interaction.response_headers,
+            body: interaction.response_body,
+        })
+    }
+}
+
+impl Response {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        self.body.clone()
+    }
+
+    /// How long the API asks to wait before retrying (`Retry-After` in
+    /// seconds or as a date).
+    pub fn retry_after(&self) -> Option<Duration> {
+        let value = self.header("retry-after")?.trim();
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        let date = DateTime::parse_from_rfc2822(value).ok()?;
+        Some(
+            (date.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Rate limit usage reported by an API (eg. YNAB's `X-Rate-Limit: 190/200`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub used: u64,
+    pub limit: u64,
+    pub time: DateTime<Utc>,
+}
+
+impl RateLimit {
+    fn of(response: &Response) -> Option<Self> {
+        let (used, limit) = response.header("x-rate-limit")?.split_once('/')?;
+        Some(RateLimit {
+            used: used.trim().parse().ok()?,
+            limit: limit.trim().parse().ok()?,
+            time: Utc::now(),
+        })
+    }
+
+    /// Requests left, all of them once the usage is older than the rate
+    /// limit's window.
+    pub fn remaining(&self) -> u64 {
+        if Utc::now() - self.time > chrono::Duration::minutes(RATE_LIMIT_WINDOW_MINUTES) {
+            self.limit
+        } else {
+            self.limit.saturating_sub(self.used)
+        }
+    }
+
+    // Warn when most of the rate limit is used up.
+    fn warn(&self) {
+        if self.limit > 0 && self.used as f64 / self.limit as f64 >= RATE_LIMIT_WARNING {
+            warn!(
+                "{}/{} requests of the rate limit used",
+                self.used, self.limit
+            );
+        }
+    }
+
+    // Share the usage with other processes, failures only cost accuracy.
+    fn save(&self, url: &str) {
+        let host = match Url::parse(url)
+            .ok()
+            .and_then(|x| x.host_str().map(String::from))
+        {
+            Some(x) => x,
+            None => return,
+        };
+        let path = match state_dir() {
+            Ok(x) => x.join(RATE_LIMIT_FILE),
+            Err(_) => return,
+        };
+        let mut rate_limits = rate_limits();
+        rate_limits.insert(host, self.clone());
+        // written to a temporary file first, so other processes never read
+        // half of it
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        let content = serde_json::to_string_pretty(&rate_limits).unwrap_or_default();
+        if write(&tmp, content).is_ok() {
+            let _ = rename(&tmp, &path);
+        }
+    }
+}
+
+/// Last rate limit usage reported by each API host (eg.
+/// `api.youneedabudget.com`), by any process.
+pub fn rate_limits() -> HashMap<String, RateLimit> {
+    state_dir()
+        .ok()
+        .and_then(|x| read_to_string(x.join(RATE_LIMIT_FILE)).ok())
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+fn path(url: &str) -> String {
+    Url::parse(url)
+        .map(|x| format!("{}{}", x.host_str().unwrap_or(""), x.path()))
+        .unwrap_or_else(|_| url.to_string())
+}
+
+fn sanitize_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    sanitize_value(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(sanitize_value),
+        _ => {}
+    }
+}
+
+/// Redact credentials, tokens and IBANs from JSON and form encoded bodies.
+fn sanitize(body: &str) -> String {
+    if let Ok(mut value) = serde_json::from_str::<Value>(body) {
+        sanitize_value(&mut value);
+        return value.to_string();
+    }
+    if body.contains('=') && !body.contains(char::is_whitespace) {
+        return form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form_urlencoded::parse(body.as_bytes()).map(|(k, v)| {
+                if SENSITIVE_KEYS.contains(&k.as_ref()) {
+                    (k.into_owned(), REDACTED.to_string())
+                } else {
+                    (k.into_owned(), v.into_owned())
+                }
+            }))
+            .finish();
+    }
+    body.to_string()
+}
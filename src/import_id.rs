@@ -0,0 +1,190 @@
+// Import ids
+//
+// YNAB recognizes transactions it has seen before by their import id. By
+// default every source derives it from what identifies a transaction at the
+// bank (eg. N26's transaction id or a hash of ING-DiBa's date, amount and
+// texts). YNAB's own file import and direct import use
+// `YNAB:<amount>:<date>:<occurrence>` instead, where the occurrence counts
+// transactions of the same amount on the same day. With
+// `--import-id-scheme ynab` synced transactions get these ids too, so
+// transactions imported by YNAB and synced by ynab-sync aren't duplicated.
+
+use crate::source::SourceTransaction;
+use crate::ErrorKind;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportIdScheme {
+    /// Ids of the source.
+    Source,
+    /// `YNAB:<amount>:<date>:<occurrence>` like YNAB's own importer.
+    Ynab,
+}
+
+impl fmt::Display for ImportIdScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                ImportIdScheme::Source => "source",
+                ImportIdScheme::Ynab => "ynab",
+            },
+        )
+    }
+}
+
+impl FromStr for ImportIdScheme {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(ImportIdScheme::Source),
+            "ynab" => Ok(ImportIdScheme::Ynab),
+            _ => Err(ErrorKind::ArgParse(format!("--import-id-scheme {}", s))),
+        }
+    }
+}
+
+impl ImportIdScheme {
+    /// Replace the ids of `transactions` (all of a statement, in its order)
+    /// with ids of this scheme.
+    pub fn apply(&self, transactions: &mut [SourceTransaction]) {
+        if *self == ImportIdScheme::Source {
+            return;
+        }
+        let ids = ynab_import_ids(transactions.iter().map(|x| (x.amount, x.date)));
+        for (transaction, id) in transactions.iter_mut().zip(ids) {
+            transaction.id = id;
+        }
+    }
+}
+
+/// YNAB import id of the `occurrence`th (starting at 1) transaction of
+/// `amount` milliunits on `date`, eg. `YNAB:-12340:2020-01-31:1`.
+pub fn ynab_import_id(amount: i32, date: NaiveDate, occurrence: usize) -> String {
+    format!("YNAB:{}:{}:{}", amount, date.format("%Y-%m-%d"), occurrence)
+}
+
+/// YNAB import ids of transactions given as amount and date. Transactions
+/// have to be those of whole days, in the order of the statement, for the
+/// occurrences to match YNAB's.
+pub fn ynab_import_ids<I>(transactions: I) -> Vec<String>
+where
+    I: IntoIterator<Item = (i32, NaiveDate)>,
+{
+    let mut occurrences: HashMap<(i32, NaiveDate), usize> = HashMap::new();
+    transactions
+        .into_iter()
+        .map(|(amount, date)| {
+            let occurrence = occurrences.entry((amount, date)).or_insert(0);
+            *occurrence += 1;
+            ynab_import_id(amount, date, *occurrence)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn transaction(id: &str, amount: i32, day: &str) -> SourceTransaction {
+        SourceTransaction {
+            id: id.to_string(),
+            date: date(day),
+            amount,
+            payee: None,
+            memo_values: HashMap::new(),
+            is_cash_withdrawal: false,
+            fee: None,
+            pending: false,
+            category: None,
+        }
+    }
+
+    #[test]
+    fn ynab_import_id_format() {
+        assert_eq!(
+            ynab_import_id(-12340, date("2020-01-31"), 1),
+            "YNAB:-12340:2020-01-31:1"
+        );
+    }
+
+    #[test]
+    fn occurrences_count_same_amount_on_same_day() {
+        let ids = ynab_import_ids(vec![
+            (-5000, date("2020-01-31")),
+            (-5000, date("2020-01-31")),
+            (-5000, date("2020-01-31")),
+        ]);
+        assert_eq!(
+            ids,
+            vec![
+                "YNAB:-5000:2020-01-31:1",
+                "YNAB:-5000:2020-01-31:2",
+                "YNAB:-5000:2020-01-31:3",
+            ]
+        );
+    }
+
+    #[test]
+    fn occurrences_are_separate_per_amount_and_day() {
+        let ids = ynab_import_ids(vec![
+            (-5000, date("2020-01-30")),
+            (-5000, date("2020-01-31")),
+            (-2500, date("2020-01-31")),
+            (-5000, date("2020-01-31")),
+            (5000, date("2020-01-31")),
+        ]);
+        assert_eq!(
+            ids,
+            vec![
+                "YNAB:-5000:2020-01-30:1",
+                "YNAB:-5000:2020-01-31:1",
+                "YNAB:-2500:2020-01-31:1",
+                "YNAB:-5000:2020-01-31:2",
+                "YNAB:5000:2020-01-31:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn occurrences_follow_the_order_of_the_statement() {
+        let ids = ynab_import_ids(vec![
+            (-5000, date("2020-01-31")),
+            (-1000, date("2020-02-01")),
+            (-5000, date("2020-01-31")),
+        ]);
+        assert_eq!(ids[0], "YNAB:-5000:2020-01-31:1");
+        assert_eq!(ids[2], "YNAB:-5000:2020-01-31:2");
+    }
+
+    #[test]
+    fn source_scheme_keeps_ids() {
+        let mut transactions = vec![transaction("a", -5000, "2020-01-31")];
+        ImportIdScheme::Source.apply(&mut transactions);
+        assert_eq!(transactions[0].id, "a");
+    }
+
+    #[test]
+    fn ynab_scheme_replaces_ids() {
+        let mut transactions = vec![
+            transaction("a", -5000, "2020-01-31"),
+            transaction("b", -5000, "2020-01-31"),
+        ];
+        ImportIdScheme::Ynab.apply(&mut transactions);
+        let ids: Vec<&str> = transactions.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["YNAB:-5000:2020-01-31:1", "YNAB:-5000:2020-01-31:2"]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::ynab::{Category, Transaction};
+use std::collections::HashMap;
+
+// Group holding YNAB's special categories, eg. "Inflow: Ready to Assign"
+// (called "Inflow: To be Budgeted" in older budgets).
+const INTERNAL_CATEGORY_GROUP: &str = "Internal Master Category";
+const INFLOW_CATEGORY_PREFIX: &str = "Inflow:";
+
+/// Assigns salary and other income to YNAB's inflow category.
+#[derive(Clone, Debug)]
+pub struct Income {
+    patterns: Vec<String>,
+    all_inflows: bool,
+    category_id: String,
+}
+
+impl Income {
+    pub fn new(patterns: &[String], all_inflows: bool, category_id: String) -> Self {
+        Income {
+            patterns: patterns.iter().map(|x| x.to_lowercase()).collect(),
+            all_inflows,
+            category_id,
+        }
+    }
+
+    /// Inflows whose payee or memo contains one of the patterns are income.
+    /// With `all_inflows` also every inflow which wasn't categorized by a
+    /// rule or mapping (ie. is not approved yet).
+    pub fn is_income(&self, transaction: &Transaction) -> bool {
+        if transaction.amount <= 0 || !transaction.subtransactions.is_empty() {
+            return false;
+        }
+        let texts: Vec<String> = vec![&transaction.payee_name, &transaction.memo]
+            .into_iter()
+            .filter_map(|x| x.as_ref().map(|x| x.to_lowercase()))
+            .collect();
+        self.patterns
+            .iter()
+            .any(|pattern| texts.iter().any(|x| x.contains(pattern)))
+            || (self.all_inflows && !transaction.approved)
+    }
+
+    pub fn categorize(&self, transaction: Transaction) -> Transaction {
+        if self.is_income(&transaction) {
+            Transaction {
+                category_id: Some(self.category_id.clone()),
+                approved: true,
+                ..transaction
+            }
+        } else {
+            transaction
+        }
+    }
+}
+
+/// Find the "Inflow: Ready to Assign" category in `categories` (see
+/// `ynab::index_categories`).
+pub fn inflow_category(categories: &HashMap<String, Category>) -> Option<&Category> {
+    let prefix = format!("{}/{}", INTERNAL_CATEGORY_GROUP, INFLOW_CATEGORY_PREFIX);
+    categories
+        .iter()
+        .find(|(key, _)| key.starts_with(&prefix))
+        .map(|(_, category)| category)
+}
@@ -1,85 +1,742 @@
-use crate::{convert_to_int_eu_style, convert_to_local_date, max_200_chars};
+use crate::max_200_chars;
+use crate::parse::{deserialize_amount_eu, deserialize_date_de, deserialize_option_amount_eu};
+use crate::source::{BankSource, Cli as DateRangeCli, SourceTransaction};
 use crate::{ErrorKind, Result};
-use chrono::{NaiveDate, Utc};
-use csv::ReaderBuilder;
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use csv::{ReaderBuilder, StringRecord};
 use encoding_rs::WINDOWS_1252;
 use encoding_rs_io::DecodeReaderBytesBuilder;
 use failure::ResultExt;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
 
 #[derive(Clone, PartialEq, Debug, Deserialize)]
 pub struct Transaction {
-    #[serde(deserialize_with = "convert_to_local_date")]
+    #[serde(deserialize_with = "deserialize_date_de")]
     pub ts: NaiveDate,
-    #[serde(deserialize_with = "convert_to_local_date")]
+    #[serde(deserialize_with = "deserialize_date_de")]
     pub currency_ts: NaiveDate,
+    // empty for interest postings
+    #[serde(default)]
     pub entity: String,
     #[serde(rename = "type")]
     pub type_: String,
     #[serde(deserialize_with = "max_200_chars")]
     pub memo: String,
-    #[serde(deserialize_with = "convert_to_int_eu_style")]
-    pub balance: i32,
-    pub balance_currency: String,
-    #[serde(deserialize_with = "convert_to_int_eu_style")]
+    // not exported for the Extra-Konto
+    #[serde(default, deserialize_with = "deserialize_option_amount_eu")]
+    pub balance: Option<i32>,
+    #[serde(default)]
+    pub balance_currency: Option<String>,
+    #[serde(deserialize_with = "deserialize_amount_eu")]
     pub amount: i32,
     pub amount_currency: String,
 }
 
+// Texts of ATM withdrawals ("GAA" is short for Geldausgabeautomat).
+const CASH_WITHDRAWAL_PATTERNS: &[&str] = &["bargeldauszahlung", "bargeldabhebung", "gaa "];
+
+// Texts of interest postings, which have no counterparty.
+const INTEREST_PATTERNS: &[&str] = &["zinsgutschrift", "zinsen"];
+
+/// Payee used for interest postings.
+pub const INTEREST_PAYEE: &str = "ING";
+
+/// Payee of the transactions syncing the value of a Depot.
+pub const DEPOT_PAYEE: &str = "Market Value Adjustment";
+
+/// Placeholders available in `--memo-template` for ING-DiBa transactions.
+pub const MEMO_FIELDS: &[&str] = &["merchant", "reference", "type"];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{merchant} :: {reference}";
+
+impl Transaction {
+    /// Whether this is a withdrawal at an ATM.
+    pub fn is_cash_withdrawal(&self) -> bool {
+        let text = format!("{} {} {}", self.type_, self.entity, self.memo).to_lowercase();
+        self.amount < 0 && CASH_WITHDRAWAL_PATTERNS.iter().any(|x| text.contains(x))
+    }
+
+    /// Whether this is an interest posting (eg. on the Extra-Konto).
+    pub fn is_interest(&self) -> bool {
+        let text = format!("{} {}", self.type_, self.memo).to_lowercase();
+        INTEREST_PATTERNS.iter().any(|x| text.contains(x))
+    }
+
+    /// Payee of the transaction: the entity, `INTEREST_PAYEE` for interest
+    /// postings without one.
+    pub fn payee(&self) -> String {
+        if self.entity.is_empty() && self.is_interest() {
+            INTEREST_PAYEE.to_string()
+        } else {
+            self.entity.clone()
+        }
+    }
+
+    // the balance is left out, older exports don't have it
+    fn dedup_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}",
+            self.ts,
+            self.currency_ts,
+            self.entity,
+            self.type_,
+            self.memo,
+            self.amount,
+            self.amount_currency
+        )
+    }
+
+    /// YNAB import id, a hash of the date, amount and texts. It stays based
+    /// on the original memo format and entity (not the payee), so changing
+    /// --memo-template or the payee of interest postings doesn't create
+    /// duplicates.
+    pub fn import_id(&self) -> String {
+        let mut import_id_sha = Sha1::new();
+        import_id_sha.input_str(&self.ts.format("%Y-%m-%d").to_string());
+        import_id_sha.input_str(&format!("{}", self.amount));
+        import_id_sha.input_str(&format!("{} :: {}", self.entity, self.memo));
+        import_id_sha.result_str()[..36].to_string()
+    }
+
+    /// Values for the placeholders listed in `MEMO_FIELDS`.
+    pub fn memo_values(&self) -> HashMap<&'static str, Option<String>> {
+        let mut values = HashMap::new();
+        values.insert("merchant", Some(self.payee()));
+        values.insert("reference", Some(self.memo.clone()));
+        values.insert("type", Some(self.type_.clone()));
+        values
+    }
+}
+
+/// Map the columns of the exported CSV to `Transaction` fields.
+///
+/// The Girokonto and the Extra-Konto exports differ in their columns (eg. the
+/// Extra-Konto has no balance), each "Währung" column belongs to the amount
+/// before it and unknown columns are kept as they are (and ignored).
+fn header(columns: &StringRecord) -> StringRecord {
+    let mut previous = "";
+    columns
+        .iter()
+        .map(|column| {
+            let field = match column.trim() {
+                "Buchung" => "ts",
+                "Valuta" => "currency_ts",
+                "Auftraggeber/Empfänger" => "entity",
+                "Buchungstext" => "type",
+                "Verwendungszweck" => "memo",
+                "Saldo" => "balance",
+                "Betrag" => "amount",
+                "Währung" if previous == "balance" => "balance_currency",
+                "Währung" if previous == "amount" => "amount_currency",
+                _ => column,
+            };
+            previous = field;
+            field
+        })
+        .collect()
+}
+
+/// Kind of account an export belongs to, told by its header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportKind {
+    Girokonto,
+    /// Savings account, its transactions have no balance.
+    ExtraKonto,
+    /// Securities account, exported as positions instead of transactions.
+    Depot,
+}
+
+/// Position of a Depot export.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct Position {
+    pub isin: String,
+    pub name: String,
+    /// Number of shares (or nominal value) as exported, eg. "12,5".
+    pub quantity: String,
+    /// Market value in milliunits.
+    #[serde(deserialize_with = "deserialize_amount_eu")]
+    pub value: i32,
+    #[serde(default)]
+    pub value_currency: Option<String>,
+}
+
+/// Map the columns of a Depot export to `Position` fields, like `header`.
+fn depot_header(columns: &StringRecord) -> StringRecord {
+    let mut previous = "";
+    columns
+        .iter()
+        .map(|column| {
+            let field = match column.trim() {
+                "ISIN" => "isin",
+                "Wertpapiername" => "name",
+                "Stück/Nominale" | "Stück" => "quantity",
+                "Kurswert" => "value",
+                "Währung" if previous == "value" => "value_currency",
+                _ => column,
+            };
+            previous = field;
+            field
+        })
+        .collect()
+}
+
+/// Whether `record` is the header of the transactions (or positions).
+/// Lines before it (the preamble) change between export versions, they're
+/// only searched for a few values.
+fn is_header(record: &StringRecord) -> bool {
+    let has = |name: &str| record.iter().any(|x| x.trim() == name);
+    (has("Buchung") && has("Betrag")) || (has("ISIN") && has("Kurswert"))
+}
+
+/// Value of a `Name;Value` line before the header (eg. "Kontoname").
+fn preamble_value<'a>(preamble: &'a [StringRecord], name: &str) -> Option<&'a str> {
+    preamble.iter().find_map(|record| match record.get(0) {
+        Some(x) if x.trim() == name => record.get(1).map(|x| x.trim()),
+        _ => None,
+    })
+}
+
+/// Tell the kind of an export by its header, with the "Kontoname" line
+/// before it (if any) telling an Extra-Konto from a Girokonto.
+fn export_kind(preamble: &[StringRecord], header: &StringRecord) -> ExportKind {
+    if header.iter().any(|x| x.trim() == "ISIN") {
+        ExportKind::Depot
+    } else if preamble_value(preamble, "Kontoname")
+        .is_some_and(|x| x.to_lowercase().contains("extra"))
+    {
+        ExportKind::ExtraKonto
+    } else {
+        ExportKind::Girokonto
+    }
+}
+
 pub struct IngDiBa {
+    pub kind: ExportKind,
     pub transactions: Vec<Transaction>,
+    /// Positions of a Depot export.
+    pub positions: Vec<Position>,
+    /// Date of the Depot export's positions.
+    pub positions_date: Option<NaiveDate>,
     pub days_to_sync: i64,
 }
 
 impl IngDiBa {
     pub fn new(csv_file: String) -> Result<Self> {
-        let mut csv: Vec<String> = vec![];
-        let reader = BufReader::new(
-            DecodeReaderBytesBuilder::new()
-                .encoding(Some(WINDOWS_1252))
-                .build(
-                    File::open(&csv_file)
-                        .context(ErrorKind::IngDiBaCsvFileCanNotOpen(csv_file.clone()))?,
-                ),
-        );
-        for rline in reader.lines() {
-            let line = rline.context(ErrorKind::IngDiBaCsvFileParse(csv_file.clone()))?;
-            if (csv.is_empty() && line != "" && line.starts_with("Buchung")) || !csv.is_empty() {
-                csv.push(line.clone());
+        let mut csv = String::new();
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(WINDOWS_1252))
+            .build(
+                File::open(&csv_file)
+                    .context(ErrorKind::IngDiBaCsvFileCanNotOpen(csv_file.clone()))?,
+            )
+            .read_to_string(&mut csv)
+            .context(ErrorKind::IngDiBaCsvFileParse(csv_file.clone()))?;
+
+        // the whole file is parsed as CSV, so quoted fields may contain
+        // semicolons and line breaks, also in the preamble
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+        let mut preamble = vec![];
+        let mut columns = None;
+        let mut rows = vec![];
+        for result in reader.records() {
+            let record = result.context(ErrorKind::IngDiBaCsvFileParse(csv_file.clone()))?;
+            if record.iter().all(|x| x.trim().is_empty()) {
+                continue;
+            }
+            match columns {
+                Some(_) => rows.push(record),
+                None if is_header(&record) => columns = Some(record),
+                None => preamble.push(record),
             }
         }
+        let columns = columns.ok_or_else(|| ErrorKind::IngDiBaCsvFileParse(csv_file.clone()))?;
 
-        csv.remove(0);
-        csv.insert(
-            0,
-            "ts;currency_ts;entity;type;memo;balance;balance_currency;amount;amount_currency"
-                .to_string(),
-        );
+        let kind = export_kind(&preamble, &columns);
+        if kind == ExportKind::Depot {
+            return IngDiBa::depot(&csv_file, &preamble, &columns, rows);
+        }
 
-        let csv_data = csv.join("\n");
-        let mut reader = ReaderBuilder::new()
-            .delimiter(b';')
-            .from_reader(csv_data.as_bytes());
         let mut transactions = vec![];
-        for result in reader.deserialize() {
+        let mut headers = header(&columns);
+        // without a value date the booking date is used
+        let ts_index = headers.iter().position(|x| x == "ts");
+        let has_currency_ts = headers.iter().any(|x| x == "currency_ts");
+        if !has_currency_ts {
+            headers.push_field("currency_ts");
+        }
+        for mut record in rows {
+            if !has_currency_ts {
+                let ts = ts_index
+                    .and_then(|x| record.get(x))
+                    .unwrap_or("")
+                    .to_string();
+                record.push_field(&ts);
+            }
             let transaction: Transaction =
-                result.context(ErrorKind::IngDiBaCsvFileParse(csv_file.clone()))?;
+                record.deserialize(Some(&headers)).with_context(|_| {
+                    ErrorKind::IngDiBaCsvTransactionParse(
+                        csv_file.clone(),
+                        record.iter().collect::<Vec<_>>().join(";"),
+                    )
+                })?;
             transactions.push(transaction);
         }
+        check_balances(&csv_file, &transactions)?;
+        // in the order they were booked, also within a day
+        if is_newest_first(&transactions) {
+            transactions.reverse();
+        }
+
+        Ok(IngDiBa {
+            kind,
+            ..IngDiBa::from_transactions(transactions)
+        })
+    }
+
+    /// Parse the `rows` of the positions of a Depot export.
+    fn depot(
+        csv_file: &str,
+        preamble: &[StringRecord],
+        columns: &StringRecord,
+        rows: Vec<StringRecord>,
+    ) -> Result<Self> {
+        let headers = depot_header(columns);
+        let mut positions = vec![];
+        for record in rows {
+            // totals and notes below the positions
+            if record.get(0).unwrap_or("").is_empty() {
+                continue;
+            }
+            let position: Position = record.deserialize(Some(&headers)).with_context(|_| {
+                ErrorKind::IngDiBaCsvTransactionParse(
+                    csv_file.to_string(),
+                    record.iter().collect::<Vec<_>>().join(";"),
+                )
+            })?;
+            positions.push(position);
+        }
+        let positions_date = preamble_value(preamble, "Datum")
+            .and_then(|x| NaiveDate::parse_from_str(x, "%d.%m.%Y").ok());
 
+        Ok(IngDiBa {
+            kind: ExportKind::Depot,
+            positions,
+            positions_date,
+            ..IngDiBa::from_transactions(vec![])
+        })
+    }
+
+    /// Market value of the positions of a Depot export in milliunits.
+    pub fn depot_value(&self) -> i64 {
+        self.positions.iter().map(|x| i64::from(x.value)).sum()
+    }
+
+    /// Combine several exports of the same account into one. Exports whose
+    /// date ranges overlap contain the same rows, those are only kept once.
+    /// Identical rows within one export (eg. two equal purchases on the same
+    /// day) are kept, a row appears as many times as in the export containing
+    /// it the most. Also returns the number of rows that were dropped.
+    ///
+    /// Depot exports are snapshots, of them the positions of the last one
+    /// are kept.
+    pub fn merge(exports: Vec<IngDiBa>) -> (Self, usize) {
+        let total: usize = exports.iter().map(|x| x.transactions.len()).sum();
+        let kind = exports.first().map_or(ExportKind::Girokonto, |x| x.kind);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut transactions = vec![];
+        let mut positions = vec![];
+        let mut positions_date = None;
+        for export in exports {
+            if export.kind == ExportKind::Depot {
+                positions = export.positions;
+                positions_date = export.positions_date;
+            }
+            let mut export_counts: HashMap<String, usize> = HashMap::new();
+            for transaction in export.transactions {
+                let key = transaction.dedup_key();
+                let export_count = export_counts.entry(key.clone()).or_insert(0);
+                *export_count += 1;
+                let count = counts.entry(key).or_insert(0);
+                if *export_count > *count {
+                    *count = *export_count;
+                    transactions.push(transaction);
+                }
+            }
+        }
+        let merged = total - transactions.len();
+        let ingdiba = IngDiBa {
+            kind,
+            positions,
+            positions_date,
+            ..IngDiBa::from_transactions(transactions)
+        };
+        (ingdiba, merged)
+    }
+
+    /// Balance ("Saldo") after the newest transaction, `None` for exports
+    /// without balances.
+    pub fn balance(&self) -> Option<i32> {
+        let newest = self.transactions.iter().map(|x| x.ts).max()?;
+        let newest: Vec<&Transaction> = self
+            .transactions
+            .iter()
+            .filter(|x| x.ts == newest && x.balance.is_some())
+            .collect();
+        // of the transactions of the same day the last booked one is the one
+        // whose balance no other started from
+        newest
+            .iter()
+            .filter_map(|x| x.balance)
+            .find(|balance| {
+                !newest
+                    .iter()
+                    .any(|x| x.balance.map(|y| y - x.amount) == Some(*balance))
+            })
+            .or_else(|| newest.first().and_then(|x| x.balance))
+    }
+
+    /// Only the transactions booked within --from-date and --to-date, with
+    /// `days_to_sync` reaching back to the oldest of them.
+    pub fn filter_dates(self, dates: &DateRangeCli) -> Self {
+        let transactions = self
+            .transactions
+            .into_iter()
+            .filter(|x| dates.contains(x.ts))
+            .collect();
+        IngDiBa {
+            kind: self.kind,
+            positions: self.positions,
+            positions_date: self.positions_date,
+            ..IngDiBa::from_transactions(transactions)
+        }
+    }
+
+    /// `transactions` of one or more exports, each in the order they were
+    /// booked. They are kept oldest first, like YNAB numbers transactions of
+    /// the same day and amount in their import ids.
+    fn from_transactions(mut transactions: Vec<Transaction>) -> Self {
+        // stable, keeps the order of the transactions of a day
         transactions.sort_by_key(|x| x.ts);
-        transactions.reverse();
         let today = Utc::today().naive_local();
         let days_to_sync = transactions
-            .last()
+            .first()
             .map(|x| NaiveDate::signed_duration_since(today, x.ts).num_days())
             .unwrap_or(0);
 
-        Ok(IngDiBa {
+        IngDiBa {
+            kind: ExportKind::Girokonto,
             transactions,
+            positions: vec![],
+            positions_date: None,
             days_to_sync,
-        })
+        }
+    }
+}
+
+/// Whether `transactions` in the order of the file are newest first, the
+/// default of the exports (which is assumed when they're of the same day).
+fn is_newest_first(transactions: &[Transaction]) -> bool {
+    match (transactions.first(), transactions.last()) {
+        (Some(first), Some(last)) => first.ts >= last.ts,
+        _ => true,
+    }
+}
+
+/// Check that the balance of each row is the balance of the row before plus
+/// its amount, so truncated or edited exports aren't synced. `transactions`
+/// are in the order of the file, usually newest first. Rows without balance
+/// (older exports, the Extra-Konto) aren't checked.
+fn check_balances(csv_file: &str, transactions: &[Transaction]) -> Result<()> {
+    let newest_first = is_newest_first(transactions);
+    for rows in transactions.windows(2) {
+        let (newer, older) = if newest_first {
+            (&rows[0], &rows[1])
+        } else {
+            (&rows[1], &rows[0])
+        };
+        if let (Some(balance), Some(older_balance)) = (newer.balance, older.balance) {
+            if balance != older_balance + newer.amount {
+                Err(ErrorKind::IngDiBaCsvBalanceMismatch(
+                    csv_file.to_string(),
+                    format!(
+                        "{} | {} | {:+.2} EUR: balance {:.2} EUR, expected {:.2} EUR after {} | {} with {:.2} EUR",
+                        newer.ts,
+                        newer.entity,
+                        newer.amount as f64 / 1000.0,
+                        balance as f64 / 1000.0,
+                        (older_balance + newer.amount) as f64 / 1000.0,
+                        older.ts,
+                        older.entity,
+                        older_balance as f64 / 1000.0
+                    ),
+                ))?
+            }
+        }
+    }
+    Ok(())
+}
+
+impl BankSource for IngDiBa {
+    fn name(&self) -> &'static str {
+        "ING-DiBa"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Transactions of the export booked in the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::today().naive_local() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.ts >= since)
+            .map(|x| SourceTransaction {
+                id: x.import_id(),
+                date: x.ts,
+                amount: x.amount,
+                payee: Some(x.payee()).filter(|x| !x.is_empty()),
+                memo_values: x.memo_values(),
+                is_cash_withdrawal: x.is_cash_withdrawal(),
+                fee: None,
+                pending: false,
+                category: None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{remove_file, write};
+
+    const PREAMBLE: &str = "Umsatzanzeige;Datei erstellt am: 01.02.2020 10:00
+;Letztes Update: aktuell
+
+IBAN;DE12 3456 7890 1234 5678 90
+Kontoname;Girokonto
+Bank;ING
+Kunde;Max Mustermann
+Zeitraum;01.01.2020 - 31.01.2020
+Saldo;1.234,56;EUR
+
+Sortierung;Datum absteigend
+
+In der CSV-Datei finden Sie alle bereits gebuchten Umsätze.
+
+";
+
+    const TRANSACTIONS: &str = "Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+31.01.2020;30.01.2020;REWE Markt GmbH;Lastschrift;Einkauf 1234;1.234,56;EUR;-12,34;EUR
+30.01.2020;30.01.2020;Arbeitgeber GmbH;Gehalt/Rente;Gehalt Januar;1.246,90;EUR;2.000,00;EUR
+";
+
+    /// Parse `csv` written to a file in Windows-1252, the encoding of the
+    /// exports.
+    fn parse(name: &str, csv: &str) -> IngDiBa {
+        let (bytes, _, unmappable) = WINDOWS_1252.encode(csv);
+        assert!(!unmappable);
+        let path = temp_dir().join(format!("ynab-sync-{}-{}.csv", name, std::process::id()));
+        write(&path, &bytes).unwrap();
+        let ingdiba = IngDiBa::new(path.to_string_lossy().into());
+        remove_file(&path).unwrap();
+        ingdiba.unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn export_with_preamble() {
+        let ingdiba = parse("preamble", &format!("{}{}", PREAMBLE, TRANSACTIONS));
+        assert_eq!(ingdiba.kind, ExportKind::Girokonto);
+        assert_eq!(ingdiba.transactions.len(), 2);
+        // oldest first
+        let transaction = &ingdiba.transactions[1];
+        assert_eq!(transaction.ts, date("2020-01-31"));
+        assert_eq!(transaction.currency_ts, date("2020-01-30"));
+        assert_eq!(transaction.entity, "REWE Markt GmbH");
+        assert_eq!(transaction.type_, "Lastschrift");
+        assert_eq!(transaction.memo, "Einkauf 1234");
+        assert_eq!(transaction.amount, -12340);
+        assert_eq!(transaction.balance, Some(1_234_560));
+        assert_eq!(ingdiba.transactions[0].amount, 2_000_000);
+        assert_eq!(ingdiba.balance(), Some(1_234_560));
+    }
+
+    #[test]
+    fn export_without_preamble() {
+        let ingdiba = parse("no-preamble", TRANSACTIONS);
+        assert_eq!(ingdiba.kind, ExportKind::Girokonto);
+        assert_eq!(ingdiba.transactions.len(), 2);
+        assert_eq!(ingdiba.transactions[1].entity, "REWE Markt GmbH");
+        assert_eq!(ingdiba.balance(), Some(1_234_560));
+    }
+
+    #[test]
+    fn export_without_balance_and_value_date() {
+        let ingdiba = parse(
+            "no-balance",
+            "Kontoname;Extra-Konto
+
+Buchung;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Betrag;Währung
+31.01.2020;;Abschluss;Zinsen;1,23;EUR
+15.01.2020;Max Mustermann;Gutschrift;Sparen;100,00;EUR
+",
+        );
+        assert_eq!(ingdiba.kind, ExportKind::ExtraKonto);
+        assert_eq!(ingdiba.transactions.len(), 2);
+        let interest = &ingdiba.transactions[1];
+        // the booking date stands in for the value date
+        assert_eq!(interest.currency_ts, interest.ts);
+        assert_eq!(interest.entity, "");
+        assert_eq!(interest.payee(), INTEREST_PAYEE);
+        assert_eq!(interest.amount, 1230);
+        assert_eq!(interest.balance, None);
+        assert_eq!(ingdiba.balance(), None);
+    }
+
+    #[test]
+    fn closing_rows_keep_their_entity_and_import_id() {
+        let ingdiba = parse(
+            "closing",
+            "Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+31.03.2020;31.03.2020;;Abschluss;Entgelt Kontoführung;987,66;EUR;-4,90;EUR
+31.03.2020;31.03.2020;;Abschluss;Zinsen;992,56;EUR;0,12;EUR
+",
+        );
+        let by_amount = |amount| {
+            ingdiba
+                .transactions
+                .iter()
+                .find(|x| x.amount == amount)
+                .unwrap()
+        };
+        let fee = by_amount(-4900);
+        assert!(!fee.is_interest());
+        assert_eq!(fee.payee(), "");
+        let interest = by_amount(120);
+        assert_eq!(interest.payee(), INTEREST_PAYEE);
+        // the import id is of the exported entity, not of the payee
+        let exported = Transaction {
+            entity: "".to_string(),
+            ..interest.clone()
+        };
+        assert_eq!(interest.import_id(), exported.import_id());
+        assert_ne!(
+            interest.import_id(),
+            Transaction {
+                entity: INTEREST_PAYEE.to_string(),
+                ..interest.clone()
+            }
+            .import_id()
+        );
+    }
+
+    #[test]
+    fn transactions_are_in_booking_order() {
+        let ingdiba = parse(
+            "booking-order",
+            "Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+02.02.2020;02.02.2020;Kiosk;Lastschrift;Zeitung;975,66;EUR;-2,00;EUR
+01.02.2020;01.02.2020;Kiosk;Lastschrift;Zeitung;977,66;EUR;-2,00;EUR
+01.02.2020;01.02.2020;Kiosk;Lastschrift;Kaffee;979,66;EUR;-3,00;EUR
+01.02.2020;01.02.2020;Kiosk;Lastschrift;Zeitung;982,66;EUR;-2,00;EUR
+",
+        );
+        let booked: Vec<_> = ingdiba
+            .transactions
+            .iter()
+            .map(|x| (x.ts, x.memo.as_str(), x.balance))
+            .collect();
+        assert_eq!(
+            booked,
+            vec![
+                (date("2020-02-01"), "Zeitung", Some(982_660)),
+                (date("2020-02-01"), "Kaffee", Some(979_660)),
+                (date("2020-02-01"), "Zeitung", Some(977_660)),
+                (date("2020-02-02"), "Zeitung", Some(975_660)),
+            ]
+        );
+        // so same-day transactions of an amount are numbered like in YNAB
+        let source = ingdiba.transactions(36500).unwrap();
+        assert_eq!(
+            source[0].memo_values["reference"].as_deref(),
+            Some("Zeitung")
+        );
+        assert_eq!(ingdiba.balance(), Some(975_660));
+    }
+
+    #[test]
+    fn quoted_memo_with_semicolon_and_line_break() {
+        let ingdiba = parse(
+            "quoted-memo",
+            "Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+31.01.2020;31.01.2020;\"Stadtwerke; Strom\";Lastschrift;\"Abschlag Januar;
+Kundennr. 42\";987,66;EUR;-45,00;EUR
+",
+        );
+        assert_eq!(ingdiba.transactions.len(), 1);
+        let transaction = &ingdiba.transactions[0];
+        assert_eq!(transaction.entity, "Stadtwerke; Strom");
+        assert_eq!(transaction.memo, "Abschlag Januar;\nKundennr. 42");
+        assert_eq!(transaction.amount, -45000);
+    }
+
+    #[test]
+    fn windows_1252_input() {
+        let ingdiba = parse(
+            "windows-1252",
+            "Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+31.01.2020;31.01.2020;Bäckerei Müller;Lastschrift;Frühstück für 3 €;987,66;EUR;-7,50;EUR
+",
+        );
+        let transaction = &ingdiba.transactions[0];
+        assert_eq!(transaction.entity, "Bäckerei Müller");
+        assert_eq!(transaction.memo, "Frühstück für 3 €");
+        assert_eq!(transaction.amount_currency, "EUR");
+    }
+
+    #[test]
+    fn long_memo_is_truncated_on_characters() {
+        // an umlaut at the 149th character, where the memo is cut
+        let memo = format!("{}ä €-Gebühr für Überweisung", "x".repeat(148));
+        let ingdiba = parse(
+            "long-memo",
+            &format!("Buchung;Valuta;Auftraggeber/Empfänger;Buchungstext;Verwendungszweck;Saldo;Währung;Betrag;Währung
+31.01.2020;31.01.2020;Bank;Entgelt;{};987,66;EUR;-1,00;EUR
+", memo),
+        );
+        let transaction = &ingdiba.transactions[0];
+        assert_eq!(transaction.memo.chars().count(), 149);
+        assert_eq!(transaction.memo, format!("{}ä", "x".repeat(148)));
+    }
+
+    #[test]
+    fn mismatching_balances_are_rejected() {
+        let csv = TRANSACTIONS.replace("1.234,56", "1.234,00");
+        let (bytes, _, _) = WINDOWS_1252.encode(&csv);
+        let path = temp_dir().join(format!("ynab-sync-mismatch-{}.csv", std::process::id()));
+        write(&path, &bytes).unwrap();
+        let ingdiba = IngDiBa::new(path.to_string_lossy().into());
+        remove_file(&path).unwrap();
+        assert!(ingdiba.is_err());
     }
 }
@@ -1,13 +1,54 @@
-use chrono::NaiveDate;
+use dirs::data_local_dir;
+use failure::ResultExt;
 use serde::de::{self, Deserializer, Visitor};
+use std::env::{current_dir, var_os};
 use std::fmt;
+use std::fs::create_dir_all;
+use std::path::PathBuf;
 use std::result;
 
+pub mod amex;
+pub mod audit;
+pub mod balance;
+pub mod cache;
+pub mod camt;
+pub mod classifier;
+pub mod config;
+pub mod csv_import;
+pub mod delta;
+pub mod encryption;
 pub mod error;
+pub mod explain;
+pub mod fuzzy;
+pub mod http;
+pub mod import_id;
+pub mod income;
 pub mod ingdiba;
 pub mod logging;
+pub mod manual;
+pub mod memo;
+pub mod month_report;
 pub mod n26;
-// TODO: pub mod rules;
+pub mod nordigen;
+pub mod notify;
+pub mod output;
+pub mod parse;
+pub mod payees;
+pub mod plaid;
+pub mod plan;
+pub mod reimbursements;
+pub mod report;
+pub mod revolut;
+pub mod rules;
+pub mod source;
+pub mod split;
+pub mod state;
+pub mod sync;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod totals;
+pub mod transfers;
+pub mod watch;
 pub mod ynab;
 
 pub use error::{Error, ErrorKind, Result};
@@ -15,76 +56,21 @@ pub use ingdiba::IngDiBa;
 pub use n26::N26;
 pub use ynab::YNAB;
 
-fn convert_to_int<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct I32Visitor;
-
-    impl<'de> Visitor<'de> for I32Visitor {
-        type Value = i32;
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a cent representation in i32 of an amount provided in f64")
-        }
-        fn visit_f64<E>(self, value: f64) -> result::Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(((value * 1000.0).round()) as Self::Value)
-        }
-    }
-
-    deserializer.deserialize_f64(I32Visitor)
+/// Directory where ynab-sync keeps its logs and state
+/// (eg. `~/.local/share/ynab-sync`), created when missing.
+pub fn state_dir() -> Result<PathBuf> {
+    let mut dir = data_local_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
+    dir.push("ynab-sync");
+    create_dir_all(&dir)
+        .with_context(|_| ErrorKind::StateDirCanNotCreate(dir.to_string_lossy().into()))?;
+    Ok(dir)
 }
 
-fn convert_to_int_eu_style<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct I32Visitor;
-
-    impl<'de> Visitor<'de> for I32Visitor {
-        type Value = i32;
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter
-                .write_str("a cent representation in i32 of an amount provided in f64 in eu style")
-        }
-        fn visit_str<E>(self, s: &str) -> result::Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            let float = s.replace(".", "").replace(",", ".");
-            match float.parse::<f64>() {
-                Ok(x) => Ok(((x * 1000.0).round()) as Self::Value),
-                Err(e) => Err(E::custom(format!("Parse error {} for {}", e, float))),
-            }
-        }
-    }
-
-    deserializer.deserialize_str(I32Visitor)
-}
-
-fn convert_to_local_date<'de, D>(deserializer: D) -> result::Result<NaiveDate, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct StrVisitor;
-
-    impl<'de> Visitor<'de> for StrVisitor {
-        type Value = NaiveDate;
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a local date representation in YYYY-MM-DD format")
-        }
-        fn visit_str<E>(self, s: &str) -> result::Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            NaiveDate::parse_from_str(s, "%d.%m.%Y")
-                .map_err(|e| E::custom(format!("Parse error {} for {}", e, s)))
-        }
-    }
-
-    deserializer.deserialize_str(StrVisitor)
+/// Whether the environment variable `name` of a flag is set (to anything but
+/// "", "0" or "false"). clap only reads environment variables of options
+/// taking a value, a flag with `env` would need `--flag VALUE`.
+pub fn env_flag(name: &str) -> bool {
+    var_os(name).is_some_and(|x| !x.is_empty() && x != "0" && x != "false")
 }
 
 fn max_200_chars<'de, D>(deserializer: D) -> result::Result<String, D::Error>
@@ -102,12 +88,8 @@ where
         where
             E: de::Error,
         {
-            if s.len() > 149 {
-                let ss = &s[0..149];
-                Ok(ss.to_string())
-            } else {
-                Ok(s.to_string())
-            }
+            // truncated on characters, memos may contain umlauts or €
+            Ok(s.chars().take(149).collect())
         }
     }
 
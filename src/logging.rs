@@ -1,13 +1,174 @@
 use crate::error::{ErrorKind, Result};
+use crate::{env_flag, state_dir};
+use chrono::{DateTime, Local, NaiveDate};
+use failure::ResultExt;
 use fern;
-use log::Level;
-use std::io;
+use log::{Level, LevelFilter};
+use std::fs::{metadata, remove_file, rename, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
 
-pub fn setup_logging(_for_crate: String, log_level: Option<Level>) -> Result<()> {
+/// Log target used for raw HTTP request/response bodies.
+///
+/// Records with this target never reach stdout, they are only written to the
+/// file provided via `--http-log`.
+pub const HTTP_TARGET: &str = "ynab_sync::http";
+
+const HTTP_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const HTTP_LOG_KEEP: usize = 3;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "http-log",
+        value_name = "FILE",
+        env = "YNAB_SYNC_HTTP_LOG",
+        parse(from_os_str),
+        help = "Write HTTP request and response bodies to this file (useful for bug reports)."
+    )]
+    pub http_log: Option<PathBuf>,
+    #[structopt(
+        long = "log-file",
+        value_name = "FILE",
+        env = "YNAB_SYNC_LOG_FILE",
+        parse(from_os_str),
+        help = "Also write logs to this file. Relative paths are placed in the state directory."
+    )]
+    pub log_file: Option<PathBuf>,
+    #[structopt(
+        long = "log-max-size",
+        value_name = "MB",
+        default_value = "10",
+        env = "YNAB_SYNC_LOG_MAX_SIZE",
+        help = "Rotate the log file once it grows beyond this size."
+    )]
+    pub log_max_size: u64,
+    #[structopt(
+        long = "log-rotate-daily",
+        help = "Additionally rotate the log file when the day changes. [env: YNAB_SYNC_LOG_ROTATE_DAILY]"
+    )]
+    pub log_rotate_daily: bool,
+    #[structopt(
+        long = "log-keep",
+        value_name = "NUMBER",
+        default_value = "5",
+        env = "YNAB_SYNC_LOG_KEEP",
+        help = "Number of rotated log files to keep."
+    )]
+    pub log_keep: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Rotation {
+    pub max_bytes: u64,
+    pub daily: bool,
+    pub keep: usize,
+}
+
+/// A log file which rotates itself (`file` -> `file.1` -> `file.2` ...) once
+/// it grows beyond `Rotation::max_bytes` or, when `Rotation::daily` is set,
+/// once the day changes. Rotation is checked on every write so long running
+/// processes (eg. daemon mode) keep rotating as well.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn new(path: &Path, rotation: Rotation) -> Result<Self> {
+        let last_modified = metadata(path)
+            .and_then(|x| x.modified())
+            .map(|x| DateTime::<Local>::from(x).naive_local().date())
+            .unwrap_or_else(|_| Local::today().naive_local());
+        let size = metadata(path).map(|x| x.len()).unwrap_or(0);
+        if size >= rotation.max_bytes
+            || (rotation.daily && last_modified < Local::today().naive_local())
+        {
+            rotate(path, rotation.keep)?;
+        }
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file: open_log_file(path)?,
+            size: metadata(path).map(|x| x.len()).unwrap_or(0),
+            opened_on: Local::today().naive_local(),
+            rotation,
+        })
+    }
+
+    fn needs_rotation(&self) -> bool {
+        self.size >= self.rotation.max_bytes
+            || (self.rotation.daily && self.opened_on < Local::today().naive_local())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation() {
+            rotate(&self.path, self.rotation.keep)
+                .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+            self.file =
+                open_log_file(&self.path).map_err(|e| io::Error::other(format!("{:?}", e)))?;
+            self.size = 0;
+            self.opened_on = Local::today().naive_local();
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_log_file(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|_| ErrorKind::LoggingFileCanNotOpen(path.to_string_lossy().into()))?;
+    Ok(file)
+}
+
+// Shift `file` -> `file.1` -> `file.2` ..., keeping at most `keep` old files
+// around.
+fn rotate(file: &Path, keep: usize) -> Result<()> {
+    let rotated = |i: usize| PathBuf::from(format!("{}.{}", file.to_string_lossy(), i));
+    let oldest = rotated(keep);
+    if oldest.exists() {
+        remove_file(&oldest).with_context(|_| {
+            ErrorKind::LoggingFileCanNotRotate(oldest.to_string_lossy().into())
+        })?;
+    }
+    for i in (1..keep).rev() {
+        if rotated(i).exists() {
+            rename(rotated(i), rotated(i + 1)).with_context(|_| {
+                ErrorKind::LoggingFileCanNotRotate(file.to_string_lossy().into())
+            })?;
+        }
+    }
+    if keep == 0 {
+        remove_file(file)
+            .with_context(|_| ErrorKind::LoggingFileCanNotRotate(file.to_string_lossy().into()))?;
+    } else if file.exists() {
+        rename(file, rotated(1))
+            .with_context(|_| ErrorKind::LoggingFileCanNotRotate(file.to_string_lossy().into()))?;
+    }
+
+    Ok(())
+}
+
+pub fn setup_logging(_for_crate: String, log_level: Option<Level>, cli: &Cli) -> Result<()> {
     let log_level_filter = log_level.unwrap_or(Level::Trace).to_level_filter();
 
-    let logging = fern::Dispatch::new()
+    let stdout = fern::Dispatch::new()
         .level(log_level_filter)
+        .filter(|metadata| metadata.target() != HTTP_TARGET)
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "[{}][{}][{}] {}",
@@ -17,10 +178,62 @@ pub fn setup_logging(_for_crate: String, log_level: Option<Level>) -> Result<()>
                 message
             ))
         })
-        .chain(io::stdout())
-        .apply();
+        .chain(io::stdout());
+
+    let mut logging = fern::Dispatch::new().chain(stdout);
+
+    if let Some(log_file) = &cli.log_file {
+        let log_file = if log_file.is_relative() {
+            state_dir()?.join(log_file)
+        } else {
+            log_file.clone()
+        };
+        let rotation = Rotation {
+            max_bytes: cli.log_max_size * 1024 * 1024,
+            daily: cli.log_rotate_daily || env_flag("YNAB_SYNC_LOG_ROTATE_DAILY"),
+            keep: cli.log_keep,
+        };
+        let file: Box<dyn Write + Send> = Box::new(RotatingFile::new(&log_file, rotation)?);
+        logging = logging.chain(
+            fern::Dispatch::new()
+                .level(log_level_filter)
+                .filter(|metadata| metadata.target() != HTTP_TARGET)
+                .format(move |out, message, record| {
+                    out.finish(format_args!(
+                        "[{}][{}][{}] {}",
+                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        record.target(),
+                        record.level(),
+                        message
+                    ))
+                })
+                .chain(file),
+        );
+    }
+
+    if let Some(http_log) = &cli.http_log {
+        let rotation = Rotation {
+            max_bytes: HTTP_LOG_MAX_BYTES,
+            daily: false,
+            keep: HTTP_LOG_KEEP,
+        };
+        let file: Box<dyn Write + Send> = Box::new(RotatingFile::new(http_log, rotation)?);
+        logging = logging.chain(
+            fern::Dispatch::new()
+                .level(LevelFilter::Off)
+                .level_for(HTTP_TARGET, LevelFilter::Trace)
+                .format(move |out, message, _record| {
+                    out.finish(format_args!(
+                        "[{}] {}",
+                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        message
+                    ))
+                })
+                .chain(file),
+        );
+    }
 
-    match logging {
+    match logging.apply() {
         Err(_) => Err(ErrorKind::LoggingSetupFailed)?,
         Ok(_) => Ok(()),
     }
@@ -0,0 +1,88 @@
+use crate::fuzzy::similarity;
+use crate::ynab::Transaction;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+// Payees at least this similar are considered the same (eg. `REWE` and
+// `REWE Markt GmbH`).
+const MIN_PAYEE_SIMILARITY: f64 = 0.8;
+
+/// Links bank transactions with transactions entered in YNAB by hand. Those
+/// have no import id, so the bank transaction would be created next to
+/// them. A manual transaction of the same amount, at most `window_days`
+/// apart and with a similar payee (when both have one) is updated instead:
+/// it gets the date and cleared state of the bank, payee, category and memo
+/// entered by hand are kept.
+#[derive(Clone, Debug)]
+pub struct ManualMatcher {
+    window_days: i64,
+}
+
+impl ManualMatcher {
+    pub fn new(window_days: i64) -> Self {
+        ManualMatcher { window_days }
+    }
+
+    /// Link the new ones of `transactions` (not in `existing_transactions`)
+    /// with `manual_transactions`. Linked transactions are turned into
+    /// updates of the manual ones, which are added to
+    /// `existing_transactions` under the import id. Every manual
+    /// transaction is linked at most once, with the closest one by date.
+    /// Returns the number of linked transactions.
+    pub fn link(
+        &self,
+        transactions: &mut [Transaction],
+        existing_transactions: &mut HashMap<String, Transaction>,
+        manual_transactions: &[Transaction],
+    ) -> usize {
+        let mut linked = vec![false; manual_transactions.len()];
+        for transaction in transactions.iter_mut() {
+            let import_id = match &transaction.import_id {
+                Some(x) if !existing_transactions.contains_key(x) => x.clone(),
+                _ => continue,
+            };
+            let date = match parse_date(transaction) {
+                Some(x) => x,
+                None => continue,
+            };
+            let closest = manual_transactions
+                .iter()
+                .enumerate()
+                .filter(|(i, x)| {
+                    !linked[*i] && x.amount == transaction.amount && similar_payees(x, transaction)
+                })
+                .filter_map(|(i, x)| Some((i, (parse_date(x)? - date).num_days().abs())))
+                .filter(|(_, days)| *days <= self.window_days)
+                .min_by_key(|(_, days)| *days);
+            if let Some((i, _)) = closest {
+                linked[i] = true;
+                let manual = &manual_transactions[i];
+                *transaction = Transaction {
+                    id: manual.id.clone(),
+                    payee_id: manual.payee_id.clone(),
+                    payee_name: manual.payee_name.clone(),
+                    category_id: manual.category_id.clone(),
+                    memo: manual.memo.clone().or(transaction.memo.take()),
+                    approved: manual.approved,
+                    flag_color: manual.flag_color.clone(),
+                    // YNAB doesn't update subtransactions
+                    subtransactions: vec![],
+                    ..transaction.clone()
+                };
+                existing_transactions.insert(import_id, manual.clone());
+            }
+        }
+        linked.iter().filter(|x| **x).count()
+    }
+}
+
+fn parse_date(transaction: &Transaction) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&transaction.date, "%Y-%m-%d").ok()
+}
+
+fn similar_payees(a: &Transaction, b: &Transaction) -> bool {
+    match (&a.payee_name, &b.payee_name) {
+        (Some(a), Some(b)) => similarity(a, b) >= MIN_PAYEE_SIMILARITY,
+        _ => true,
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{ErrorKind, Result};
+use std::collections::HashMap;
+
+/// Template used to build the memo of YNAB transactions, eg.
+///
+/// ```text
+/// {reference|merchant city}
+/// ```
+///
+/// - `{field}` is replaced with the value of `field`
+/// - `{a|b}` is replaced with `a`, or with `b` when `a` is empty
+/// - `{a b}` is replaced with `a` and `b` separated by a space, skipping the
+///   empty ones
+///
+/// Whitespace is collapsed after rendering and an empty memo is omitted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoTemplate {
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Placeholder(Vec<Vec<String>>),
+}
+
+impl MemoTemplate {
+    /// Parse `template`, allowing only placeholders listed in `fields`.
+    pub fn parse(template: &str, fields: &[&str]) -> Result<Self> {
+        let invalid =
+            |reason: String| ErrorKind::ArgParseMemoTemplate(template.to_string(), reason);
+
+        let mut tokens = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_string()));
+            }
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => Err(invalid("missing closing }".to_string()))?,
+            };
+            let alternatives: Vec<Vec<String>> = rest[start + 1..end]
+                .split('|')
+                .map(|x| x.split_whitespace().map(String::from).collect())
+                .collect();
+            for field in alternatives.iter().flatten() {
+                if !fields.contains(&field.as_str()) {
+                    Err(invalid(format!(
+                        "unknown placeholder {}, use one of: {}",
+                        field,
+                        fields.join(", ")
+                    )))?
+                }
+            }
+            if alternatives.iter().any(|x| x.is_empty()) {
+                Err(invalid("empty placeholder".to_string()))?
+            }
+            tokens.push(Token::Placeholder(alternatives));
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            Err(invalid("missing opening {".to_string()))?
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Literal(rest.to_string()));
+        }
+
+        Ok(MemoTemplate { tokens })
+    }
+
+    pub fn render(&self, values: &HashMap<&str, Option<String>>) -> Option<String> {
+        let value = |field: &String| -> String {
+            values
+                .get(field.as_str())
+                .cloned()
+                .unwrap_or(None)
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+
+        let mut memo = String::new();
+        for token in &self.tokens {
+            match token {
+                Token::Literal(literal) => memo.push_str(literal),
+                Token::Placeholder(alternatives) => {
+                    let rendered = alternatives
+                        .iter()
+                        .map(|fields| {
+                            fields
+                                .iter()
+                                .map(value)
+                                .filter(|x| !x.is_empty())
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        })
+                        .find(|x| !x.is_empty())
+                        .unwrap_or_default();
+                    memo.push_str(&rendered);
+                }
+            }
+        }
+
+        let memo = memo.split_whitespace().collect::<Vec<_>>().join(" ");
+        if memo.is_empty() {
+            None
+        } else {
+            Some(memo)
+        }
+    }
+}
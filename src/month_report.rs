@@ -0,0 +1,54 @@
+use crate::ynab::{Category, Month, Transaction};
+use std::collections::HashSet;
+
+/// Print how syncing changed the budget month: "Ready to Assign", the
+/// activity of the categories of `transactions` and the age of money.
+pub fn print(before: &Month, after: &Month, transactions: &[Transaction]) {
+    println!("Budget month {}:", after.month);
+    println!(
+        " - | Ready to Assign | {:>10.2} EUR | {:>+10.2} EUR",
+        (after.to_be_budgeted as f64 / 1000.0),
+        ((after.to_be_budgeted - before.to_be_budgeted) as f64 / 1000.0),
+    );
+
+    let affected: HashSet<&String> = transactions
+        .iter()
+        .flat_map(|x| {
+            x.subtransactions
+                .iter()
+                .filter_map(|x| x.category_id.as_ref())
+                .chain(x.category_id.as_ref())
+        })
+        .collect();
+    let mut categories: Vec<&Category> = after
+        .categories
+        .iter()
+        .filter(|x| affected.contains(&x.id))
+        .collect();
+    categories.sort_by(|a, b| a.name.cmp(&b.name));
+    for category in categories {
+        let activity_before = before
+            .categories
+            .iter()
+            .find(|x| x.id == category.id)
+            .map(|x| x.activity)
+            .unwrap_or(0);
+        println!(
+            " - | {} | activity {:>10.2} EUR | {:>+10.2} EUR | available {:.2} EUR",
+            category.name,
+            (category.activity as f64 / 1000.0),
+            ((category.activity - activity_before) as f64 / 1000.0),
+            (category.balance as f64 / 1000.0),
+        );
+    }
+
+    let age_of_money = |x: Option<i64>| match x {
+        Some(days) => format!("{} days", days),
+        None => "unknown".to_string(),
+    };
+    println!(
+        " - | Age of money | {} (was {})",
+        age_of_money(after.age_of_money),
+        age_of_money(before.age_of_money),
+    );
+}
@@ -1,21 +1,38 @@
-use crate::convert_to_int;
+use crate::encryption;
+use crate::http::{self, Client, Request, Response};
+use crate::notify::{self, Notification};
+use crate::parse::{deserialize_milliunits, deserialize_option_milliunits};
+use crate::source::{BankSource, SourceTransaction};
 use crate::{ErrorKind, Result};
 use chrono::serde::ts_milliseconds;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
 use dirs::cache_dir;
 use failure::ResultExt;
 use log::{debug, info};
 use reqwest::header;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env::current_dir;
-use std::fs::{read_to_string, write};
+use std::fmt;
+use std::fs::{read_dir, read_to_string, remove_file, write};
+use std::path::{Path, PathBuf};
+use std::result;
+use std::str::FromStr;
+use std::sync::OnceLock;
 use std::thread::sleep;
 use std::time;
 use structopt::StructOpt;
 
-const API_URL: &str = "https://api.tech26.de";
+pub const API_URL: &str = "https://api.tech26.de";
 const API_BASIC_AUTH_HEADER: &str = "Basic YW5kcm9pZDpzZWNyZXQ=";
+const TOKEN_FILE_PREFIX: &str = "ynab-sync-n26-token";
+// single token file of older versions
+const LEGACY_TOKEN_FILE: &str = "ynab-sync-token-data.json";
+// seconds between checks whether the login was approved
+const MFA_POLL_SECONDS: u64 = 5;
 const API_USER_AGENT : &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/59.0.3071.86 Safari/537.36";
 
 #[derive(StructOpt, Debug)]
@@ -36,15 +53,82 @@ pub struct Cli {
         help = "Password that you use to login to https://app.n26.com"
     )]
     pub password: String,
+    #[structopt(
+        long = "n26-token-cache",
+        value_name = "FILE",
+        env = "N26_TOKEN_CACHE",
+        help = "File in which the N26 tokens are kept between runs. Defaults to a file per --n26-username in the cache directory."
+    )]
+    pub token_cache: Option<String>,
+    #[structopt(
+        long = "n26-device-token",
+        value_name = "UUID",
+        env = "N26_DEVICE_TOKEN",
+        hide_env_values = true,
+        help = "Device token sent with logins, so N26 sees them coming from the same (paired) device. Any UUID, keep it the same between runs."
+    )]
+    pub device_token: Option<String>,
+    #[structopt(
+        long = "n26-mfa-timeout",
+        value_name = "SECONDS",
+        default_value = "60",
+        env = "N26_MFA_TIMEOUT",
+        help = "How long to wait for the login to be approved in the N26 app. With 0 a login needing an approval fails right away (eg. on servers relying on stored tokens). Either failure exits with code 75."
+    )]
+    pub mfa_timeout: u64,
+}
+
+static TOKEN_CACHE: OnceLock<PathBuf> = OnceLock::new();
+static DEVICE_TOKEN: OnceLock<String> = OnceLock::new();
+static MFA_TIMEOUT: OnceLock<u64> = OnceLock::new();
+
+/// Keep the N26 tokens in --n26-token-cache instead of the default file and
+/// log in with --n26-device-token and --n26-mfa-timeout.
+pub fn setup(cli: &Cli) {
+    // the first configuration wins
+    if let Some(token_cache) = &cli.token_cache {
+        let _ = TOKEN_CACHE.set(PathBuf::from(token_cache));
+    }
+    if let Some(device_token) = &cli.device_token {
+        let _ = DEVICE_TOKEN.set(device_token.clone());
+    }
+    let _ = MFA_TIMEOUT.set(cli.mfa_timeout);
+}
+
+/// Seconds to wait for a login approval, 60 unless configured.
+fn mfa_timeout() -> u64 {
+    MFA_TIMEOUT.get().copied().unwrap_or(60)
+}
+
+/// Add the `device-token` header to a login request, when configured.
+fn with_device_token(request: Request) -> Request {
+    match DEVICE_TOKEN.get() {
+        Some(device_token) => request.header(
+            header::HeaderName::from_static("device-token"),
+            device_token,
+        ),
+        None => request,
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct N26 {
+    #[serde(skip, default = "default_api_url")]
+    pub api_url: String,
+
     pub expiration_time: i64,
 
     pub access_token: String,
 
     pub refresh_token: String,
+
+    /// Account the tokens belong to, empty for tokens stored by older
+    /// versions.
+    #[serde(default)]
+    pub username: String,
+
+    #[serde(skip)]
+    pub client: Option<Client>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,6 +156,40 @@ pub struct Category {
     name: String,
 }
 
+/// Balances of the N26 account in milliunits.
+#[derive(Debug, Deserialize)]
+pub struct Balance {
+    /// Balance including pending transactions.
+    #[serde(
+        rename = "availableBalance",
+        deserialize_with = "deserialize_milliunits"
+    )]
+    pub available_balance: i32,
+
+    #[serde(rename = "usableBalance", deserialize_with = "deserialize_milliunits")]
+    pub usable_balance: i32,
+
+    /// Balance of the settled transactions.
+    #[serde(
+        rename = "bankBalance",
+        default,
+        deserialize_with = "deserialize_option_milliunits"
+    )]
+    pub bank_balance: Option<i32>,
+}
+
+impl Balance {
+    /// Balance the cleared balance in YNAB should match when pending
+    /// transactions are synced with `pending`, `None` when N26 doesn't tell
+    /// the balance of the settled transactions.
+    pub fn cleared(&self, pending: PendingMode) -> Option<i32> {
+        match pending {
+            PendingMode::Cleared => Some(self.available_balance),
+            _ => self.bank_balance,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Transaction {
     pub id: String,
@@ -80,22 +198,24 @@ pub struct Transaction {
     pub user_id: String,
 
     #[serde(rename = "type")]
-    pub type_: String, // XXX: enum
+    pub type_: TransactionType,
 
-    #[serde(deserialize_with = "convert_to_int")]
+    #[serde(deserialize_with = "deserialize_milliunits")]
     pub amount: i32,
 
     #[serde(rename = "currencyCode")]
-    pub currency_code: String, // XXX: enum
+    pub currency_code: Currency,
+
+    #[serde(
+        rename = "originalAmount",
+        default,
+        deserialize_with = "deserialize_option_milliunits"
+    )]
+    pub original_amount: Option<i32>,
+
+    #[serde(rename = "originalCurrency")]
+    pub original_currency: Option<Currency>,
 
-    // TODO: Doesn't work with Option
-    //
-    // #[serde(rename = "originalAmount", deserialize_with = "convert_to_int")]
-    // pub original_amount: Option<i32>,
-    //
-    //#[serde(rename = "originalCurrency")]
-    //pub original_currency: String, // XXX: enum
-    //
     #[serde(rename = "exchangeRate")]
     pub exchange_rate: Option<f64>,
 
@@ -147,7 +267,7 @@ pub struct Transaction {
     pub pending: bool,
 
     #[serde(rename = "transactionNature")]
-    pub transaction_nature: String, // XXX: enum
+    pub transaction_nature: TransactionNature,
 
     #[serde(rename = "createdTS", with = "ts_milliseconds")]
     pub created_ts: DateTime<Utc>,
@@ -163,115 +283,420 @@ pub struct Transaction {
 
     #[serde(rename = "confirmed", with = "ts_milliseconds")]
     pub confirmed: DateTime<Utc>,
+
+    #[serde(rename = "transactionTerminal", default)]
+    pub transaction_terminal: Option<String>, // XXX: enum
 }
 
-fn complete_mfa_approval(mfa_token: String) -> Result<N26> {
-    info!("Calling complete_mfa_approval");
+/// Type of an N26 transaction, types not known here are kept as `Other`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TransactionType {
+    /// Card payment (`PT`).
+    CardPayment,
+    /// Card payment which isn't settled yet (`AA`).
+    CardAuthorization,
+    /// Reversed card authorization (`AV`).
+    CardReversal,
+    /// Card refund (`AE`).
+    CardRefund,
+    /// Incoming transfer (`CT`).
+    IncomingTransfer,
+    /// Outgoing transfer (`DT`).
+    OutgoingTransfer,
+    /// Direct debit (`DD`).
+    DirectDebit,
+    /// Returned direct debit (`DR`).
+    DirectDebitReversal,
+    Other(String),
+}
 
-    let client = reqwest::Client::new();
+impl From<String> for TransactionType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "PT" => TransactionType::CardPayment,
+            "AA" => TransactionType::CardAuthorization,
+            "AV" => TransactionType::CardReversal,
+            "AE" => TransactionType::CardRefund,
+            "CT" => TransactionType::IncomingTransfer,
+            "DT" => TransactionType::OutgoingTransfer,
+            "DD" => TransactionType::DirectDebit,
+            "DR" => TransactionType::DirectDebitReversal,
+            _ => TransactionType::Other(s),
+        }
+    }
+}
+
+impl From<TransactionType> for String {
+    fn from(type_: TransactionType) -> Self {
+        type_.to_string()
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TransactionType::CardPayment => "PT",
+                TransactionType::CardAuthorization => "AA",
+                TransactionType::CardReversal => "AV",
+                TransactionType::CardRefund => "AE",
+                TransactionType::IncomingTransfer => "CT",
+                TransactionType::OutgoingTransfer => "DT",
+                TransactionType::DirectDebit => "DD",
+                TransactionType::DirectDebitReversal => "DR",
+                TransactionType::Other(x) => x,
+            },
+        )
+    }
+}
+
+impl TransactionType {
+    /// Readable name, eg. for memos.
+    pub fn name(&self) -> &str {
+        match self {
+            TransactionType::CardPayment => "card payment",
+            TransactionType::CardAuthorization => "card authorization",
+            TransactionType::CardReversal => "card reversal",
+            TransactionType::CardRefund => "card refund",
+            TransactionType::IncomingTransfer => "incoming transfer",
+            TransactionType::OutgoingTransfer => "outgoing transfer",
+            TransactionType::DirectDebit => "direct debit",
+            TransactionType::DirectDebitReversal => "direct debit reversal",
+            TransactionType::Other(x) => x,
+        }
+    }
+}
+
+/// ISO 4217 currency code, currencies not known here are kept as `Other`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Currency {
+    EUR,
+    USD,
+    GBP,
+    CHF,
+    Other(String),
+}
+
+impl From<String> for Currency {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "EUR" => Currency::EUR,
+            "USD" => Currency::USD,
+            "GBP" => Currency::GBP,
+            "CHF" => Currency::CHF,
+            _ => Currency::Other(s),
+        }
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.to_string()
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Currency::EUR => "EUR",
+                Currency::USD => "USD",
+                Currency::GBP => "GBP",
+                Currency::CHF => "CHF",
+                Currency::Other(x) => x,
+            },
+        )
+    }
+}
+
+/// Nature of an N26 transaction, natures not known here are kept as
+/// `Other`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TransactionNature {
+    Normal,
+    Other(String),
+}
+
+impl From<String> for TransactionNature {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "NORMAL" => TransactionNature::Normal,
+            _ => TransactionNature::Other(s),
+        }
+    }
+}
+
+impl From<TransactionNature> for String {
+    fn from(nature: TransactionNature) -> Self {
+        nature.to_string()
+    }
+}
+
+impl fmt::Display for TransactionNature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TransactionNature::Normal => "NORMAL",
+                TransactionNature::Other(x) => x,
+            },
+        )
+    }
+}
+
+// Merchant category codes of ATM withdrawals.
+const ATM_MCCS: &[i32] = &[6010, 6011];
+
+/// Placeholders available in `--memo-template` for N26 transactions.
+pub const MEMO_FIELDS: &[&str] = &[
+    "type",
+    "reference",
+    "merchant",
+    "city",
+    "partner",
+    "iban",
+    "original_amount",
+    "exchange_rate",
+    "original",
+];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{reference|merchant city} {original}";
+
+/// How transactions which are still pending (card authorizations whose
+/// amount may change on settlement) are synced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PendingMode {
+    /// Synced as uncleared and cleared (with the settled amount) by the sync
+    /// after their settlement.
+    Uncleared,
+    /// Synced as cleared, like settled ones.
+    Cleared,
+    /// Synced only once settled.
+    Skip,
+}
+
+impl fmt::Display for PendingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                PendingMode::Uncleared => "uncleared",
+                PendingMode::Cleared => "cleared",
+                PendingMode::Skip => "skip",
+            },
+        )
+    }
+}
+
+impl FromStr for PendingMode {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "uncleared" => Ok(PendingMode::Uncleared),
+            "cleared" => Ok(PendingMode::Cleared),
+            "skip" => Ok(PendingMode::Skip),
+            _ => Err(ErrorKind::ArgParse(format!("--n26-pending {}", s))),
+        }
+    }
+}
+
+/// Number of transactions `get_transactions` fetches per request.
+pub const TRANSACTIONS_PAGE_SIZE: usize = 200;
+
+impl Transaction {
+    /// Day of the transaction in local time, as the N26 app shows it.
+    pub fn date(&self) -> NaiveDate {
+        self.visible_ts.with_timezone(&Local).date().naive_local()
+    }
+
+    /// Whether this is a withdrawal at an ATM.
+    pub fn is_cash_withdrawal(&self) -> bool {
+        self.amount < 0
+            && (self.transaction_terminal.as_deref() == Some("ATM")
+                || self.mcc.map(|x| ATM_MCCS.contains(&x)).unwrap_or(false)
+                || self.category == "micro-v2-atm")
+    }
+
+    /// Fee charged on top of the original amount (eg. by an ATM operator), as
+    /// a negative amount. Only known when the original amount is in the
+    /// account currency, currency conversion markups are part of the
+    /// exchange rate.
+    pub fn fee(&self) -> Option<i32> {
+        match (self.original_amount, &self.original_currency) {
+            (Some(original_amount), Some(currency))
+                if *currency == self.currency_code && self.amount < original_amount =>
+            {
+                Some(self.amount - original_amount)
+            }
+            _ => None,
+        }
+    }
+
+    /// Merchant of card payments, counterparty of transfers.
+    pub fn payee(&self) -> Option<&String> {
+        self.merchant_name.as_ref().or(self.partner_name.as_ref())
+    }
+
+    /// Values for the placeholders listed in `MEMO_FIELDS`.
+    pub fn memo_values(&self) -> HashMap<&'static str, Option<String>> {
+        let original_amount = match (&self.original_amount, &self.original_currency) {
+            (Some(amount), Some(currency)) if *currency != self.currency_code => {
+                Some(format!("{:.2} {}", amount.abs() as f64 / 1000.0, currency))
+            }
+            _ => None,
+        };
+
+        let exchange_rate = original_amount
+            .as_ref()
+            .and(self.exchange_rate)
+            .map(|x| format!("{}", x));
+
+        // eg. "(12.34 USD @ 0.92)"
+        let original = match (&original_amount, &exchange_rate) {
+            (Some(amount), Some(rate)) => Some(format!("({} @ {})", amount, rate)),
+            (Some(amount), None) => Some(format!("({})", amount)),
+            _ => None,
+        };
+
+        let mut values = HashMap::new();
+        values.insert("type", Some(self.type_.name().to_string()));
+        values.insert("reference", self.reference_text.clone());
+        values.insert("merchant", self.merchant_name.clone());
+        values.insert("city", self.merchant_city.clone());
+        values.insert("partner", self.partner_name.clone());
+        values.insert("iban", self.partner_iban.clone());
+        values.insert("original_amount", original_amount);
+        values.insert("exchange_rate", exchange_rate);
+        values.insert("original", original);
+        values
+    }
+}
+
+fn complete_mfa_approval(api_url: &str, client: Option<&Client>, mfa_token: String) -> Result<N26> {
+    info!("Calling complete_mfa_approval");
 
     let mut data = HashMap::new();
     data.insert("grant_type", "mfa_oob");
     data.insert("mfaToken", mfa_token.as_str());
 
-    let url = format!("{}/oauth/token", API_URL);
+    let url = format!("{}/oauth/token", api_url);
     debug!("Url to complete mfa is: {}", url);
-    let mut res = client
-        .post(&url)
+    let request = Request::post(&url)
+        .client(client)
         .header(header::AUTHORIZATION, API_BASIC_AUTH_HEADER)
         .header(header::USER_AGENT, API_USER_AGENT)
         .header(header::ACCEPT, "application/json")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .form(&data)
+        .form(&data);
+    let res = with_device_token(request)
         .send()
         .context(ErrorKind::N26AuthenticateCompleteMFA)?;
 
-    let body = res.text().context(ErrorKind::N26AuthenticateCompleteMFA)?;
-    debug!("{}", body);
+    let body = res.text();
 
     if res.status() == 200 {
         let data: TokenData = serde_json::from_str(&body)
             .with_context(|e| ErrorKind::N26AuthenticateCompleteMFAParse(e.to_string()))?;
         Ok(N26 {
+            api_url: api_url.to_string(),
             expiration_time: Utc::now().timestamp() + data.expires_in,
             access_token: data.access_token.clone(),
             refresh_token: data.refresh_token.clone(),
+            username: String::new(),
+            client: None,
         })
     } else {
         Err(ErrorKind::N26AuthenticateCompleteMFA)?
     }
 }
 
-fn request_mfa_approval(mfa_token: String) -> Result<N26> {
+fn request_mfa_approval(api_url: &str, client: Option<&Client>, mfa_token: String) -> Result<N26> {
     info!("Calling request_mfa_approval");
 
-    let client = reqwest::Client::new();
-
     let mut data = HashMap::new();
     data.insert("challengeType", "oob");
     data.insert("mfaToken", mfa_token.as_str());
 
-    let url = format!("{}/api/mfa/challenge", API_URL);
+    let url = format!("{}/api/mfa/challenge", api_url);
     debug!("Url to start mfa approval is: {}", url);
-    let mut res = client
-        .post(&url)
+    let request = Request::post(&url)
+        .client(client)
         .header(header::AUTHORIZATION, API_BASIC_AUTH_HEADER)
         .header(header::USER_AGENT, API_USER_AGENT)
         .header(header::ACCEPT, "application/json")
-        .header(header::CONTENT_TYPE, "application/json")
-        .json(&data)
+        .json(&data);
+    let res = with_device_token(request)
         .send()
         .context(ErrorKind::N26AuthenticateMfaApproval)?;
 
-    let body = res.text().context(ErrorKind::N26AuthenticateMfaApproval)?;
-    debug!("{}", body);
-
     if res.status() != 201 {
         Err(ErrorKind::N26AuthenticateMfaApproval)?
     } else {
-        let mut token = complete_mfa_approval(mfa_token.clone());
+        let timeout = mfa_timeout();
+        notify::send(&Notification::attention(
+            "N26 login needs approval",
+            &format!(
+                "Approve the login in the N26 app within the next {} seconds.",
+                timeout
+            ),
+        ));
+        let mut token = complete_mfa_approval(api_url, client, mfa_token.clone());
         if token.is_ok() {
             token
         } else {
-            for i in 1..13 {
-                debug!("Sleeping for 5 seconds");
-                sleep(time::Duration::from_secs(5));
-                token = complete_mfa_approval(mfa_token.clone());
+            let attempts = timeout / MFA_POLL_SECONDS;
+            for i in 1..=attempts {
+                if !http::is_replaying() {
+                    debug!("Sleeping for {} seconds", MFA_POLL_SECONDS);
+                    sleep(time::Duration::from_secs(MFA_POLL_SECONDS));
+                }
+                token = complete_mfa_approval(api_url, client, mfa_token.clone());
                 debug!("token data: {:?}", token);
                 if token.is_ok() {
                     break;
                 }
-                info!("Remaining {} seconds", (12 - i) * 5);
+                info!("Remaining {} seconds", (attempts - i) * MFA_POLL_SECONDS);
             }
-            token
+            Ok(token.context(ErrorKind::N26MfaTimeout(timeout))?)
         }
     }
 }
 
-fn new_authenticate(username: String, password: String) -> Result<N26> {
+fn new_authenticate(
+    api_url: &str,
+    client: Option<&Client>,
+    username: String,
+    password: String,
+) -> Result<N26> {
     info!("Calling new_authenticate");
 
-    let client = reqwest::Client::new();
-
     let mut data = HashMap::new();
     data.insert("grant_type", "password");
     data.insert("username", username.as_str());
     data.insert("password", password.as_str());
 
-    let url = format!("{}/oauth2/token", API_URL);
+    let url = format!("{}/oauth2/token", api_url);
     debug!("Url to start authorization is: {}", url);
-    let mut res = client
-        .post(&url)
+    let request = Request::post(&url)
+        .client(client)
         .header(header::AUTHORIZATION, API_BASIC_AUTH_HEADER)
         .header(header::USER_AGENT, API_USER_AGENT)
         .header(header::ACCEPT, "application/json")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .form(&data)
+        .form(&data);
+    let res = with_device_token(request)
         .send()
         .context(ErrorKind::N26AuthenticateNew)?;
 
-    let body = res.text().context(ErrorKind::N26AuthenticateNew)?;
-    debug!("{}", body);
+    let body = res.text();
 
     if res.status() != 403 {
         Err(ErrorKind::N26AuthenticateNew)?
@@ -281,13 +706,17 @@ fn new_authenticate(username: String, password: String) -> Result<N26> {
 
         if data.error != "mfa_required" {
             Err(ErrorKind::N26AuthenticateNew)?
+        } else if mfa_timeout() == 0 {
+            Err(ErrorKind::N26MfaRequired)?
         } else {
-            request_mfa_approval(data.mfa_token)
+            request_mfa_approval(api_url, client, data.mfa_token)
         }
     }
 }
 
 fn refresh_authenticate(
+    api_url: &str,
+    client: Option<&Client>,
     username: String,
     password: String,
     refresh_token: Option<String>,
@@ -295,146 +724,417 @@ fn refresh_authenticate(
     info!("Calling refresh_authenticate");
     debug!("refresh_token is: {:?}", refresh_token);
 
-    let client = reqwest::Client::new();
-
-    let n26 = if let Some(token) = refresh_token {
-        let mut data = HashMap::new();
-        data.insert("grant_type", "refresh_token");
-        data.insert("refresh_token", token.as_str());
-        debug!("{}", token);
-
-        let url = format!("{}/oauth/token", API_URL);
-        let mut res = client
-            .post(&url)
-            .header(header::AUTHORIZATION, API_BASIC_AUTH_HEADER)
-            .header(header::USER_AGENT, API_USER_AGENT)
-            .header(header::ACCEPT, "application/json")
-            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-            .form(&data)
-            .send()
-            .context(ErrorKind::N26AuthenticateRefreshToken)?;
-
-        let body = res.text().context(ErrorKind::N26AuthenticateRefreshToken)?;
-        debug!("{}", body);
-
-        if res.status() != 403 {
-            let data: TokenData = serde_json::from_str(&body)
-                .with_context(|e| ErrorKind::N26AuthenticateRefreshTokenParse(e.to_string()))?;
-            N26 {
-                expiration_time: Utc::now().timestamp() + data.expires_in,
-                access_token: data.access_token.clone(),
-                refresh_token: data.refresh_token.clone(),
-            }
-        } else {
-            new_authenticate(username, password)?
-        }
-    } else {
-        new_authenticate(username, password)?
+    let owner = username.clone();
+    let refreshed = match refresh_token {
+        Some(token) => refresh(api_url, client, &token)?,
+        None => None,
     };
+    let mut n26 = match refreshed {
+        Some(n26) => n26,
+        None => new_authenticate(api_url, client, username, password)?,
+    };
+    n26.username = owner;
+    n26.client = client.cloned();
+    save_token(&n26)?;
+    Ok(n26)
+}
+
+/// New tokens for `refresh_token`, `None` when N26 rejects it.
+fn refresh(api_url: &str, client: Option<&Client>, refresh_token: &str) -> Result<Option<N26>> {
+    let mut data = HashMap::new();
+    data.insert("grant_type", "refresh_token");
+    data.insert("refresh_token", refresh_token);
+
+    let url = format!("{}/oauth/token", api_url);
+    let request = Request::post(&url)
+        .client(client)
+        .header(header::AUTHORIZATION, API_BASIC_AUTH_HEADER)
+        .header(header::USER_AGENT, API_USER_AGENT)
+        .header(header::ACCEPT, "application/json")
+        .form(&data);
+    let res = with_device_token(request)
+        .send()
+        .context(ErrorKind::N26AuthenticateRefreshToken)?;
+
+    let body = res.text();
+
+    if res.status() == 403 {
+        return Ok(None);
+    }
+    let data: TokenData = serde_json::from_str(&body)
+        .with_context(|e| ErrorKind::N26AuthenticateRefreshTokenParse(e.to_string()))?;
+    Ok(Some(N26 {
+        api_url: api_url.to_string(),
+        expiration_time: Utc::now().timestamp() + data.expires_in,
+        access_token: data.access_token.clone(),
+        refresh_token: data.refresh_token.clone(),
+        username: String::new(),
+        client: None,
+    }))
+}
+
+fn save_token(n26: &N26) -> Result<()> {
+    // recorded tokens are redacted, don't overwrite the real ones with them
+    if http::is_replaying() {
+        return Ok(());
+    }
 
     // save token to file
-    let mut config_file = cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
-    config_file.push("ynab-sync-token-data.json");
+    let config_file = token_file(&n26.username)?;
     info!("Cache token file is: {}", config_file.to_string_lossy());
 
     let config_file_content =
-        serde_json::to_string(&n26).context(ErrorKind::N26WritingToTokenFile)?;
+        serde_json::to_string(n26).context(ErrorKind::N26WritingToTokenFile)?;
+    let config_file_content =
+        encryption::encrypt(&config_file_content).context(ErrorKind::N26WritingToTokenFile)?;
 
     write(config_file, config_file_content).context(ErrorKind::N26WritingToTokenFile)?;
 
-    Ok(n26)
+    // tokens moved from the single file of older versions
+    let legacy = token_dir()?.join(LEGACY_TOKEN_FILE);
+    if TOKEN_CACHE.get().is_none()
+        && N26::load(&legacy).is_ok_and(|x| x.username.is_empty() || x.username == n26.username)
+    {
+        remove_file(&legacy).context(ErrorKind::N26WritingToTokenFile)?;
+    }
+
+    Ok(())
+}
+
+fn token_dir() -> Result<PathBuf> {
+    Ok(cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?))
+}
+
+/// File in which the N26 tokens of `username` are kept between runs,
+/// --n26-token-cache or a file named after the hashed username.
+pub fn token_file(username: &str) -> Result<PathBuf> {
+    if let Some(token_cache) = TOKEN_CACHE.get() {
+        return Ok(token_cache.clone());
+    }
+    let mut sha = Sha256::new();
+    sha.input_str(username);
+    let hash: String = sha.result_str().chars().take(16).collect();
+    Ok(token_dir()?.join(format!("{}-{}.json", TOKEN_FILE_PREFIX, hash)))
+}
+
+/// Files with N26 tokens, of every account.
+pub fn token_files() -> Result<Vec<PathBuf>> {
+    if let Some(token_cache) = TOKEN_CACHE.get() {
+        return Ok(vec![token_cache.clone()]
+            .into_iter()
+            .filter(|x| x.exists())
+            .collect());
+    }
+    let dir = token_dir()?;
+    let mut files: Vec<PathBuf> = match read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| {
+                x.file_name()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| x.starts_with(TOKEN_FILE_PREFIX) && x.ends_with(".json"))
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    let legacy = dir.join(LEGACY_TOKEN_FILE);
+    if legacy.exists() {
+        files.push(legacy);
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn default_api_url() -> String {
+    API_URL.to_string()
 }
 
 impl N26 {
     pub fn new(username: String, password: String) -> Result<Self> {
-        let mut config_file = cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
-        config_file.push("ynab-sync-token-data.json");
-        info!("Cache token file is: {}", config_file.to_string_lossy());
+        N26::with_api_url(API_URL, username, password)
+    }
+
+    /// Like `N26::new` but talking to a different API (eg. a mock server).
+    pub fn with_api_url(api_url: &str, username: String, password: String) -> Result<Self> {
+        N26::authenticate(api_url, None, username, password)
+    }
+
+    /// Like `N26::new` but sending requests with `client` instead of the
+    /// shared one.
+    pub fn with_client(client: Client, username: String, password: String) -> Result<Self> {
+        N26::authenticate(API_URL, Some(&client), username, password)
+    }
 
-        let n26 = if config_file.exists() {
-            let n26_string =
-                read_to_string(config_file).context(ErrorKind::N26TokenDataFileCanNotRead)?;
-            let n26: N26 = serde_json::from_str(&n26_string)
-                .context(ErrorKind::N26TokenDataFileCanNotParse)?;
+    fn authenticate(
+        api_url: &str,
+        client: Option<&Client>,
+        username: String,
+        password: String,
+    ) -> Result<Self> {
+        let cached = N26::cached(&username)?;
+        let n26 = if let Some(mut n26) = cached {
+            n26.api_url = api_url.to_string();
+            n26.client = client.cloned();
 
             if n26.is_valid() {
                 info!("Using token from file");
                 n26
             } else {
-                refresh_authenticate(username, password, Some(n26.refresh_token))?
+                refresh_authenticate(api_url, client, username, password, Some(n26.refresh_token))?
             }
         } else {
-            refresh_authenticate(username, password, None)?
+            refresh_authenticate(api_url, client, username, password, None)?
         };
 
         Ok(n26)
     }
 
-    pub fn is_valid(self: &Self) -> bool {
-        Utc::now().timestamp() < self.expiration_time
+    /// Tokens of `username` stored by a previous run, if any. Tokens stored
+    /// in the single file of older versions are used when they belong to
+    /// `username`, they're moved to its own file when saved.
+    pub fn cached(username: &str) -> Result<Option<Self>> {
+        let config_file = token_file(username)?;
+        info!("Cache token file is: {}", config_file.to_string_lossy());
+        if config_file.exists() {
+            // --n26-token-cache may hold tokens of another account
+            return Ok(Some(N26::load(&config_file)?)
+                .filter(|x| x.username.is_empty() || x.username == username));
+        }
+        let legacy = token_dir()?.join(LEGACY_TOKEN_FILE);
+        if TOKEN_CACHE.get().is_none() && legacy.exists() {
+            return Ok(Some(N26::load(&legacy)?)
+                .filter(|x| x.username.is_empty() || x.username == username));
+        }
+        Ok(None)
     }
 
-    pub fn get_categories(self: &Self) -> Result<HashMap<String, String>> {
-        let url = format!("{}/api/smrt/categories", API_URL);
+    /// Tokens stored in `token_file` (see `token_files`).
+    pub fn load(token_file: &Path) -> Result<Self> {
+        let n26_string =
+            read_to_string(token_file).context(ErrorKind::N26TokenDataFileCanNotRead)?;
+        let n26_string =
+            encryption::decrypt(&n26_string).context(ErrorKind::N26TokenDataFileCanNotRead)?;
+        let n26: N26 =
+            serde_json::from_str(&n26_string).context(ErrorKind::N26TokenDataFileCanNotParse)?;
+        Ok(n26)
+    }
 
-        let client = reqwest::Client::new();
-        let authorization = format!("Bearer {}", self.access_token);
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
-            .send()
-            .context(ErrorKind::N26GetCategories)?;
+    /// Refresh the tokens stored in `token_file` without logging in again
+    /// (which needs an approval in the app), so long running processes keep
+    /// them usable. Returns the new tokens.
+    pub fn refresh_cached(token_file: &Path) -> Result<Self> {
+        let n26 = N26::load(token_file)?;
+        let mut refreshed = refresh(API_URL, None, &n26.refresh_token)?
+            .ok_or(ErrorKind::N26AuthenticateRefreshToken)?;
+        refreshed.username = n26.username;
+        save_token(&refreshed)?;
+        Ok(refreshed)
+    }
 
-        let body = res.text().context(ErrorKind::N26GetCategories)?;
-        debug!("{}", body);
+    pub fn is_valid(&self) -> bool {
+        Utc::now().timestamp() < self.expiration_time
+    }
 
-        if !res.status().is_success() {
-            let http_error = ErrorKind::N26GetCategoriesHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
-        }
+    pub fn get_categories(&self) -> Result<HashMap<String, String>> {
+        let url = format!("{}/api/smrt/categories", self.api_url);
+        let res = self
+            .get_request(&url)
+            .send()
+            .context(ErrorKind::N26GetCategories)?;
+        parse_categories(&res)
+    }
 
-        let categories_vec: Vec<Category> = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::N26GetCategoriesParse(e.to_string()))?;
+    pub fn get_balance(&self) -> Result<Balance> {
+        let url = format!("{}/api/accounts", self.api_url);
+        let res = self
+            .get_request(&url)
+            .send()
+            .context(ErrorKind::N26GetBalance)?;
+        parse_response(
+            &res,
+            ErrorKind::N26GetBalanceHttp,
+            ErrorKind::N26GetBalanceParse,
+        )
+    }
 
-        let mut categories = HashMap::new();
-        for category in &categories_vec {
-            categories.insert(category.id.clone(), category.name.clone());
+    /// Transactions of the last `days` days (and today), newest first.
+    /// They're fetched in pages of `TRANSACTIONS_PAGE_SIZE`, each continuing
+    /// after the last transaction of the previous one.
+    pub fn get_transactions(&self, days: i64) -> Result<Vec<Transaction>> {
+        let (from, to) = transactions_range(days);
+        let mut transactions: Vec<Transaction> = vec![];
+        loop {
+            let last_id = transactions.last().map(|x| x.id.clone());
+            let url = self.transactions_page_url(from, to, last_id.as_deref());
+            let res = self
+                .get_request(&url)
+                .send()
+                .context(ErrorKind::N26GetTransactions)?;
+            if add_transactions_page(&mut transactions, &res)? {
+                break;
+            }
         }
 
-        Ok(categories)
+        Ok(transactions)
     }
 
-    pub fn get_transactions(self: &Self, days: i64, limit: i64) -> Result<Vec<Transaction>> {
-        let now = Utc::now();
-        let days_ago = now - Duration::days(days);
-
-        // `from` and `to` have to be used together.
-        let from = days_ago.timestamp_millis();
-        let to = now.timestamp_millis();
-        let url = format!(
+    fn transactions_page_url(&self, from: i64, to: i64, last_id: Option<&str>) -> String {
+        let mut url = format!(
             "{}/api/smrt/transactions?from={}&to={}&limit={}",
-            API_URL, from, to, limit
+            self.api_url, from, to, TRANSACTIONS_PAGE_SIZE
         );
+        if let Some(last_id) = last_id {
+            url.push_str(&format!("&lastId={}", last_id));
+        }
+        url
+    }
 
-        let client = reqwest::Client::new();
+    fn get_request(&self, url: &str) -> Request {
         let authorization = format!("Bearer {}", self.access_token);
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
-            .send()
-            .context(ErrorKind::N26GetTransactions)?;
+        Request::get(url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+    }
+}
 
-        let body = res.text().context(ErrorKind::N26GetTransactions)?;
-        debug!("{}", body);
+/// `async fn` variants of the getters.
+#[cfg(feature = "async")]
+impl N26 {
+    pub async fn get_categories_async(&self) -> Result<HashMap<String, String>> {
+        let url = format!("{}/api/smrt/categories", self.api_url);
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::N26GetCategories)?;
+        parse_categories(&res)
+    }
 
-        if !res.status().is_success() {
-            let http_error = ErrorKind::N26GetTransactionsHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
-        }
+    pub async fn get_balance_async(&self) -> Result<Balance> {
+        let url = format!("{}/api/accounts", self.api_url);
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::N26GetBalance)?;
+        parse_response(
+            &res,
+            ErrorKind::N26GetBalanceHttp,
+            ErrorKind::N26GetBalanceParse,
+        )
+    }
 
-        let transactions = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::N26GetTransactionsParse(e.to_string()))?;
+    /// See `get_transactions`, the pages are fetched one after another.
+    pub async fn get_transactions_async(&self, days: i64) -> Result<Vec<Transaction>> {
+        let (from, to) = transactions_range(days);
+        let mut transactions: Vec<Transaction> = vec![];
+        loop {
+            let last_id = transactions.last().map(|x| x.id.clone());
+            let url = self.transactions_page_url(from, to, last_id.as_deref());
+            let res = self
+                .get_request(&url)
+                .send_async()
+                .await
+                .context(ErrorKind::N26GetTransactions)?;
+            if add_transactions_page(&mut transactions, &res)? {
+                break;
+            }
+        }
 
         Ok(transactions)
     }
 }
+
+/// `from` and `to` (which have to be used together) of the transactions of
+/// the last `days` days.
+fn transactions_range(days: i64) -> (i64, i64) {
+    let now = Utc::now();
+    // whole days, with a part of the first day the occurrences of YNAB
+    // import ids (see `import_id`) would depend on the time of the sync
+    let first_day = Local::today() - Duration::days(days);
+    (
+        first_day.and_hms(0, 0, 0).timestamp_millis(),
+        now.timestamp_millis(),
+    )
+}
+
+/// Add the page of transactions of `res` to `transactions`. Returns whether
+/// it was the last page.
+fn add_transactions_page(transactions: &mut Vec<Transaction>, res: &Response) -> Result<bool> {
+    let page: Vec<Transaction> = parse_response(
+        res,
+        ErrorKind::N26GetTransactionsHttp,
+        ErrorKind::N26GetTransactionsParse,
+    )?;
+    let page_size = page.len();
+    // an API ignoring `lastId` would return the same page again
+    let known: HashSet<&str> = transactions.iter().map(|x| x.id.as_str()).collect();
+    let page: Vec<Transaction> = page
+        .into_iter()
+        .filter(|x| !known.contains(x.id.as_str()))
+        .collect();
+    let done = page_size < TRANSACTIONS_PAGE_SIZE || page.is_empty();
+    transactions.extend(page);
+    if !done {
+        debug!("Fetched {} N26 transactions so far", transactions.len());
+    }
+    Ok(done)
+}
+
+fn parse_categories(res: &Response) -> Result<HashMap<String, String>> {
+    let categories: Vec<Category> = parse_response(
+        res,
+        ErrorKind::N26GetCategoriesHttp,
+        ErrorKind::N26GetCategoriesParse,
+    )?;
+    Ok(categories.into_iter().map(|x| (x.id, x.name)).collect())
+}
+
+fn parse_response<T: DeserializeOwned>(
+    res: &Response,
+    http_error: fn(u16, String) -> ErrorKind,
+    parse_error: fn(String) -> ErrorKind,
+) -> Result<T> {
+    let body = res.text();
+    if !res.status().is_success() {
+        Err(http_error(res.status().as_u16(), body.clone()))?;
+    }
+    Ok(serde_json::from_str(&body).with_context(|e| parse_error(e.to_string()))?)
+}
+
+impl BankSource for N26 {
+    fn name(&self) -> &'static str {
+        "N26"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Transactions of the last `days` days, oldest first like in YNAB's
+    /// own import, which counts the occurrences of its import ids in that
+    /// order.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let mut transactions = self.get_transactions(days)?;
+        transactions.sort_by_key(|x| x.visible_ts);
+        Ok(transactions
+            .iter()
+            .map(|x| SourceTransaction {
+                id: x.id.clone(),
+                date: x.date(),
+                amount: x.amount,
+                payee: x.payee().cloned(),
+                memo_values: x.memo_values(),
+                is_cash_withdrawal: x.is_cash_withdrawal(),
+                fee: x.fee(),
+                pending: x.pending,
+                category: Some(x.category.clone()),
+            })
+            .collect())
+    }
+}
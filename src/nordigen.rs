@@ -0,0 +1,654 @@
+// GoCardless Bank Account Data (formerly Nordigen)
+//
+// PSD2 aggregator which gives read access to the accounts of many European
+// banks. Access to a bank is granted once per requisition: ynab-sync creates
+// one for --nordigen-institution, prints the link where the bank's consent
+// is given and waits until the requisition is linked. Tokens and requisitions
+// are kept (encrypted with --encryption-*) in the cache directory, next to
+// the N26 tokens.
+//
+// Only booked transactions are synced, pending ones have no stable id yet.
+
+use crate::encryption;
+use crate::http::{self, Request};
+use crate::notify::{self, Notification};
+use crate::parse::{amount, AmountFormat};
+use crate::source::{BankSource, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use dirs::cache_dir;
+use failure::ResultExt;
+use log::{debug, info};
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env::current_dir;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time;
+use structopt::StructOpt;
+
+pub const API_URL: &str = "https://bankaccountdata.gocardless.com/api/v2";
+
+/// Placeholders available in `--memo-template` for Nordigen transactions.
+pub const MEMO_FIELDS: &[&str] = &["payee", "remittance", "end_to_end_id", "iban", "info"];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{remittance|info}";
+
+// Bank transaction code of ATM withdrawals (ISO 20022 domain, family and sub
+// family).
+const CASH_WITHDRAWAL_CODE: &str = "PMNT-CCRD-CWDL";
+
+// Requisition statuses, see
+// https://developer.gocardless.com/bank-account-data/statuses
+const REQUISITION_LINKED: &str = "LN";
+const REQUISITION_FAILED: &[&str] = &["RJ", "EX", "SU"];
+
+// How often and how long to wait for the bank's consent.
+const REQUISITION_POLL_SECONDS: u64 = 5;
+const REQUISITION_POLLS: u64 = 120;
+
+#[derive(StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "nordigen-secret-id",
+        required = true,
+        value_name = "TEXT",
+        env = "NORDIGEN_SECRET_ID",
+        help = "Secret id of your user secret from https://bankaccountdata.gocardless.com"
+    )]
+    pub secret_id: String,
+    #[structopt(
+        long = "nordigen-secret-key",
+        required = true,
+        value_name = "TEXT",
+        env = "NORDIGEN_SECRET_KEY",
+        help = "Secret key of your user secret from https://bankaccountdata.gocardless.com"
+    )]
+    pub secret_key: String,
+    #[structopt(
+        long = "nordigen-institution",
+        required = true,
+        value_name = "ID",
+        env = "NORDIGEN_INSTITUTION",
+        help = "Id of your bank at Nordigen (eg. ING_INGDDEFF)."
+    )]
+    pub institution: String,
+    #[structopt(
+        long = "nordigen-account",
+        value_name = "ID|IBAN",
+        env = "NORDIGEN_ACCOUNT",
+        help = "Account to sync, needed when access to more than one account of the bank was given."
+    )]
+    pub account: Option<String>,
+    #[structopt(
+        long = "nordigen-redirect",
+        value_name = "URL",
+        default_value = "http://localhost",
+        help = "Where the bank redirects to after giving access."
+    )]
+    pub redirect: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Nordigen {
+    #[serde(skip, default = "default_api_url")]
+    pub api_url: String,
+
+    /// Secret the tokens belong to.
+    pub secret_id: String,
+
+    pub access_token: String,
+
+    pub access_expiration_time: i64,
+
+    pub refresh_token: String,
+
+    pub refresh_expiration_time: i64,
+
+    /// Requisition ids by institution id.
+    #[serde(default)]
+    pub requisitions: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenData {
+    pub access: String,
+
+    pub access_expires: i64,
+
+    #[serde(default)]
+    pub refresh: Option<String>,
+
+    #[serde(default)]
+    pub refresh_expires: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Requisition {
+    pub id: String,
+
+    pub status: String,
+
+    pub institution_id: String,
+
+    /// Where the bank's consent is given.
+    pub link: String,
+
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountDetailsWrapper {
+    account: AccountDetails,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccountDetails {
+    #[serde(skip)]
+    pub id: String,
+
+    pub iban: Option<String>,
+
+    pub name: Option<String>,
+
+    pub currency: Option<String>,
+
+    #[serde(rename = "ownerName")]
+    pub owner_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionsWrapper {
+    transactions: Transactions,
+}
+
+#[derive(Debug, Deserialize)]
+struct Transactions {
+    booked: Vec<Transaction>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransactionAmount {
+    pub amount: String,
+
+    pub currency: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransactionAccount {
+    pub iban: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub transaction_id: Option<String>,
+
+    pub internal_transaction_id: Option<String>,
+
+    pub booking_date: Option<NaiveDate>,
+
+    pub value_date: Option<NaiveDate>,
+
+    pub transaction_amount: TransactionAmount,
+
+    pub creditor_name: Option<String>,
+
+    pub creditor_account: Option<TransactionAccount>,
+
+    pub debtor_name: Option<String>,
+
+    pub debtor_account: Option<TransactionAccount>,
+
+    pub remittance_information_unstructured: Option<String>,
+
+    #[serde(default)]
+    pub remittance_information_unstructured_array: Vec<String>,
+
+    pub additional_information: Option<String>,
+
+    pub bank_transaction_code: Option<String>,
+
+    pub end_to_end_id: Option<String>,
+}
+
+impl Transaction {
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.booking_date.or(self.value_date)
+    }
+
+    pub fn amount(&self) -> Result<i32> {
+        amount(&self.transaction_amount.amount, &AmountFormat::PLAIN)
+    }
+
+    fn is_outflow(&self) -> bool {
+        self.transaction_amount.amount.trim().starts_with('-')
+    }
+
+    /// The other party: the creditor of outflows and the debtor of inflows.
+    pub fn payee(&self) -> Option<&String> {
+        if self.is_outflow() {
+            self.creditor_name.as_ref()
+        } else {
+            self.debtor_name.as_ref()
+        }
+    }
+
+    pub fn remittance(&self) -> Option<String> {
+        self.remittance_information_unstructured
+            .clone()
+            .or_else(|| Some(self.remittance_information_unstructured_array.join(" ")))
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+    }
+
+    pub fn is_cash_withdrawal(&self) -> bool {
+        self.bank_transaction_code.as_deref() == Some(CASH_WITHDRAWAL_CODE)
+    }
+
+    /// Values for the placeholders listed in `MEMO_FIELDS`.
+    pub fn memo_values(&self) -> HashMap<&'static str, Option<String>> {
+        let account = if self.is_outflow() {
+            self.creditor_account.as_ref()
+        } else {
+            self.debtor_account.as_ref()
+        };
+        let mut values = HashMap::new();
+        values.insert("payee", self.payee().cloned());
+        values.insert("remittance", self.remittance());
+        values.insert(
+            "end_to_end_id",
+            self.end_to_end_id.clone().filter(|x| x != "NOTPROVIDED"),
+        );
+        values.insert("iban", account.and_then(|x| x.iban.clone()));
+        values.insert("info", self.additional_information.clone());
+        values
+    }
+}
+
+/// Booked transactions of an account, fetched once and synced as a
+/// `BankSource`.
+pub struct NordigenAccount {
+    pub details: AccountDetails,
+    pub transactions: Vec<SourceTransaction>,
+}
+
+impl BankSource for NordigenAccount {
+    fn name(&self) -> &'static str {
+        "Nordigen"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::now().naive_utc().date() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.date >= since)
+            .cloned()
+            .collect())
+    }
+}
+
+/// File in which the Nordigen tokens and requisitions are kept between runs.
+pub fn token_file() -> Result<PathBuf> {
+    let mut token_file = cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
+    token_file.push("ynab-sync-nordigen-token-data.json");
+    Ok(token_file)
+}
+
+fn default_api_url() -> String {
+    API_URL.to_string()
+}
+
+fn new_token(api_url: &str, secret_id: &str, secret_key: &str) -> Result<Nordigen> {
+    info!("Calling new_token");
+
+    let mut data = HashMap::new();
+    data.insert("secret_id", secret_id);
+    data.insert("secret_key", secret_key);
+
+    let url = format!("{}/token/new/", api_url);
+    let res = Request::post(&url)
+        .header(header::ACCEPT, "application/json")
+        .json(&data)
+        .send()
+        .context(ErrorKind::NordigenAuthenticate)?;
+
+    let body = res.text();
+
+    if !res.status().is_success() {
+        let http_error = ErrorKind::NordigenAuthenticateHttp(res.status().as_u16(), body.clone());
+        Err(http_error)?;
+    }
+
+    let data: TokenData = serde_json::from_str(&body)
+        .with_context(|e| ErrorKind::NordigenAuthenticateParse(e.to_string()))?;
+    let now = Utc::now().timestamp();
+    Ok(Nordigen {
+        api_url: api_url.to_string(),
+        secret_id: secret_id.to_string(),
+        access_token: data.access,
+        access_expiration_time: now + data.access_expires,
+        refresh_token: data.refresh.unwrap_or_default(),
+        refresh_expiration_time: now + data.refresh_expires.unwrap_or(0),
+        requisitions: HashMap::new(),
+    })
+}
+
+impl Nordigen {
+    pub fn new(secret_id: String, secret_key: String) -> Result<Self> {
+        Nordigen::with_api_url(API_URL, secret_id, secret_key)
+    }
+
+    /// Like `Nordigen::new` but talking to a different API (eg. a mock
+    /// server).
+    pub fn with_api_url(api_url: &str, secret_id: String, secret_key: String) -> Result<Self> {
+        // tokens (and requisitions) of another secret are replaced
+        let cached = Nordigen::cached()?.filter(|x| x.secret_id == secret_id);
+        let now = Utc::now().timestamp();
+        let nordigen = match cached {
+            Some(mut nordigen) if now < nordigen.access_expiration_time => {
+                info!("Using token from file");
+                nordigen.api_url = api_url.to_string();
+                nordigen
+            }
+            Some(mut nordigen) if now < nordigen.refresh_expiration_time => {
+                nordigen.api_url = api_url.to_string();
+                nordigen.refresh()?;
+                nordigen
+            }
+            Some(nordigen) => Nordigen {
+                requisitions: nordigen.requisitions,
+                ..new_token(api_url, &secret_id, &secret_key)?
+            },
+            None => new_token(api_url, &secret_id, &secret_key)?,
+        };
+        nordigen.save()?;
+
+        Ok(nordigen)
+    }
+
+    /// Tokens and requisitions stored by a previous run, if any.
+    pub fn cached() -> Result<Option<Self>> {
+        let token_file = token_file()?;
+        info!("Nordigen token file is: {}", token_file.to_string_lossy());
+        if !token_file.exists() {
+            return Ok(None);
+        }
+        let content =
+            read_to_string(token_file).context(ErrorKind::NordigenTokenDataFileCanNotRead)?;
+        let content =
+            encryption::decrypt(&content).context(ErrorKind::NordigenTokenDataFileCanNotRead)?;
+        let nordigen: Nordigen =
+            serde_json::from_str(&content).context(ErrorKind::NordigenTokenDataFileCanNotParse)?;
+        Ok(Some(nordigen))
+    }
+
+    fn save(&self) -> Result<()> {
+        // recorded tokens are redacted, don't overwrite the real ones with them
+        if http::is_replaying() {
+            return Ok(());
+        }
+        let content =
+            serde_json::to_string(&self).context(ErrorKind::NordigenWritingToTokenFile)?;
+        let content =
+            encryption::encrypt(&content).context(ErrorKind::NordigenWritingToTokenFile)?;
+        write(token_file()?, content).context(ErrorKind::NordigenWritingToTokenFile)?;
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        info!("Calling refresh");
+
+        let mut data = HashMap::new();
+        data.insert("refresh", self.refresh_token.as_str());
+
+        let url = format!("{}/token/refresh/", self.api_url);
+        let res = Request::post(&url)
+            .header(header::ACCEPT, "application/json")
+            .json(&data)
+            .send()
+            .context(ErrorKind::NordigenRefreshToken)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error =
+                ErrorKind::NordigenRefreshTokenHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let data: TokenData = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::NordigenRefreshTokenParse(e.to_string()))?;
+        self.access_token = data.access;
+        self.access_expiration_time = Utc::now().timestamp() + data.access_expires;
+        Ok(())
+    }
+
+    fn get(&self, url: &str) -> Request {
+        let authorization = format!("Bearer {}", self.access_token);
+        Request::get(url)
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+    }
+
+    pub fn get_requisition(&self, requisition_id: &str) -> Result<Requisition> {
+        let url = format!("{}/requisitions/{}/", self.api_url, requisition_id);
+        let res = self
+            .get(&url)
+            .send()
+            .context(ErrorKind::NordigenGetRequisition)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error =
+                ErrorKind::NordigenGetRequisitionHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let requisition = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::NordigenGetRequisitionParse(e.to_string()))?;
+        Ok(requisition)
+    }
+
+    pub fn create_requisition(&self, institution_id: &str, redirect: &str) -> Result<Requisition> {
+        let mut data = HashMap::new();
+        data.insert("institution_id", institution_id);
+        data.insert("redirect", redirect);
+
+        let url = format!("{}/requisitions/", self.api_url);
+        let authorization = format!("Bearer {}", self.access_token);
+        let res = Request::post(&url)
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+            .json(&data)
+            .send()
+            .context(ErrorKind::NordigenCreateRequisition)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error =
+                ErrorKind::NordigenCreateRequisitionHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let requisition = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::NordigenCreateRequisitionParse(e.to_string()))?;
+        Ok(requisition)
+    }
+
+    /// Linked requisition of the institution. A new one is created when
+    /// there's none yet or the previous one expired, and the user is asked to
+    /// give access to the bank through its link.
+    pub fn requisition(&mut self, institution_id: &str, redirect: &str) -> Result<Requisition> {
+        if let Some(requisition_id) = self.requisitions.get(institution_id) {
+            let requisition = self.get_requisition(requisition_id)?;
+            if requisition.status == REQUISITION_LINKED {
+                return Ok(requisition);
+            }
+            info!(
+                "Requisition {} is not linked (status {})",
+                requisition.id, requisition.status
+            );
+        }
+
+        let mut requisition = self.create_requisition(institution_id, redirect)?;
+        self.requisitions
+            .insert(institution_id.to_string(), requisition.id.clone());
+        self.save()?;
+
+        println!(" => Give access to your bank at {}", requisition.link);
+        notify::send(&Notification::attention(
+            "Nordigen needs access to your bank",
+            &format!("Give access to your bank at {}", requisition.link),
+        ));
+
+        for i in 0..REQUISITION_POLLS {
+            if requisition.status == REQUISITION_LINKED
+                || REQUISITION_FAILED.contains(&requisition.status.as_str())
+            {
+                break;
+            }
+            if !http::is_replaying() {
+                debug!("Sleeping for {} seconds", REQUISITION_POLL_SECONDS);
+                sleep(time::Duration::from_secs(REQUISITION_POLL_SECONDS));
+            }
+            requisition = self.get_requisition(&requisition.id)?;
+            info!(
+                "Remaining {} seconds",
+                (REQUISITION_POLLS - i - 1) * REQUISITION_POLL_SECONDS
+            );
+        }
+
+        if requisition.status != REQUISITION_LINKED {
+            Err(ErrorKind::NordigenRequisitionNotLinked(
+                requisition.link.clone(),
+            ))?;
+        }
+        Ok(requisition)
+    }
+
+    pub fn get_account(&self, account_id: &str) -> Result<AccountDetails> {
+        let url = format!("{}/accounts/{}/details/", self.api_url, account_id);
+        let res = self
+            .get(&url)
+            .send()
+            .context(ErrorKind::NordigenGetAccount)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error = ErrorKind::NordigenGetAccountHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let wrapper: AccountDetailsWrapper = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::NordigenGetAccountParse(e.to_string()))?;
+        let mut account = wrapper.account;
+        account.id = account_id.to_string();
+        Ok(account)
+    }
+
+    pub fn get_accounts(&self, requisition: &Requisition) -> Result<Vec<AccountDetails>> {
+        requisition
+            .accounts
+            .iter()
+            .map(|x| self.get_account(x))
+            .collect()
+    }
+
+    /// Booked transactions of the last `days` days.
+    pub fn get_transactions(&self, account_id: &str, days: i64) -> Result<Vec<Transaction>> {
+        let date_from = Utc::now().naive_utc().date() - Duration::days(days);
+        let url = format!(
+            "{}/accounts/{}/transactions/?date_from={}",
+            self.api_url,
+            account_id,
+            date_from.format("%Y-%m-%d")
+        );
+        let res = self
+            .get(&url)
+            .send()
+            .context(ErrorKind::NordigenGetTransactions)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error =
+                ErrorKind::NordigenGetTransactionsHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let wrapper: TransactionsWrapper = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::NordigenGetTransactionsParse(e.to_string()))?;
+        Ok(wrapper.transactions.booked)
+    }
+
+    /// Transactions of the last `days` days of `account`, ready to be synced.
+    pub fn account(&self, account: AccountDetails, days: i64) -> Result<NordigenAccount> {
+        let mut transactions = vec![];
+        // transactions without ids (eg. two equal card payments on the same
+        // day) get different import ids
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        for transaction in self.get_transactions(&account.id, days)? {
+            let date = match transaction.date() {
+                Some(x) => x,
+                None => Err(ErrorKind::NordigenGetTransactionsParse(format!(
+                    "transaction without date: {:?}",
+                    transaction
+                )))?,
+            };
+            let amount = transaction.amount()?;
+            let key = match transaction
+                .transaction_id
+                .as_ref()
+                .or(transaction.internal_transaction_id.as_ref())
+            {
+                Some(id) => id.clone(),
+                None => format!(
+                    "{}|{}|{}|{}",
+                    date,
+                    amount,
+                    transaction.payee().cloned().unwrap_or_default(),
+                    transaction.remittance().unwrap_or_default()
+                ),
+            };
+            let occurrence = occurrences.entry(key.clone()).or_insert(0);
+            *occurrence += 1;
+            let mut import_id_sha = Sha1::new();
+            import_id_sha.input_str(&key);
+            import_id_sha.input_str(&format!("{}", occurrence));
+
+            transactions.push(SourceTransaction {
+                id: import_id_sha.result_str()[..36].to_string(),
+                date,
+                amount,
+                payee: transaction.payee().cloned(),
+                memo_values: transaction.memo_values(),
+                is_cash_withdrawal: transaction.is_cash_withdrawal(),
+                fee: None,
+                pending: false,
+                category: None,
+            });
+        }
+        Ok(NordigenAccount {
+            details: account,
+            transactions,
+        })
+    }
+}
@@ -0,0 +1,205 @@
+// Notifications
+//
+// Runs without anyone watching the terminal (eg. scheduled with `--yes`)
+// report when they finish or need the user's attention (N26 MFA approval,
+// transactions without a category) as desktop notifications and/or as
+// messages to a Matrix room.
+
+use crate::http::{self, Request};
+use crate::{env_flag, ErrorKind, Result};
+use chrono::Utc;
+use failure::ResultExt;
+use log::warn;
+use reqwest::{header, Method};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use structopt::StructOpt;
+use url::Url;
+
+const APP_NAME: &str = "ynab-sync";
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "notify",
+        help = "Show a desktop notification when the sync finishes or needs attention. [env: YNAB_SYNC_NOTIFY]"
+    )]
+    pub notify: bool,
+    #[structopt(
+        long = "matrix-homeserver",
+        value_name = "URL",
+        env = "YNAB_SYNC_MATRIX_HOMESERVER",
+        requires_all = &["matrix_access_token", "matrix_room_id"],
+        help = "Matrix homeserver (eg. https://matrix.org) to send notifications to."
+    )]
+    pub matrix_homeserver: Option<String>,
+    #[structopt(
+        long = "matrix-access-token",
+        value_name = "TEXT",
+        env = "YNAB_SYNC_MATRIX_ACCESS_TOKEN",
+        requires = "matrix_homeserver",
+        help = "Access token of the Matrix user sending notifications."
+    )]
+    pub matrix_access_token: Option<String>,
+    #[structopt(
+        long = "matrix-room-id",
+        value_name = "ID",
+        env = "YNAB_SYNC_MATRIX_ROOM_ID",
+        requires = "matrix_homeserver",
+        help = "Matrix room (eg. !abcdefg:matrix.org) to send notifications to."
+    )]
+    pub matrix_room_id: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Matrix {
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Urgency {
+    Normal,
+    /// The user needs to act (eg. approve a login).
+    Attention,
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+}
+
+impl Notification {
+    pub fn new(summary: &str, body: &str) -> Self {
+        Notification {
+            summary: summary.to_string(),
+            body: body.to_string(),
+            urgency: Urgency::Normal,
+        }
+    }
+
+    pub fn attention(summary: &str, body: &str) -> Self {
+        Notification {
+            urgency: Urgency::Attention,
+            ..Notification::new(summary, body)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Config {
+    desktop: bool,
+    matrix: Option<Matrix>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+static MATRIX_TRANSACTION: AtomicUsize = AtomicUsize::new(0);
+
+/// Enable notifications with `--notify` and/or `--matrix-*`, without them
+/// `send` does nothing.
+pub fn setup(cli: &Cli) {
+    let matrix = match (
+        &cli.matrix_homeserver,
+        &cli.matrix_access_token,
+        &cli.matrix_room_id,
+    ) {
+        (Some(homeserver), Some(access_token), Some(room_id)) => Some(Matrix {
+            homeserver: homeserver.clone(),
+            access_token: access_token.clone(),
+            room_id: room_id.clone(),
+        }),
+        _ => None,
+    };
+    // the first configuration wins
+    let _ = CONFIG.set(Config {
+        desktop: cli.notify || env_flag("YNAB_SYNC_NOTIFY"),
+        matrix,
+    });
+}
+
+/// Send `notification` to all configured sinks. Failing to do so (eg.
+/// without a notification daemon) is logged and otherwise ignored.
+pub fn send(notification: &Notification) {
+    let config = CONFIG.get().cloned().unwrap_or_default();
+    if config.desktop {
+        send_desktop(notification);
+    }
+    if let Some(matrix) = &config.matrix {
+        // recorded/replayed sessions only contain the bank and YNAB calls
+        if http::is_live() {
+            if let Err(e) = send_matrix(matrix, notification) {
+                warn!("Failed to send Matrix notification: {}", e);
+            }
+        }
+    }
+}
+
+fn send_desktop(notification: &Notification) {
+    let urgency = match notification.urgency {
+        Urgency::Normal => notify_rust::Urgency::Normal,
+        Urgency::Attention => notify_rust::Urgency::Critical,
+    };
+    let result = notify_rust::Notification::new()
+        .appname(APP_NAME)
+        .summary(&notification.summary)
+        .body(&notification.body)
+        .urgency(urgency)
+        .show();
+    if let Err(e) = result {
+        warn!("Failed to show desktop notification: {}", e);
+    }
+}
+
+fn send_matrix(matrix: &Matrix, notification: &Notification) -> Result<()> {
+    // the transaction id makes retries of the same message idempotent
+    let transaction_id = format!(
+        "ynab-sync-{}-{}",
+        Utc::now().timestamp_millis(),
+        MATRIX_TRANSACTION.fetch_add(1, Ordering::SeqCst)
+    );
+    let mut url = Url::parse(&matrix.homeserver)
+        .with_context(|_| ErrorKind::MatrixSendMessage(matrix.homeserver.clone()))?;
+    url.path_segments_mut()
+        .map_err(|_| ErrorKind::MatrixSendMessage(matrix.homeserver.clone()))?
+        .pop_if_empty()
+        .extend(&[
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            &matrix.room_id,
+            "send",
+            "m.room.message",
+            &transaction_id,
+        ]);
+
+    let prefix = match notification.urgency {
+        Urgency::Normal => "",
+        Urgency::Attention => "⚠ ",
+    };
+    let authorization = format!("Bearer {}", matrix.access_token);
+    let res = Request::new(Method::PUT, url.as_str())
+        .header(header::AUTHORIZATION, &authorization)
+        .json(&json!({
+            "msgtype": "m.text",
+            "body": format!(
+                "{}{}: {}\n{}",
+                prefix, APP_NAME, notification.summary, notification.body
+            ),
+        }))
+        .send()
+        .with_context(|_| ErrorKind::MatrixSendMessage(matrix.homeserver.clone()))?;
+
+    if !res.status().is_success() {
+        Err(ErrorKind::MatrixSendMessageHttp(
+            res.status().as_u16(),
+            res.text(),
+        ))?
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use crate::error::{find_kinds, ErrorKind};
+use exitfailure::ExitFailure;
+use serde::Serialize;
+use std::fmt;
+use std::process::exit;
+use std::result;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "output",
+        value_name = "FORMAT",
+        default_value = "human",
+        possible_values = &["human", "json"],
+        env = "YNAB_SYNC_OUTPUT",
+        help = "Output format. With json, errors are printed to stderr as JSON."
+    )]
+    pub output: OutputFormat,
+}
+
+impl Cli {
+    /// The options as environment variables of another ynab-sync process.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("YNAB_SYNC_OUTPUT", self.output.to_string())]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonError {
+    kind: Option<String>,
+    message: String,
+    causes: Vec<String>,
+    http_status: Option<u16>,
+    http_body: Option<String>,
+    endpoint: Option<String>,
+    transaction: Option<String>,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                OutputFormat::Human => "human",
+                OutputFormat::Json => "json",
+            },
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(ErrorKind::ArgParse(format!("--output {}", s))),
+        }
+    }
+}
+
+/// Turn an error returned by a binary into its final form.
+///
+/// In `human` mode the error is handed to `ExitFailure` which prints the
+/// familiar "Error: ... Info: caused by ..." output. In `json` mode the error
+/// is printed to stderr as a single JSON object and the process exits.
+/// Errors with their own exit code (see `ErrorKind::exit_code`) exit with
+/// it in both modes.
+pub fn report_error(
+    format: &OutputFormat,
+    error: failure::Error,
+) -> result::Result<(), ExitFailure> {
+    let exit_code = find_kinds(&error).iter().find_map(|x| x.exit_code());
+    match format {
+        OutputFormat::Human => match exit_code {
+            Some(code) => {
+                eprintln!("Error: {:?}", ExitFailure::from(error));
+                exit(code)
+            }
+            None => Err(error.into()),
+        },
+        OutputFormat::Json => {
+            // the details can come from any error in the chain, eg. a typed
+            // YNAB error wrapped in the call which failed
+            let kinds = find_kinds(&error);
+            let json_error = JsonError {
+                kind: kinds.last().map(|x| x.name()),
+                message: error.to_string(),
+                causes: error.iter_causes().map(|x| x.to_string()).collect(),
+                http_status: kinds.iter().find_map(|x| x.http_status()),
+                http_body: kinds.iter().find_map(|x| x.http_body()).map(String::from),
+                endpoint: kinds.iter().find_map(|x| x.endpoint()).map(String::from),
+                transaction: kinds.iter().find_map(|x| x.transaction()).map(String::from),
+            };
+            eprintln!(
+                "{}",
+                serde_json::to_string(&json_error).unwrap_or_else(|_| format!("{:?}", json_error))
+            );
+            exit(exit_code.unwrap_or(1))
+        }
+    }
+}
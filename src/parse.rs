@@ -0,0 +1,434 @@
+// Parsing amounts and dates
+//
+// Banks export amounts and dates in their own locale (eg. `-1.234,56` and
+// `31.12.2019` for German banks). The functions here turn them into YNAB
+// milliunits and `NaiveDate`s according to a `Locale`, and the
+// `deserialize_*` functions can be used with `#[serde(deserialize_with)]` in
+// CSV/JSON connectors.
+
+use crate::{ErrorKind, Result};
+use chrono::NaiveDate;
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AmountFormat {
+    pub decimal_separator: char,
+    pub grouping_separator: Option<char>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    pub amount: AmountFormat,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+}
+
+impl AmountFormat {
+    /// `1.234,56`
+    pub const EU: AmountFormat = AmountFormat {
+        decimal_separator: ',',
+        grouping_separator: Some('.'),
+    };
+
+    /// `1,234.56`
+    pub const US: AmountFormat = AmountFormat {
+        decimal_separator: '.',
+        grouping_separator: Some(','),
+    };
+
+    /// `1234.56`
+    pub const PLAIN: AmountFormat = AmountFormat {
+        decimal_separator: '.',
+        grouping_separator: None,
+    };
+
+    /// `1'234.56` (also with a typographic apostrophe, `1’234.56`)
+    pub const CH: AmountFormat = AmountFormat {
+        decimal_separator: '.',
+        grouping_separator: Some('\''),
+    };
+
+    /// `1 234,56` (grouped with a space, no-break space or narrow no-break
+    /// space)
+    pub const FR: AmountFormat = AmountFormat {
+        decimal_separator: ',',
+        grouping_separator: Some(' '),
+    };
+
+    /// Format of `s` when it can be told from the value itself, otherwise
+    /// `preferred`:
+    ///
+    /// - with both `,` and `.` the last one is the decimal separator
+    ///   (`1.234,56`, `1,234.56`)
+    /// - a separator used more than once groups thousands (`1.234.567`)
+    /// - a single separator not followed by exactly three digits is the
+    ///   decimal separator (`12,5`, `-0.99`)
+    ///
+    /// A single separator followed by three digits (`1,234`) stays ambiguous
+    /// and is read according to `preferred`, as are values grouped with its
+    /// grouping separator when that can't be a decimal separator (the
+    /// apostrophe of `1'234.50`).
+    pub fn detect(s: &str, preferred: &AmountFormat) -> AmountFormat {
+        let s = s.trim();
+        let grouped = preferred
+            .grouping_separator
+            .filter(|x| *x != ',' && *x != '.')
+            .is_some_and(|x| s.replace('’', "'").contains(x));
+        if grouped {
+            return preferred.clone();
+        }
+        let (commas, periods) = (s.matches(',').count(), s.matches('.').count());
+        let with_decimal = |decimal_separator: char| {
+            let grouping_separator = match decimal_separator {
+                ',' => '.',
+                _ => ',',
+            };
+            AmountFormat {
+                decimal_separator,
+                grouping_separator: Some(grouping_separator),
+            }
+        };
+        match (commas, periods) {
+            (0, 0) => preferred.clone(),
+            (c, p) if c > 0 && p > 0 => {
+                if s.rfind(',') > s.rfind('.') {
+                    with_decimal(',')
+                } else {
+                    with_decimal('.')
+                }
+            }
+            (c, 0) if c > 1 => with_decimal('.'),
+            (0, p) if p > 1 => with_decimal(','),
+            (_, p) => {
+                let separator = if p == 1 { '.' } else { ',' };
+                let decimals = s.rsplit(separator).next().unwrap_or("");
+                let grouping_like =
+                    decimals.len() == 3 && decimals.chars().all(|x| x.is_ascii_digit());
+                if !grouping_like {
+                    with_decimal(separator)
+                } else if preferred.decimal_separator == separator {
+                    preferred.clone()
+                } else {
+                    AmountFormat {
+                        decimal_separator: preferred.decimal_separator,
+                        grouping_separator: Some(separator),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Locale {
+    /// German banks: `-1.234,56` and `31.12.2019`.
+    pub const DE: Locale = Locale {
+        amount: AmountFormat::EU,
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '.',
+    };
+
+    /// US banks: `-1,234.56` and `12/31/2019`.
+    pub const US: Locale = Locale {
+        amount: AmountFormat::US,
+        date_order: DateOrder::MonthDayYear,
+        date_separator: '/',
+    };
+
+    /// Swiss banks: `-1'234.56` and `31.12.2019`.
+    pub const CH: Locale = Locale {
+        amount: AmountFormat::CH,
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '.',
+    };
+
+    /// French banks: `-1 234,56` and `31/12/2019`.
+    pub const FR: Locale = Locale {
+        amount: AmountFormat::FR,
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '/',
+    };
+
+    /// `-1234.56` and `2019-12-31`.
+    pub const ISO: Locale = Locale {
+        amount: AmountFormat::PLAIN,
+        date_order: DateOrder::YearMonthDay,
+        date_separator: '-',
+    };
+
+    /// chrono format string of dates in this locale (eg. `%d.%m.%Y`).
+    pub fn date_format(&self) -> String {
+        let parts = match self.date_order {
+            DateOrder::DayMonthYear => ["%d", "%m", "%Y"],
+            DateOrder::MonthDayYear => ["%m", "%d", "%Y"],
+            DateOrder::YearMonthDay => ["%Y", "%m", "%d"],
+        };
+        parts.join(&self.date_separator.to_string())
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Locale::DE {
+            write!(f, "de")
+        } else if *self == Locale::US {
+            write!(f, "us")
+        } else if *self == Locale::CH {
+            write!(f, "ch")
+        } else if *self == Locale::FR {
+            write!(f, "fr")
+        } else if *self == Locale::ISO {
+            write!(f, "iso")
+        } else {
+            write!(f, "{:?}", self)
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "de" => Ok(Locale::DE),
+            "us" => Ok(Locale::US),
+            "ch" => Ok(Locale::CH),
+            "fr" => Ok(Locale::FR),
+            "iso" => Ok(Locale::ISO),
+            _ => Err(ErrorKind::ArgParse(format!("locale {}", s))),
+        }
+    }
+}
+
+/// Parse an amount (eg. `-1.234,56` with `AmountFormat::EU`) into YNAB
+/// milliunits (`-1234560`). Whitespace (including no-break spaces) is
+/// ignored.
+pub fn amount(s: &str, format: &AmountFormat) -> Result<i32> {
+    let normalized: String = s
+        .trim()
+        .chars()
+        // typographic apostrophes are used in Swiss amounts too
+        .map(|x| if x == '’' { '\'' } else { x })
+        .filter(|x| Some(*x) != format.grouping_separator && !x.is_whitespace())
+        .map(|x| {
+            if x == format.decimal_separator {
+                '.'
+            } else {
+                x
+            }
+        })
+        .collect();
+    match normalized.parse::<f64>() {
+        Ok(x) => Ok(milliunits(x)),
+        Err(_) => Err(ErrorKind::AmountParse(s.to_string()))?,
+    }
+}
+
+/// Parse an amount whose separators may not match the bank's locale (eg.
+/// exports edited in a spreadsheet), see `AmountFormat::detect`.
+pub fn amount_detect(s: &str, preferred: &AmountFormat) -> Result<i32> {
+    amount(s, &AmountFormat::detect(s, preferred))
+}
+
+/// Parse a date (eg. `31.12.2019` with `Locale::DE`).
+pub fn date(s: &str, locale: &Locale) -> Result<NaiveDate> {
+    match NaiveDate::parse_from_str(s.trim(), &locale.date_format()) {
+        Ok(x) => Ok(x),
+        Err(_) => Err(ErrorKind::DateParse(s.to_string()))?,
+    }
+}
+
+/// Convert an amount in currency units (eg. `12.34`) into YNAB milliunits.
+pub fn milliunits(value: f64) -> i32 {
+    (value * 1000.0).round() as i32
+}
+
+/// Deserialize a string amount in the given format into milliunits.
+pub fn deserialize_amount<'de, D>(
+    deserializer: D,
+    format: &AmountFormat,
+) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    amount(&s, format).map_err(|e| de::Error::custom(format!("{} ({})", e, s)))
+}
+
+/// Deserialize a string date in the given locale.
+pub fn deserialize_date<'de, D>(
+    deserializer: D,
+    locale: &Locale,
+) -> result::Result<NaiveDate, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    date(&s, locale).map_err(|e| de::Error::custom(format!("{} ({})", e, s)))
+}
+
+/// Deserialize a numeric amount (eg. `12.34`) into milliunits.
+pub fn deserialize_milliunits<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct I32Visitor;
+
+    impl<'de> Visitor<'de> for I32Visitor {
+        type Value = i32;
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a cent representation in i32 of an amount provided in f64")
+        }
+        fn visit_f64<E>(self, value: f64) -> result::Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(milliunits(value))
+        }
+    }
+
+    deserializer.deserialize_f64(I32Visitor)
+}
+
+/// Like `deserialize_milliunits` but for optional (or missing) amounts.
+pub fn deserialize_option_milliunits<'de, D>(
+    deserializer: D,
+) -> result::Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<f64> = Option::deserialize(deserializer)?;
+    Ok(value.map(milliunits))
+}
+
+/// `deserialize_amount` with `AmountFormat::EU` (eg. `-1.234,56`).
+pub fn deserialize_amount_eu<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_amount(deserializer, &AmountFormat::EU)
+}
+
+/// `deserialize_amount` with `AmountFormat::US` (eg. `-1,234.56`).
+pub fn deserialize_amount_us<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_amount(deserializer, &AmountFormat::US)
+}
+
+/// `deserialize_amount` with `AmountFormat::CH` (eg. `-1'234.56`).
+pub fn deserialize_amount_ch<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_amount(deserializer, &AmountFormat::CH)
+}
+
+/// `deserialize_amount` with `AmountFormat::FR` (eg. `-1 234,56`).
+pub fn deserialize_amount_fr<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_amount(deserializer, &AmountFormat::FR)
+}
+
+/// `deserialize_date` with `Locale::DE` (eg. `31.12.2019`).
+pub fn deserialize_date_de<'de, D>(deserializer: D) -> result::Result<NaiveDate, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_date(deserializer, &Locale::DE)
+}
+
+/// `deserialize_date` with `Locale::US` (eg. `12/31/2019`).
+pub fn deserialize_date_us<'de, D>(deserializer: D) -> result::Result<NaiveDate, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_date(deserializer, &Locale::US)
+}
+
+/// `deserialize_date` with `Locale::ISO` (eg. `2019-12-31`).
+pub fn deserialize_date_iso<'de, D>(deserializer: D) -> result::Result<NaiveDate, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_date(deserializer, &Locale::ISO)
+}
+
+/// `deserialize_amount_eu` for optional columns, empty values are `None`.
+pub fn deserialize_option_amount_eu<'de, D>(
+    deserializer: D,
+) -> result::Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    if s.trim().is_empty() {
+        return Ok(None);
+    }
+    amount(&s, &AmountFormat::EU)
+        .map(Some)
+        .map_err(|e| de::Error::custom(format!("{} ({})", e, s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_keeps_swiss_grouping() {
+        assert_eq!(amount("1'234.50", &AmountFormat::CH).unwrap(), 1_234_500);
+        assert_eq!(
+            amount_detect("1'234.50", &AmountFormat::CH).unwrap(),
+            1_234_500
+        );
+        assert_eq!(
+            amount_detect("1’234.50", &AmountFormat::CH).unwrap(),
+            1_234_500
+        );
+        assert_eq!(
+            amount_detect("-1'234'567", &AmountFormat::CH).unwrap(),
+            -1_234_567_000
+        );
+        assert_eq!(amount_detect("12.5", &AmountFormat::CH).unwrap(), 12_500);
+        assert_eq!(
+            amount_detect("1234.50", &AmountFormat::CH).unwrap(),
+            1_234_500
+        );
+    }
+
+    #[test]
+    fn detect_tells_separators_apart() {
+        assert_eq!(
+            amount_detect("1.234,56", &AmountFormat::US).unwrap(),
+            1_234_560
+        );
+        assert_eq!(
+            amount_detect("1,234.56", &AmountFormat::EU).unwrap(),
+            1_234_560
+        );
+        assert_eq!(amount_detect("12,5", &AmountFormat::US).unwrap(), 12_500);
+        // ambiguous, read according to the preferred format
+        assert_eq!(
+            amount_detect("1,234", &AmountFormat::US).unwrap(),
+            1_234_000
+        );
+        assert_eq!(amount_detect("1,234", &AmountFormat::EU).unwrap(), 1_234);
+        assert_eq!(
+            amount_detect("1 234,56", &AmountFormat::FR).unwrap(),
+            1_234_560
+        );
+    }
+}
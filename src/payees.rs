@@ -0,0 +1,173 @@
+use crate::ynab::{Payee as YNABPayee, Transaction};
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+/// Mapping of source merchant/entity names to YNAB payees, read from the file
+/// provided via `--payee-mapping`:
+///
+/// ```json
+/// {
+///   "REWE": "Rewe",
+///   "Telefonica Germany": "3c4b1e8e-1d4f-4a37-9c55-0f1c3c2d1e1a"
+/// }
+/// ```
+///
+/// Values which look like a YNAB id are sent as `payee_id`, everything else
+/// as `payee_name`.
+#[derive(Clone, Debug, Default)]
+pub struct PayeeMapping {
+    mapping: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Payee {
+    pub id: Option<String>,
+    pub name: Option<String>,
+}
+
+impl PayeeMapping {
+    pub fn from_file(payee_mapping_file: &str) -> Result<Self> {
+        if !PathBuf::from(payee_mapping_file).exists() {
+            Err(ErrorKind::ArgParsePayeeMappingCanNotRead(
+                payee_mapping_file.to_string(),
+            ))?
+        }
+        let payee_mapping_string = read_to_string(payee_mapping_file).with_context(|_| {
+            ErrorKind::ArgParsePayeeMappingCanNotRead(payee_mapping_file.to_string())
+        })?;
+        let mapping: HashMap<String, String> = serde_json::from_str(&payee_mapping_string)
+            .context(ErrorKind::ArgParsePayeeMappingCanNotParse(
+                payee_mapping_file.to_string(),
+            ))?;
+
+        // longer (more specific) keys first
+        let mut mapping: Vec<(String, String)> = mapping
+            .into_iter()
+            .map(|(k, v)| (k.to_lowercase(), v))
+            .collect();
+        mapping.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)));
+
+        Ok(PayeeMapping { mapping })
+    }
+
+    /// Replace payee names with the ids of existing YNAB payees of that
+    /// (case insensitive) name, so transactions are assigned to them
+    /// instead of YNAB matching the name itself. Unknown names are kept and
+    /// YNAB creates the payee.
+    pub fn resolve(mut self, payees: &[YNABPayee]) -> Self {
+        let ids: HashMap<String, &String> = payees
+            .iter()
+            .filter(|x| !x.deleted && x.transfer_account_id.is_none())
+            .map(|x| (x.name.to_lowercase(), &x.id))
+            .collect();
+        for (_, value) in self.mapping.iter_mut() {
+            if is_ynab_id(value) {
+                continue;
+            }
+            if let Some(id) = ids.get(&value.to_lowercase()) {
+                *value = id.to_string();
+            }
+        }
+        self
+    }
+
+    /// Find the YNAB payee for a source merchant/entity name.
+    ///
+    /// An exact (case insensitive) match wins, otherwise the longest key
+    /// contained in `source` is used.
+    pub fn payee(&self, source: &str) -> Option<Payee> {
+        let source = source.to_lowercase();
+        self.mapping
+            .iter()
+            .find(|(k, _)| *k == source)
+            .or_else(|| {
+                self.mapping
+                    .iter()
+                    .find(|(k, _)| source.contains(k.as_str()))
+            })
+            .map(|(_, v)| {
+                if is_ynab_id(v) {
+                    Payee {
+                        id: Some(v.clone()),
+                        name: None,
+                    }
+                } else {
+                    Payee {
+                        id: None,
+                        name: Some(v.clone()),
+                    }
+                }
+            })
+    }
+}
+
+/// Most recently used YNAB category per payee, built from an account's
+/// transaction history and keyed by payee id and lowercased payee name.
+#[derive(Clone, Debug, Default)]
+pub struct PayeeHistory {
+    categories: HashMap<String, String>,
+}
+
+impl PayeeHistory {
+    pub fn from_transactions(transactions: &[Transaction]) -> Self {
+        let mut transactions: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|x| x.category_id.is_some())
+            .collect();
+        // dates are YYYY-MM-DD, newer transactions override older ones
+        transactions.sort_by(|a, b| a.date.cmp(&b.date));
+
+        let mut categories = HashMap::new();
+        for transaction in transactions {
+            let category_id = transaction.category_id.clone().unwrap_or_default();
+            if let Some(payee_id) = &transaction.payee_id {
+                categories.insert(payee_id.clone(), category_id.clone());
+            }
+            if let Some(payee_name) = &transaction.payee_name {
+                categories.insert(payee_name.to_lowercase(), category_id);
+            }
+        }
+
+        PayeeHistory { categories }
+    }
+
+    /// Category id last used for the payee with `id` or (case insensitive)
+    /// `name`.
+    pub fn category(&self, id: Option<&str>, name: Option<&str>) -> Option<String> {
+        id.and_then(|x| self.categories.get(x))
+            .or_else(|| name.and_then(|x| self.categories.get(&x.to_lowercase())))
+            .cloned()
+    }
+}
+
+// Legal forms left out when comparing payee names.
+const LEGAL_FORMS: &[&str] = &[
+    "ag", "bv", "co", "corp", "gmbh", "inc", "kg", "llc", "ltd", "sa", "sarl", "se",
+];
+
+/// Key under which payees created from raw bank texts of the same merchant
+/// (eg. "AMAZON EU SARL", "Amazon EU S.a.r.l. 1234" and "amazon eu") are
+/// grouped: lowercased, without punctuation, spaces, legal forms and
+/// reference numbers. Empty when nothing is left to compare.
+pub fn payee_key(name: &str) -> String {
+    name.to_lowercase()
+        // abbreviations like "s.a.r.l."
+        .replace('.', "")
+        .split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty() && !LEGAL_FORMS.contains(x))
+        // reference, store and card numbers, but not names like "o2"
+        .filter(|x| x.chars().filter(|x| x.is_numeric()).count() * 2 <= x.chars().count())
+        .collect()
+}
+
+/// YNAB ids are lowercase UUIDs, eg. `3c4b1e8e-1d4f-4a37-9c55-0f1c3c2d1e1a`.
+pub fn is_ynab_id(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
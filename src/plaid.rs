@@ -0,0 +1,590 @@
+// Plaid
+//
+// Aggregator for banks in the US and Canada. Access to a bank (an "item") is
+// given once through Plaid's hosted Link: ynab-sync prints its URL and waits
+// until it was completed, then exchanges the resulting public token for an
+// access token.
+//
+// Transactions are fetched with `/transactions/sync`, which only returns what
+// changed since the cursor of the previous run. The transactions of the item
+// are therefore kept, together with the access token and the cursor
+// (encrypted with --encryption-*), in the cache directory.
+//
+// Pending transactions are synced as uncleared. Once Plaid posts them, the
+// posted transaction replaces the pending one and keeps its import id, so the
+// same YNAB transaction is updated instead of a second one being created.
+
+use crate::encryption;
+use crate::http::{self, Request};
+use crate::notify::{self, Notification};
+use crate::parse::milliunits;
+use crate::source::{BankSource, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use dirs::cache_dir;
+use failure::ResultExt;
+use log::{debug, info};
+use reqwest::header;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env::current_dir;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use std::result;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time;
+use structopt::StructOpt;
+
+/// Placeholders available in `--memo-template` for Plaid transactions.
+pub const MEMO_FIELDS: &[&str] = &["name", "merchant", "category", "channel"];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{name}";
+
+// Personal finance category of ATM withdrawals.
+const CASH_WITHDRAWAL_CATEGORY: &str = "TRANSFER_OUT_WITHDRAWAL";
+
+// Transactions per `/transactions/sync` page, the maximum allowed.
+const SYNC_COUNT: usize = 500;
+
+// How often and how long to wait for Link to be completed.
+const LINK_POLL_SECONDS: u64 = 5;
+const LINK_POLLS: u64 = 120;
+
+#[derive(StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "plaid-client-id",
+        required = true,
+        value_name = "TEXT",
+        env = "PLAID_CLIENT_ID",
+        help = "Client id from https://dashboard.plaid.com/developers/keys"
+    )]
+    pub client_id: String,
+    #[structopt(
+        long = "plaid-secret",
+        required = true,
+        value_name = "TEXT",
+        env = "PLAID_SECRET",
+        help = "Secret of --plaid-environment from https://dashboard.plaid.com/developers/keys"
+    )]
+    pub secret: String,
+    #[structopt(
+        long = "plaid-environment",
+        value_name = "ENVIRONMENT",
+        default_value = "production",
+        possible_values = &["sandbox", "production"],
+        env = "PLAID_ENVIRONMENT",
+        help = "Plaid environment to use."
+    )]
+    pub environment: Environment,
+    #[structopt(
+        long = "plaid-country",
+        value_name = "CODE",
+        default_value = "US",
+        number_of_values = 1,
+        help = "Country of your bank, when linking it (eg. US or CA). Can be repeated."
+    )]
+    pub countries: Vec<String>,
+    #[structopt(
+        long = "plaid-account",
+        value_name = "ID|NAME|MASK",
+        env = "PLAID_ACCOUNT",
+        help = "Account to sync, needed when access to more than one account of the bank was given."
+    )]
+    pub account: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Environment {
+    Sandbox,
+    Production,
+}
+
+impl Environment {
+    pub fn api_url(&self) -> &'static str {
+        match self {
+            Environment::Sandbox => "https://sandbox.plaid.com",
+            Environment::Production => "https://production.plaid.com",
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Environment::Sandbox => "sandbox",
+                Environment::Production => "production",
+            },
+        )
+    }
+}
+
+impl FromStr for Environment {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "sandbox" => Ok(Environment::Sandbox),
+            "production" => Ok(Environment::Production),
+            _ => Err(ErrorKind::ArgParse(format!("--plaid-environment {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Plaid {
+    #[serde(skip)]
+    pub api_url: String,
+
+    #[serde(skip)]
+    secret: String,
+
+    pub client_id: String,
+
+    pub access_token: String,
+
+    pub item_id: String,
+
+    /// Cursor of the last `/transactions/sync`.
+    #[serde(default)]
+    pub cursor: Option<String>,
+
+    /// Transactions of the item by transaction id.
+    #[serde(default)]
+    pub transactions: HashMap<String, Transaction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkToken {
+    link_token: String,
+    hosted_link_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublicToken {
+    access_token: String,
+    item_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountsWrapper {
+    accounts: Vec<Account>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Account {
+    pub account_id: String,
+
+    pub name: String,
+
+    pub mask: Option<String>,
+
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionsSync {
+    added: Vec<Transaction>,
+    modified: Vec<Transaction>,
+    removed: Vec<RemovedTransaction>,
+    next_cursor: String,
+    has_more: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemovedTransaction {
+    transaction_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PersonalFinanceCategory {
+    pub primary: String,
+
+    pub detailed: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Transaction {
+    pub transaction_id: String,
+
+    pub account_id: String,
+
+    /// Positive for money leaving the account.
+    pub amount: f64,
+
+    pub iso_currency_code: Option<String>,
+
+    pub date: NaiveDate,
+
+    pub name: Option<String>,
+
+    pub merchant_name: Option<String>,
+
+    pub pending: bool,
+
+    /// Pending transaction this posted transaction replaces.
+    pub pending_transaction_id: Option<String>,
+
+    pub payment_channel: Option<String>,
+
+    pub personal_finance_category: Option<PersonalFinanceCategory>,
+}
+
+impl Transaction {
+    pub fn payee(&self) -> Option<&String> {
+        self.merchant_name.as_ref().or(self.name.as_ref())
+    }
+
+    pub fn is_cash_withdrawal(&self) -> bool {
+        self.personal_finance_category
+            .as_ref()
+            .map(|x| x.detailed == CASH_WITHDRAWAL_CATEGORY)
+            .unwrap_or(false)
+    }
+
+    /// Values for the placeholders listed in `MEMO_FIELDS`.
+    pub fn memo_values(&self) -> HashMap<&'static str, Option<String>> {
+        let mut values = HashMap::new();
+        values.insert("name", self.name.clone());
+        values.insert("merchant", self.merchant_name.clone());
+        values.insert(
+            "category",
+            self.personal_finance_category
+                .as_ref()
+                .map(|x| x.detailed.clone()),
+        );
+        values.insert("channel", self.payment_channel.clone());
+        values
+    }
+}
+
+/// Transactions of one account of the item, synced as a `BankSource`.
+pub struct PlaidAccount {
+    pub account: Account,
+    pub transactions: Vec<SourceTransaction>,
+}
+
+impl BankSource for PlaidAccount {
+    fn name(&self) -> &'static str {
+        "Plaid"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::now().naive_utc().date() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.date >= since)
+            .cloned()
+            .collect())
+    }
+}
+
+/// File in which the Plaid access token and transactions are kept between
+/// runs.
+pub fn token_file() -> Result<PathBuf> {
+    let mut token_file = cache_dir().unwrap_or(current_dir().context(ErrorKind::CurrentDir)?);
+    token_file.push("ynab-sync-plaid-token-data.json");
+    Ok(token_file)
+}
+
+// Plaid's transaction ids are longer than the 36 characters YNAB allows for
+// import ids.
+fn import_id(transaction_id: &str) -> String {
+    let mut import_id_sha = Sha1::new();
+    import_id_sha.input_str(transaction_id);
+    import_id_sha.result_str()[..36].to_string()
+}
+
+// Error kinds of an endpoint: when it can't be reached, for an unsuccessful
+// response and when the response can't be parsed.
+type Errors = (
+    ErrorKind,
+    fn(u16, String) -> ErrorKind,
+    fn(String) -> ErrorKind,
+);
+
+/// POST `data` (with the credentials added) to `endpoint`, the only method
+/// Plaid's API uses.
+fn post<T: DeserializeOwned>(
+    api_url: &str,
+    client_id: &str,
+    secret: &str,
+    endpoint: &str,
+    mut data: Value,
+    errors: Errors,
+) -> Result<T> {
+    let (error, http_error, parse_error) = errors;
+    data["client_id"] = json!(client_id);
+    data["secret"] = json!(secret);
+
+    let url = format!("{}{}", api_url, endpoint);
+    let res = Request::post(&url)
+        .header(header::ACCEPT, "application/json")
+        .json(&data)
+        .send()
+        .context(error)?;
+
+    let body = res.text();
+
+    if !res.status().is_success() {
+        Err(http_error(res.status().as_u16(), body.clone()))?;
+    }
+
+    let data = serde_json::from_str(&body).with_context(|e| parse_error(e.to_string()))?;
+    Ok(data)
+}
+
+/// Let the user link their bank through hosted Link and exchange the public
+/// token for an access token.
+fn link(api_url: &str, cli: &Cli) -> Result<Plaid> {
+    info!("Calling link");
+
+    let link_token: LinkToken = post(
+        api_url,
+        &cli.client_id,
+        &cli.secret,
+        "/link/token/create",
+        json!({
+            "client_name": "ynab-sync",
+            "user": { "client_user_id": "ynab-sync" },
+            "products": ["transactions"],
+            "country_codes": cli.countries,
+            "language": "en",
+            "hosted_link": {},
+        }),
+        (
+            ErrorKind::PlaidCreateLinkToken,
+            ErrorKind::PlaidCreateLinkTokenHttp,
+            ErrorKind::PlaidCreateLinkTokenParse,
+        ),
+    )?;
+    let url = link_token.hosted_link_url.unwrap_or_default();
+
+    println!(" => Link your bank at {}", url);
+    notify::send(&Notification::attention(
+        "Plaid needs access to your bank",
+        &format!("Link your bank at {}", url),
+    ));
+
+    let mut public_token = None;
+    for i in 0..LINK_POLLS {
+        let link: Value = post(
+            api_url,
+            &cli.client_id,
+            &cli.secret,
+            "/link/token/get",
+            json!({ "link_token": link_token.link_token }),
+            (
+                ErrorKind::PlaidGetLinkToken,
+                ErrorKind::PlaidGetLinkTokenHttp,
+                ErrorKind::PlaidGetLinkTokenParse,
+            ),
+        )?;
+        public_token = link["link_sessions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|x| x["results"]["item_add_results"].as_array())
+            .flatten()
+            .find_map(|x| x["public_token"].as_str())
+            .map(|x| x.to_string());
+        if public_token.is_some() {
+            break;
+        }
+        if !http::is_replaying() {
+            debug!("Sleeping for {} seconds", LINK_POLL_SECONDS);
+            sleep(time::Duration::from_secs(LINK_POLL_SECONDS));
+        }
+        info!(
+            "Remaining {} seconds",
+            (LINK_POLLS - i - 1) * LINK_POLL_SECONDS
+        );
+    }
+    let public_token = public_token.ok_or(ErrorKind::PlaidLinkNotCompleted(url))?;
+
+    let exchanged: PublicToken = post(
+        api_url,
+        &cli.client_id,
+        &cli.secret,
+        "/item/public_token/exchange",
+        json!({ "public_token": public_token }),
+        (
+            ErrorKind::PlaidExchangePublicToken,
+            ErrorKind::PlaidExchangePublicTokenHttp,
+            ErrorKind::PlaidExchangePublicTokenParse,
+        ),
+    )?;
+
+    Ok(Plaid {
+        api_url: api_url.to_string(),
+        secret: cli.secret.clone(),
+        client_id: cli.client_id.clone(),
+        access_token: exchanged.access_token,
+        item_id: exchanged.item_id,
+        cursor: None,
+        transactions: HashMap::new(),
+    })
+}
+
+impl Plaid {
+    pub fn new(cli: &Cli) -> Result<Self> {
+        Plaid::with_api_url(cli.environment.api_url(), cli)
+    }
+
+    /// Like `Plaid::new` but talking to a different API (eg. a mock server).
+    pub fn with_api_url(api_url: &str, cli: &Cli) -> Result<Self> {
+        // items linked with another client are linked again
+        let cached = Plaid::cached()?.filter(|x| x.client_id == cli.client_id);
+        let plaid = match cached {
+            Some(mut plaid) => {
+                info!("Using access token from file");
+                plaid.api_url = api_url.to_string();
+                plaid.secret = cli.secret.clone();
+                plaid
+            }
+            None => {
+                let plaid = link(api_url, cli)?;
+                plaid.save()?;
+                plaid
+            }
+        };
+
+        Ok(plaid)
+    }
+
+    /// Access token and transactions stored by a previous run, if any.
+    pub fn cached() -> Result<Option<Self>> {
+        let token_file = token_file()?;
+        info!("Plaid token file is: {}", token_file.to_string_lossy());
+        if !token_file.exists() {
+            return Ok(None);
+        }
+        let content =
+            read_to_string(token_file).context(ErrorKind::PlaidTokenDataFileCanNotRead)?;
+        let content =
+            encryption::decrypt(&content).context(ErrorKind::PlaidTokenDataFileCanNotRead)?;
+        let plaid: Plaid =
+            serde_json::from_str(&content).context(ErrorKind::PlaidTokenDataFileCanNotParse)?;
+        Ok(Some(plaid))
+    }
+
+    fn save(&self) -> Result<()> {
+        // recorded tokens are redacted, don't overwrite the real ones with them
+        if http::is_replaying() {
+            return Ok(());
+        }
+        let content = serde_json::to_string(&self).context(ErrorKind::PlaidWritingToTokenFile)?;
+        let content = encryption::encrypt(&content).context(ErrorKind::PlaidWritingToTokenFile)?;
+        write(token_file()?, content).context(ErrorKind::PlaidWritingToTokenFile)?;
+        Ok(())
+    }
+
+    pub fn get_accounts(&self) -> Result<Vec<Account>> {
+        let wrapper: AccountsWrapper = post(
+            &self.api_url,
+            &self.client_id,
+            &self.secret,
+            "/accounts/get",
+            json!({ "access_token": self.access_token }),
+            (
+                ErrorKind::PlaidGetAccounts,
+                ErrorKind::PlaidGetAccountsHttp,
+                ErrorKind::PlaidGetAccountsParse,
+            ),
+        )?;
+        Ok(wrapper.accounts)
+    }
+
+    /// Apply the changes since the last run to the stored transactions.
+    /// Returns the number of added, modified and removed transactions.
+    pub fn sync_transactions(&mut self) -> Result<(usize, usize, usize)> {
+        let (mut added, mut modified, mut removed) = (0, 0, 0);
+        loop {
+            let mut data = json!({
+                "access_token": self.access_token,
+                "count": SYNC_COUNT,
+            });
+            if let Some(cursor) = &self.cursor {
+                data["cursor"] = json!(cursor);
+            }
+            let page: TransactionsSync = post(
+                &self.api_url,
+                &self.client_id,
+                &self.secret,
+                "/transactions/sync",
+                data,
+                (
+                    ErrorKind::PlaidSyncTransactions,
+                    ErrorKind::PlaidSyncTransactionsHttp,
+                    ErrorKind::PlaidSyncTransactionsParse,
+                ),
+            )?;
+            added += page.added.len();
+            modified += page.modified.len();
+            removed += page.removed.len();
+            for transaction in page.added.into_iter().chain(page.modified) {
+                self.transactions
+                    .insert(transaction.transaction_id.clone(), transaction);
+            }
+            for transaction in page.removed {
+                self.transactions.remove(&transaction.transaction_id);
+            }
+            self.cursor = Some(page.next_cursor);
+            if !page.has_more {
+                break;
+            }
+        }
+        self.save()?;
+        Ok((added, modified, removed))
+    }
+
+    /// Stored transactions of `account`, ready to be synced.
+    pub fn account(&self, account: Account) -> PlaidAccount {
+        let mut transactions: Vec<SourceTransaction> = self
+            .transactions
+            .values()
+            .filter(|x| x.account_id == account.account_id)
+            .map(|x| SourceTransaction {
+                // posted transactions update the YNAB transaction of the
+                // pending one
+                id: import_id(
+                    x.pending_transaction_id
+                        .as_ref()
+                        .unwrap_or(&x.transaction_id),
+                ),
+                date: x.date,
+                amount: milliunits(-x.amount),
+                payee: x.payee().cloned(),
+                memo_values: x.memo_values(),
+                is_cash_withdrawal: x.is_cash_withdrawal(),
+                fee: None,
+                pending: x.pending,
+                category: None,
+            })
+            .collect();
+        transactions.sort_by(|a, b| a.date.cmp(&b.date).then(a.id.cmp(&b.id)));
+        PlaidAccount {
+            account,
+            transactions,
+        }
+    }
+}
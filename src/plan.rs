@@ -0,0 +1,109 @@
+// Plan/apply
+//
+// With `--plan FILE` a sync computes which transactions it would create or
+// update, but instead of asking and saving them it writes them to FILE. The
+// plan can be generated on a headless machine, reviewed (or diffed) and
+// applied later with `ynab-sync apply FILE`, which compares it with YNAB
+// again, so applying it twice changes nothing.
+//
+// The plan is plain JSON, also with `--encrypt-state`, since it's meant to be
+// read.
+
+use crate::ynab::Transaction;
+use crate::{ErrorKind, Result};
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, write};
+use std::sync::{Mutex, OnceLock};
+use structopt::StructOpt;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "plan",
+        value_name = "FILE",
+        env = "YNAB_SYNC_PLAN",
+        help = "Write the transactions which would be created or updated to FILE instead of syncing them. Apply it later with `ynab-sync apply FILE`."
+    )]
+    pub plan_file: Option<String>,
+}
+
+static PLAN_FILE: OnceLock<Option<String>> = OnceLock::new();
+
+// changes recorded so far in this run
+static PLAN: Mutex<Option<Plan>> = Mutex::new(None);
+
+/// Write an empty plan when --plan is used, so a sync with nothing to do
+/// doesn't leave an old plan behind.
+pub fn setup(cli: &Cli) -> Result<()> {
+    // the first configuration wins
+    if PLAN_FILE.set(cli.plan_file.clone()).is_err() {
+        return Ok(());
+    }
+    if let Some(plan_file) = &cli.plan_file {
+        let plan = Plan {
+            created: Utc::now(),
+            changes: vec![],
+        };
+        plan.save(plan_file)?;
+        *PLAN.lock().unwrap() = Some(plan);
+    }
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    matches!(PLAN_FILE.get(), Some(Some(_)))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub created: DateTime<Utc>,
+    pub changes: Vec<Change>,
+}
+
+/// Transactions one sync would save to a budget.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub budget_id: String,
+    pub force_update: bool,
+    pub create: Vec<Transaction>,
+    pub update: Vec<Transaction>,
+    /// YNAB's version of the updated transactions when the plan was made.
+    pub before: Vec<Transaction>,
+}
+
+impl Plan {
+    pub fn load(plan_file: &str) -> Result<Self> {
+        let content = read_to_string(plan_file)
+            .with_context(|_| ErrorKind::PlanCanNotRead(plan_file.to_string()))?;
+        let plan = serde_json::from_str(&content)
+            .with_context(|_| ErrorKind::PlanCanNotParse(plan_file.to_string()))?;
+        Ok(plan)
+    }
+
+    fn save(&self, plan_file: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)
+            .with_context(|_| ErrorKind::PlanCanNotWrite(plan_file.to_string()))?;
+        write(plan_file, content)
+            .with_context(|_| ErrorKind::PlanCanNotWrite(plan_file.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Add `change` to the plan and write it.
+pub fn record(change: Change) -> Result<()> {
+    let plan_file = match PLAN_FILE.get() {
+        Some(Some(x)) => x,
+        _ => return Ok(()),
+    };
+    let mut plan = PLAN.lock().unwrap();
+    let plan = plan.get_or_insert_with(|| Plan {
+        created: Utc::now(),
+        changes: vec![],
+    });
+    plan.changes.push(change);
+    plan.save(plan_file)?;
+    println!(" => Added to plan {}", plan_file);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::ynab::{Transaction, TransactionFlagColor};
+use chrono::NaiveDate;
+
+/// Links expenses with their refunds: an inflow from the same payee with
+/// the opposite amount, at most `window_days` after the expense. Both get
+/// the same flag and a memo pointing at the other, so spending which is
+/// awaiting (or got) reimbursement is easy to find in YNAB.
+#[derive(Clone, Debug)]
+pub struct Reimbursements {
+    window_days: i64,
+    flag_color: TransactionFlagColor,
+}
+
+impl Reimbursements {
+    pub fn new(window_days: i64, flag_color: TransactionFlagColor) -> Self {
+        Reimbursements {
+            window_days,
+            flag_color,
+        }
+    }
+
+    /// Flag and annotate expense/refund pairs in `transactions`. Every
+    /// expense is paired with at most one refund, the earliest one.
+    pub fn link(&self, mut transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let dates: Vec<Option<NaiveDate>> = transactions
+            .iter()
+            .map(|x| NaiveDate::parse_from_str(&x.date, "%Y-%m-%d").ok())
+            .collect();
+        let mut order: Vec<usize> = (0..transactions.len()).collect();
+        order.sort_by_key(|x| dates[*x]);
+
+        let mut paired = vec![false; transactions.len()];
+        let mut pairs = vec![];
+        for &refund in &order {
+            if transactions[refund].amount <= 0 || paired[refund] {
+                continue;
+            }
+            let refund_date = match dates[refund] {
+                Some(x) => x,
+                None => continue,
+            };
+            let expense = order.iter().copied().find(|&expense| {
+                expense != refund
+                    && !paired[expense]
+                    && transactions[expense].amount == -transactions[refund].amount
+                    && payee(&transactions[expense]).is_some()
+                    && payee(&transactions[expense]) == payee(&transactions[refund])
+                    && dates[expense].is_some_and(|x| {
+                        x <= refund_date && (refund_date - x).num_days() <= self.window_days
+                    })
+            });
+            if let Some(expense) = expense {
+                paired[expense] = true;
+                paired[refund] = true;
+                pairs.push((expense, refund));
+            }
+        }
+
+        for (expense, refund) in pairs {
+            let expense_date = transactions[expense].date.clone();
+            let refund_date = transactions[refund].date.clone();
+            annotate(
+                &mut transactions[expense],
+                &format!("[refunded {}]", refund_date),
+                &self.flag_color,
+            );
+            annotate(
+                &mut transactions[refund],
+                &format!("[refund of {}]", expense_date),
+                &self.flag_color,
+            );
+        }
+        transactions
+    }
+}
+
+fn payee(transaction: &Transaction) -> Option<&String> {
+    transaction
+        .payee_id
+        .as_ref()
+        .or(transaction.payee_name.as_ref())
+}
+
+fn annotate(transaction: &mut Transaction, note: &str, flag_color: &TransactionFlagColor) {
+    transaction.memo = Some(match &transaction.memo {
+        Some(memo) if !memo.is_empty() => format!("{} {}", memo, note),
+        _ => note.to_string(),
+    });
+    transaction.flag_color = Some(flag_color.clone());
+}
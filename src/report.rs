@@ -0,0 +1,88 @@
+use crate::ynab::Transaction;
+use std::collections::BTreeSet;
+
+/// What a sync did, returned by `YNAB::sync` and printed at the end of a
+/// sync.
+#[derive(Clone, Debug, Default)]
+pub struct SyncReport {
+    /// Whether YNAB is up to date with the transactions afterwards.
+    pub synced: bool,
+    /// Transactions created in YNAB (and with --update-duplicates the
+    /// duplicates of imported ones updated instead).
+    pub created: Vec<Transaction>,
+    /// Transactions updated in YNAB.
+    pub updated: Vec<Transaction>,
+    /// Number of transactions already up to date in YNAB.
+    pub unchanged: usize,
+    /// Number of transactions not saved: ones YNAB would reject, ones synced
+    /// before and duplicates of imported ones.
+    pub skipped: usize,
+}
+
+impl SyncReport {
+    /// Created and updated transactions.
+    pub fn saved(&self) -> impl Iterator<Item = &Transaction> {
+        self.created.iter().chain(self.updated.iter())
+    }
+
+    /// Sum of the created and updated transactions in milliunits.
+    pub fn amount(&self) -> i64 {
+        self.saved().map(|x| i64::from(x.amount)).sum()
+    }
+
+    /// Created and updated transactions without a category.
+    pub fn uncategorized(&self) -> Vec<&Transaction> {
+        self.saved()
+            .filter(|x| {
+                // transfers (to a transfer payee, without payee name) have no
+                // category
+                let transfer = x.payee_id.is_some() && x.payee_name.is_none();
+                x.category_id.is_none() && x.subtransactions.is_empty() && !transfer
+            })
+            .collect()
+    }
+
+    /// Payees of the uncategorized transactions, which no payee mapping,
+    /// payee history or rule matched.
+    pub fn unmatched_payees(&self) -> BTreeSet<String> {
+        self.uncategorized()
+            .into_iter()
+            .filter_map(|x| x.payee_name.clone())
+            .collect()
+    }
+
+    /// Add the report of another sync (eg. of a second account).
+    pub fn merge(&mut self, other: SyncReport) {
+        self.synced &= other.synced;
+        self.created.extend(other.created);
+        self.updated.extend(other.updated);
+        self.unchanged += other.unchanged;
+        self.skipped += other.skipped;
+    }
+
+    /// Print the report as a table.
+    pub fn print(&self) {
+        println!("Summary:");
+        println!(" - | {:<14} | {:>10} |", "new", self.created.len());
+        println!(" - | {:<14} | {:>10} |", "updated", self.updated.len());
+        println!(" - | {:<14} | {:>10} |", "unchanged", self.unchanged);
+        println!(" - | {:<14} | {:>10} |", "skipped", self.skipped);
+        println!(
+            " - | {:<14} | {:>+10.2} | EUR",
+            "amount",
+            self.amount() as f64 / 1000.0
+        );
+        println!(
+            " - | {:<14} | {:>10} |",
+            "uncategorized",
+            self.uncategorized().len()
+        );
+        let unmatched_payees = self.unmatched_payees();
+        if !unmatched_payees.is_empty() {
+            println!("Payees without a category:");
+            for payee in &unmatched_payees {
+                println!(" - {}", payee);
+            }
+        }
+    }
+}
@@ -0,0 +1,363 @@
+// Revolut
+//
+// Transactions of Revolut's CSV export (the account statement of the app,
+// "Excel" format). An export contains the rows of all pockets of an account,
+// one currency each. Rows of pockets in another currency than the budget's
+// are converted with the ECB reference rate of their day (or a fixed rate
+// given with --exchange-rate), the original amount and the rate end up in the
+// memo.
+//
+// Pending rows are synced as uncleared, reverted and declined ones are
+// skipped.
+
+use crate::http::Request;
+use crate::parse::{deserialize_amount, milliunits, AmountFormat};
+use crate::source::{BankSource, SourceTransaction};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use csv::ReaderBuilder;
+use failure::ResultExt;
+use log::info;
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::result;
+
+pub const EXCHANGE_RATES_URL: &str = "https://api.frankfurter.app";
+
+/// Placeholders available in `--memo-template` for Revolut transactions.
+pub const MEMO_FIELDS: &[&str] = &[
+    "description",
+    "type",
+    "product",
+    "original_amount",
+    "exchange_rate",
+    "original",
+];
+
+pub const DEFAULT_MEMO_TEMPLATE: &str = "{description} {original}";
+
+// States of rows which never reached the account.
+const SKIPPED_STATES: &[&str] = &["REVERTED", "DECLINED", "FAILED"];
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Transaction {
+    #[serde(rename = "Type")]
+    pub type_: String,
+
+    #[serde(rename = "Product")]
+    pub product: String,
+
+    #[serde(rename = "Started Date", deserialize_with = "deserialize_date_time")]
+    pub started: NaiveDateTime,
+
+    #[serde(rename = "Description")]
+    pub description: String,
+
+    /// Amount in the pocket's currency, without the fee.
+    #[serde(rename = "Amount", deserialize_with = "deserialize_amount_plain")]
+    pub amount: i32,
+
+    /// Fee charged on top of the amount, as a positive amount.
+    #[serde(rename = "Fee", deserialize_with = "deserialize_amount_plain")]
+    pub fee: i32,
+
+    #[serde(rename = "Currency")]
+    pub currency: String,
+
+    #[serde(rename = "State")]
+    pub state: String,
+
+    /// Rate the amounts were converted with into the budget currency, set by
+    /// `Revolut::convert`.
+    #[serde(skip)]
+    pub exchange_rate: Option<f64>,
+
+    /// Amount including the fee before the conversion.
+    #[serde(skip)]
+    pub original_amount: Option<i32>,
+
+    #[serde(skip)]
+    pub import_id: String,
+}
+
+fn deserialize_amount_plain<'de, D>(deserializer: D) -> result::Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_amount(deserializer, &AmountFormat::PLAIN)
+}
+
+fn deserialize_date_time<'de, D>(deserializer: D) -> result::Result<NaiveDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S").map_err(serde::de::Error::custom)
+}
+
+impl Transaction {
+    pub fn is_cash_withdrawal(&self) -> bool {
+        self.type_ == "ATM"
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.state == "PENDING"
+    }
+
+    /// Values for the placeholders listed in `MEMO_FIELDS`.
+    pub fn memo_values(&self) -> HashMap<&'static str, Option<String>> {
+        let original_amount = self
+            .original_amount
+            .map(|x| format!("{:.2} {}", x.abs() as f64 / 1000.0, self.currency));
+        let exchange_rate = self.exchange_rate.map(|x| format!("{:.4}", x));
+
+        // eg. "(12.34 USD @ 0.9200)"
+        let original = match (&original_amount, &exchange_rate) {
+            (Some(amount), Some(rate)) => Some(format!("({} @ {})", amount, rate)),
+            _ => None,
+        };
+
+        let mut values = HashMap::new();
+        values.insert("description", Some(self.description.clone()));
+        values.insert("type", Some(self.type_.clone()));
+        values.insert("product", Some(self.product.clone()));
+        values.insert("original_amount", original_amount);
+        values.insert("exchange_rate", exchange_rate);
+        values.insert("original", original);
+        values
+    }
+}
+
+pub struct Revolut {
+    pub transactions: Vec<Transaction>,
+    pub days_to_sync: i64,
+}
+
+impl Revolut {
+    /// Rows of the exports of the products (eg. `Current`) and pockets
+    /// (currencies, all of them when empty) to sync. Rows in more than one
+    /// export are only kept once.
+    pub fn new(csv_files: &[String], products: &[String], pockets: &[String]) -> Result<Self> {
+        let mut transactions: Vec<Transaction> = vec![];
+        let mut import_ids = HashSet::new();
+        for csv_file in csv_files {
+            for transaction in parse_file(csv_file)? {
+                if !products.contains(&transaction.product)
+                    || (!pockets.is_empty() && !pockets.contains(&transaction.currency))
+                    || SKIPPED_STATES.contains(&transaction.state.as_str())
+                    || !import_ids.insert(transaction.import_id.clone())
+                {
+                    continue;
+                }
+                transactions.push(transaction);
+            }
+        }
+
+        let today = Utc::today().naive_local();
+        let days_to_sync = transactions
+            .iter()
+            .map(|x| (today - x.started.date()).num_days())
+            .max()
+            .unwrap_or(0);
+
+        Ok(Revolut {
+            transactions,
+            days_to_sync,
+        })
+    }
+
+    /// Convert the amounts of rows in other currencies into `currency`.
+    /// Returns the number of converted rows.
+    pub fn convert(&mut self, currency: &str, rates: &ExchangeRates) -> Result<usize> {
+        let mut converted = 0;
+        for transaction in &mut self.transactions {
+            if transaction.currency == currency {
+                continue;
+            }
+            let rate = rates.rate(&transaction.currency, currency, transaction.started.date())?;
+            transaction.original_amount = Some(transaction.amount - transaction.fee);
+            transaction.amount = milliunits(transaction.amount as f64 / 1000.0 * rate);
+            transaction.fee = milliunits(transaction.fee as f64 / 1000.0 * rate);
+            transaction.exchange_rate = Some(rate);
+            converted += 1;
+        }
+        Ok(converted)
+    }
+}
+
+fn parse_file(csv_file: &str) -> Result<Vec<Transaction>> {
+    let file = File::open(csv_file)
+        .with_context(|_| ErrorKind::RevolutCsvFileCanNotOpen(csv_file.to_string()))?;
+    let mut reader = ReaderBuilder::new().from_reader(file);
+    let headers = reader
+        .headers()
+        .with_context(|_| ErrorKind::RevolutCsvFileParse(csv_file.to_string()))?
+        .clone();
+
+    let mut transactions = vec![];
+    // identical rows (eg. two equal purchases at the same time) get
+    // different import ids
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for result in reader.records() {
+        let record =
+            result.with_context(|_| ErrorKind::RevolutCsvFileParse(csv_file.to_string()))?;
+        let mut transaction: Transaction =
+            record.deserialize(Some(&headers)).with_context(|_| {
+                ErrorKind::RevolutCsvTransactionParse(
+                    csv_file.to_string(),
+                    record.iter().collect::<Vec<_>>().join(","),
+                )
+            })?;
+
+        // the completed date and the state change once a pending row is
+        // completed, the import id has to stay the same
+        let key = format!(
+            "{}|{}|{}|{}|{}|{}",
+            transaction.type_,
+            transaction.product,
+            transaction.started,
+            transaction.description,
+            transaction.amount,
+            transaction.currency
+        );
+        let occurrence = occurrences.entry(key.clone()).or_insert(0);
+        *occurrence += 1;
+        let mut import_id_sha = Sha1::new();
+        import_id_sha.input_str(&key);
+        import_id_sha.input_str(&format!("{}", occurrence));
+        transaction.import_id = import_id_sha.result_str()[..36].to_string();
+
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+impl BankSource for Revolut {
+    fn name(&self) -> &'static str {
+        "Revolut"
+    }
+
+    fn memo_fields(&self) -> &'static [&'static str] {
+        MEMO_FIELDS
+    }
+
+    fn default_memo_template(&self) -> &'static str {
+        DEFAULT_MEMO_TEMPLATE
+    }
+
+    /// Rows of the exports started in the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>> {
+        let since = Utc::today().naive_local() - Duration::days(days);
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|x| x.started.date() >= since)
+            .map(|x| SourceTransaction {
+                id: x.import_id.clone(),
+                date: x.started.date(),
+                amount: x.amount - x.fee,
+                payee: Some(x.description.clone()).filter(|x| !x.is_empty()),
+                memo_values: x.memo_values(),
+                is_cash_withdrawal: x.is_cash_withdrawal(),
+                fee: Some(-x.fee).filter(|x| *x != 0),
+                pending: x.is_pending(),
+                category: None,
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExchangeRatesResponse {
+    rates: HashMap<String, f64>,
+}
+
+/// Exchange rates between currencies, fixed ones given with
+/// `--exchange-rate` or the ECB reference rates of a day.
+#[derive(Debug, Default)]
+pub struct ExchangeRates {
+    api_url: String,
+    fixed: HashMap<String, f64>,
+    fetched: RefCell<HashMap<(String, String, NaiveDate), f64>>,
+}
+
+impl ExchangeRates {
+    /// Rates given as `CURRENCY=RATE` (eg. `USD=0.92`), the value of one unit
+    /// of the currency in the budget currency.
+    pub fn new(fixed: &[String]) -> Result<Self> {
+        let mut rates = HashMap::new();
+        for rate in fixed {
+            let error = || ErrorKind::ArgParse(format!("--exchange-rate {}", rate));
+            let mut parts = rate.splitn(2, '=');
+            let currency = parts.next().unwrap_or("").trim().to_uppercase();
+            let value: f64 = parts
+                .next()
+                .ok_or_else(error)?
+                .trim()
+                .parse()
+                .map_err(|_| error())?;
+            if currency.is_empty() {
+                Err(error())?;
+            }
+            rates.insert(currency, value);
+        }
+        Ok(ExchangeRates {
+            api_url: EXCHANGE_RATES_URL.to_string(),
+            fixed: rates,
+            fetched: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Like `ExchangeRates::new` but fetching rates from a different API
+    /// (eg. a mock server).
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.to_string();
+        self
+    }
+
+    /// Value of one unit of `from` in `to` on `date`.
+    pub fn rate(&self, from: &str, to: &str, date: NaiveDate) -> Result<f64> {
+        if let Some(rate) = self.fixed.get(from) {
+            return Ok(*rate);
+        }
+        let key = (from.to_string(), to.to_string(), date);
+        if let Some(rate) = self.fetched.borrow().get(&key) {
+            return Ok(*rate);
+        }
+
+        let url = format!(
+            "{}/{}?from={}&to={}",
+            self.api_url,
+            date.format("%Y-%m-%d"),
+            from,
+            to
+        );
+        info!("Fetching exchange rate from {}", url);
+        let res = Request::get(&url)
+            .send()
+            .context(ErrorKind::ExchangeRateGet)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            let http_error = ErrorKind::ExchangeRateGetHttp(res.status().as_u16(), body.clone());
+            Err(http_error)?;
+        }
+
+        let response: ExchangeRatesResponse = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::ExchangeRateGetParse(e.to_string()))?;
+        let rate = match response.rates.get(to) {
+            Some(x) => *x,
+            None => Err(ErrorKind::ExchangeRateGetParse(format!(
+                "no rate from {} to {}",
+                from, to
+            )))?,
+        };
+        self.fetched.borrow_mut().insert(key, rate);
+        Ok(rate)
+    }
+}
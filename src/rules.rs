@@ -1,42 +1,472 @@
+// Category rules
+//
+// A rules file is a list of rules (a JSON array, `[[rules]]` tables in a
+// `.toml` file, a list in a `.yaml` file), each matching a text field of a
+// transaction and assigning a category (or a category group) and/or actions
+// like a flag color. Rules are evaluated by their `priority` (highest first)
+// and their order in the file, the first matching rule with a category wins.
+// With `--rule-match most-specific` the matching rule with the longest value
+// wins instead (among the ones with the highest priority). Matching rules
+// without a category only add their actions.
+
+use crate::fuzzy::best_match;
+use crate::ynab::{Category, CategoryGroup, Transaction, TransactionCleared, TransactionFlagColor};
 use crate::{ErrorKind, Result};
-use crate::ynab::{Transaction, Category};
 use failure::ResultExt;
 use serde::{Deserialize, Serialize};
-use serde_json;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fmt;
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
 use std::result;
+use std::slice;
 use std::str::FromStr;
+use structopt::StructOpt;
+
+// Unknown categories are only taken for a misspelling of a category at
+// least this similar.
+const SUGGESTION_SIMILARITY: f64 = 0.8;
+
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "rule-match",
+        value_name = "MODE",
+        default_value = "first",
+        possible_values = &["first", "most-specific"],
+        env = "YNAB_RULE_MATCH",
+        help = "Which of several matching category rules wins: the first one, or the most specific one (longest value). Rules with a higher \"priority\" always win."
+    )]
+    pub match_mode: MatchMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchMode {
+    First,
+    MostSpecific,
+}
+
+impl fmt::Display for MatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                MatchMode::First => "first",
+                MatchMode::MostSpecific => "most-specific",
+            },
+        )
+    }
+}
+
+impl FromStr for MatchMode {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(MatchMode::First),
+            "most-specific" => Ok(MatchMode::MostSpecific),
+            _ => Err(ErrorKind::ArgParse(format!("--rule-match {}", s))),
+        }
+    }
+}
+
+/// What a sync does with rules referring to categories which don't exist in
+/// the budget (eg. misspelled ones).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownCategories {
+    /// Ask whether to use the most similar category instead.
+    Ask,
+    /// Fail.
+    Strict,
+}
+
+impl fmt::Display for UnknownCategories {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                UnknownCategories::Ask => "ask",
+                UnknownCategories::Strict => "strict",
+            }
+        )
+    }
+}
+
+impl FromStr for UnknownCategories {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "ask" => Ok(UnknownCategories::Ask),
+            "strict" => Ok(UnknownCategories::Strict),
+            _ => Err(ErrorKind::ArgParse(format!("--unknown-categories {}", s))),
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "rule")]
-enum Rules {
+pub enum Rules {
     Contains {
         value: String,
         #[serde(with = "serde_str")]
         field: TransactionField,
-        category: String,
+        #[serde(flatten)]
+        target: Option<RuleTarget>,
+        #[serde(flatten)]
+        actions: RuleActions,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        priority: i64,
     },
     StartsWith {
         value: String,
         #[serde(with = "serde_str")]
         field: TransactionField,
-        category: String,
+        #[serde(flatten)]
+        target: Option<RuleTarget>,
+        #[serde(flatten)]
+        actions: RuleActions,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        priority: i64,
     },
     EndsWith {
         value: String,
         #[serde(with = "serde_str")]
         field: TransactionField,
+        #[serde(flatten)]
+        target: Option<RuleTarget>,
+        #[serde(flatten)]
+        actions: RuleActions,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        priority: i64,
+    },
+}
+
+/// What a matching rule assigns: a category, or a category group in which
+/// `default` is used or, without it, the user picks the category.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RuleTarget {
+    Category {
         category: String,
     },
+    CategoryGroup {
+        category_group: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default: Option<String>,
+    },
+}
+
+/// What a matching rule changes besides the category. With several matching
+/// rules the first one setting an action wins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawRuleActions")]
+pub struct RuleActions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag_color: Option<TransactionFlagColor>,
+    /// Replaces the memo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Put in front of the memo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo_prefix: Option<String>,
+    /// Cleared (`true`) or uncleared (`false`) in YNAB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cleared: Option<bool>,
+    /// Only `false` makes a difference, the transaction needs to be approved
+    /// in YNAB even when a rule assigned its category.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approved: Option<bool>,
+    /// Don't sync the transaction at all.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip: bool,
+}
+
+/// `RuleActions` as written in rules files, where `ignore` is read as
+/// `skip` (serde's aliases don't work in flattened structs).
+#[derive(Deserialize)]
+struct RawRuleActions {
+    #[serde(default)]
+    flag_color: Option<TransactionFlagColor>,
+    #[serde(default)]
+    memo: Option<String>,
+    #[serde(default)]
+    memo_prefix: Option<String>,
+    #[serde(default)]
+    cleared: Option<bool>,
+    #[serde(default)]
+    approved: Option<bool>,
+    #[serde(default)]
+    skip: bool,
+    #[serde(default)]
+    ignore: bool,
+}
+
+impl From<RawRuleActions> for RuleActions {
+    fn from(raw: RawRuleActions) -> Self {
+        RuleActions {
+            flag_color: raw.flag_color,
+            memo: raw.memo,
+            memo_prefix: raw.memo_prefix,
+            cleared: raw.cleared,
+            approved: raw.approved,
+            skip: raw.skip || raw.ignore,
+        }
+    }
+}
+
+impl RuleActions {
+    pub fn is_empty(&self) -> bool {
+        *self == RuleActions::default()
+    }
+
+    /// Add the actions of a later matching rule which aren't set yet.
+    pub fn merge(&mut self, other: &RuleActions) {
+        self.flag_color = self.flag_color.take().or_else(|| other.flag_color.clone());
+        self.memo = self.memo.take().or_else(|| other.memo.clone());
+        self.memo_prefix = self
+            .memo_prefix
+            .take()
+            .or_else(|| other.memo_prefix.clone());
+        self.cleared = self.cleared.or(other.cleared);
+        self.approved = self.approved.or(other.approved);
+        self.skip |= other.skip;
+    }
+
+    pub fn apply(&self, mut transaction: Transaction) -> Transaction {
+        if let Some(flag_color) = &self.flag_color {
+            transaction.flag_color = Some(flag_color.clone());
+        }
+        if let Some(memo) = &self.memo {
+            transaction.memo = Some(memo.clone());
+        }
+        if let Some(memo_prefix) = &self.memo_prefix {
+            transaction.memo = Some(format!(
+                "{}{}",
+                memo_prefix,
+                transaction.memo.unwrap_or_default()
+            ));
+        }
+        match self.cleared {
+            Some(true) => transaction.cleared = TransactionCleared::Cleared,
+            Some(false) => transaction.cleared = TransactionCleared::Uncleared,
+            None => (),
+        }
+        if self.approved == Some(false) {
+            transaction.approved = false;
+        }
+        transaction
+    }
+}
+
+impl Rules {
+    pub fn field(&self) -> &TransactionField {
+        match self {
+            Rules::Contains { field, .. }
+            | Rules::StartsWith { field, .. }
+            | Rules::EndsWith { field, .. } => field,
+        }
+    }
+
+    pub fn target(&self) -> Option<&RuleTarget> {
+        match self {
+            Rules::Contains { target, .. }
+            | Rules::StartsWith { target, .. }
+            | Rules::EndsWith { target, .. } => target.as_ref(),
+        }
+    }
+
+    fn target_mut(&mut self) -> Option<&mut RuleTarget> {
+        match self {
+            Rules::Contains { target, .. }
+            | Rules::StartsWith { target, .. }
+            | Rules::EndsWith { target, .. } => target.as_mut(),
+        }
+    }
+
+    pub fn actions(&self) -> &RuleActions {
+        match self {
+            Rules::Contains { actions, .. }
+            | Rules::StartsWith { actions, .. }
+            | Rules::EndsWith { actions, .. } => actions,
+        }
+    }
+
+    /// Whether the search for a matching rule ends with this rule, which it
+    /// does with a category or when the transaction is skipped.
+    pub fn is_final(&self) -> bool {
+        self.target().is_some() || self.actions().skip
+    }
+
+    /// What the rule does, eg. `category "Groceries", flag orange`.
+    pub fn outcome(&self) -> String {
+        let target = self.target().map(|x| x.to_string());
+        let outcome = target
+            .into_iter()
+            .chain(Some(self.actions().to_string()).filter(|x| !x.is_empty()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if outcome.is_empty() {
+            "nothing".to_string()
+        } else {
+            outcome
+        }
+    }
+
+    pub fn priority(&self) -> i64 {
+        match self {
+            Rules::Contains { priority, .. }
+            | Rules::StartsWith { priority, .. }
+            | Rules::EndsWith { priority, .. } => *priority,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Rules::Contains { value, .. }
+            | Rules::StartsWith { value, .. }
+            | Rules::EndsWith { value, .. } => value,
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        match self {
+            Rules::Contains { value, .. } => text.contains(&value.to_lowercase()),
+            Rules::StartsWith { value, .. } => text.starts_with(&value.to_lowercase()),
+            Rules::EndsWith { value, .. } => text.ends_with(&value.to_lowercase()),
+        }
+    }
+
+    /// Whether the rule matches a transaction with `memo` and `entity`.
+    pub fn matches_transaction(&self, memo: &str, entity: &str) -> bool {
+        self.matches(match self.field() {
+            TransactionField::Memo => memo,
+            TransactionField::Entity => entity,
+        })
+    }
+
+    /// Whether every text `other` matches is matched by this rule too.
+    pub fn covers(&self, other: &Rules) -> bool {
+        if self.field() != other.field() {
+            return false;
+        }
+        let value = self.value().to_lowercase();
+        let other_value = other.value().to_lowercase();
+        match (self, other) {
+            (Rules::Contains { .. }, _) => other_value.contains(&value),
+            (Rules::StartsWith { .. }, Rules::StartsWith { .. }) => other_value.starts_with(&value),
+            (Rules::EndsWith { .. }, Rules::EndsWith { .. }) => other_value.ends_with(&value),
+            _ => false,
+        }
+    }
+
+    /// Read the rules of a `--category-rules` file, in the format of its
+    /// extension.
+    pub fn from_file(file: &str) -> Result<Vec<Rules>> {
+        if !PathBuf::from(file).exists() {
+            Err(ErrorKind::ArgParseCategoryRulesCanNotRead(file.to_string()))?
+        }
+        let content = read_to_string(file)
+            .with_context(|_| ErrorKind::ArgParseCategoryRulesCanNotRead(file.to_string()))?;
+        Ok(parse(&content, RulesFormat::of(file))
+            .with_context(|_| ErrorKind::ArgParseCategoryRulesCanNotParse(file.to_string()))?)
+    }
+
+    /// Add `rule` to the end of a `--category-rules` file. TOML files and
+    /// YAML files with a block list are appended to as they are (keeping
+    /// their comments), other files are rewritten.
+    pub fn append_to_file(file: &str, rule: &Rules) -> Result<()> {
+        let format = RulesFormat::of(file);
+        let content = read_to_string(file)
+            .with_context(|_| ErrorKind::ArgParseCategoryRulesCanNotRead(file.to_string()))?;
+        let appendable = match format {
+            RulesFormat::Toml => true,
+            RulesFormat::Yaml => content.trim().is_empty() || !yaml_item_lines(&content).is_empty(),
+            RulesFormat::Json => false,
+        };
+        let content = match format {
+            _ if appendable => {
+                let separator = match content.trim_end().len() {
+                    0 => "",
+                    x if content[x..].matches('\n').count() >= 2 => "",
+                    x if content[x..].contains('\n') => "\n",
+                    _ => "\n\n",
+                };
+                content + separator + &serialize(slice::from_ref(rule), format)?
+            }
+            _ => {
+                let mut rules = Rules::from_file(file)?;
+                rules.push(rule.clone());
+                serialize(&rules, format)?
+            }
+        };
+        write(file, content).with_context(|_| ErrorKind::RulesCanNotWrite(file.to_string()))?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rule = match self {
+            Rules::Contains { .. } => "Contains",
+            Rules::StartsWith { .. } => "StartsWith",
+            Rules::EndsWith { .. } => "EndsWith",
+        };
+        write!(f, "{} {:?} in {}", rule, self.value(), self.field())
+    }
+}
+
+impl fmt::Display for RuleActions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut actions = vec![];
+        if self.skip {
+            actions.push("skip".to_string());
+        }
+        if let Some(flag_color) = &self.flag_color {
+            actions.push(format!("flag {}", flag_color));
+        }
+        if let Some(memo) = &self.memo {
+            actions.push(format!("memo {:?}", memo));
+        }
+        if let Some(memo_prefix) = &self.memo_prefix {
+            actions.push(format!("memo prefix {:?}", memo_prefix));
+        }
+        match self.cleared {
+            Some(true) => actions.push("cleared".to_string()),
+            Some(false) => actions.push("uncleared".to_string()),
+            None => (),
+        }
+        if self.approved == Some(false) {
+            actions.push("unapproved".to_string());
+        }
+        write!(f, "{}", actions.join(", "))
+    }
+}
+
+impl fmt::Display for RuleTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleTarget::Category { category } => write!(f, "category {:?}", category),
+            RuleTarget::CategoryGroup {
+                category_group,
+                default: Some(default),
+            } => write!(f, "category {:?} of group {:?}", default, category_group),
+            RuleTarget::CategoryGroup {
+                category_group,
+                default: None,
+            } => write!(f, "category group {:?}", category_group),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum TransactionField {
+pub enum TransactionField {
     Memo,
-    Payee,
+    Entity,
 }
 
 impl fmt::Display for TransactionField {
@@ -46,7 +476,7 @@ impl fmt::Display for TransactionField {
             "{}",
             match *self {
                 TransactionField::Memo => "memo",
-                TransactionField::Payee => "payee",
+                TransactionField::Entity => "entity",
             },
         )
     }
@@ -58,73 +488,521 @@ impl FromStr for TransactionField {
     fn from_str(s: &str) -> result::Result<Self, Self::Err> {
         match s {
             "memo" => Ok(TransactionField::Memo),
-            "payee" => Ok(TransactionField::Payee),
+            "entity" => Ok(TransactionField::Entity),
             _ => Err(ErrorKind::YNABAccountTypeParse),
         }
     }
 }
 
-fn read_rules(category_rules_file: String) -> Result<Vec<Rules>> {
-    // check if --category-rules file exists and that it is of JSON format
-    if !PathBuf::from(category_rules_file.clone()).exists() {
-        Err(ErrorKind::ArgParseCategoryRulesCanNotRead(
-            category_rules_file.clone(),
-        ))?
+/// What `rules lint` and `doctor` suggest for invalid rules.
+pub const RULE_HELP: &str = "Each rule needs \"rule\" (Contains, StartsWith, EndsWith), \"field\" (memo, entity), \"value\" and \"category\", \"category_group\" or actions (flag_color, memo, memo_prefix, cleared, approved, skip).";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RulesFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl RulesFormat {
+    /// Format of a rules file by its extension, JSON when unknown.
+    pub fn of(file: &str) -> Self {
+        let extension = Path::new(file)
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("toml") => RulesFormat::Toml,
+            Some("yaml") | Some("yml") => RulesFormat::Yaml,
+            _ => RulesFormat::Json,
+        }
+    }
+}
+
+/// TOML documents are tables, the rules are `[[rules]]` tables in it.
+#[derive(Serialize, Deserialize)]
+struct TomlRules {
+    #[serde(default)]
+    rules: Vec<Rules>,
+}
+
+/// Parse the rules of a file in `format`. Errors name the line of the
+/// mistake.
+pub fn parse(content: &str, format: RulesFormat) -> Result<Vec<Rules>> {
+    match format {
+        RulesFormat::Json => {
+            Ok(serde_json::from_str(content).map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?)
+        }
+        RulesFormat::Toml => Ok(toml::from_str::<TomlRules>(content)
+            .map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?
+            .rules),
+        RulesFormat::Yaml => {
+            Ok(serde_yaml::from_str(content).map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?)
+        }
     }
-    let category_rules_string =
-        read_to_string(category_rules_file.to_string()).with_context(|_| {
-            ErrorKind::ArgParseCategoryRulesCanNotRead(category_rules_file.clone())
-        })?;
-    serde_json::from_str(&category_rules_string).context(
-        ErrorKind::ArgParseCategoryRulesCanNotParse(category_rules_file.clone()),
-    )?
 }
 
-fn apply_rules(rules: Vec<Rules>, categories: HashMap<String, Category>, transaction: Transaction) -> Option<Category> {
-    let memo = transaction.clone().memo.unwrap_or("".to_string());
-    let payee = transaction.clone().payee_name.unwrap_or("".to_string());
-    for rule in &rules {
-        match rule {
-            Rules::Contains {
-                value,
-                field,
-                category,
-            } => {
-                let text = match field {
-                    TransactionField::Memo => &memo,
-                    TransactionField::Payee => &payee,
-                };
-                if text.to_lowercase().contains(&value.to_lowercase()) {
-                    return categories.get(category).cloned();
-                }
+/// `rules` in `format`, eg. to write them to a file.
+pub fn serialize(rules: &[Rules], format: RulesFormat) -> Result<String> {
+    match format {
+        RulesFormat::Toml => Ok(toml::to_string(&TomlRules {
+            rules: rules.to_vec(),
+        })
+        .map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?),
+        RulesFormat::Yaml => {
+            let content =
+                serde_yaml::to_string(rules).map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?;
+            // without the document start, so the rules can be appended
+            Ok(content.trim_start_matches("---\n").to_string() + "\n")
+        }
+        _ => Ok(serde_json::to_string_pretty(rules)
+            .map_err(|e| ErrorKind::RulesSyntax(e.to_string()))?
+            + "\n"),
+    }
+}
+
+fn is_zero(x: &i64) -> bool {
+    *x == 0
+}
+
+/// Rank of `rules[i]`, of several matching rules the one with the highest
+/// rank wins: the highest priority, with `MatchMode::MostSpecific` the
+/// longest value, the first in the file.
+pub fn rank(rules: &[Rules], i: usize, mode: MatchMode) -> (i64, usize, Reverse<usize>) {
+    let specificity = match mode {
+        MatchMode::First => 0,
+        MatchMode::MostSpecific => rules[i].value().chars().count(),
+    };
+    (rules[i].priority(), specificity, Reverse(i))
+}
+
+/// Whether `rules[i]` wins over `rules[j]` whenever both match, so
+/// `rules[j]` never makes a difference: `rules[i]` ends the search (see
+/// `Rules::is_final`), matches every text `rules[j]` matches and ranks
+/// higher. With `MatchMode::MostSpecific` the actions of rules without a
+/// category apply anyway.
+pub fn shadows(rules: &[Rules], i: usize, j: usize, mode: MatchMode) -> bool {
+    i != j
+        && rules[i].is_final()
+        && (rules[j].is_final() || mode == MatchMode::First)
+        && rules[i].covers(&rules[j])
+        && rank(rules, i, mode) > rank(rules, j, mode)
+}
+
+/// Indexes of `rules` by rank, highest first.
+pub fn evaluation_order(rules: &[Rules], mode: MatchMode) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..rules.len()).collect();
+    order.sort_by_key(|i| Reverse(rank(rules, *i, mode)));
+    order
+}
+
+/// Rules matching a transaction, as a sync applies them.
+#[derive(Clone, Debug, Default)]
+pub struct RuleMatches<'a> {
+    /// Winning rule with a category or skipping the transaction (see
+    /// `Rules::is_final`).
+    pub rule: Option<(usize, &'a Rules)>,
+    /// Matching rules whose actions apply, the winning one included, by
+    /// rank.
+    pub applied: Vec<(usize, &'a Rules)>,
+    /// Other matching rules with a category (or skipping) and a different
+    /// outcome than the winning one.
+    pub conflicts: Vec<(usize, &'a Rules)>,
+    /// With `MatchMode::First` the rules ranked after the winning one.
+    pub not_evaluated: Vec<usize>,
+}
+
+impl RuleMatches<'_> {
+    /// Actions of the applied rules, the highest ranked one setting an
+    /// action wins.
+    pub fn actions(&self) -> RuleActions {
+        let mut actions = RuleActions::default();
+        for (_, rule) in &self.applied {
+            actions.merge(rule.actions());
+        }
+        actions
+    }
+}
+
+/// Apply `rules` to a transaction with `memo` and `entity`.
+pub fn apply<'a>(rules: &'a [Rules], mode: MatchMode, memo: &str, entity: &str) -> RuleMatches<'a> {
+    let order = evaluation_order(rules, mode);
+    let matching: Vec<usize> = order
+        .iter()
+        .copied()
+        .filter(|i| rules[*i].matches_transaction(memo, entity))
+        .collect();
+    let winner = matching.iter().copied().find(|i| rules[*i].is_final());
+    let winner_position = winner.and_then(|x| order.iter().position(|i| *i == x));
+    let evaluated = |i: usize| {
+        mode == MatchMode::MostSpecific
+            || winner_position.is_none_or(|x| order.iter().position(|y| *y == i) <= Some(x))
+    };
+    let outcome = |i: usize| (rules[i].target(), rules[i].actions().skip);
+
+    RuleMatches {
+        rule: winner.map(|i| (i, &rules[i])),
+        applied: matching
+            .iter()
+            .copied()
+            .filter(|i| Some(*i) == winner || (!rules[*i].is_final() && evaluated(*i)))
+            .map(|i| (i, &rules[i]))
+            .collect(),
+        conflicts: matching
+            .iter()
+            .copied()
+            .filter(|i| rules[*i].is_final() && Some(*i) != winner)
+            .filter(|i| winner.is_some_and(|x| outcome(x) != outcome(*i)))
+            .map(|i| (i, &rules[i]))
+            .collect(),
+        not_evaluated: order.iter().copied().filter(|i| !evaluated(*i)).collect(),
+    }
+}
+
+/// Part of a `RuleTarget` naming a category or a category group.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetPart {
+    Category,
+    CategoryGroup,
+    Default,
+}
+
+/// A category (or category group) a rule refers to which doesn't exist in the
+/// budget, with the most similar one which does.
+#[derive(Clone, Debug)]
+pub struct UnknownCategory {
+    pub rule: usize,
+    pub part: TargetPart,
+    pub name: String,
+    pub suggestion: Option<String>,
+}
+
+impl UnknownCategory {
+    /// Use the suggested category (or category group) in `rules`.
+    pub fn fix(&self, rules: &mut [Rules]) {
+        let suggestion = match &self.suggestion {
+            Some(x) => x.clone(),
+            None => return,
+        };
+        match (rules[self.rule].target_mut(), self.part) {
+            (Some(RuleTarget::Category { category }), TargetPart::Category) => {
+                *category = suggestion
             }
-            Rules::StartsWith {
-                value,
-                field,
-                category,
-            } => {
-                let text = match field {
-                    TransactionField::Memo => &memo,
-                    TransactionField::Payee => &payee,
-                };
-                if text.to_lowercase().starts_with(&value.to_lowercase()) {
-                    return categories.get(category).cloned();
-                }
+            (Some(RuleTarget::CategoryGroup { category_group, .. }), TargetPart::CategoryGroup) => {
+                *category_group = suggestion
+            }
+            (Some(RuleTarget::CategoryGroup { default, .. }), TargetPart::Default) => {
+                *default = Some(suggestion)
             }
-            Rules::EndsWith {
-                value,
-                field,
+            _ => (),
+        }
+    }
+}
+
+impl fmt::Display for UnknownCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let part = match self.part {
+            TargetPart::Category => "category",
+            TargetPart::CategoryGroup => "category group",
+            TargetPart::Default => "default category",
+        };
+        write!(
+            f,
+            "rule #{}: {} \"{}\" doesn't exist in the budget",
+            self.rule + 1,
+            part,
+            self.name
+        )
+    }
+}
+
+/// Categories and category groups `rules` refer to which don't exist in the
+/// budget (as indexed by `ynab::index_categories` and
+/// `ynab::index_category_groups`).
+pub fn unknown_categories(
+    rules: &[Rules],
+    categories: &HashMap<String, Category>,
+    category_groups: &HashMap<String, CategoryGroup>,
+) -> Vec<UnknownCategory> {
+    // one name per category, qualified by its group when the name is
+    // ambiguous
+    let groups: Vec<&CategoryGroup> = category_groups
+        .iter()
+        .filter(|(key, group)| **key == group.name && !group.deleted)
+        .map(|(_, group)| group)
+        .collect();
+    let category_names: Vec<String> = groups
+        .iter()
+        .flat_map(|group| {
+            group
+                .categories
+                .iter()
+                .filter(|x| !x.deleted)
+                .map(|x| {
+                    if categories.contains_key(&x.name) {
+                        x.name.clone()
+                    } else {
+                        format!("{}:{}", group.name, x.name)
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let suggest = |name: &str, candidates: Vec<&str>| {
+        best_match(name, candidates)
+            .filter(|(_, score)| *score >= SUGGESTION_SIMILARITY)
+            .map(|(x, _)| x.to_string())
+    };
+
+    let mut unknown = vec![];
+    for (rule, target) in rules
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| Some((i, x.target()?)))
+    {
+        let (part, name, candidates) = match target {
+            RuleTarget::Category { category } if !categories.contains_key(category) => (
+                TargetPart::Category,
                 category,
-            } => {
-                let text = match field {
-                    TransactionField::Memo => &memo,
-                    TransactionField::Payee => &payee,
-                };
-                if text.to_lowercase().ends_with(&value.to_lowercase()) {
-                    return categories.get(category).cloned();
+                category_names.iter().map(|x| x.as_str()).collect(),
+            ),
+            RuleTarget::Category { .. } => continue,
+            RuleTarget::CategoryGroup {
+                category_group,
+                default,
+            } => match (category_groups.get(category_group), default) {
+                (None, _) => (
+                    TargetPart::CategoryGroup,
+                    category_group,
+                    groups.iter().map(|x| x.name.as_str()).collect(),
+                ),
+                (Some(group), Some(default)) if group.category(default).is_none() => (
+                    TargetPart::Default,
+                    default,
+                    group
+                        .categories
+                        .iter()
+                        .filter(|x| !x.deleted)
+                        .map(|x| x.name.as_str())
+                        .collect(),
+                ),
+                _ => continue,
+            },
+        };
+        unknown.push(UnknownCategory {
+            rule,
+            part,
+            name: name.clone(),
+            suggestion: suggest(name, candidates),
+        });
+    }
+    unknown
+}
+
+/// Line (1-based) on which each rule in `content` starts, to point at rules
+/// by line.
+pub fn rule_lines(content: &str, format: RulesFormat) -> Vec<usize> {
+    match format {
+        RulesFormat::Toml => content
+            .lines()
+            .enumerate()
+            .filter(|(_, x)| x.trim() == "[[rules]]")
+            .map(|(i, _)| i + 1)
+            .collect(),
+        RulesFormat::Yaml => match yaml_item_lines(content) {
+            // flow lists are like JSON arrays
+            x if x.is_empty() => json_element_lines(content),
+            x => x,
+        },
+        _ => json_element_lines(content),
+    }
+}
+
+/// Line (1-based) of each item of a YAML block list which isn't indented.
+fn yaml_item_lines(content: &str) -> Vec<usize> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, x)| x.trim_end() == "-" || x.starts_with("- "))
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// Line (1-based) on which each element of the top-level JSON array in
+/// `content` starts.
+fn json_element_lines(content: &str) -> Vec<usize> {
+    let mut lines = vec![];
+    let mut line = 1;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in content.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => {
+                if depth == 1 {
+                    lines.push(line);
                 }
+                depth += 1;
             }
+            ']' | '}' => depth -= 1,
+            _ => (),
         }
-    };
-    None
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(value: &str, category: &str, priority: i64) -> Rules {
+        Rules::Contains {
+            value: value.to_string(),
+            field: TransactionField::Entity,
+            target: Some(RuleTarget::Category {
+                category: category.to_string(),
+            }),
+            actions: RuleActions::default(),
+            priority,
+        }
+    }
+
+    fn winner(rules: &[Rules], mode: MatchMode, entity: &str) -> Option<usize> {
+        apply(rules, mode, "", entity).rule.map(|(i, _)| i)
+    }
+
+    #[test]
+    fn higher_priority_wins_in_both_modes() {
+        let rules = vec![rule("rewe markt", "A", 0), rule("rewe", "B", 1)];
+        for mode in &[MatchMode::First, MatchMode::MostSpecific] {
+            assert_eq!(winner(&rules, *mode, "REWE Markt GmbH"), Some(1));
+            assert_eq!(evaluation_order(&rules, *mode), vec![1, 0]);
+        }
+    }
+
+    #[test]
+    fn longer_value_wins_only_when_most_specific() {
+        let rules = vec![rule("rewe", "A", 0), rule("rewe markt", "B", 0)];
+        assert_eq!(winner(&rules, MatchMode::First, "REWE Markt GmbH"), Some(0));
+        assert_eq!(
+            winner(&rules, MatchMode::MostSpecific, "REWE Markt GmbH"),
+            Some(1)
+        );
+        let matches = apply(&rules, MatchMode::First, "", "REWE Markt GmbH");
+        assert_eq!(matches.conflicts.len(), 1);
+        assert_eq!(matches.not_evaluated, vec![1]);
+    }
+
+    #[test]
+    fn file_order_breaks_ties_in_both_modes() {
+        let rules = vec![rule("rewe", "A", 0), rule("REWE", "B", 0)];
+        for mode in &[MatchMode::First, MatchMode::MostSpecific] {
+            assert_eq!(winner(&rules, *mode, "rewe"), Some(0));
+            assert_eq!(evaluation_order(&rules, *mode), vec![0, 1]);
+        }
+    }
+
+    #[test]
+    fn shadowed_rules_rank_lower() {
+        let rules = vec![rule("rewe", "A", 0), rule("rewe markt", "B", 0)];
+        assert!(shadows(&rules, 0, 1, MatchMode::First));
+        assert!(!shadows(&rules, 1, 0, MatchMode::First));
+        // the more specific rule wins, it isn't shadowed
+        assert!(!shadows(&rules, 0, 1, MatchMode::MostSpecific));
+
+        // a higher priority isn't shadowed by a more general rule
+        let rules = vec![rule("rewe", "A", 0), rule("rewe markt", "B", 1)];
+        assert!(!shadows(&rules, 0, 1, MatchMode::First));
+        let rules = vec![rule("rewe", "A", 1), rule("rewe markt", "B", 0)];
+        assert!(shadows(&rules, 0, 1, MatchMode::MostSpecific));
+    }
+
+    #[test]
+    fn actions_of_rules_without_category_apply_when_most_specific() {
+        let flag = Rules::Contains {
+            value: "rewe markt".to_string(),
+            field: TransactionField::Entity,
+            target: None,
+            actions: RuleActions {
+                flag_color: Some(TransactionFlagColor::Red),
+                ..RuleActions::default()
+            },
+            priority: 0,
+        };
+        let rules = vec![rule("rewe", "A", 0), flag];
+        assert!(shadows(&rules, 0, 1, MatchMode::First));
+        assert!(!shadows(&rules, 0, 1, MatchMode::MostSpecific));
+    }
+
+    const YAML_RULES: &str = "# groceries
+- rule: Contains
+  field: entity
+  value: REWE
+  category: &groceries Groceries
+- rule: StartsWith
+  field: memo
+  value: EDEKA # also groceries
+  category: *groceries
+  priority: 1
+- {rule: EndsWith, field: memo, value: Miete, flag_color: red, skip: true}
+";
+
+    #[test]
+    fn yaml_rules() {
+        let rules = parse(YAML_RULES, RulesFormat::Yaml).unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0], rule("REWE", "Groceries", 0));
+        assert_eq!(rules[1].to_string(), "StartsWith \"EDEKA\" in memo");
+        assert_eq!(rules[1].outcome(), "category \"Groceries\"");
+        assert_eq!(rules[1].priority(), 1);
+        assert_eq!(rules[2].outcome(), "skip, flag red");
+        assert_eq!(rule_lines(YAML_RULES, RulesFormat::Yaml), vec![2, 6, 11]);
+
+        let written = serialize(&rules, RulesFormat::Yaml).unwrap();
+        assert!(written.starts_with("- rule: Contains\n"));
+        assert_eq!(parse(&written, RulesFormat::Yaml).unwrap(), rules);
+
+        let error = parse("- rule: Contains\n  value: [\n", RulesFormat::Yaml).unwrap_err();
+        assert!(error.to_string().contains("line"));
+    }
+
+    #[test]
+    fn appending_to_yaml_keeps_comments() {
+        let file =
+            std::env::temp_dir().join(format!("ynab-sync-rules-{}.yaml", std::process::id()));
+        let file = file.to_string_lossy().to_string();
+        write(&file, YAML_RULES).unwrap();
+        Rules::append_to_file(&file, &rule("Aldi", "Groceries", 0)).unwrap();
+        let content = read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert!(content.starts_with(YAML_RULES));
+        let rules = parse(&content, RulesFormat::Yaml).unwrap();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[3], rule("Aldi", "Groceries", 0));
+    }
+
+    #[test]
+    fn ignore_is_read_as_skip() {
+        let rules = parse(
+            r#"[{"rule": "Contains", "field": "memo", "value": "x", "ignore": true}]"#,
+            RulesFormat::Json,
+        )
+        .unwrap();
+        assert!(rules[0].actions().skip);
+        assert!(serialize(&rules, RulesFormat::Json)
+            .unwrap()
+            .contains("\"skip\": true"));
+    }
 }
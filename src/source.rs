@@ -0,0 +1,173 @@
+// Bank sources
+//
+// Every bank provides its transactions in its own shape (N26's API, ING-DiBa's
+// CSV export, ...). `BankSource` turns them into `SourceTransaction`s, the
+// fields the sync needs from any bank, so new banks and binaries can share
+// the conversion to YNAB transactions. Banks with a categorization of their
+// own (eg. N26's categories or ING-DiBa's --category-rules) plug it into the
+// sync with the optional methods of the trait.
+
+use crate::explain::Trace;
+use crate::ynab::{BudgetCategories, Category, CategoryGroup, Transaction, YNAB};
+use crate::Result;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+/// Date range of the transactions synced from exports, which often contain
+/// months of transactions synced before.
+#[derive(Clone, StructOpt, Debug)]
+pub struct Cli {
+    #[structopt(
+        long = "from-date",
+        value_name = "YYYY-MM-DD",
+        help = "Only sync transactions of the export booked on or after this date."
+    )]
+    pub from_date: Option<NaiveDate>,
+    #[structopt(
+        long = "to-date",
+        value_name = "YYYY-MM-DD",
+        help = "Only sync transactions of the export booked on or before this date."
+    )]
+    pub to_date: Option<NaiveDate>,
+}
+
+impl Cli {
+    /// Whether `date` is within --from-date and --to-date.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from_date.is_none_or(|x| date >= x) && self.to_date.is_none_or(|x| date <= x)
+    }
+}
+
+/// Transactions not to sync at all, given by --exclude-pattern and
+/// --exclude-zero-amounts.
+#[derive(Clone, Debug, Default)]
+pub struct Exclusions {
+    patterns: Vec<String>,
+    zero_amounts: bool,
+}
+
+impl Exclusions {
+    pub fn new(patterns: &[String], zero_amounts: bool) -> Self {
+        Exclusions {
+            patterns: patterns
+                .iter()
+                .map(|x| x.to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect(),
+            zero_amounts,
+        }
+    }
+
+    /// Why the transaction with `amount` and `values` (see `memo_values` of
+    /// the bank transactions) isn't synced, `None` when it is.
+    pub fn reason(
+        &self,
+        amount: i32,
+        values: &HashMap<&'static str, Option<String>>,
+    ) -> Option<String> {
+        if self.zero_amounts && amount == 0 {
+            return Some("zero amount".to_string());
+        }
+        let texts: Vec<String> = values
+            .values()
+            .filter_map(|x| x.as_ref().map(|x| x.to_lowercase()))
+            .collect();
+        self.patterns
+            .iter()
+            .find(|x| texts.iter().any(|text| text.contains(x.as_str())))
+            .map(|x| format!("--exclude-pattern {:?}", x))
+    }
+}
+
+/// A bank transaction, independent of the bank it comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceTransaction {
+    /// Stable id of the transaction at the bank, used as YNAB import id.
+    pub id: String,
+    pub date: NaiveDate,
+    /// Amount in YNAB milliunits, negative for outflows.
+    pub amount: i32,
+    /// Merchant or counterparty.
+    pub payee: Option<String>,
+    /// Values for the placeholders of `--memo-template`, also matched by
+    /// rules and own account patterns.
+    pub memo_values: HashMap<&'static str, Option<String>>,
+    pub is_cash_withdrawal: bool,
+    /// Fee charged on top of the amount, as a negative amount.
+    pub fee: Option<i32>,
+    /// Not booked by the bank yet, synced as uncleared.
+    pub pending: bool,
+    /// Category the bank assigned (eg. N26's), for `BankSource::category`.
+    pub category: Option<String>,
+}
+
+/// Category the bank's categorization found in the budget.
+#[derive(Clone, Debug)]
+pub enum SourceCategory {
+    Category(Category),
+    /// The user picks one of the group's categories after the conversion.
+    CategoryGroup(CategoryGroup),
+}
+
+pub trait BankSource {
+    /// Name of the bank, eg. for notifications.
+    fn name(&self) -> &'static str;
+
+    /// Placeholders available in `--memo-template`.
+    fn memo_fields(&self) -> &'static [&'static str];
+
+    /// `--memo-template` used when none is given.
+    fn default_memo_template(&self) -> &'static str;
+
+    /// Transactions of the last `days` days.
+    fn transactions(&self, days: i64) -> Result<Vec<SourceTransaction>>;
+
+    /// Balance the cleared balance of the YNAB account should have after
+    /// the sync (see `balance::BalanceGuard`), `None` when the bank doesn't
+    /// report it.
+    fn balance(&self) -> Result<Option<i32>> {
+        Ok(None)
+    }
+
+    /// Check the categorization against the budget once its categories are
+    /// fetched, before any transaction is converted.
+    fn prepare(&mut self, _categories: &BudgetCategories) -> Result<()> {
+        Ok(())
+    }
+
+    /// Why the categorization skips the transaction, `None` when it doesn't.
+    fn skip_reason(&self, _transaction: &SourceTransaction) -> Option<String> {
+        None
+    }
+
+    /// Category of the transaction, which wins over the payee history,
+    /// --classify and --default-category. How it was found goes to `trace`.
+    fn category(
+        &self,
+        _transaction: &SourceTransaction,
+        _categories: &BudgetCategories,
+        _trace: &mut Trace,
+    ) -> Option<SourceCategory> {
+        None
+    }
+
+    /// Changes to the converted transaction besides its category (eg. the
+    /// actions of rules).
+    fn amend(&self, _transaction: &SourceTransaction, converted: Transaction) -> Transaction {
+        converted
+    }
+
+    /// Category of a transaction `category` found none for and which isn't
+    /// in YNAB yet, eg. searched by the user. `None` keeps the one of the
+    /// payee history, --classify or --default-category.
+    fn categorize_uncategorized(
+        &mut self,
+        _ynab: &YNAB,
+        _transaction: &Transaction,
+        _source: &SourceTransaction,
+        _categories: &BudgetCategories,
+    ) -> Result<Option<Category>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::ynab::{SubTransaction, Transaction};
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+/// Shared expenses, read from the file provided via `--split-config`:
+///
+/// ```json
+/// {
+///   "share": 60,
+///   "match": ["REWE", "Stadtwerke"],
+///   "category": "Splitwise"
+/// }
+/// ```
+///
+/// Transactions whose payee or memo contains one of `match` (all
+/// transactions when empty) are split: `share` percent stay as they are and
+/// the rest (the partner's share) either goes into a tracking `category` of
+/// the same transaction (a YNAB split transaction) or, with `"budget"` and
+/// `"account"` instead of `"category"`, is written to the partner's budget.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplitConfig {
+    pub share: f64,
+    #[serde(default, rename = "match")]
+    pub matches: Vec<String>,
+    pub category: Option<String>,
+    pub budget: Option<String>,
+    pub account: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SplitTarget {
+    /// Id of the tracking category in the synced budget.
+    Category(String),
+    /// Ids of the partner's budget and account.
+    Account {
+        budget_id: String,
+        account_id: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Splitter {
+    config: SplitConfig,
+    target: SplitTarget,
+}
+
+impl SplitConfig {
+    pub fn from_file(split_config_file: &str) -> Result<Self> {
+        if !PathBuf::from(split_config_file).exists() {
+            Err(ErrorKind::ArgParseSplitConfigCanNotRead(
+                split_config_file.to_string(),
+            ))?
+        }
+        let split_config_string = read_to_string(split_config_file).with_context(|_| {
+            ErrorKind::ArgParseSplitConfigCanNotRead(split_config_file.to_string())
+        })?;
+        let config: SplitConfig = serde_json::from_str(&split_config_string).context(
+            ErrorKind::ArgParseSplitConfigCanNotParse(split_config_file.to_string()),
+        )?;
+
+        let has_account = config.budget.is_some() && config.account.is_some();
+        if !(0.0..=100.0).contains(&config.share) || config.category.is_some() == has_account {
+            Err(ErrorKind::ArgParseSplitConfigCanNotParse(
+                split_config_file.to_string(),
+            ))?
+        }
+
+        Ok(config)
+    }
+}
+
+impl Splitter {
+    pub fn new(config: SplitConfig, target: SplitTarget) -> Self {
+        Splitter { config, target }
+    }
+
+    pub fn target(&self) -> &SplitTarget {
+        &self.target
+    }
+
+    fn matches(&self, transaction: &Transaction) -> bool {
+        if self.config.matches.is_empty() {
+            return true;
+        }
+        let texts: Vec<String> = vec![&transaction.payee_name, &transaction.memo]
+            .into_iter()
+            .filter_map(|x| x.as_ref().map(|x| x.to_lowercase()))
+            .collect();
+        self.config.matches.iter().any(|value| {
+            let value = value.to_lowercase();
+            texts.iter().any(|x| x.contains(&value))
+        })
+    }
+
+    /// Own and partner's share of `amount` (in milliunits), rounded to cents.
+    pub fn split_amount(&self, amount: i32) -> (i32, i32) {
+        let own = ((amount as f64 * self.config.share / 100.0 / 10.0).round() as i32) * 10;
+        (own, amount - own)
+    }
+
+    /// Split matching transactions, returning the transactions for the synced
+    /// account and the partner's share for `SplitTarget::Account` (empty
+    /// otherwise).
+    pub fn apply(&self, transactions: Vec<Transaction>) -> (Vec<Transaction>, Vec<Transaction>) {
+        let mut own_transactions = vec![];
+        let mut partner_transactions = vec![];
+        for transaction in transactions {
+            if !self.matches(&transaction) {
+                own_transactions.push(transaction);
+                continue;
+            }
+            let (own, partner) = self.split_amount(transaction.amount);
+            match &self.target {
+                SplitTarget::Category(category_id) => {
+                    let subtransaction =
+                        |amount: i32, category_id: Option<String>| SubTransaction {
+                            amount,
+                            payee_id: transaction.payee_id.clone(),
+                            payee_name: transaction.payee_name.clone(),
+                            category_id,
+                            memo: transaction.memo.clone(),
+                        };
+                    let subtransactions = vec![
+                        subtransaction(own, transaction.category_id.clone()),
+                        subtransaction(partner, Some(category_id.clone())),
+                    ];
+                    own_transactions.push(Transaction {
+                        // YNAB sets the category of split transactions itself
+                        category_id: None,
+                        subtransactions,
+                        ..transaction
+                    });
+                }
+                SplitTarget::Account { account_id, .. } => {
+                    partner_transactions.push(Transaction {
+                        account_id: account_id.clone(),
+                        amount: partner,
+                        // payees and categories differ between budgets
+                        payee_id: None,
+                        category_id: None,
+                        approved: false,
+                        subtransactions: vec![],
+                        ..transaction.clone()
+                    });
+                    own_transactions.push(Transaction {
+                        amount: own,
+                        ..transaction
+                    });
+                }
+            }
+        }
+        (own_transactions, partner_transactions)
+    }
+}
@@ -0,0 +1,280 @@
+// Persistent sync state
+//
+// Kept as JSON in the state directory (see `state_dir`) and shared between
+// all binaries, keyed by YNAB account id: the date of the newest synced
+// transaction, when the last sync finished and the import ids synced in the
+// last year. The import ids let a sync tell transactions it already created
+// apart from new ones even after they left the window of YNAB transactions
+// it fetches (or were deleted in YNAB). Sources can keep a cursor (eg. of
+// incremental APIs) here too, the server knowledge of YNAB lists is kept with
+// their snapshots (see `delta`).
+//
+// Syncs running at the same time (eg. profiles of `ynab-sync sync --jobs`)
+// each save the entries they changed: under a lock the file is read again,
+// the changes are merged into it and it's replaced as a whole.
+
+use crate::{encryption, state_dir, ErrorKind, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{read_to_string, rename, write, OpenOptions};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+pub(crate) const STATE_FILE: &str = "state.json";
+
+// How long import ids of synced transactions are remembered.
+const SYNCED_IMPORT_IDS_DAYS: i64 = 366;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct StateData {
+    #[serde(default)]
+    last_synced: HashMap<String, NaiveDate>,
+    #[serde(default)]
+    last_sync_time: HashMap<String, DateTime<Utc>>,
+    /// Import ids of synced transactions and their dates.
+    #[serde(default)]
+    synced_import_ids: HashMap<String, HashMap<String, NaiveDate>>,
+    #[serde(default)]
+    cursors: HashMap<String, String>,
+}
+
+impl StateData {
+    fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(StateData::default());
+        }
+        let content = read_to_string(path)
+            .with_context(|_| ErrorKind::StateCanNotRead(path.to_string_lossy().into()))?;
+        let content = encryption::decrypt(&content)
+            .with_context(|_| ErrorKind::StateCanNotRead(path.to_string_lossy().into()))?;
+        Ok(serde_json::from_str(&content)
+            .with_context(|_| ErrorKind::StateCanNotRead(path.to_string_lossy().into()))?)
+    }
+
+    /// Apply the changes from `loaded` to `changed` (entries set, changed or
+    /// removed since) to this data.
+    fn merge(&mut self, loaded: &StateData, changed: &StateData) {
+        merge_changes(
+            &mut self.last_synced,
+            &loaded.last_synced,
+            &changed.last_synced,
+        );
+        merge_changes(
+            &mut self.last_sync_time,
+            &loaded.last_sync_time,
+            &changed.last_sync_time,
+        );
+        merge_changes(
+            &mut self.synced_import_ids,
+            &loaded.synced_import_ids,
+            &changed.synced_import_ids,
+        );
+        merge_changes(&mut self.cursors, &loaded.cursors, &changed.cursors);
+    }
+}
+
+fn merge_changes<K, V>(current: &mut HashMap<K, V>, loaded: &HashMap<K, V>, changed: &HashMap<K, V>)
+where
+    K: Clone + Eq + Hash,
+    V: Clone + PartialEq,
+{
+    for (key, value) in changed {
+        if loaded.get(key) != Some(value) {
+            current.insert(key.clone(), value.clone());
+        }
+    }
+    for key in loaded.keys().filter(|x| !changed.contains_key(x)) {
+        current.remove(key);
+    }
+}
+
+#[derive(Debug)]
+pub struct State {
+    path: PathBuf,
+    /// As read by `load`, to tell which entries were changed since.
+    loaded: StateData,
+    data: StateData,
+}
+
+impl State {
+    /// Load the state from the state directory, an empty state is returned
+    /// when nothing was stored yet.
+    pub fn load() -> Result<Self> {
+        Self::load_from(state_dir()?.join(STATE_FILE))
+    }
+
+    fn load_from(path: PathBuf) -> Result<Self> {
+        let data = StateData::read(&path)?;
+        Ok(State {
+            path,
+            loaded: data.clone(),
+            data,
+        })
+    }
+
+    /// Save the entries changed since the state was loaded, keeping the
+    /// ones other processes saved in the meantime.
+    pub fn save(&mut self) -> Result<()> {
+        let error = || ErrorKind::StateCanNotWrite(self.path.to_string_lossy().into());
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path.with_extension("lock"))
+            .with_context(|_| error())?;
+        lock.lock().with_context(|_| error())?;
+
+        let mut data = StateData::read(&self.path)?;
+        data.merge(&self.loaded, &self.data);
+        let content = serde_json::to_string_pretty(&data).with_context(|_| error())?;
+        let content = encryption::encrypt(&content).with_context(|_| error())?;
+        // written to a temporary file first, so other processes never read
+        // half of it
+        let tmp = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        write(&tmp, content).with_context(|_| error())?;
+        rename(&tmp, &self.path).with_context(|_| error())?;
+        lock.unlock().with_context(|_| error())?;
+
+        self.loaded = data.clone();
+        self.data = data;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accounts and dates of their newest synced transaction.
+    pub fn all_last_synced(&self) -> &HashMap<String, NaiveDate> {
+        &self.data.last_synced
+    }
+
+    /// Date of the newest transaction successfully synced to the account.
+    pub fn last_synced(&self, account_id: &str) -> Option<NaiveDate> {
+        self.data.last_synced.get(account_id).cloned()
+    }
+
+    pub fn set_last_synced(&mut self, account_id: &str, date: NaiveDate) {
+        self.data.last_synced.insert(account_id.to_string(), date);
+    }
+
+    /// When the last sync to the account finished.
+    pub fn last_sync_time(&self, account_id: &str) -> Option<DateTime<Utc>> {
+        self.data.last_sync_time.get(account_id).cloned()
+    }
+
+    pub fn set_last_sync_time(&mut self, account_id: &str, time: DateTime<Utc>) {
+        self.data
+            .last_sync_time
+            .insert(account_id.to_string(), time);
+    }
+
+    /// Whether a transaction with `import_id` was synced to the account.
+    pub fn is_synced(&self, account_id: &str, import_id: &str) -> bool {
+        self.data
+            .synced_import_ids
+            .get(account_id)
+            .is_some_and(|x| x.contains_key(import_id))
+    }
+
+    /// Number of remembered import ids of the account.
+    pub fn synced_count(&self, account_id: &str) -> usize {
+        self.data
+            .synced_import_ids
+            .get(account_id)
+            .map_or(0, |x| x.len())
+    }
+
+    /// Remember import ids (and dates) of transactions synced to the
+    /// account, ones older than a year are forgotten.
+    pub fn add_synced<'a>(
+        &mut self,
+        account_id: &str,
+        synced: impl IntoIterator<Item = (&'a str, NaiveDate)>,
+    ) {
+        let import_ids = self
+            .data
+            .synced_import_ids
+            .entry(account_id.to_string())
+            .or_default();
+        for (import_id, date) in synced {
+            import_ids.insert(import_id.to_string(), date);
+        }
+        let oldest = Utc::today().naive_local() - Duration::days(SYNCED_IMPORT_IDS_DAYS);
+        import_ids.retain(|_, date| *date >= oldest);
+    }
+
+    /// Cursor of a source (eg. `plaid-<item id>`), where its next sync
+    /// continues.
+    pub fn cursor(&self, source: &str) -> Option<&str> {
+        self.data.cursors.get(source).map(|x| x.as_str())
+    }
+
+    pub fn set_cursor(&mut self, source: &str, cursor: &str) {
+        self.data
+            .cursors
+            .insert(source.to_string(), cursor.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::remove_file;
+    use std::thread;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn profiles_syncing_at_the_same_time_keep_each_others_changes() {
+        let path = temp_dir().join(format!("ynab-sync-state-{}.json", std::process::id()));
+        let _ = remove_file(&path);
+        let mut state = State::load_from(path.clone()).unwrap();
+        state.set_last_synced("shared", date("2020-01-01"));
+        state.set_cursor("plaid-item", "1");
+        state.save().unwrap();
+
+        // both profiles load the state before either of them saves
+        let profiles: Vec<State> = (0..2)
+            .map(|_| State::load_from(path.clone()).unwrap())
+            .collect();
+        let handles: Vec<_> = profiles
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut state)| {
+                thread::spawn(move || {
+                    let account_id = format!("account-{}", i);
+                    state.set_last_synced(&account_id, date("2020-02-01"));
+                    state.add_synced(
+                        &account_id,
+                        vec![("YNAB:-1000:2020-02-01:1", Utc::today().naive_local())],
+                    );
+                    if i == 1 {
+                        state.set_cursor("plaid-item", "2");
+                    }
+                    state.save().unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let state = State::load_from(path.clone()).unwrap();
+        remove_file(&path).unwrap();
+        let _ = remove_file(path.with_extension("lock"));
+        assert_eq!(state.last_synced("shared"), Some(date("2020-01-01")));
+        assert_eq!(state.last_synced("account-0"), Some(date("2020-02-01")));
+        assert_eq!(state.last_synced("account-1"), Some(date("2020-02-01")));
+        assert!(state.is_synced("account-0", "YNAB:-1000:2020-02-01:1"));
+        assert!(state.is_synced("account-1", "YNAB:-1000:2020-02-01:1"));
+        assert_eq!(state.cursor("plaid-item"), Some("2"));
+    }
+}
@@ -0,0 +1,474 @@
+// Syncing a bank source
+//
+// The conversion of `SourceTransaction`s to YNAB transactions and the sync
+// itself, shared by the binaries of all sources. Categories come from the
+// source's own categorization (see `BankSource::category`), the payee
+// history, --classify and --default-category, the other YNAB options (payee
+// mapping, income, transfers, reimbursements, splits, mirrors, ...) work the
+// same for every bank.
+
+use crate::balance::BalanceGuard;
+use crate::cache::Cache;
+use crate::delta::Deltas;
+use crate::explain::Trace;
+use crate::http;
+use crate::memo::MemoTemplate;
+use crate::month_report;
+use crate::notify::{self, Notification};
+use crate::source::{BankSource, Exclusions, SourceCategory, SourceTransaction};
+use crate::state::State;
+use crate::totals::{finished_body, Totals};
+use crate::ynab::{BudgetCategories, Cli, Transaction, TransactionCleared, YNAB};
+use crate::{ErrorKind, Result};
+use chrono::{Duration, NaiveDate, Utc};
+
+/// Transactions to sync.
+#[derive(Clone, Copy, Debug)]
+pub enum Window {
+    /// Of the last days.
+    Days(i64),
+    /// From the date on, without one from the date of the last synced
+    /// transaction minus `overlap` days.
+    Since {
+        date: Option<NaiveDate>,
+        overlap: i64,
+    },
+}
+
+impl Window {
+    /// Days to sync into `account_id`, including today.
+    fn days(&self, state: &State, account_id: &str) -> Result<i64> {
+        let since = match *self {
+            Window::Days(days) => return Ok(days),
+            Window::Since {
+                date: Some(date), ..
+            } => date,
+            Window::Since {
+                date: None,
+                overlap,
+            } => match state.last_synced(account_id) {
+                Some(last_synced) => last_synced - Duration::days(overlap),
+                None => Err(ErrorKind::SyncFromUnknown(account_id.to_string()))?,
+            },
+        };
+        Ok(Utc::now()
+            .naive_utc()
+            .date()
+            .signed_duration_since(since)
+            .num_days()
+            + 1)
+    }
+}
+
+/// Sync the transactions of `window` of `source` into the account of
+/// `cli`. Prints steps 2 to 6 of 6, the first one (reading the source) is
+/// up to the binary. Returns whether all transactions were synced.
+pub fn sync_source(
+    source: &mut dyn BankSource,
+    window: Window,
+    cli: Cli,
+    memo_template: &MemoTemplate,
+    balance_guard: &BalanceGuard,
+) -> Result<bool> {
+    let ynab = YNAB::new(cli.token.clone())
+        .with_cache(Cache::new(Duration::hours(cli.cache_ttl), cli.refresh)?)
+        .with_deltas(Deltas::new(cli.refresh)?)
+        .with_assume_yes(cli.assume_yes())
+        .with_since_date(cli.since_date)
+        .with_transaction_type(cli.transaction_type.clone())
+        .with_batch_size(cli.batch_size)
+        .with_update_duplicates(cli.update_duplicates)
+        .with_dry_run(cli.dry_run);
+    sync_source_with(&ynab, source, window, cli, memo_template, balance_guard)
+}
+
+/// `sync_source` with a YNAB client set up by the caller, eg. one talking to
+/// the mock API of `test_util`. The options of `cli` configuring the client
+/// (--yes, --ynab-batch-size, ...) are up to the caller as well.
+pub fn sync_source_with(
+    ynab: &YNAB,
+    source: &mut dyn BankSource,
+    window: Window,
+    cli: Cli,
+    memo_template: &MemoTemplate,
+    balance_guard: &BalanceGuard,
+) -> Result<bool> {
+    // validate ynab cli options
+    let cli = ynab.validate_cli(cli, 1, 6)?;
+    let mut state = State::load()?;
+    let days_to_sync = window.days(&state, &cli.account_id)?;
+
+    println!("[4/6] Fetching YNAB categories");
+    println!(
+        "[5/6] Fetching YNAB transactions for the last {} days",
+        days_to_sync
+    );
+    let (ynab_category_groups, ynab_transactions) = http::join(
+        || ynab.get_category_groups(cli.budget_id.clone()),
+        || {
+            ynab.get_transactions_and_manual(
+                cli.budget_id.clone(),
+                cli.account_id.clone(),
+                days_to_sync,
+            )
+        },
+    );
+    let budget_categories = BudgetCategories::new(ynab_category_groups?);
+    let ynab_categories = &budget_categories.categories;
+    let (ynab_transactions, manual_transactions) = ynab_transactions?;
+    source.prepare(&budget_categories)?;
+    let default_category = ynab.default_category(&cli, ynab_categories)?;
+    let payee_mapping = ynab.payee_mapping(&cli)?;
+    let payee_history = ynab.payee_history(&cli)?;
+    let classifier = ynab.classifier(&cli)?;
+    let splitter = ynab.splitter(&cli, ynab_categories)?;
+    let mirrors = ynab.mirrors(&cli)?;
+    let cash_account = ynab.cash_account(&cli)?;
+    let own_accounts = ynab.own_accounts(&cli)?;
+    let exclusions = Exclusions::new(&cli.exclude_patterns, cli.exclude_zero_amounts);
+    let reimbursements = ynab.reimbursements(&cli);
+    let manual_matcher = ynab.manual_matcher(&cli);
+    let income = ynab.income(&cli, ynab_categories)?;
+    let fee_category = ynab.fee_category(&cli, ynab_categories)?;
+
+    let convert_transaction = |transaction: &SourceTransaction| {
+        let mut trace = Trace::new(cli.explain);
+        let source_payee = transaction.payee.as_ref();
+        let payee = source_payee.and_then(|x| payee_mapping.payee(x));
+        if let (Some(source_payee), Some(payee)) = (source_payee, &payee) {
+            trace.add(|| {
+                format!(
+                    "payee mapping: {:?} => {}",
+                    source_payee,
+                    payee
+                        .name
+                        .as_ref()
+                        .or(payee.id.as_ref())
+                        .cloned()
+                        .unwrap_or_default()
+                )
+            });
+        }
+
+        let source_category = source.category(transaction, &budget_categories, &mut trace);
+
+        // category last used for the same payee, which needs to be reviewed
+        let history_category = payee_history.category(
+            payee.as_ref().and_then(|x| x.id.as_deref()),
+            payee
+                .as_ref()
+                .and_then(|x| x.name.as_deref())
+                .or(source_payee.map(|x| x.as_str())),
+        );
+
+        // category most likely for the payee, which needs to be reviewed
+        // as well
+        let classified_category = payee
+            .as_ref()
+            .and_then(|x| x.name.as_deref())
+            .or(source_payee.map(|x| x.as_str()))
+            .and_then(|x| classifier.classify(x));
+
+        let (category, approved) = match (&source_category, history_category, classified_category) {
+            (Some(SourceCategory::Category(x)), _, _) => (Some(x.id.clone()), true),
+            // picked by the user after the conversion
+            (Some(SourceCategory::CategoryGroup(x)), _, _) => {
+                trace.add(|| format!("category is picked from group {:?}", x.name));
+                (None, false)
+            }
+            (None, Some(x), _) => {
+                trace.add(|| "using the payee's last category".into());
+                (Some(x), false)
+            }
+            (None, None, Some((x, probability))) => {
+                trace.add(|| {
+                    format!(
+                        "no payee history, --classify guesses the category ({:.0}% likely)",
+                        probability * 100.0
+                    )
+                });
+                (Some(x), false)
+            }
+            // fallback to --default-category
+            (None, None, None) => {
+                trace.add(|| match &default_category {
+                    Some(x) => {
+                        format!("no payee history, using --default-category {:?}", x.name)
+                    }
+                    None => "no payee history".into(),
+                });
+                (
+                    default_category.as_ref().map(|x| x.id.clone()),
+                    default_category.is_some(),
+                )
+            }
+        };
+
+        let ynab_transaction = Transaction {
+            id: None,
+            account_id: cli.account_id.clone(),
+            date: transaction.date.format("%Y-%m-%d").to_string(),
+            amount: transaction.amount,
+            payee_id: payee.as_ref().and_then(|x| x.id.clone()),
+            // without a mapping YNAB matches the bank's payee itself
+            payee_name: payee
+                .map(|x| x.name)
+                .unwrap_or_else(|| source_payee.cloned()),
+            category_id: category,
+            memo: memo_template.render(&transaction.memo_values),
+            cleared: if transaction.pending {
+                TransactionCleared::Uncleared
+            } else {
+                TransactionCleared::Cleared
+            },
+            approved,
+            flag_color: None,
+            import_id: Some(transaction.id.clone()),
+            subtransactions: vec![],
+        };
+        let ynab_transaction = source.amend(transaction, ynab_transaction);
+
+        let ynab_transaction = match &income {
+            Some(income) => {
+                if income.is_income(&ynab_transaction) {
+                    trace.add(|| "income, using the inflow category".into());
+                }
+                income.categorize(ynab_transaction)
+            }
+            None => ynab_transaction,
+        };
+
+        let ynab_transaction = match (
+            &cash_account,
+            own_accounts.transfer_account(&transaction.memo_values),
+        ) {
+            (Some(cash_account), _) if transaction.is_cash_withdrawal => {
+                trace.add(|| format!("cash withdrawal, transfer to {:?}", cash_account.name));
+                ynab_transaction.into_transfer(cash_account)
+            }
+            (_, Some(account)) => {
+                trace.add(|| format!("own account, transfer to {:?}", account.name));
+                ynab_transaction.into_transfer(account)
+            }
+            _ => ynab_transaction,
+        };
+
+        if let Some(fee) = transaction.fee {
+            trace.add(|| format!("fee of {:.2} EUR split out", fee as f32 / 1000.0));
+        }
+        let ynab_transaction = ynab_transaction
+            .split_fee(transaction.fee, fee_category.as_ref().map(|x| x.id.clone()));
+
+        trace.print(&ynab_transaction, ynab_categories);
+        (ynab_transaction, source_category)
+    };
+
+    println!(
+        "[6/6] Converting {} transactions to YNAB transactions",
+        source.name()
+    );
+    let mut source_transactions = source.transactions(days_to_sync)?;
+    cli.import_id_scheme.apply(&mut source_transactions);
+    let source_transactions: Vec<SourceTransaction> = source_transactions
+        .into_iter()
+        // transactions between own accounts configured to be skipped,
+        // excluded ones and ones the source's categorization skips
+        .filter(|x| {
+            let reason = if own_accounts.is_skipped(&x.memo_values) {
+                Some("own account".to_string())
+            } else {
+                source
+                    .skip_reason(x)
+                    .or_else(|| exclusions.reason(x.amount, &x.memo_values))
+            };
+            if let (Some(reason), true) = (&reason, cli.explain) {
+                println!(
+                    " - | {} | {} | {:>+10.2} EUR |\n     => skipped, {}",
+                    x.date,
+                    x.payee.clone().unwrap_or_default(),
+                    (x.amount as f32 / 1000.0),
+                    reason
+                );
+            }
+            reason.is_none()
+        })
+        .collect();
+    let converted: Vec<(Transaction, Option<SourceCategory>)> = source_transactions
+        .iter()
+        .map(convert_transaction)
+        .collect();
+
+    // transactions synced before which are no longer among the fetched YNAB
+    // transactions (older than the window or deleted in YNAB) aren't created
+    // again
+    let mut transactions = vec![];
+    let mut synced_before = vec![];
+    for ((transaction, source_category), source_transaction) in
+        converted.into_iter().zip(&source_transactions)
+    {
+        let import_id = transaction.import_id.as_deref().unwrap_or_default();
+        if ynab_transactions.contains_key(import_id) || !state.is_synced(&cli.account_id, import_id)
+        {
+            transactions.push((transaction, source_category, source_transaction));
+        } else {
+            synced_before.push(transaction);
+        }
+    }
+    if !synced_before.is_empty() {
+        println!(
+            " => Skipping {} transactions synced before",
+            synced_before.len()
+        );
+    }
+
+    // categories of transactions the source's categorization left to the
+    // user, the ones already in YNAB keep the category they got there
+    let is_transfer = |x: &SourceTransaction| {
+        (cash_account.is_some() && x.is_cash_withdrawal)
+            || own_accounts.transfer_account(&x.memo_values).is_some()
+    };
+    let mut categorized = vec![];
+    for (mut transaction, source_category, source_transaction) in transactions {
+        let existing_transaction = transaction
+            .import_id
+            .as_ref()
+            .and_then(|x| ynab_transactions.get(x));
+        match (&source_category, existing_transaction) {
+            _ if is_transfer(source_transaction) => (),
+            (Some(SourceCategory::CategoryGroup(_)), Some(existing_transaction))
+                if transaction.category_id.is_none() =>
+            {
+                transaction.category_id = existing_transaction.category_id.clone();
+                transaction.approved = existing_transaction.approved;
+            }
+            (Some(SourceCategory::CategoryGroup(group)), None)
+                if transaction.category_id.is_none() =>
+            {
+                if let Some(category_id) = ynab.pick_category(&transaction, group) {
+                    transaction.category_id = Some(category_id);
+                    transaction.approved = true;
+                }
+            }
+            (None, None) => {
+                let category = source.categorize_uncategorized(
+                    ynab,
+                    &transaction,
+                    source_transaction,
+                    &budget_categories,
+                )?;
+                if let Some(category) = category {
+                    transaction.category_id = Some(category.id);
+                    transaction.approved = true;
+                }
+            }
+            _ => (),
+        }
+        categorized.push(transaction);
+    }
+    let transactions = categorized;
+
+    let transactions = match &reimbursements {
+        Some(reimbursements) => reimbursements.link(transactions),
+        None => transactions,
+    };
+
+    let (transactions, partner_transactions) = match &splitter {
+        Some(splitter) => splitter.apply(transactions),
+        None => (transactions, vec![]),
+    };
+
+    let mirror_transactions = transactions.clone();
+    let mut transactions = transactions;
+    let mut ynab_transactions = ynab_transactions;
+    if let Some(manual_matcher) = &manual_matcher {
+        let linked = manual_matcher.link(
+            &mut transactions,
+            &mut ynab_transactions,
+            &manual_transactions,
+        );
+        if linked > 0 {
+            println!(" => Updating {} transactions entered by hand", linked);
+        }
+    }
+    if balance_guard.is_enabled() {
+        match source.balance()? {
+            Some(bank_balance) => {
+                let expected = ynab.expected_cleared_balance(
+                    &cli.budget_id,
+                    &cli.account_id,
+                    &transactions,
+                    &ynab_transactions,
+                )?;
+                balance_guard.check(expected, i64::from(bank_balance))?;
+            }
+            None => println!(
+                " => {} doesn't report the balance, not checking it",
+                source.name()
+            ),
+        }
+    }
+    let month_before = ynab.month_before_sync(&cli)?;
+    let mut report = ynab.sync(
+        transactions,
+        ynab_transactions,
+        cli.budget_id.clone(),
+        cli.force_update,
+        6,
+        6,
+    )?;
+    report.skipped += synced_before.len();
+    let mut synced = report.synced;
+    if let Some(splitter) = &splitter {
+        synced &= ynab.sync_split(
+            splitter,
+            partner_transactions,
+            days_to_sync,
+            cli.force_update,
+            6,
+            6,
+        )?;
+    }
+    synced &= ynab.sync_mirrors(
+        &mirrors,
+        &mirror_transactions,
+        ynab_categories,
+        days_to_sync,
+        cli.force_update,
+        6,
+        6,
+    )?;
+
+    // recorded sessions don't reflect the real account, keep the state as is
+    if synced && !http::is_replaying() {
+        let synced_transactions: Vec<(&str, NaiveDate)> = mirror_transactions
+            .iter()
+            .filter_map(|x| {
+                let date = NaiveDate::parse_from_str(&x.date, "%Y-%m-%d").ok()?;
+                Some((x.import_id.as_deref()?, date))
+            })
+            .collect();
+        if let Some(last_transaction) = synced_transactions.iter().map(|x| x.1).max() {
+            state.set_last_synced(&cli.account_id, last_transaction);
+        }
+        state.add_synced(&cli.account_id, synced_transactions);
+        state.set_last_sync_time(&cli.account_id, Utc::now());
+        state.save()?;
+    }
+
+    report.print();
+    let totals = Totals::new(&mirror_transactions, ynab_categories);
+    if cli.totals > 0 && !totals.is_empty() {
+        totals.print(cli.totals);
+    }
+    if let Some(before) = &month_before {
+        let after = ynab.get_month(&cli.budget_id, "current")?;
+        month_report::print(before, &after, &mirror_transactions);
+    }
+
+    notify::send(&Notification::new(
+        &format!("{} sync finished", source.name()),
+        &finished_body(synced, &totals, cli.totals),
+    ));
+
+    Ok(synced)
+}
@@ -0,0 +1,466 @@
+// Test utilities (enabled with the `test-util` feature)
+//
+// Canned YNAB and N26 mock servers plus fixture builders, so that code built
+// on top of this crate can be tested without talking to the real APIs:
+//
+//     let account = test_util::account("Checking");
+//     let api = YNABMock::new(test_util::budget("My Budget"))
+//         .account(account.clone())
+//         .start();
+//     let ynab = api.ynab();
+//     assert_eq!(ynab.get_accounts(api.budget_id())?.len(), 1);
+//
+// Whole syncs go through `sync::sync_source_with` with such a client (see
+// `tests/sync_source.rs`), the `async fn` getters of the `async` feature
+// are tested the same way in `tests/async_api.rs`.
+//
+// The API clients are blocking, so every mock server runs on its own tokio
+// runtime in the background and all methods here are synchronous.
+
+use crate::n26::N26;
+use crate::ynab::{
+    Account, AccountType, Budget, Category, CategoryGroup, CurrencyFormat, DateFormat, Payee,
+    Transaction, TransactionCleared, YNAB,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::runtime::Runtime;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+pub const YNAB_TOKEN: &str = "ynab-test-token";
+pub const N26_ACCESS_TOKEN: &str = "n26-test-access-token";
+pub const N26_REFRESH_TOKEN: &str = "n26-test-refresh-token";
+
+/// A running mock server. Stops when dropped.
+pub struct MockApi {
+    server: MockServer,
+    budget_id: Option<String>,
+    // dropped last, the server needs the runtime to shut down
+    runtime: Runtime,
+}
+
+impl MockApi {
+    /// Start an empty mock server, use `mount` to add endpoints.
+    pub fn start() -> Self {
+        let runtime = Runtime::new().expect("failed to start tokio runtime");
+        let server = runtime.block_on(MockServer::start());
+        MockApi {
+            server,
+            budget_id: None,
+            runtime,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Id of the budget served by a mock started with `YNABMock`.
+    pub fn budget_id(&self) -> String {
+        self.budget_id.clone().unwrap_or_default()
+    }
+
+    /// Add (or override, later mounts win) an endpoint.
+    pub fn mount(&self, mock: Mock) {
+        self.runtime.block_on(self.server.register(mock));
+    }
+
+    /// Requests received so far, eg. to inspect transactions sent to YNAB.
+    pub fn received_requests(&self) -> Vec<wiremock::Request> {
+        self.runtime
+            .block_on(self.server.received_requests())
+            .unwrap_or_default()
+    }
+
+    /// YNAB client talking to this server.
+    pub fn ynab(&self) -> YNAB {
+        YNAB {
+            api_url: self.url(),
+            ..YNAB::new(YNAB_TOKEN.to_string())
+        }
+    }
+
+    /// Already authenticated N26 client talking to this server.
+    pub fn n26(&self) -> N26 {
+        N26 {
+            api_url: self.url(),
+            expiration_time: Utc::now().timestamp() + 3600,
+            access_token: N26_ACCESS_TOKEN.to_string(),
+            refresh_token: N26_REFRESH_TOKEN.to_string(),
+            username: String::new(),
+            client: None,
+        }
+    }
+}
+
+/// Canned YNAB API serving a single budget.
+///
+/// Serves `GET /budgets`, `GET /budgets/{id}/accounts`,
+/// `GET /budgets/{id}/accounts/{account_id}`, `GET /budgets/{id}/categories`,
+/// `GET /budgets/{id}/payees`,
+/// `GET /budgets/{id}/accounts/{account_id}/transactions` and accepts
+/// `POST`/`PATCH /budgets/{id}/transactions`.
+#[derive(Clone, Debug)]
+pub struct YNABMock {
+    budget: Budget,
+    accounts: Vec<Account>,
+    category_groups: Vec<CategoryGroup>,
+    payees: Vec<Payee>,
+    transactions: Vec<Transaction>,
+}
+
+impl YNABMock {
+    pub fn new(budget: Budget) -> Self {
+        YNABMock {
+            budget,
+            accounts: vec![],
+            category_groups: vec![],
+            payees: vec![],
+            transactions: vec![],
+        }
+    }
+
+    pub fn account(mut self, account: Account) -> Self {
+        self.accounts.push(account);
+        self
+    }
+
+    pub fn category_group(mut self, category_group: CategoryGroup) -> Self {
+        self.category_groups.push(category_group);
+        self
+    }
+
+    pub fn payee(mut self, payee: Payee) -> Self {
+        self.payees.push(payee);
+        self
+    }
+
+    pub fn transaction(mut self, transaction: Transaction) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    pub fn start(self) -> MockApi {
+        let mut api = MockApi::start();
+        let budget_path = format!("/budgets/{}", self.budget.id);
+
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path("/budgets"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "budgets": [&self.budget], "default_budget": null }
+                }))),
+        );
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path(format!("{}/accounts", budget_path)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "accounts": &self.accounts, "server_knowledge": 0 }
+                }))),
+        );
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path(format!("{}/categories", budget_path)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "category_groups": &self.category_groups, "server_knowledge": 0 }
+                }))),
+        );
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path(format!("{}/payees", budget_path)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "payees": &self.payees }
+                }))),
+        );
+        for account in &self.accounts {
+            api.mount(
+                Mock::given(method("GET"))
+                    .and(path(format!("{}/accounts/{}", budget_path, account.id)))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                        "data": { "account": account }
+                    }))),
+            );
+            let transactions: Vec<&Transaction> = self
+                .transactions
+                .iter()
+                .filter(|x| x.account_id == account.id)
+                .collect();
+            api.mount(
+                Mock::given(method("GET"))
+                    .and(path(format!(
+                        "{}/accounts/{}/transactions",
+                        budget_path, account.id
+                    )))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                        "data": { "transactions": transactions, "server_knowledge": 0 }
+                    }))),
+            );
+        }
+        api.mount(
+            Mock::given(method("POST"))
+                .and(path(format!("{}/transactions", budget_path)))
+                .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "data": {} }))),
+        );
+        api.mount(
+            Mock::given(method("PATCH"))
+                .and(path(format!("{}/transactions", budget_path)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": {} }))),
+        );
+
+        api.budget_id = Some(self.budget.id);
+        api
+    }
+}
+
+/// Canned N26 API.
+///
+/// Serves `GET /api/smrt/categories`, `GET /api/smrt/transactions` (paged
+/// with `limit` and `lastId`, `from` and `to` are ignored) and `GET
+/// /api/accounts` (balances summed up from the transactions) and answers
+/// token refreshes on `POST /oauth/token`.
+#[derive(Clone, Debug, Default)]
+pub struct N26Mock {
+    categories: Vec<(String, String)>,
+    transactions: Vec<Value>,
+}
+
+impl N26Mock {
+    pub fn new() -> Self {
+        N26Mock::default()
+    }
+
+    pub fn category(mut self, id: &str, name: &str) -> Self {
+        self.categories.push((id.to_string(), name.to_string()));
+        self
+    }
+
+    /// Add a transaction as returned by the API, see `n26_transaction`.
+    pub fn transaction(mut self, transaction: Value) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    pub fn start(self) -> MockApi {
+        let api = MockApi::start();
+        let categories: Vec<Value> = self
+            .categories
+            .iter()
+            .map(|(id, name)| json!({ "id": id, "name": name }))
+            .collect();
+
+        api.mount(
+            Mock::given(method("POST"))
+                .and(path("/oauth/token"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "access_token": N26_ACCESS_TOKEN,
+                    "token_type": "bearer",
+                    "refresh_token": N26_REFRESH_TOKEN,
+                    "expires_in": 3600,
+                }))),
+        );
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path("/api/smrt/categories"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(categories)),
+        );
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path("/api/smrt/transactions"))
+                .respond_with(N26TransactionPages(self.transactions.clone())),
+        );
+        let amount = |x: &Value| x["amount"].as_f64().unwrap_or_default();
+        let pending = |x: &Value| x["pending"].as_bool().unwrap_or_default();
+        let available_balance: f64 = self.transactions.iter().map(amount).sum();
+        let bank_balance: f64 = self
+            .transactions
+            .iter()
+            .filter(|x| !pending(x))
+            .map(amount)
+            .sum();
+        api.mount(
+            Mock::given(method("GET"))
+                .and(path("/api/accounts"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "availableBalance": available_balance,
+                    "usableBalance": available_balance,
+                    "bankBalance": bank_balance,
+                }))),
+        );
+
+        api
+    }
+}
+
+/// Page of N26 transactions: `limit` of them after the one with `lastId`.
+struct N26TransactionPages(Vec<Value>);
+
+impl Respond for N26TransactionPages {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let query: HashMap<_, _> = request.url.query_pairs().collect();
+        let limit = query
+            .get("limit")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(self.0.len());
+        let start = match query.get("lastId") {
+            Some(last_id) => self
+                .0
+                .iter()
+                .position(|x| x["id"] == last_id.as_ref())
+                .map(|x| x + 1)
+                .unwrap_or(self.0.len()),
+            None => 0,
+        };
+        let page: Vec<&Value> = self.0.iter().skip(start).take(limit).collect();
+        ResponseTemplate::new(200).set_body_json(page)
+    }
+}
+
+fn id(prefix: &str, name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|x| if x.is_alphanumeric() { x } else { '-' })
+        .collect();
+    format!("{}-{}", prefix, slug)
+}
+
+/// A EUR budget with id derived from its name (`budget-<name>`).
+pub fn budget(name: &str) -> Budget {
+    Budget {
+        id: id("budget", name),
+        name: name.to_string(),
+        last_modified_on: "2020-01-01T00:00:00+00:00".to_string(),
+        first_month: "2020-01-01".to_string(),
+        last_month: "2020-01-01".to_string(),
+        date_format: DateFormat {
+            format: "DD.MM.YYYY".to_string(),
+        },
+        currency_format: CurrencyFormat {
+            iso_code: "EUR".to_string(),
+            example_format: "123.456,78".to_string(),
+            decimal_digits: 2,
+            decimal_separator: ",".to_string(),
+            symbol_first: false,
+            group_separator: ".".to_string(),
+            currency_symbol: "€".to_string(),
+            display_symbol: true,
+        },
+    }
+}
+
+/// An open, on budget checking account with id `account-<name>`.
+pub fn account(name: &str) -> Account {
+    Account {
+        id: id("account", name),
+        name: name.to_string(),
+        type_: AccountType::Checking,
+        on_budget: true,
+        closed: false,
+        note: None,
+        balance: 0,
+        cleared_balance: 0,
+        uncleared_balance: 0,
+        transfer_payee_id: id("payee", name),
+        deleted: false,
+    }
+}
+
+/// A payee with id `payee-<name>`.
+pub fn payee(name: &str) -> Payee {
+    Payee {
+        id: id("payee", name),
+        name: name.to_string(),
+        transfer_account_id: None,
+        deleted: false,
+    }
+}
+
+/// A category with id `category-<name>` in the group with `group_id`.
+pub fn category(group_id: &str, name: &str) -> Category {
+    Category {
+        id: id("category", name),
+        category_group_id: group_id.to_string(),
+        name: name.to_string(),
+        hidden: false,
+        original_category_group_id: None,
+        note: None,
+        budgeted: 0,
+        activity: 0,
+        balance: 0,
+        goal_creation_month: None,
+        goal_target: None,
+        goal_target_month: None,
+        goal_percentage_complete: None,
+        deleted: false,
+    }
+}
+
+/// A category group with id `group-<name>` containing categories with the
+/// given names.
+pub fn category_group(name: &str, categories: &[&str]) -> CategoryGroup {
+    let group_id = id("group", name);
+    CategoryGroup {
+        categories: categories.iter().map(|x| category(&group_id, x)).collect(),
+        id: group_id,
+        name: name.to_string(),
+        hidden: false,
+        deleted: false,
+    }
+}
+
+/// An uncleared, unapproved transaction. `amount` is in milliunits.
+pub fn transaction(account: &Account, date: &str, amount: i32) -> Transaction {
+    Transaction {
+        id: None,
+        account_id: account.id.clone(),
+        date: date.to_string(),
+        amount,
+        payee_id: None,
+        payee_name: None,
+        category_id: None,
+        memo: None,
+        cleared: TransactionCleared::Uncleared,
+        approved: false,
+        flag_color: None,
+        import_id: None,
+        subtransactions: vec![],
+    }
+}
+
+/// An N26 card transaction as returned by `/api/smrt/transactions`. `amount`
+/// is in EUR (eg. `-12.5`), use `serde_json` to adjust other fields.
+pub fn n26_transaction(id: &str, visible: DateTime<Utc>, amount: f64, merchant: &str) -> Value {
+    let ts = visible.timestamp_millis();
+    json!({
+        "id": id,
+        "userId": "user-id",
+        "type": "PT",
+        "amount": amount,
+        "currencyCode": "EUR",
+        "originalAmount": amount,
+        "originalCurrency": "EUR",
+        "exchangeRate": 1.0,
+        "merchantCity": null,
+        "visibleTS": ts,
+        "mcc": null,
+        "mccGroup": null,
+        "merchantName": merchant,
+        "partnerAccountIsSepa": null,
+        "partnerName": null,
+        "accountId": "account-id",
+        "partnerIban": null,
+        "category": "micro-v2-miscellaneous",
+        "cardId": null,
+        "referenceText": null,
+        "userCertified": ts,
+        "pending": false,
+        "transactionNature": "NORMAL",
+        "createdTS": ts,
+        "merchantCountry": null,
+        "smartLinkId": id,
+        "linkId": id,
+        "confirmed": ts,
+    })
+}
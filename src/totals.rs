@@ -0,0 +1,109 @@
+use crate::ynab::{Category, Transaction};
+use std::collections::HashMap;
+
+/// Amounts of the synced transactions summed up per category and per payee,
+/// which make the sync summary (and notifications) a small spending digest.
+#[derive(Clone, Debug, Default)]
+pub struct Totals {
+    by_category: Vec<(String, i64)>,
+    by_payee: Vec<(String, i64)>,
+}
+
+impl Totals {
+    /// Split transactions count towards the categories of their
+    /// subtransactions. Category ids are looked up in `categories`.
+    pub fn new(transactions: &[Transaction], categories: &HashMap<String, Category>) -> Self {
+        let category_name = |id: &Option<String>| match id {
+            Some(id) => categories
+                .get(id)
+                .map(|x| x.name.clone())
+                .unwrap_or_else(|| id.clone()),
+            None => "(no category)".to_string(),
+        };
+
+        let mut by_category: HashMap<String, i64> = HashMap::new();
+        let mut by_payee: HashMap<String, i64> = HashMap::new();
+        for transaction in transactions {
+            if transaction.subtransactions.is_empty() {
+                *by_category
+                    .entry(category_name(&transaction.category_id))
+                    .or_insert(0) += i64::from(transaction.amount);
+            } else {
+                for subtransaction in &transaction.subtransactions {
+                    *by_category
+                        .entry(category_name(&subtransaction.category_id))
+                        .or_insert(0) += i64::from(subtransaction.amount);
+                }
+            }
+            *by_payee
+                .entry(
+                    transaction
+                        .payee_name
+                        .clone()
+                        .unwrap_or_else(|| "(no payee)".to_string()),
+                )
+                .or_insert(0) += i64::from(transaction.amount);
+        }
+
+        Totals {
+            by_category: sorted(by_category),
+            by_payee: sorted(by_payee),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_category.is_empty()
+    }
+
+    /// Print the `limit` largest category and payee totals.
+    pub fn print(&self, limit: usize) {
+        println!("Totals by category:");
+        for (name, amount) in self.by_category.iter().take(limit) {
+            println!(" - | {:>+10.2} EUR | {}", (*amount as f64 / 1000.0), name);
+        }
+        println!("Totals by payee:");
+        for (name, amount) in self.by_payee.iter().take(limit) {
+            println!(" - | {:>+10.2} EUR | {}", (*amount as f64 / 1000.0), name);
+        }
+    }
+
+    /// Short text of the `limit` largest category and payee totals, for
+    /// notifications.
+    pub fn summary(&self, limit: usize) -> String {
+        let format = |totals: &[(String, i64)]| {
+            totals
+                .iter()
+                .take(limit)
+                .map(|(name, amount)| format!("{} {:+.2}", name, *amount as f64 / 1000.0))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!(
+            "Categories: {}\nPayees: {}",
+            format(&self.by_category),
+            format(&self.by_payee)
+        )
+    }
+}
+
+// largest amounts (in or out) first
+fn sorted(totals: HashMap<String, i64>) -> Vec<(String, i64)> {
+    let mut totals: Vec<(String, i64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+/// Body of the notification sent when a sync finishes, with the `limit`
+/// largest totals when asked for.
+pub fn finished_body(synced: bool, totals: &Totals, limit: usize) -> String {
+    let status = if synced {
+        "YNAB is up to date."
+    } else {
+        "Not all transactions were synced to YNAB."
+    };
+    if limit > 0 && !totals.is_empty() {
+        format!("{}\n{}", status, totals.summary(limit))
+    } else {
+        status.to_string()
+    }
+}
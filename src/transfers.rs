@@ -0,0 +1,86 @@
+use crate::ynab::Account;
+use std::collections::HashMap;
+
+/// Texts of automatic moves to savings (N26 round-ups and Spaces, Revolut
+/// vaults, ...), compared without spaces.
+pub const SAVINGS_SWEEP_PATTERNS: &[&str] = &[
+    "round-up",
+    "roundup",
+    "aufrundung",
+    "aufrunden",
+    "frommainaccount",
+    "tomainaccount",
+    "vomhauptkonto",
+    "zumhauptkonto",
+    "vault",
+];
+
+/// One of the user's own accounts, recognized by an IBAN or keyword.
+#[derive(Clone, Debug)]
+pub struct OwnAccount {
+    pattern: String,
+    /// YNAB account transactions are imported as transfers to, `None` skips
+    /// them.
+    pub account: Option<Account>,
+}
+
+impl OwnAccount {
+    /// `None` for an empty pattern, which would match every transaction.
+    pub fn new(pattern: &str, account: Option<Account>) -> Option<Self> {
+        let pattern = normalize(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(OwnAccount { pattern, account })
+    }
+}
+
+/// Moving money between the user's own banks shows up as an expense in one
+/// account and an income in the other. Such transactions are skipped or
+/// imported as transfers instead.
+///
+/// YNAB creates the other side of a transfer itself, so transactions
+/// imported as transfers should be skipped when syncing the other account.
+#[derive(Clone, Debug, Default)]
+pub struct OwnAccounts {
+    own_accounts: Vec<OwnAccount>,
+}
+
+impl OwnAccounts {
+    pub fn new(own_accounts: Vec<OwnAccount>) -> Self {
+        OwnAccounts { own_accounts }
+    }
+
+    /// Own account whose pattern is contained in any of `values` (see
+    /// `memo_values` of the bank transactions). Spaces are ignored, so IBANs
+    /// match however they are grouped.
+    pub fn find(&self, values: &HashMap<&'static str, Option<String>>) -> Option<&OwnAccount> {
+        let texts: Vec<String> = values
+            .values()
+            .filter_map(|x| x.as_ref().map(|x| normalize(x)))
+            .collect();
+        self.own_accounts
+            .iter()
+            .find(|x| texts.iter().any(|text| text.contains(&x.pattern)))
+    }
+
+    /// Whether the transaction is between own accounts and should be skipped.
+    pub fn is_skipped(&self, values: &HashMap<&'static str, Option<String>>) -> bool {
+        self.find(values).is_some_and(|x| x.account.is_none())
+    }
+
+    /// Account the transaction should be imported as a transfer to.
+    pub fn transfer_account(
+        &self,
+        values: &HashMap<&'static str, Option<String>>,
+    ) -> Option<&Account> {
+        self.find(values).and_then(|x| x.account.as_ref())
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|x| !x.is_whitespace())
+        .flat_map(|x| x.to_lowercase())
+        .collect()
+}
@@ -0,0 +1,105 @@
+// Watching a directory
+//
+// Bank exports are usually downloaded into the same directory (eg.
+// `~/Downloads`). A `Watcher` polls it for files matching a pattern, once a
+// file stopped growing it's handed out to be synced and afterwards moved to
+// the archive directory, so every export is synced once.
+
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_dir, rename};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    dir: PathBuf,
+    pattern: String,
+    archive_dir: PathBuf,
+    /// Sizes of matching files at the last poll.
+    sizes: HashMap<PathBuf, u64>,
+    /// Files handed out before which weren't archived (eg. failed syncs),
+    /// they are only handed out again when they change.
+    seen: HashMap<PathBuf, u64>,
+}
+
+impl Watcher {
+    /// Watch `dir` for files whose name matches `pattern` (`*` matches any
+    /// text), synced files are moved to `archive_dir`.
+    pub fn new(dir: &Path, pattern: &str, archive_dir: &Path) -> Self {
+        Watcher {
+            dir: dir.to_path_buf(),
+            pattern: pattern.to_string(),
+            archive_dir: archive_dir.to_path_buf(),
+            sizes: HashMap::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Matching files which didn't change since the last poll, sorted by
+    /// name.
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        let dir = self.dir.to_string_lossy().to_string();
+        let mut sizes = HashMap::new();
+        for entry in
+            read_dir(&self.dir).with_context(|_| ErrorKind::WatchDirCanNotRead(dir.clone()))?
+        {
+            let entry = entry.with_context(|_| ErrorKind::WatchDirCanNotRead(dir.clone()))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = match entry.metadata() {
+                Ok(x) if x.is_file() => x,
+                _ => continue,
+            };
+            if matches(&self.pattern, &name) {
+                sizes.insert(entry.path(), metadata.len());
+            }
+        }
+
+        let mut ready: Vec<PathBuf> = sizes
+            .iter()
+            .filter(|(path, size)| self.sizes.get(*path) == Some(size))
+            .filter(|(path, size)| self.seen.get(*path) != Some(size))
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in &ready {
+            self.seen.insert(path.clone(), sizes[path]);
+        }
+        let paths: HashSet<&PathBuf> = sizes.keys().collect();
+        self.seen.retain(|x, _| paths.contains(x));
+        self.sizes = sizes;
+        Ok(ready)
+    }
+
+    /// Move `path` to the archive directory. Returns the new path.
+    pub fn archive(&mut self, path: &Path) -> Result<PathBuf> {
+        let archive_dir = self.archive_dir.to_string_lossy().to_string();
+        create_dir_all(&self.archive_dir)
+            .with_context(|_| ErrorKind::WatchCanNotArchive(archive_dir.clone()))?;
+        let target = self.archive_dir.join(path.file_name().unwrap_or_default());
+        rename(path, &target)
+            .with_context(|_| ErrorKind::WatchCanNotArchive(path.to_string_lossy().into()))?;
+        self.sizes.remove(path);
+        self.seen.remove(path);
+        Ok(target)
+    }
+}
+
+/// Whether `name` matches `pattern`, in which `*` matches any text.
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let name = match name.strip_prefix(prefix) {
+                Some(x) => x,
+                None => return false,
+            };
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| matches(rest, &name[i..]))
+        }
+    }
+}
@@ -1,16 +1,32 @@
 extern crate serde_str;
 
-use crate::{ErrorKind, Result};
-use chrono::{Duration, Utc};
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
+use crate::audit::{self, Action};
+use crate::cache::Cache;
+use crate::classifier::Classifier;
+use crate::delta::{self, Deltas, Snapshot};
+use crate::fuzzy::similarity;
+use crate::http::{self, Client, Request, Response};
+use crate::import_id::ImportIdScheme;
+use crate::income::{inflow_category, Income};
+use crate::manual::ManualMatcher;
+use crate::notify::{self, Notification};
+use crate::payees::{PayeeHistory, PayeeMapping};
+use crate::plan;
+use crate::reimbursements::Reimbursements;
+use crate::report::SyncReport;
+use crate::split::{SplitConfig, SplitTarget, Splitter};
+use crate::transfers::{OwnAccount, OwnAccounts, SAVINGS_SWEEP_PATTERNS};
+use crate::{env_flag, ErrorKind, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate, Utc};
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::Select;
+use dialoguer::{Input, Select};
 use failure::ResultExt;
-use log::info;
+use log::warn;
 use reqwest::{header, Method};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 use std::iter::FromIterator;
@@ -18,7 +34,17 @@ use std::result;
 use std::str::FromStr;
 use structopt::StructOpt;
 
-const API_URL: &str = "https://api.youneedabudget.com/v1";
+pub const API_URL: &str = "https://api.youneedabudget.com/v1";
+
+// Constraints YNAB puts on transactions, violating them rejects the whole
+// batch.
+const MEMO_MAX_CHARS: usize = 500;
+const IMPORT_ID_MAX_CHARS: usize = 36;
+const MAX_YEARS_AGO: i32 = 5;
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+// Number of categories offered for a search of `YNAB::search_category`.
+const SEARCH_RESULTS: usize = 10;
 
 #[derive(Clone, StructOpt, Debug)]
 pub struct Cli {
@@ -31,19 +57,21 @@ pub struct Cli {
     )]
     pub token: String,
     #[structopt(
-        long = "ynab-account-id",
+        long = "ynab-account",
+        alias = "ynab-account-id",
         required = true,
-        value_name = "TEXT",
+        value_name = "NAME|ID",
         env = "YNAB_ACCOUNT_ID",
-        help = "YNAB account id which you want to sync."
+        help = "YNAB account (name or id) which you want to sync."
     )]
     pub account_id: String,
     #[structopt(
-        long = "ynab-budget-id",
+        long = "ynab-budget",
+        alias = "ynab-budget-id",
         required = true,
-        value_name = "TEXT",
+        value_name = "NAME|ID",
         env = "YNAB_BUDGET_ID",
-        help = "YNAB budget id which you want to sync."
+        help = "YNAB budget (name or id) which you want to sync."
     )]
     pub budget_id: String,
     #[structopt(
@@ -51,11 +79,263 @@ pub struct Cli {
         help = "Force updating all transactions on YNAB."
     )]
     pub force_update: bool,
+    #[structopt(
+        long = "yes",
+        help = "Sync without asking for confirmation (eg. for scheduled runs). [env: YNAB_SYNC_YES]"
+    )]
+    pub yes: bool,
+    #[structopt(
+        long = "dry-run",
+        help = "Print which transactions would be created or updated (date, amount, payee, category, import id) without saving anything to YNAB."
+    )]
+    pub dry_run: bool,
+    #[structopt(
+        long = "explain",
+        help = "Print how every transaction was categorized: which rules or mapping entries were evaluated, which matched and which fallbacks were used."
+    )]
+    pub explain: bool,
+    #[structopt(
+        long = "totals",
+        value_name = "COUNT",
+        default_value = "0",
+        help = "Print the COUNT categories and payees with the largest totals of the synced transactions, also in notifications. 0 disables it."
+    )]
+    pub totals: usize,
+    #[structopt(
+        long = "month-report",
+        help = "After syncing print how the current budget month changed: Ready to Assign, activity of the categories of the synced transactions and age of money."
+    )]
+    pub month_report: bool,
+    #[structopt(
+        long = "default-category",
+        value_name = "TEXT",
+        env = "YNAB_DEFAULT_CATEGORY",
+        help = "YNAB category for transactions which no rule or mapping matched."
+    )]
+    pub default_category: Option<String>,
+    #[structopt(
+        long = "payee-mapping",
+        value_name = "FILE",
+        env = "YNAB_PAYEE_MAPPING",
+        help = "JSON file which maps merchant/entity names to YNAB payee names or ids."
+    )]
+    pub payee_mapping_file: Option<String>,
+    #[structopt(
+        long = "payee-history-days",
+        value_name = "DAYS",
+        default_value = "365",
+        env = "YNAB_PAYEE_HISTORY_DAYS",
+        help = "Categorize transactions no rule or mapping matched with the category last used for the same payee in this many days of account history (as unapproved). 0 disables it."
+    )]
+    pub payee_history_days: i64,
+    #[structopt(
+        long = "classify",
+        help = "Categorize transactions no rule, mapping or payee history matched with the category most likely for their payee, learned from the categorized transactions of --classify-days (as unapproved)."
+    )]
+    pub classify: bool,
+    #[structopt(
+        long = "classify-days",
+        value_name = "DAYS",
+        default_value = "365",
+        env = "YNAB_CLASSIFY_DAYS",
+        help = "Days of account history --classify learns from."
+    )]
+    pub classify_days: i64,
+    #[structopt(
+        long = "classify-min-probability",
+        value_name = "NUMBER",
+        default_value = "0.8",
+        env = "YNAB_CLASSIFY_MIN_PROBABILITY",
+        help = "Only use categories --classify finds at least this likely (0 to 1)."
+    )]
+    pub classify_min_probability: f64,
+    #[structopt(
+        long = "split-config",
+        value_name = "FILE",
+        env = "YNAB_SPLIT_CONFIG",
+        help = "JSON file describing how to split shared expenses with a partner."
+    )]
+    pub split_config_file: Option<String>,
+    #[structopt(
+        long = "fee-category",
+        value_name = "TEXT",
+        env = "YNAB_FEE_CATEGORY",
+        help = "YNAB category for fees (eg. ATM operator fees) which are split out of transactions."
+    )]
+    pub fee_category: Option<String>,
+    #[structopt(
+        long = "income-payee",
+        value_name = "TEXT",
+        number_of_values = 1,
+        help = "Assign inflows whose payee or memo contains this text to \"Inflow: Ready to Assign\". Can be repeated."
+    )]
+    pub income_payees: Vec<String>,
+    #[structopt(
+        long = "income-all-inflows",
+        help = "Assign every inflow no rule or mapping matched to \"Inflow: Ready to Assign\"."
+    )]
+    pub income_all_inflows: bool,
+    #[structopt(
+        long = "cash-account",
+        value_name = "NAME|ID",
+        env = "YNAB_CASH_ACCOUNT",
+        help = "Import ATM withdrawals as transfers to this YNAB account."
+    )]
+    pub cash_account: Option<String>,
+    #[structopt(
+        long = "mirror-to",
+        value_name = "BUDGET:ACCOUNT",
+        number_of_values = 1,
+        help = "Also sync the transactions to this YNAB budget and account (names or ids). Can be repeated."
+    )]
+    pub mirror_to: Vec<String>,
+    #[structopt(
+        long = "reimbursement-days",
+        value_name = "DAYS",
+        default_value = "0",
+        env = "YNAB_REIMBURSEMENT_DAYS",
+        help = "Flag expenses and refunds of the same amount from the same payee within this many days and note the other's date in their memos. 0 disables it."
+    )]
+    pub reimbursement_days: i64,
+    #[structopt(
+        long = "reimbursement-flag",
+        value_name = "COLOR",
+        default_value = "purple",
+        possible_values = &["red", "orange", "yellow", "green", "blue", "purple"],
+        help = "Flag color of expense/refund pairs."
+    )]
+    pub reimbursement_flag: TransactionFlagColor,
+    #[structopt(
+        long = "match-manual-days",
+        value_name = "DAYS",
+        default_value = "0",
+        env = "YNAB_MATCH_MANUAL_DAYS",
+        help = "Update transactions entered in YNAB by hand with the same amount and a similar payee at most this many days apart, instead of creating the bank's transaction next to them. 0 disables it."
+    )]
+    pub match_manual_days: i64,
+    #[structopt(
+        long = "own-account",
+        value_name = "TEXT[=NAME|ID]",
+        number_of_values = 1,
+        help = "IBAN or text identifying transactions from/to one of your own accounts. With =ACCOUNT they are imported as transfers to that YNAB account, otherwise they are skipped. Can be repeated."
+    )]
+    pub own_accounts: Vec<String>,
+    #[structopt(
+        long = "savings-account",
+        value_name = "NAME|ID",
+        env = "YNAB_SAVINGS_ACCOUNT",
+        help = "Import round-ups and moves to savings spaces or vaults (eg. N26 Spaces) as transfers to this YNAB account."
+    )]
+    pub savings_account: Option<String>,
+    #[structopt(
+        long = "savings-pattern",
+        value_name = "TEXT",
+        number_of_values = 1,
+        requires = "savings_account",
+        help = "Text identifying moves to savings besides the built-in ones (eg. the name of a space). Can be repeated."
+    )]
+    pub savings_patterns: Vec<String>,
+    #[structopt(
+        long = "exclude-pattern",
+        value_name = "TEXT",
+        number_of_values = 1,
+        help = "Don't sync transactions whose payee, memo or reference contains this text (eg. credit card settlements). Can be repeated."
+    )]
+    pub exclude_patterns: Vec<String>,
+    #[structopt(
+        long = "exclude-zero-amounts",
+        help = "Don't sync transactions with an amount of 0 (eg. card authorizations)."
+    )]
+    pub exclude_zero_amounts: bool,
+    #[structopt(
+        long = "cache-ttl",
+        value_name = "HOURS",
+        default_value = "24",
+        env = "YNAB_CACHE_TTL",
+        help = "How long YNAB categories and accounts are cached on disk. 0 disables the cache."
+    )]
+    pub cache_ttl: i64,
+    #[structopt(
+        long = "refresh",
+        help = "Refetch cached YNAB categories and accounts, and download categories, accounts and transactions completely instead of only the changes since the last sync."
+    )]
+    pub refresh: bool,
+    #[structopt(
+        long = "ynab-since-date",
+        value_name = "YYYY-MM-DD",
+        help = "Compare with the YNAB transactions since this date instead of the synced days (eg. to catch transactions synced long ago)."
+    )]
+    pub since_date: Option<NaiveDate>,
+    #[structopt(
+        long = "ynab-transaction-type",
+        value_name = "TYPE",
+        possible_values = &["uncleared", "unapproved"],
+        help = "Only fetch uncleared or unapproved YNAB transactions to compare with, transactions synced before are recognized by the sync state."
+    )]
+    pub transaction_type: Option<TransactionType>,
+    #[structopt(
+        long = "ynab-batch-size",
+        value_name = "NUMBER",
+        default_value = "100",
+        env = "YNAB_BATCH_SIZE",
+        help = "Maximum number of transactions created or updated with one request."
+    )]
+    pub batch_size: usize,
+    #[structopt(
+        long = "update-duplicates",
+        help = "Update transactions YNAB refuses to create because their import id is already used, instead of skipping them."
+    )]
+    pub update_duplicates: bool,
+    #[structopt(
+        long = "import-id-scheme",
+        value_name = "SCHEME",
+        default_value = "source",
+        possible_values = &["source", "ynab"],
+        env = "YNAB_IMPORT_ID_SCHEME",
+        help = "Import ids of synced transactions: derived from the bank's transactions (source) or YNAB:<amount>:<date>:<occurrence> like YNAB's own file and direct import (ynab)."
+    )]
+    pub import_id_scheme: ImportIdScheme,
+}
+
+impl Cli {
+    /// Whether to sync without asking, with --yes or YNAB_SYNC_YES.
+    pub fn assume_yes(&self) -> bool {
+        self.yes || env_flag("YNAB_SYNC_YES")
+    }
 }
 
 #[derive(Debug)]
 pub struct YNAB {
     pub token: String,
+    pub api_url: String,
+    pub client: Option<Client>,
+    pub cache: Option<Cache>,
+    pub deltas: Option<Deltas>,
+    pub since_date: Option<NaiveDate>,
+    pub transaction_type: Option<TransactionType>,
+    pub batch_size: usize,
+    pub update_duplicates: bool,
+    pub assume_yes: bool,
+    pub dry_run: bool,
+}
+
+/// Budget and account which receive a copy of the synced transactions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mirror {
+    pub budget_id: String,
+    pub account_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub id: String,
+    pub name: String,
+    pub detail: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -78,6 +358,15 @@ pub struct CategoryGroup {
     pub categories: Vec<Category>,
 }
 
+impl CategoryGroup {
+    /// Category of the group by name or id.
+    pub fn category(&self, name_or_id: &str) -> Option<&Category> {
+        self.categories
+            .iter()
+            .find(|x| x.id == name_or_id || x.name == name_or_id)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Category {
     pub id: String,
@@ -116,6 +405,87 @@ pub struct AccountsWrapper {
     pub server_knowledge: i64,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SingleAccountRequest {
+    pub data: AccountWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountWrapper {
+    pub account: Account,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthRequest {
+    pub data: MonthWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthWrapper {
+    pub month: Month,
+}
+
+/// A budget month (YNAB's `MonthDetail`), amounts of its categories are
+/// those of the month.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Month {
+    pub month: String, // date
+    pub income: i64,
+    pub budgeted: i64,
+    pub activity: i64,
+    pub to_be_budgeted: i64,
+    pub age_of_money: Option<i64>,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthCategoryRequest {
+    pub data: MonthCategoryWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthCategoryWrapper {
+    pub category: Category,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveMonthCategoryWrapper {
+    pub category: SaveMonthCategory,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveMonthCategory {
+    pub budgeted: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PayeesRequest {
+    pub data: PayeesWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PayeesWrapper {
+    pub payees: Vec<Payee>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Payee {
+    pub id: String,
+    pub name: String,
+    pub transfer_account_id: Option<String>,
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavePayeeWrapper {
+    pub payee: SavePayee,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavePayee {
+    pub name: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Account {
     pub id: String,
@@ -171,7 +541,7 @@ pub struct Budget {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DateFormat {
-    format: String,
+    pub format: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -188,7 +558,32 @@ pub struct CurrencyFormat {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionsRequest {
-    pub data: TransactionsWrapper,
+    pub data: TransactionListWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionListWrapper {
+    pub transactions: Vec<ListedTransaction>,
+    pub server_knowledge: i64,
+}
+
+/// Transaction of a list, delta requests also list deleted ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListedTransaction {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SingleTransactionRequest {
+    pub data: TransactionWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionWrapper {
+    pub transaction: Transaction,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -196,8 +591,23 @@ pub struct TransactionsWrapper {
     pub transactions: Vec<Transaction>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveTransactionsRequest {
+    pub data: SaveTransactionsWrapper,
+}
+
+/// Response of creating transactions, those whose import id is already used
+/// in the account are not created.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveTransactionsWrapper {
+    #[serde(default)]
+    pub duplicate_import_ids: Vec<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub account_id: String,
     pub date: String,
     pub amount: i32,
@@ -210,6 +620,196 @@ pub struct Transaction {
     pub approved: bool,
     pub flag_color: Option<TransactionFlagColor>,
     pub import_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtransactions: Vec<SubTransaction>,
+}
+
+impl Transaction {
+    /// Turn the transaction into a transfer to `account` (eg. a cash
+    /// withdrawal into a transfer to the cash account).
+    pub fn into_transfer(self, account: &Account) -> Self {
+        Transaction {
+            payee_id: Some(account.transfer_payee_id.clone()),
+            payee_name: None,
+            // transfers between budget accounts have no category
+            category_id: None,
+            approved: true,
+            subtransactions: vec![],
+            ..self
+        }
+    }
+
+    /// Truncate the memo (and memos of subtransactions) to what YNAB accepts.
+    pub fn truncate_memo(self) -> Self {
+        let truncate =
+            |memo: Option<String>| memo.map(|x| x.chars().take(MEMO_MAX_CHARS).collect());
+        Transaction {
+            memo: truncate(self.memo),
+            subtransactions: self
+                .subtransactions
+                .into_iter()
+                .map(|x| SubTransaction {
+                    memo: truncate(x.memo.clone()),
+                    ..x
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Reasons why YNAB would reject the transaction, empty when it is valid.
+    pub fn violations(&self, today: NaiveDate) -> Vec<String> {
+        let mut violations = vec![];
+        match NaiveDate::parse_from_str(&self.date, "%Y-%m-%d") {
+            Ok(date) if date > today => violations.push("date is in the future".to_string()),
+            Ok(date)
+                if date
+                    < today
+                        .with_year(today.year() - MAX_YEARS_AGO)
+                        .unwrap_or(today) =>
+            {
+                violations.push(format!("date is more than {} years ago", MAX_YEARS_AGO))
+            }
+            Ok(_) => (),
+            Err(_) => violations.push(format!("date {} is not YYYY-MM-DD", self.date)),
+        }
+        if let Some(import_id) = &self.import_id {
+            if import_id.chars().count() > IMPORT_ID_MAX_CHARS {
+                violations.push(format!(
+                    "import id is longer than {} characters",
+                    IMPORT_ID_MAX_CHARS
+                ));
+            }
+        }
+        if !self.subtransactions.is_empty() {
+            let total: i32 = self.subtransactions.iter().map(|x| x.amount).sum();
+            if total != self.amount {
+                violations.push("amounts of the split don't add up".to_string());
+            }
+            if self.amount == 0 {
+                violations.push("split transactions need a non-zero amount".to_string());
+            }
+        }
+        violations
+    }
+
+    /// Split a `fee` (negative, in milliunits) included in the amount out
+    /// into a subtransaction in `fee_category_id`. Without a fee category the
+    /// transaction is left unapproved.
+    pub fn split_fee(self, fee: Option<i32>, fee_category_id: Option<String>) -> Self {
+        let fee = match fee {
+            Some(fee) if fee != 0 && fee != self.amount && self.subtransactions.is_empty() => fee,
+            _ => return self,
+        };
+        Transaction {
+            payee_id: None,
+            category_id: None,
+            approved: self.approved && fee_category_id.is_some(),
+            subtransactions: vec![
+                SubTransaction {
+                    amount: self.amount - fee,
+                    payee_id: self.payee_id.clone(),
+                    payee_name: self.payee_name.clone(),
+                    category_id: self.category_id.clone(),
+                    memo: self.memo.clone(),
+                },
+                SubTransaction {
+                    amount: fee,
+                    payee_id: None,
+                    payee_name: self.payee_name.clone(),
+                    category_id: fee_category_id,
+                    memo: Some("Fee".to_string()),
+                },
+            ],
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubTransaction {
+    pub amount: i32,
+    pub payee_id: Option<String>,
+    pub payee_name: Option<String>,
+    pub category_id: Option<String>,
+    pub memo: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledTransactionsRequest {
+    pub data: ScheduledTransactionsWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledTransactionsWrapper {
+    pub scheduled_transactions: Vec<ScheduledTransaction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreatedScheduledTransactionRequest {
+    pub data: CreatedScheduledTransactionWrapper,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreatedScheduledTransactionWrapper {
+    pub scheduled_transaction: ScheduledTransaction,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledTransaction {
+    pub id: String,
+    pub date_first: String,
+    /// Date of the next occurrence.
+    pub date_next: String,
+    pub frequency: ScheduledFrequency,
+    pub amount: i32,
+    pub memo: Option<String>,
+    pub flag_color: Option<TransactionFlagColor>,
+    pub account_id: String,
+    pub payee_id: Option<String>,
+    pub payee_name: Option<String>,
+    pub category_id: Option<String>,
+    pub transfer_account_id: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveScheduledTransactionWrapper {
+    pub scheduled_transaction: SaveScheduledTransaction,
+}
+
+/// A scheduled transaction to create, first occurring on `date` (at most a
+/// year in the future).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveScheduledTransaction {
+    pub account_id: String,
+    pub date: String,
+    pub amount: i32,
+    pub payee_id: Option<String>,
+    pub payee_name: Option<String>,
+    pub category_id: Option<String>,
+    pub memo: Option<String>,
+    pub flag_color: Option<TransactionFlagColor>,
+    pub frequency: ScheduledFrequency,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduledFrequency {
+    Never,
+    Daily,
+    Weekly,
+    EveryOtherWeek,
+    TwiceAMonth,
+    Every4Weeks,
+    Monthly,
+    EveryOtherMonth,
+    Every3Months,
+    Every4Months,
+    TwiceAYear,
+    Yearly,
+    EveryOtherYear,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -219,7 +819,8 @@ pub enum TransactionCleared {
     Reconciled,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionFlagColor {
     Red,
     Orange,
@@ -359,168 +960,1046 @@ impl FromStr for TransactionFlagColor {
     }
 }
 
-impl YNAB {
-    pub fn validate_cli(&self, cli: Cli, step: i32, steps: i32) -> Result<()> {
-        // Fetch budgets and verify that budget_id is correct
-        println!("[ {}/{}] Verifying --budget-id", step + 1, steps);
-        if self
-            .get_budgets()?
-            .into_iter()
-            .filter(|x| x.id == cli.budget_id)
-            .count()
-            != 1
-        {
-            Err(ErrorKind::WrongBudgetId(cli.budget_id.clone()))?
-        }
-
-        // Fetch accounts and verify that account_id is correct
-        println!("[ {}/{}] Verifying --account-id", step + 2, steps);
-        if self
-            .get_accounts(cli.budget_id.clone())?
-            .into_iter()
-            .filter(|x| x.id == cli.account_id)
-            .count()
-            != 1
-        {
-            Err(ErrorKind::WrongAccountId(cli.account_id.clone()))?
-        }
+/// `type` filter of the transaction list endpoints.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransactionType {
+    Uncleared,
+    Unapproved,
+}
 
-        Ok(())
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                TransactionType::Uncleared => "uncleared",
+                TransactionType::Unapproved => "unapproved",
+            },
+        )
     }
-    pub fn get_categories(&self, budget_id: String) -> Result<HashMap<String, Category>> {
-        let url = format!("{}/budgets/{}/categories", API_URL, budget_id);
-        let authorization = format!("Bearer {}", self.token);
-        let client = reqwest::Client::new();
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
-            .send()
-            .context(ErrorKind::YNABGetCategories)?;
+}
 
-        let body = res.text().context(ErrorKind::YNABGetCategories)?;
-        info!("{}", body);
+impl FromStr for TransactionType {
+    type Err = ErrorKind;
 
-        if !res.status().is_success() {
-            let http_error = ErrorKind::YNABGetCategoriesHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "uncleared" => Ok(TransactionType::Uncleared),
+            "unapproved" => Ok(TransactionType::Unapproved),
+            _ => Err(ErrorKind::ArgParse(format!(
+                "--ynab-transaction-type {}",
+                s
+            ))),
         }
+    }
+}
+
+/// Categories by id, by "<Group>:<Category>" (or "<Group>/<Category>") and,
+/// when no other group has a category with the same name, by name. Deleted
+/// categories are left out.
+pub fn index_categories(category_groups: Vec<CategoryGroup>) -> HashMap<String, Category> {
+    let mut categories = HashMap::new();
+    let mut names: HashMap<String, Vec<Category>> = HashMap::new();
+    for group in category_groups {
+        for category in group.categories.into_iter().filter(|x| !x.deleted) {
+            categories.insert(category.id.clone(), category.clone());
+            for separator in &[":", "/"] {
+                categories.insert(
+                    format!("{}{}{}", group.name, separator, category.name),
+                    category.clone(),
+                );
+            }
+            names
+                .entry(category.name.clone())
+                .or_default()
+                .push(category);
+        }
+    }
+    for (name, mut same_name) in names {
+        if same_name.len() == 1 {
+            categories.insert(name, same_name.remove(0));
+        } else {
+            warn!(
+                "Category name \"{}\" is used in {} groups, refer to it as \"<Group>:{}\" or by id.",
+                name,
+                same_name.len(),
+                name
+            );
+        }
+    }
+    categories
+}
+
+/// Category groups keyed by id and by name.
+pub fn index_category_groups(
+    category_groups: Vec<CategoryGroup>,
+) -> HashMap<String, CategoryGroup> {
+    let mut groups = HashMap::new();
+    for group in category_groups {
+        groups.insert(group.id.clone(), group.clone());
+        groups.insert(group.name.clone(), group);
+    }
+    groups
+}
+
+/// Categories and category groups of a budget, indexed by
+/// `index_categories` and `index_category_groups`.
+#[derive(Clone, Debug, Default)]
+pub struct BudgetCategories {
+    pub categories: HashMap<String, Category>,
+    pub groups: HashMap<String, CategoryGroup>,
+    /// Category groups in the order of the budget.
+    pub group_list: Vec<CategoryGroup>,
+}
+
+impl BudgetCategories {
+    pub fn new(category_groups: Vec<CategoryGroup>) -> Self {
+        BudgetCategories {
+            categories: index_categories(category_groups.clone()),
+            groups: index_category_groups(category_groups.clone()),
+            group_list: category_groups,
+        }
+    }
+}
+
+impl YNAB {
+    pub fn new(token: String) -> Self {
+        YNAB {
+            token,
+            api_url: API_URL.to_string(),
+            client: None,
+            cache: None,
+            deltas: None,
+            since_date: None,
+            transaction_type: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            update_duplicates: false,
+            assume_yes: false,
+            dry_run: false,
+        }
+    }
+
+    /// Sync without asking for confirmation.
+    pub fn with_assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Only print what a sync would change.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Create or update at most `batch_size` transactions with one request.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Update transactions which can't be created because their import id is
+    /// already used.
+    pub fn with_update_duplicates(mut self, update_duplicates: bool) -> Self {
+        self.update_duplicates = update_duplicates;
+        self
+    }
+
+    /// Send requests with `client` instead of the shared one.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Cache categories and accounts on disk.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    fn cached<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.cache.as_ref().and_then(|x| x.get(key))
+    }
+
+    fn cache<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        match &self.cache {
+            Some(cache) => cache.put(key, value),
+            None => Ok(()),
+        }
+    }
+
+    /// Compare synced transactions with the YNAB transactions since
+    /// `since_date` instead of the synced days.
+    pub fn with_since_date(mut self, since_date: Option<NaiveDate>) -> Self {
+        self.since_date = since_date;
+        self
+    }
+
+    /// Only compare synced transactions with YNAB transactions of a type.
+    pub fn with_transaction_type(mut self, transaction_type: Option<TransactionType>) -> Self {
+        self.transaction_type = transaction_type;
+        self
+    }
+
+    /// Only download changes of categories, accounts and transactions since
+    /// the last request.
+    pub fn with_deltas(mut self, deltas: Deltas) -> Self {
+        self.deltas = Some(deltas);
+        self
+    }
+
+    fn snapshot<T: DeserializeOwned>(&self, key: &str) -> Option<Snapshot<T>> {
+        self.deltas.as_ref().and_then(|x| x.get(key))
+    }
+
+    fn store_snapshot<T: Serialize>(&self, key: &str, snapshot: &Snapshot<T>) -> Result<()> {
+        match &self.deltas {
+            Some(deltas) => deltas.put(key, snapshot),
+            None => Ok(()),
+        }
+    }
+
+    /// `url` only asking for changes since `snapshot`, if any.
+    fn delta_url<T>(&self, mut url: String, snapshot: Option<&Snapshot<T>>) -> String {
+        if let Some(snapshot) = snapshot {
+            url.push_str(&format!(
+                "?last_knowledge_of_server={}",
+                snapshot.server_knowledge
+            ));
+        }
+        url
+    }
 
-        let req: CategoriesRequest = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::YNABGetCategoriesParse(e.to_string()))?;
-
-        let categories = req
+    fn get_request(&self, url: &str) -> Request {
+        let authorization = format!("Bearer {}", self.token);
+        Request::get(url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+    }
+
+    /// Verify --ynab-budget and --ynab-account and resolve them to ids when
+    /// they were given by name.
+    pub fn validate_cli(&self, mut cli: Cli, step: i32, steps: i32) -> Result<Cli> {
+        // Fetch budgets and accounts and verify that budget_id and account_id
+        // are correct. Budgets given by id are verified while their accounts
+        // are fetched.
+        println!("[ {}/{}] Verifying --ynab-budget", step + 1, steps);
+        println!("[ {}/{}] Verifying --ynab-account", step + 2, steps);
+        let (budget_id, accounts) = if is_id(&cli.budget_id) {
+            http::join(
+                || self.resolve_budget_id(&cli.budget_id),
+                || self.get_accounts(cli.budget_id.clone()),
+            )
+        } else {
+            let budget_id = self.resolve_budget_id(&cli.budget_id)?;
+            let accounts = self.get_accounts(budget_id.clone());
+            (Ok(budget_id), accounts)
+        };
+        cli.budget_id = budget_id?;
+        cli.account_id = find_account_id(&cli.account_id, accounts?)?;
+
+        Ok(cli)
+    }
+    /// Id of the budget with id or name `budget`.
+    pub fn resolve_budget_id(&self, budget: &str) -> Result<String> {
+        Ok(self.get_budget(budget)?.id)
+    }
+
+    /// Budget with id or name `budget`.
+    pub fn get_budget(&self, budget: &str) -> Result<Budget> {
+        let budgets = self.get_budgets()?;
+        match find_by_id_or_name(budget, &budgets, |x| &x.id, |x| &x.name).as_slice() {
+            [budget] => Ok((*budget).clone()),
+            [] => Err(ErrorKind::WrongBudgetId(budget.to_string()))?,
+            _ => Err(ErrorKind::AmbiguousBudget(budget.to_string()))?,
+        }
+    }
+    /// Id of the (not deleted) account with id or name `account`.
+    pub fn resolve_account_id(&self, budget_id: &str, account: &str) -> Result<String> {
+        find_account_id(account, self.get_accounts(budget_id.to_string())?)
+    }
+    /// Look up the category provided via --default-category, failing when it
+    /// does not exist in the budget.
+    pub fn default_category(
+        &self,
+        cli: &Cli,
+        categories: &HashMap<String, Category>,
+    ) -> Result<Option<Category>> {
+        match &cli.default_category {
+            Some(name) => match categories.get(name) {
+                Some(category) => Ok(Some(category.clone())),
+                None => Err(ErrorKind::WrongDefaultCategory(name.clone()))?,
+            },
+            None => Ok(None),
+        }
+    }
+    /// Look up the category provided via --fee-category, failing when it does
+    /// not exist in the budget.
+    pub fn fee_category(
+        &self,
+        cli: &Cli,
+        categories: &HashMap<String, Category>,
+    ) -> Result<Option<Category>> {
+        match &cli.fee_category {
+            Some(name) => match categories.get(name) {
+                Some(category) => Ok(Some(category.clone())),
+                None => Err(ErrorKind::WrongFeeCategory(name.clone()))?,
+            },
+            None => Ok(None),
+        }
+    }
+    /// Load the mapping provided via --payee-mapping, or an empty mapping.
+    /// Names of existing payees are resolved to their ids.
+    pub fn payee_mapping(&self, cli: &Cli) -> Result<PayeeMapping> {
+        match &cli.payee_mapping_file {
+            Some(file) => {
+                let payees = self.get_payees(&cli.budget_id)?;
+                Ok(PayeeMapping::from_file(file)?.resolve(&payees))
+            }
+            None => Ok(PayeeMapping::default()),
+        }
+    }
+    /// Load the config provided via --split-config and resolve its category
+    /// or partner's budget and account.
+    pub fn splitter(
+        &self,
+        cli: &Cli,
+        categories: &HashMap<String, Category>,
+    ) -> Result<Option<Splitter>> {
+        let config = match &cli.split_config_file {
+            Some(file) => SplitConfig::from_file(file)?,
+            None => return Ok(None),
+        };
+        let target = match (&config.category, &config.budget, &config.account) {
+            (Some(name), _, _) => match categories.get(name) {
+                Some(category) => SplitTarget::Category(category.id.clone()),
+                None => Err(ErrorKind::WrongSplitCategory(name.clone()))?,
+            },
+            (None, Some(budget), Some(account)) => {
+                let budget_id = self.resolve_budget_id(budget)?;
+                let account_id = self.resolve_account_id(&budget_id, account)?;
+                SplitTarget::Account {
+                    budget_id,
+                    account_id,
+                }
+            }
+            _ => Err(ErrorKind::ArgParseSplitConfigCanNotParse(
+                cli.split_config_file.clone().unwrap_or_default(),
+            ))?,
+        };
+        Ok(Some(Splitter::new(config, target)))
+    }
+    pub fn get_category_groups(&self, budget_id: String) -> Result<Vec<CategoryGroup>> {
+        let key = format!("ynab-{}-categories", budget_id);
+        if let Some(category_groups) = self.cached(&key) {
+            return Ok(category_groups);
+        }
+
+        let snapshot: Option<Snapshot<CategoryGroup>> = self.snapshot(&key);
+        let url = self.delta_url(
+            format!("{}/budgets/{}/categories", self.api_url, budget_id),
+            snapshot.as_ref(),
+        );
+        let res = self
+            .get_request(&url)
+            .send()
+            .context(ErrorKind::YNABGetCategories)?;
+        self.merge_category_groups(&key, snapshot, &res)
+    }
+
+    /// Categories of the response to a (delta) request of `key` merged
+    /// into `snapshot`.
+    fn merge_category_groups(
+        &self,
+        key: &str,
+        snapshot: Option<Snapshot<CategoryGroup>>,
+        res: &Response,
+    ) -> Result<Vec<CategoryGroup>> {
+        let req: CategoriesRequest = parse_response(
+            res,
+            ErrorKind::YNABGetCategories,
+            ErrorKind::YNABGetCategoriesHttp,
+            ErrorKind::YNABGetCategoriesParse,
+        )?;
+
+        let mut snapshot = snapshot.unwrap_or(Snapshot {
+            server_knowledge: 0,
+            since_date: None,
+            items: vec![],
+        });
+        // changed groups only list their changed categories
+        let changed = req
             .data
             .category_groups
             .into_iter()
-            .map(|x| x.categories)
-            .flatten()
-            .map(|x| (x.name.clone(), x.clone()));
+            .map(|mut group| {
+                if let Some(known) = snapshot.items.iter().find(|x| x.id == group.id) {
+                    let mut categories = known.categories.clone();
+                    delta::merge(&mut categories, group.categories, |x| &x.id, |x| x.deleted);
+                    group.categories = categories;
+                }
+                group
+            })
+            .collect();
+        snapshot.merge(changed, req.data.server_knowledge, |x| &x.id, |x| x.deleted);
+        self.store_snapshot(key, &snapshot)?;
+
+        self.cache(key, &snapshot.items)?;
+        Ok(snapshot.items)
+    }
 
-        Ok(HashMap::from_iter(categories))
+    /// Categories of a budget keyed by every name they can be referred to in
+    /// rules and mapping files:
+    ///
+    /// - category id (eg. `0a1b2c3d-...`)
+    /// - group-qualified name (eg. `Monthly Bills/Internet`)
+    /// - bare name (eg. `Internet`), only when no other group contains a
+    ///   category with the same name
+    pub fn get_categories(&self, budget_id: String) -> Result<HashMap<String, Category>> {
+        Ok(index_categories(self.get_category_groups(budget_id)?))
     }
 
     pub fn get_budgets(&self) -> Result<Vec<Budget>> {
-        let url = format!("{}/budgets", API_URL,);
-        let authorization = format!("Bearer {}", self.token);
-        let client = reqwest::Client::new();
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
+        let url = format!("{}/budgets", self.api_url);
+        let res = self
+            .get_request(&url)
             .send()
             .context(ErrorKind::YNABGetBudgets)?;
+        parse_budgets(&res)
+    }
 
-        let body = res.text().context(ErrorKind::YNABGetBudgets)?;
-        info!("{}", body);
+    /// Check the token by fetching the authenticated user. Returns the rate
+    /// limit usage YNAB reports (eg. `36/200` requests in the current hour).
+    pub fn rate_limit(&self) -> Result<Option<String>> {
+        let url = format!("{}/user", self.api_url);
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::get(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABGetUser)?;
 
         if !res.status().is_success() {
-            let http_error = ErrorKind::YNABGetBudgetsHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
+            Err(response_error(
+                res.status().as_u16(),
+                res.text(),
+                ErrorKind::YNABGetUser,
+                ErrorKind::YNABGetUserHttp,
+            ))?;
         }
 
-        let req: BudgetsRequest = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::YNABGetBudgetsParse(e.to_string()))?;
+        Ok(res.header("x-rate-limit").map(String::from))
+    }
+
+    /// Account with `account_id`, always fetched (not cached) so the
+    /// balances are current.
+    pub fn get_account(&self, budget_id: &str, account_id: &str) -> Result<Account> {
+        let url = format!(
+            "{}/budgets/{}/accounts/{}",
+            self.api_url, budget_id, account_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::get(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABGetAccount)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABGetAccount,
+                ErrorKind::YNABGetAccountHttp,
+            ))?;
+        }
+
+        let req: SingleAccountRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABGetAccountParse(e.to_string()))?;
+
+        Ok(req.data.account)
+    }
 
-        Ok(req.data.budgets)
+    /// Cleared balance of the account `account_id` once `transactions` are
+    /// synced, each replacing the existing transaction with its import id.
+    pub fn expected_cleared_balance(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        transactions: &[Transaction],
+        existing_transactions: &HashMap<String, Transaction>,
+    ) -> Result<i64> {
+        let cleared_amount = |x: &Transaction| match x.cleared {
+            TransactionCleared::Uncleared => 0,
+            _ => i64::from(x.amount),
+        };
+        let account = self.get_account(budget_id, account_id)?;
+        let change: i64 = transactions
+            .iter()
+            .filter(|x| x.account_id == account_id)
+            .map(|x| {
+                let existing = x
+                    .import_id
+                    .as_ref()
+                    .and_then(|x| existing_transactions.get(x));
+                cleared_amount(x) - existing.map(cleared_amount).unwrap_or(0)
+            })
+            .sum();
+        Ok(account.cleared_balance + change)
     }
 
     pub fn get_accounts(&self, budget_id: String) -> Result<Vec<Account>> {
-        let url = format!("{}/budgets/{}/accounts", API_URL, budget_id);
-        let authorization = format!("Bearer {}", self.token);
-        let client = reqwest::Client::new();
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
+        let key = format!("ynab-{}-accounts", budget_id);
+        if let Some(accounts) = self.cached(&key) {
+            return Ok(accounts);
+        }
+
+        let snapshot: Option<Snapshot<Account>> = self.snapshot(&key);
+        let url = self.delta_url(
+            format!("{}/budgets/{}/accounts", self.api_url, budget_id),
+            snapshot.as_ref(),
+        );
+        let res = self
+            .get_request(&url)
             .send()
             .context(ErrorKind::YNABGetAccounts)?;
+        self.merge_accounts(&key, snapshot, &res)
+    }
+
+    /// Accounts of the response to a (delta) request of `key` merged into
+    /// `snapshot`.
+    fn merge_accounts(
+        &self,
+        key: &str,
+        snapshot: Option<Snapshot<Account>>,
+        res: &Response,
+    ) -> Result<Vec<Account>> {
+        let req: AccountsRequest = parse_response(
+            res,
+            ErrorKind::YNABGetAccounts,
+            ErrorKind::YNABGetAccountsHttp,
+            ErrorKind::YNABGetAccountsParse,
+        )?;
+
+        let mut snapshot = snapshot.unwrap_or(Snapshot {
+            server_knowledge: 0,
+            since_date: None,
+            items: vec![],
+        });
+        snapshot.merge(
+            req.data.accounts,
+            req.data.server_knowledge,
+            |x| &x.id,
+            |x| x.deleted,
+        );
+        self.store_snapshot(key, &snapshot)?;
+
+        self.cache(key, &snapshot.items)?;
+        Ok(snapshot.items)
+    }
+    /// Budget month, eg. `current`, with "Ready to Assign" and the
+    /// activity of every category. Not cached, it changes with every sync.
+    pub fn get_month(&self, budget_id: &str, month: &str) -> Result<Month> {
+        let url = format!("{}/budgets/{}/months/{}", self.api_url, budget_id, month);
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::get(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABGetMonth)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABGetMonth,
+                ErrorKind::YNABGetMonthHttp,
+            ))?;
+        }
+
+        let req: MonthRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABGetMonthParse(e.to_string()))?;
+
+        Ok(req.data.month)
+    }
+    /// Set the amount budgeted (in milliunits) in the category with
+    /// `category_id` in `month` (`YYYY-MM-01` or `current`). Returns the
+    /// category with the month's amounts.
+    pub fn update_month_category(
+        &self,
+        budget_id: &str,
+        month: &str,
+        category_id: &str,
+        budgeted: i64,
+    ) -> Result<Category> {
+        let url = format!(
+            "{}/budgets/{}/months/{}/categories/{}",
+            self.api_url, budget_id, month, category_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let req_body = serde_json::to_string(&SaveMonthCategoryWrapper {
+            category: SaveMonthCategory { budgeted },
+        })
+        .context(ErrorKind::YNABUpdateMonthCategory)?;
+
+        let res = Request::new(Method::PATCH, &url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(req_body)
+            .send()
+            .context(ErrorKind::YNABUpdateMonthCategory)?;
 
-        let body = res.text().context(ErrorKind::YNABGetAccounts)?;
-        info!("{}", body);
+        let body = res.text();
 
         if !res.status().is_success() {
-            let http_error = ErrorKind::YNABGetAccountsHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABUpdateMonthCategory,
+                ErrorKind::YNABUpdateMonthCategoryHttp,
+            ))?;
         }
 
-        let req: AccountsRequest = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::YNABGetAccountsParse(e.to_string()))?;
+        let req: MonthCategoryRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABUpdateMonthCategoryParse(e.to_string()))?;
 
-        Ok(req.data.accounts)
+        Ok(req.data.category)
     }
+    /// Current budget month before syncing, to compare with after syncing
+    /// when --month-report is used.
+    pub fn month_before_sync(&self, cli: &Cli) -> Result<Option<Month>> {
+        if !cli.month_report {
+            return Ok(None);
+        }
+        Ok(Some(self.get_month(&cli.budget_id, "current")?))
+    }
+    /// All payees of the budget, not cached since `payees cleanup` renames
+    /// them.
+    pub fn get_payees(&self, budget_id: &str) -> Result<Vec<Payee>> {
+        let url = format!("{}/budgets/{}/payees", self.api_url, budget_id);
+        let res = self
+            .get_request(&url)
+            .send()
+            .context(ErrorKind::YNABGetPayees)?;
+        parse_payees(&res)
+    }
+    /// Rename the payee with `payee_id` to `name`.
+    pub fn rename_payee(&self, budget_id: &str, payee_id: &str, name: &str) -> Result<()> {
+        let url = format!("{}/budgets/{}/payees/{}", self.api_url, budget_id, payee_id);
+        let authorization = format!("Bearer {}", self.token);
+        let req_body = serde_json::to_string(&SavePayeeWrapper {
+            payee: SavePayee {
+                name: name.to_string(),
+            },
+        })
+        .context(ErrorKind::YNABSavePayee)?;
+
+        let res = Request::new(Method::PATCH, &url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(req_body)
+            .send()
+            .context(ErrorKind::YNABSavePayee)?;
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                res.text(),
+                ErrorKind::YNABSavePayee,
+                ErrorKind::YNABSavePayeeHttp,
+            ))?;
+        }
+
+        Ok(())
+    }
+    /// Scheduled transactions of all accounts of the budget.
+    pub fn get_scheduled_transactions(&self, budget_id: &str) -> Result<Vec<ScheduledTransaction>> {
+        let url = format!(
+            "{}/budgets/{}/scheduled_transactions",
+            self.api_url, budget_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::get(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABGetScheduledTransactions)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABGetScheduledTransactions,
+                ErrorKind::YNABGetScheduledTransactionsHttp,
+            ))?;
+        }
+
+        let req: ScheduledTransactionsRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABGetScheduledTransactionsParse(e.to_string()))?;
+
+        Ok(req
+            .data
+            .scheduled_transactions
+            .into_iter()
+            .filter(|x| !x.deleted)
+            .collect())
+    }
+    /// Create a scheduled transaction (eg. for a standing order), returns it
+    /// as created by YNAB.
+    pub fn create_scheduled_transaction(
+        &self,
+        budget_id: &str,
+        scheduled_transaction: SaveScheduledTransaction,
+    ) -> Result<ScheduledTransaction> {
+        let url = format!(
+            "{}/budgets/{}/scheduled_transactions",
+            self.api_url, budget_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let req_body = serde_json::to_string(&SaveScheduledTransactionWrapper {
+            scheduled_transaction,
+        })
+        .context(ErrorKind::YNABCreateScheduledTransaction)?;
+
+        let res = Request::post(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(req_body)
+            .send()
+            .context(ErrorKind::YNABCreateScheduledTransaction)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABCreateScheduledTransaction,
+                ErrorKind::YNABCreateScheduledTransactionHttp,
+            ))?;
+        }
+
+        let req: CreatedScheduledTransactionRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABCreateScheduledTransactionParse(e.to_string()))?;
+
+        Ok(req.data.scheduled_transaction)
+    }
+    /// Transactions of an account to compare synced transactions with, by
+    /// import id. Covers the last `days` days or, when set, the transactions
+    /// since `with_since_date` (of the type of `with_transaction_type`).
     pub fn get_transactions(
         &self,
         budget_id: String,
         account_id: String,
         days: i64,
     ) -> Result<HashMap<String, Transaction>> {
-        let now = Utc::now();
-        let days_ago = now - Duration::days(days);
-        let since_date = days_ago.format("%Y-%m-%d");
-
-        let url = format!(
-            "{}/budgets/{}/accounts/{}/transactions?since_date={}",
-            API_URL, budget_id, account_id, since_date
+        Ok(self
+            .get_transactions_and_manual(budget_id, account_id, days)?
+            .0)
+    }
+    /// Like `get_transactions`, also returning the transactions without
+    /// import id (eg. entered by hand).
+    pub fn get_transactions_and_manual(
+        &self,
+        budget_id: String,
+        account_id: String,
+        days: i64,
+    ) -> Result<(HashMap<String, Transaction>, Vec<Transaction>)> {
+        let since_date = self.since_date.unwrap_or_else(|| days_ago(days));
+        let (imported, manual): (Vec<Transaction>, Vec<Transaction>) = self
+            .get_account_transactions_since(
+                &budget_id,
+                &account_id,
+                since_date,
+                self.transaction_type.as_ref(),
+            )?
+            .into_iter()
+            .partition(|x| x.import_id.is_some());
+        let transactions = HashMap::from_iter(
+            imported
+                .into_iter()
+                .filter_map(|x| Some((x.import_id.clone()?, x))),
         );
-        let authorization = format!("Bearer {}", self.token);
-        let client = reqwest::Client::new();
-        let mut res = client
-            .get(&url)
-            .header(header::AUTHORIZATION, authorization)
+
+        Ok((transactions, manual))
+    }
+    /// All transactions of an account from the last `days` days.
+    pub fn get_account_transactions(
+        &self,
+        budget_id: String,
+        account_id: String,
+        days: i64,
+    ) -> Result<Vec<Transaction>> {
+        self.get_account_transactions_since(&budget_id, &account_id, days_ago(days), None)
+    }
+    /// Transactions of an account since `since_date`, only uncleared or
+    /// unapproved ones with `transaction_type`. Large accounts are only
+    /// fetched whole once, later calls fetch the changes since then (see
+    /// `Snapshot`).
+    pub fn get_account_transactions_since(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        since_date: NaiveDate,
+        transaction_type: Option<&TransactionType>,
+    ) -> Result<Vec<Transaction>> {
+        let (url, snapshot) =
+            self.account_transactions_url(budget_id, account_id, since_date, transaction_type);
+        let res = self
+            .get_request(&url)
             .send()
             .context(ErrorKind::YNABGetTransactions)?;
+        self.merge_account_transactions(
+            budget_id,
+            account_id,
+            since_date,
+            transaction_type,
+            snapshot,
+            &res,
+        )
+    }
 
-        let body = res.text().context(ErrorKind::YNABGetTransactions)?;
-        info!("{}", body);
+    /// Url of `get_account_transactions_since` with the snapshot its
+    /// response is merged into, if any.
+    fn account_transactions_url(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        since_date: NaiveDate,
+        transaction_type: Option<&TransactionType>,
+    ) -> (String, Option<Snapshot<ListedTransaction>>) {
+        // snapshots only hold complete lists which go back far enough
+        let snapshot_key = format!("ynab-{}-{}-transactions", budget_id, account_id);
+        let snapshot: Option<Snapshot<ListedTransaction>> = match transaction_type {
+            Some(_) => None,
+            None => self.snapshot(&snapshot_key),
+        };
+        let snapshot = snapshot.filter(|x| x.since_date.is_some_and(|x| x <= since_date));
+
+        // changes are fetched for everything the snapshot covers, with a
+        // later date edits and deletions of its older transactions would be
+        // missed by later calls with an earlier date
+        let request_since_date = snapshot
+            .as_ref()
+            .and_then(|x| x.since_date)
+            .unwrap_or(since_date);
+        let mut url = format!(
+            "{}/budgets/{}/accounts/{}/transactions?since_date={}",
+            self.api_url,
+            budget_id,
+            account_id,
+            request_since_date.format("%Y-%m-%d")
+        );
+        if let Some(transaction_type) = transaction_type {
+            url.push_str(&format!("&type={}", transaction_type));
+        }
+        if let Some(snapshot) = &snapshot {
+            url.push_str(&format!(
+                "&last_knowledge_of_server={}",
+                snapshot.server_knowledge
+            ));
+        }
+        (url, snapshot)
+    }
 
-        if !res.status().is_success() {
-            let http_error =
-                ErrorKind::YNABGetTransactionsHttp(res.status().as_u16(), body.clone());
-            Err(http_error)?;
+    /// Transactions of the response of `get_account_transactions_since`,
+    /// merged into `snapshot`.
+    fn merge_account_transactions(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        since_date: NaiveDate,
+        transaction_type: Option<&TransactionType>,
+        snapshot: Option<Snapshot<ListedTransaction>>,
+        res: &Response,
+    ) -> Result<Vec<Transaction>> {
+        let list = parse_response::<TransactionsRequest>(
+            res,
+            ErrorKind::YNABGetTransactions,
+            ErrorKind::YNABGetTransactionsHttp,
+            ErrorKind::YNABGetTransactionsParse,
+        )?
+        .data;
+
+        let since_date_value = since_date.format("%Y-%m-%d").to_string();
+        let current = |transactions: Vec<ListedTransaction>| -> Vec<Transaction> {
+            transactions
+                .into_iter()
+                .filter(|x| !x.deleted)
+                .map(|x| x.transaction)
+                .filter(|x| x.date >= since_date_value)
+                .collect()
+        };
+
+        if transaction_type.is_some() {
+            return Ok(current(list.transactions));
         }
 
-        let req: TransactionsRequest = serde_json::from_str(&body)
-            .with_context(|e| ErrorKind::YNABGetTransactionsParse(e.to_string()))?;
+        let snapshot_key = format!("ynab-{}-{}-transactions", budget_id, account_id);
+        let mut snapshot = snapshot.unwrap_or(Snapshot {
+            server_knowledge: 0,
+            since_date: Some(since_date),
+            items: vec![],
+        });
+        snapshot.merge(
+            list.transactions,
+            list.server_knowledge,
+            |x| x.transaction.id.as_deref().unwrap_or_default(),
+            |x| x.deleted,
+        );
+        self.store_snapshot(&snapshot_key, &snapshot)?;
 
-        let transactions = HashMap::from_iter(
-            req.data
-                .transactions
+        Ok(current(snapshot.items))
+    }
+    /// Categories last used per payee in the account, used for transactions
+    /// no rule or mapping matched. Empty with `--payee-history-days 0`.
+    pub fn payee_history(&self, cli: &Cli) -> Result<PayeeHistory> {
+        if cli.payee_history_days <= 0 {
+            return Ok(PayeeHistory::default());
+        }
+        let transactions = self.get_account_transactions(
+            cli.budget_id.clone(),
+            cli.account_id.clone(),
+            cli.payee_history_days,
+        )?;
+        Ok(PayeeHistory::from_transactions(&transactions))
+    }
+    /// Classifier learning categories from the account's history, used for
+    /// transactions no rule, mapping or payee history matched. Empty without
+    /// `--classify`.
+    pub fn classifier(&self, cli: &Cli) -> Result<Classifier> {
+        if !cli.classify {
+            return Ok(Classifier::default());
+        }
+        let transactions = self.get_account_transactions(
+            cli.budget_id.clone(),
+            cli.account_id.clone(),
+            cli.classify_days,
+        )?;
+        Ok(Classifier::from_transactions(
+            &transactions,
+            cli.classify_min_probability,
+        ))
+    }
+    /// Let the user pick a category of `group` for `transaction`. `None` when
+    /// the user leaves it uncategorized or with `--yes`.
+    pub fn pick_category(
+        &self,
+        transaction: &Transaction,
+        group: &CategoryGroup,
+    ) -> Option<String> {
+        if self.assume_yes {
+            return None;
+        }
+        let categories: Vec<&Category> = group
+            .categories
+            .iter()
+            .filter(|x| !x.hidden && !x.deleted)
+            .collect();
+        let mut items: Vec<String> = categories.iter().map(|x| x.name.clone()).collect();
+        items.push("(leave uncategorized)".to_string());
+
+        let prompt = format!(
+            "Category in {} for | {} | {} | {:>+10.2} EUR | {}",
+            group.name,
+            transaction.date,
+            transaction.payee_name.clone().unwrap_or_default(),
+            (transaction.amount as f32 / 1000.0),
+            transaction.memo.clone().unwrap_or_default(),
+        );
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(&prompt)
+            .default(categories.len())
+            .items(&items[..])
+            .interact()
+            .unwrap();
+        categories.get(selection).map(|x| x.id.clone())
+    }
+    /// Let the user search a category for `transaction` among the categories
+    /// of `category_groups`. `None` when the user keeps the transaction's
+    /// category or with `--yes`.
+    pub fn search_category(
+        &self,
+        transaction: &Transaction,
+        category_groups: &[CategoryGroup],
+    ) -> Option<Category> {
+        if self.assume_yes {
+            return None;
+        }
+        let categories: Vec<(String, &Category)> = category_groups
+            .iter()
+            .filter(|x| !x.hidden && !x.deleted)
+            .flat_map(|group| {
+                group
+                    .categories
+                    .iter()
+                    .filter(|x| !x.hidden && !x.deleted)
+                    .map(move |x| (format!("{}: {}", group.name, x.name), x))
+            })
+            .collect();
+        let current = match &transaction.category_id {
+            Some(id) => categories
+                .iter()
+                .find(|x| x.1.id == *id)
+                .map(|x| x.0.clone())
+                .unwrap_or_else(|| id.clone()),
+            None => "uncategorized".to_string(),
+        };
+
+        println!(
+            " - | {} | {} | {:>+10.2} EUR | {}",
+            transaction.date,
+            transaction.payee_name.clone().unwrap_or_default(),
+            (transaction.amount as f32 / 1000.0),
+            transaction.memo.clone().unwrap_or_default(),
+        );
+        loop {
+            let search: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt(&format!("Search category (empty keeps {})", current))
+                .allow_empty(true)
+                .interact()
+                .unwrap();
+            if search.trim().is_empty() {
+                return None;
+            }
+            let mut ranked: Vec<(f64, &(String, &Category))> = categories
                 .iter()
-                .filter(|x| x.import_id.is_some())
                 .map(|x| {
                     (
-                        x.import_id.clone().unwrap_or_else(|| {
-                            let mut import_id_sha = Sha1::new();
-                            import_id_sha.input_str(&x.date);
-                            //import_id_sha.input_str(&format!("{}", x.amount));
-                            //import_id_sha.input_str(&x.memo.unwrap_or(""));
-                            import_id_sha.result_str()[..36].to_string()
-                        }),
-                        x.clone(),
+                        similarity(&search, &x.1.name).max(similarity(&search, &x.0)),
+                        x,
                     )
-                }),
-        );
-
-        Ok(transactions)
+                })
+                .collect();
+            ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+            ranked.truncate(SEARCH_RESULTS);
+            let mut items: Vec<String> = ranked.iter().map(|x| (x.1).0.clone()).collect();
+            items.push("(search again)".to_string());
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Category")
+                .default(0)
+                .items(&items[..])
+                .interact()
+                .unwrap();
+            if let Some((_, (_, category))) = ranked.get(selection) {
+                return Some((*category).clone());
+            }
+        }
     }
+    /// Create new and update changed transactions after confirmation.
+    /// Returns what was synced, see `SyncReport`.
     pub fn sync(
         &self,
         transactions: Vec<Transaction>,
@@ -529,38 +2008,51 @@ impl YNAB {
         force_update: bool,
         step: i32,
         steps: i32,
-    ) -> Result<()> {
-        // figure out which transactions are new and which we need to update
-        let mut new_transactions: Vec<Transaction> = vec![];
-        let mut update_transactions: Vec<Transaction> = vec![];
-        for transaction in transactions.iter() {
-            if let Some(import_id) = transaction.import_id.clone() {
-                // filter out transactions that don't need to be updated
-                // that means if import_id matches amount and date should
-                // be the same as in n26 transaction
-                let existing_transaction = existing_transactions.get(&import_id);
-                if existing_transaction.map(|x| x.amount) == Some(transaction.amount)
-                    && existing_transaction.map(|x| x.date.clone())
-                        == Some(transaction.date.clone())
-                    && (!force_update
-                        || existing_transaction.map(|x| x.category_id.clone())
-                            == Some(transaction.category_id.clone()))
-                {
-                    continue;
-                }
-                if existing_transactions.contains_key(import_id.as_str()) {
-                    update_transactions.push(transaction.clone());
-                } else {
-                    new_transactions.push(transaction.clone());
-                }
-            } else {
-                new_transactions.push(transaction.clone());
+    ) -> Result<SyncReport> {
+        let (transactions, invalid_transactions) = validate_transactions(transactions);
+        if !invalid_transactions.is_empty() {
+            println!("Skipping transactions YNAB would reject:");
+            for (transaction, violations) in &invalid_transactions {
+                warn!(
+                    "Skipping transaction {:?}: {}",
+                    transaction.import_id,
+                    violations.join(", ")
+                );
+                println!(
+                    " - | {} | {} | {:>+10.2} EUR | {}",
+                    transaction.date,
+                    transaction.memo.clone().unwrap_or("".to_string()),
+                    (transaction.amount as f32 / 1000.0),
+                    violations.join(", ")
+                );
             }
         }
+        let valid = invalid_transactions.is_empty();
+
+        let (new_transactions, update_transactions) =
+            changed_transactions(&transactions, &existing_transactions, force_update);
+        let mut report = SyncReport {
+            unchanged: transactions.len() - new_transactions.len() - update_transactions.len(),
+            skipped: invalid_transactions.len(),
+            ..SyncReport::default()
+        };
 
         if new_transactions.is_empty() && update_transactions.is_empty() {
             println!("[ {}/{}] No transactions to update.", step, steps);
-            return Ok(());
+            report.synced = valid;
+            return Ok(report);
+        }
+
+        // nothing is saved, so nothing counts as synced
+        if self.dry_run {
+            let categories = self.get_categories(budget_id)?;
+            print_dry_run(
+                &new_transactions,
+                &update_transactions,
+                &existing_transactions,
+                &categories,
+            );
+            return Ok(report);
         }
 
         let selections = &["Yes", "No"];
@@ -602,50 +2094,385 @@ impl YNAB {
             }
         }
 
+        // the plan is applied later, nothing counts as synced yet
+        if plan::is_enabled() {
+            plan::record(plan::Change {
+                budget_id,
+                force_update,
+                before: update_transactions
+                    .iter()
+                    .filter_map(|x| x.import_id.as_ref())
+                    .filter_map(|x| existing_transactions.get(x))
+                    .cloned()
+                    .collect(),
+                create: new_transactions,
+                update: update_transactions,
+            })?;
+            return Ok(report);
+        }
+
         let prompt = format!(
             "[[{: >2}/10] ] Do you want to sync transactions with YNAB [{}/{}]?",
             step + 1,
             new_transactions.len(),
             update_transactions.len(),
         );
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt(&prompt)
-            .default(1)
-            .items(&selections[..])
-            .interact()
-            .unwrap();
+        let selection = if self.assume_yes {
+            println!("{} Yes", prompt);
+            0
+        } else {
+            Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(&prompt)
+                .default(1)
+                .items(&selections[..])
+                .interact()
+                .unwrap()
+        };
 
         if selection == 0 {
+            let unapproved = new_transactions.iter().filter(|x| !x.approved).count();
             if !new_transactions.is_empty() {
                 println!(" => Creating new YNAB transactions");
-                self.save_transactions(new_transactions, budget_id.clone(), Method::POST)?;
+                let total = new_transactions.len();
+                report.created = self.save_transactions(
+                    new_transactions,
+                    &existing_transactions,
+                    budget_id.clone(),
+                    Method::POST,
+                )?;
+                // duplicates of imported transactions
+                report.skipped += total.saturating_sub(report.created.len());
             }
             if !update_transactions.is_empty() {
                 println!(" => Updating YNAB transactions");
-                self.save_transactions(update_transactions, budget_id.clone(), Method::PATCH)?;
+                report.updated = self.save_transactions(
+                    update_transactions,
+                    &existing_transactions,
+                    budget_id.clone(),
+                    Method::PATCH,
+                )?;
+            }
+            if unapproved > 0 {
+                notify::send(&Notification::attention(
+                    "Transactions need review",
+                    &format!(
+                        "{} new transactions in YNAB need to be categorized or approved.",
+                        unapproved
+                    ),
+                ));
             }
         }
 
-        Ok(())
+        report.synced = selection == 0 && valid;
+        Ok(report)
     }
-    fn save_transactions(
+    /// Income detection configured via --income-payee/--income-all-inflows.
+    pub fn income(
         &self,
+        cli: &Cli,
+        categories: &HashMap<String, Category>,
+    ) -> Result<Option<Income>> {
+        if cli.income_payees.is_empty() && !cli.income_all_inflows {
+            return Ok(None);
+        }
+        match inflow_category(categories) {
+            Some(category) => Ok(Some(Income::new(
+                &cli.income_payees,
+                cli.income_all_inflows,
+                category.id.clone(),
+            ))),
+            None => Err(ErrorKind::YNABInflowCategoryMissing)?,
+        }
+    }
+    /// Look up the account provided via --cash-account.
+    pub fn cash_account(&self, cli: &Cli) -> Result<Option<Account>> {
+        let cash_account = match &cli.cash_account {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let account_id = self.resolve_account_id(&cli.budget_id, cash_account)?;
+        Ok(self
+            .get_accounts(cli.budget_id.clone())?
+            .into_iter()
+            .find(|x| x.id == account_id))
+    }
+    /// Expense/refund pairing enabled with --reimbursement-days.
+    pub fn reimbursements(&self, cli: &Cli) -> Option<Reimbursements> {
+        if cli.reimbursement_days <= 0 {
+            return None;
+        }
+        Some(Reimbursements::new(
+            cli.reimbursement_days,
+            cli.reimbursement_flag.clone(),
+        ))
+    }
+    /// Linking of transactions entered by hand enabled with
+    /// --match-manual-days.
+    pub fn manual_matcher(&self, cli: &Cli) -> Option<ManualMatcher> {
+        if cli.match_manual_days <= 0 {
+            return None;
+        }
+        Some(ManualMatcher::new(cli.match_manual_days))
+    }
+    /// Resolve the accounts provided via --own-account, followed by the
+    /// savings sweeps going to --savings-account.
+    pub fn own_accounts(&self, cli: &Cli) -> Result<OwnAccounts> {
+        let mut own_accounts = vec![];
+        for own_account in &cli.own_accounts {
+            let (pattern, account) = match own_account.split_once('=') {
+                Some((pattern, account)) => (pattern, Some(account)),
+                None => (own_account.as_str(), None),
+            };
+            let account = match account {
+                Some(account) => {
+                    let account_id = self.resolve_account_id(&cli.budget_id, account)?;
+                    self.get_accounts(cli.budget_id.clone())?
+                        .into_iter()
+                        .find(|x| x.id == account_id)
+                }
+                None => None,
+            };
+            own_accounts
+                .push(OwnAccount::new(pattern, account).ok_or_else(|| {
+                    ErrorKind::ArgParse(format!("--own-account {}", own_account))
+                })?);
+        }
+        if let Some(savings_account) = &cli.savings_account {
+            let account_id = self.resolve_account_id(&cli.budget_id, savings_account)?;
+            let account = self
+                .get_accounts(cli.budget_id.clone())?
+                .into_iter()
+                .find(|x| x.id == account_id);
+            for pattern in SAVINGS_SWEEP_PATTERNS
+                .iter()
+                .copied()
+                .chain(cli.savings_patterns.iter().map(|x| x.as_str()))
+            {
+                own_accounts.push(OwnAccount::new(pattern, account.clone()).ok_or_else(|| {
+                    ErrorKind::ArgParse(format!("--savings-pattern {}", pattern))
+                })?);
+            }
+        }
+        Ok(OwnAccounts::new(own_accounts))
+    }
+    /// Resolve the budgets and accounts provided via --mirror-to.
+    pub fn mirrors(&self, cli: &Cli) -> Result<Vec<Mirror>> {
+        let mut mirrors = vec![];
+        for mirror in &cli.mirror_to {
+            let (budget, account) = match mirror.split_once(':') {
+                Some(x) => x,
+                None => Err(ErrorKind::ArgParse(format!("--mirror-to {}", mirror)))?,
+            };
+            let budget_id = self.resolve_budget_id(budget)?;
+            let account_id = self.resolve_account_id(&budget_id, account)?;
+            mirrors.push(Mirror {
+                budget_id,
+                account_id,
+            });
+        }
+        Ok(mirrors)
+    }
+    /// Sync copies of `transactions` to every mirror. Import ids stay the same
+    /// so re-runs don't create duplicates, payees and categories are matched
+    /// by name since their ids differ between budgets.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sync_mirrors(
+        &self,
+        mirrors: &[Mirror],
+        transactions: &[Transaction],
+        categories: &HashMap<String, Category>,
+        days: i64,
+        force_update: bool,
+        step: i32,
+        steps: i32,
+    ) -> Result<bool> {
+        let mut synced = true;
+        for mirror in mirrors {
+            println!(
+                "[ {}/{}] Syncing transactions to mirror account {}",
+                step, steps, mirror.account_id
+            );
+            let mirror_categories = self.get_categories(mirror.budget_id.clone())?;
+            let mirror_category = |category_id: &Option<String>| {
+                category_id
+                    .as_ref()
+                    .and_then(|x| categories.get(x))
+                    .and_then(|x| mirror_categories.get(&x.name))
+                    .map(|x| x.id.clone())
+            };
+            let mirror_transactions = transactions
+                .iter()
+                .map(|x| {
+                    let category_id = mirror_category(&x.category_id);
+                    Transaction {
+                        account_id: mirror.account_id.clone(),
+                        payee_id: None,
+                        approved: x.approved && (x.category_id.is_none() || category_id.is_some()),
+                        category_id,
+                        subtransactions: x
+                            .subtransactions
+                            .iter()
+                            .map(|sub| SubTransaction {
+                                payee_id: None,
+                                category_id: mirror_category(&sub.category_id),
+                                ..sub.clone()
+                            })
+                            .collect(),
+                        ..x.clone()
+                    }
+                })
+                .collect();
+            let existing_transactions =
+                self.get_transactions(mirror.budget_id.clone(), mirror.account_id.clone(), days)?;
+            synced &= self
+                .sync(
+                    mirror_transactions,
+                    existing_transactions,
+                    mirror.budget_id.clone(),
+                    force_update,
+                    step,
+                    steps,
+                )?
+                .synced;
+        }
+        Ok(synced)
+    }
+    /// Sync the partner's share of shared expenses (see `Splitter::apply`) to
+    /// the partner's budget.
+    pub fn sync_split(
+        &self,
+        splitter: &Splitter,
         transactions: Vec<Transaction>,
+        days: i64,
+        force_update: bool,
+        step: i32,
+        steps: i32,
+    ) -> Result<bool> {
+        match splitter.target() {
+            SplitTarget::Account {
+                budget_id,
+                account_id,
+            } if !transactions.is_empty() => {
+                println!(
+                    "[ {}/{}] Syncing partner's share of shared expenses",
+                    step, steps
+                );
+                let existing_transactions =
+                    self.get_transactions(budget_id.clone(), account_id.clone(), days)?;
+                Ok(self
+                    .sync(
+                        transactions,
+                        existing_transactions,
+                        budget_id.clone(),
+                        force_update,
+                        step,
+                        steps,
+                    )?
+                    .synced)
+            }
+            _ => Ok(true),
+        }
+    }
+    /// Create (`Method::POST`) or update (`Method::PATCH`) transactions in
+    /// batches of `batch_size` and record the changes, compared to
+    /// `existing_transactions`, in the audit log. Batches saved before a
+    /// failing one stay saved, syncing again only saves the rest.
+    ///
+    /// Transactions whose import id is already used (eg. by a transaction
+    /// older than the fetched ones) are skipped by YNAB, they're reported and
+    /// with `update_duplicates` updated instead. Returns the saved
+    /// transactions.
+    pub fn save_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        existing_transactions: &HashMap<String, Transaction>,
         budget_id: String,
         method: Method,
-    ) -> Result<()> {
+    ) -> Result<Vec<Transaction>> {
+        let total = transactions.len();
+        let batches = transactions.chunks(self.batch_size.max(1));
+        let mut saved = 0;
+        let mut saved_transactions = vec![];
+        let mut duplicates = vec![];
+        for batch in batches {
+            let result =
+                self.save_batch(batch.to_vec(), existing_transactions, &budget_id, &method);
+            let duplicate_import_ids = if saved > 0 {
+                result.with_context(|_| ErrorKind::YNABSaveTransactionsPartial(saved, total))?
+            } else {
+                result?
+            };
+            let (batch_duplicates, batch_saved): (Vec<&Transaction>, Vec<&Transaction>) =
+                batch.iter().partition(|x| {
+                    x.import_id
+                        .as_ref()
+                        .is_some_and(|x| duplicate_import_ids.contains(x))
+                });
+            duplicates.extend(batch_duplicates);
+            saved_transactions.extend(batch_saved.into_iter().cloned());
+            saved += batch.len();
+            if total > self.batch_size {
+                println!(" => Saved {}/{} transactions", saved, total);
+            }
+        }
+
+        if duplicates.is_empty() {
+            return Ok(saved_transactions);
+        }
+        println!(
+            " => {} transactions were already imported before:",
+            duplicates.len()
+        );
+        for transaction in &duplicates {
+            println!(
+                "    {} | {} | {:>+10.2} | {}",
+                transaction.import_id.clone().unwrap_or_default(),
+                transaction.date,
+                (transaction.amount as f32 / 1000.0),
+                transaction.payee_name.clone().unwrap_or_default(),
+            );
+        }
+        if !self.update_duplicates {
+            println!(" => Skipped them, use --update-duplicates to update them instead");
+            return Ok(saved_transactions);
+        }
+        // transactions are updated by their import id
+        println!(" => Updating them instead");
+        let updates = duplicates
+            .into_iter()
+            .cloned()
+            .map(|x| Transaction { id: None, ..x });
+        saved_transactions.extend(self.save_transactions(
+            updates.collect(),
+            existing_transactions,
+            budget_id,
+            Method::PATCH,
+        )?);
+        Ok(saved_transactions)
+    }
+
+    fn save_batch(
+        &self,
+        transactions: Vec<Transaction>,
+        existing_transactions: &HashMap<String, Transaction>,
+        budget_id: &str,
+        method: &Method,
+    ) -> Result<Vec<String>> {
+        let action = if method == Method::POST {
+            Action::Create
+        } else {
+            Action::Update
+        };
         let wrapper = TransactionsWrapper { transactions };
 
-        let url = format!("{}/budgets/{}/transactions", API_URL, budget_id);
+        let url = format!("{}/budgets/{}/transactions", self.api_url, budget_id);
         let authorization = format!("Bearer {}", self.token);
         let req_body =
             serde_json::to_string(&wrapper).context(ErrorKind::YNABSaveTransactions.clone())?;
-        info!("{}", req_body);
 
-        let client = reqwest::Client::new();
-        let mut res = client
-            .request(method, &url)
-            .header(header::AUTHORIZATION, authorization)
+        let res = Request::new(method.clone(), &url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
             .header(header::ACCEPT, "application/json")
             .header(header::CONTENT_TYPE, "application/json")
             .body(req_body)
@@ -653,19 +2480,552 @@ impl YNAB {
             .context(ErrorKind::YNABSaveTransactions.clone())?;
 
         if !res.status().is_success() {
-            let res_body = res
-                .text()
-                .context(ErrorKind::YNABSaveTransactions.clone())?;
-            let http_error =
-                ErrorKind::YNABSaveTransactionsHttp(res.status().as_u16(), res_body.clone());
-            Err(http_error)?;
+            Err(response_error(
+                res.status().as_u16(),
+                res.text(),
+                ErrorKind::YNABSaveTransactions,
+                ErrorKind::YNABSaveTransactionsHttp,
+            ))?;
+        }
+
+        let duplicate_import_ids = if method == Method::POST {
+            let response: SaveTransactionsRequest = serde_json::from_str(&res.text())
+                .with_context(|e| ErrorKind::YNABSaveTransactionsParse(e.to_string()))?;
+            response.data.duplicate_import_ids
         } else {
-            let res_body = res
-                .text()
-                .context(ErrorKind::YNABSaveTransactions.clone())?;
-            println!("{}", res_body);
+            vec![]
+        };
+
+        // duplicates weren't created
+        let saved: Vec<Transaction> = wrapper
+            .transactions
+            .into_iter()
+            .filter(|x| {
+                x.import_id
+                    .as_ref()
+                    .is_none_or(|x| !duplicate_import_ids.contains(x))
+            })
+            .collect();
+        audit::record(&audit::Entry::saved(
+            action,
+            budget_id,
+            &saved,
+            existing_transactions,
+        ))?;
+        Ok(duplicate_import_ids)
+    }
+    /// Transaction with `transaction_id` (of any account of the budget).
+    pub fn get_transaction(&self, budget_id: &str, transaction_id: &str) -> Result<Transaction> {
+        let url = format!(
+            "{}/budgets/{}/transactions/{}",
+            self.api_url, budget_id, transaction_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::get(&url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABGetTransaction)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABGetTransaction,
+                ErrorKind::YNABGetTransactionHttp,
+            ))?;
         }
 
-        Ok(())
+        let req: SingleTransactionRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABGetTransactionParse(e.to_string()))?;
+
+        Ok(req.data.transaction)
+    }
+    /// Replace the YNAB transaction `existing` (by its id) by `transaction`
+    /// and record the change in the audit log. Returns the transaction as
+    /// updated by YNAB.
+    pub fn update_transaction(
+        &self,
+        budget_id: &str,
+        existing: &Transaction,
+        transaction: Transaction,
+    ) -> Result<Transaction> {
+        let transaction_id = match &existing.id {
+            Some(x) => x,
+            None => Err(ErrorKind::YNABUpdateTransaction)?,
+        };
+        let url = format!(
+            "{}/budgets/{}/transactions/{}",
+            self.api_url, budget_id, transaction_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let entry = audit::Entry::updated(budget_id, existing, &transaction);
+        let req_body = serde_json::to_string(&TransactionWrapper {
+            transaction: Transaction {
+                id: None,
+                ..transaction
+            },
+        })
+        .context(ErrorKind::YNABUpdateTransaction)?;
+
+        let res = Request::new(Method::PUT, &url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(req_body)
+            .send()
+            .context(ErrorKind::YNABUpdateTransaction)?;
+
+        let body = res.text();
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                body.clone(),
+                ErrorKind::YNABUpdateTransaction,
+                ErrorKind::YNABUpdateTransactionHttp,
+            ))?;
+        }
+
+        let req: SingleTransactionRequest = serde_json::from_str(&body)
+            .with_context(|e| ErrorKind::YNABUpdateTransactionParse(e.to_string()))?;
+
+        audit::record(&[entry])?;
+        Ok(req.data.transaction)
+    }
+    /// Delete `transaction` (by its id) and record it in the audit log.
+    pub fn delete_transaction(&self, budget_id: &str, transaction: &Transaction) -> Result<()> {
+        let transaction_id = match &transaction.id {
+            Some(x) => x,
+            None => Err(ErrorKind::YNABDeleteTransaction)?,
+        };
+        let url = format!(
+            "{}/budgets/{}/transactions/{}",
+            self.api_url, budget_id, transaction_id
+        );
+        let authorization = format!("Bearer {}", self.token);
+        let res = Request::new(Method::DELETE, &url)
+            .client(self.client.as_ref())
+            .header(header::AUTHORIZATION, &authorization)
+            .send()
+            .context(ErrorKind::YNABDeleteTransaction)?;
+
+        if !res.status().is_success() {
+            Err(response_error(
+                res.status().as_u16(),
+                res.text(),
+                ErrorKind::YNABDeleteTransaction,
+                ErrorKind::YNABDeleteTransactionHttp,
+            ))?;
+        }
+
+        audit::record(&[audit::Entry::deleted(budget_id, transaction)])
+    }
+}
+
+/// What `YNAB::get_account_data_async` fetches for syncing into an account.
+#[cfg(feature = "async")]
+#[derive(Clone, Debug)]
+pub struct AccountData {
+    pub accounts: Vec<Account>,
+    pub category_groups: Vec<CategoryGroup>,
+    pub payees: Vec<Payee>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// `async fn` variants of the getters, which share caches, snapshots and
+/// errors with the blocking ones.
+#[cfg(feature = "async")]
+impl YNAB {
+    pub async fn get_budgets_async(&self) -> Result<Vec<Budget>> {
+        let url = format!("{}/budgets", self.api_url);
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::YNABGetBudgets)?;
+        parse_budgets(&res)
+    }
+
+    pub async fn get_accounts_async(&self, budget_id: &str) -> Result<Vec<Account>> {
+        let key = format!("ynab-{}-accounts", budget_id);
+        if let Some(accounts) = self.cached(&key) {
+            return Ok(accounts);
+        }
+
+        let snapshot: Option<Snapshot<Account>> = self.snapshot(&key);
+        let url = self.delta_url(
+            format!("{}/budgets/{}/accounts", self.api_url, budget_id),
+            snapshot.as_ref(),
+        );
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::YNABGetAccounts)?;
+        self.merge_accounts(&key, snapshot, &res)
+    }
+
+    pub async fn get_category_groups_async(&self, budget_id: &str) -> Result<Vec<CategoryGroup>> {
+        let key = format!("ynab-{}-categories", budget_id);
+        if let Some(category_groups) = self.cached(&key) {
+            return Ok(category_groups);
+        }
+
+        let snapshot: Option<Snapshot<CategoryGroup>> = self.snapshot(&key);
+        let url = self.delta_url(
+            format!("{}/budgets/{}/categories", self.api_url, budget_id),
+            snapshot.as_ref(),
+        );
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::YNABGetCategories)?;
+        self.merge_category_groups(&key, snapshot, &res)
+    }
+
+    pub async fn get_payees_async(&self, budget_id: &str) -> Result<Vec<Payee>> {
+        let url = format!("{}/budgets/{}/payees", self.api_url, budget_id);
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::YNABGetPayees)?;
+        parse_payees(&res)
+    }
+
+    pub async fn get_account_transactions_since_async(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        since_date: NaiveDate,
+        transaction_type: Option<&TransactionType>,
+    ) -> Result<Vec<Transaction>> {
+        let (url, snapshot) =
+            self.account_transactions_url(budget_id, account_id, since_date, transaction_type);
+        let res = self
+            .get_request(&url)
+            .send_async()
+            .await
+            .context(ErrorKind::YNABGetTransactions)?;
+        self.merge_account_transactions(
+            budget_id,
+            account_id,
+            since_date,
+            transaction_type,
+            snapshot,
+            &res,
+        )
+    }
+
+    /// Accounts, categories, payees and the transactions of `account_id`
+    /// since `since_date`, fetched at the same time. Like `http::join`, one
+    /// after another while recording or replaying so the order of the
+    /// interactions doesn't change.
+    pub async fn get_account_data_async(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+        since_date: NaiveDate,
+    ) -> Result<AccountData> {
+        let accounts = self.get_accounts_async(budget_id);
+        let category_groups = self.get_category_groups_async(budget_id);
+        let payees = self.get_payees_async(budget_id);
+        let transactions =
+            self.get_account_transactions_since_async(budget_id, account_id, since_date, None);
+        let (accounts, category_groups, payees, transactions) = if http::is_live() {
+            futures::try_join!(accounts, category_groups, payees, transactions)?
+        } else {
+            (
+                accounts.await?,
+                category_groups.await?,
+                payees.await?,
+                transactions.await?,
+            )
+        };
+        Ok(AccountData {
+            accounts,
+            category_groups,
+            payees,
+            transactions,
+        })
+    }
+}
+
+/// Truncate memos of `transactions` and separate the ones YNAB would reject,
+/// together with the reasons.
+pub fn validate_transactions(
+    transactions: Vec<Transaction>,
+) -> (Vec<Transaction>, Vec<(Transaction, Vec<String>)>) {
+    let today = Local::now().naive_local().date();
+    let mut valid = vec![];
+    let mut invalid = vec![];
+    for transaction in transactions {
+        let transaction = transaction.truncate_memo();
+        let violations = transaction.violations(today);
+        if violations.is_empty() {
+            valid.push(transaction);
+        } else {
+            invalid.push((transaction, violations));
+        }
+    }
+    (valid, invalid)
+}
+
+/// Split `transactions` into the ones which are new and the ones which
+/// changed compared to `existing_transactions` (keyed by import id). With
+/// `force_update` a changed category counts as a change too.
+pub fn changed_transactions(
+    transactions: &[Transaction],
+    existing_transactions: &HashMap<String, Transaction>,
+    force_update: bool,
+) -> (Vec<Transaction>, Vec<Transaction>) {
+    // figure out which transactions are new and which we need to update
+    let mut new_transactions: Vec<Transaction> = vec![];
+    let mut update_transactions: Vec<Transaction> = vec![];
+    for transaction in transactions.iter() {
+        if let Some(import_id) = transaction.import_id.clone() {
+            // filter out transactions that don't need to be updated
+            // that means if import_id matches amount and date should
+            // be the same as in n26 transaction
+            let existing_transaction = existing_transactions.get(&import_id);
+            if existing_transaction.map(|x| x.amount) == Some(transaction.amount)
+                && existing_transaction.map(|x| x.date.clone()) == Some(transaction.date.clone())
+                // eg. an expense flagged once its refund arrived
+                && (transaction.flag_color.is_none()
+                    || existing_transaction.map(|x| x.flag_color.clone())
+                        == Some(transaction.flag_color.clone()))
+                && (!force_update
+                    || existing_transaction.map(|x| x.category_id.clone())
+                        == Some(transaction.category_id.clone()))
+                // eg. a pending transaction which settled since
+                && (matches!(transaction.cleared, TransactionCleared::Uncleared)
+                    || existing_transaction
+                        .is_none_or(|x| !matches!(x.cleared, TransactionCleared::Uncleared)))
+            {
+                continue;
+            }
+            if existing_transactions.contains_key(import_id.as_str()) {
+                update_transactions.push(transaction.clone());
+            } else {
+                new_transactions.push(transaction.clone());
+            }
+        } else {
+            new_transactions.push(transaction.clone());
+        }
+    }
+
+    (new_transactions, update_transactions)
+}
+
+/// Print every field of the transactions a sync would create and, for
+/// updates, how the fields differ from `existing_transactions`.
+fn print_dry_run(
+    new_transactions: &[Transaction],
+    update_transactions: &[Transaction],
+    existing_transactions: &HashMap<String, Transaction>,
+    categories: &HashMap<String, Category>,
+) {
+    let category = |id: &Option<String>| match id {
+        Some(id) => categories
+            .get(id)
+            .map(|x| x.name.clone())
+            .unwrap_or_else(|| id.clone()),
+        None => "none".to_string(),
+    };
+    let payee = |transaction: &Transaction| {
+        transaction
+            .payee_name
+            .clone()
+            .or_else(|| transaction.payee_id.clone())
+            .unwrap_or_default()
+    };
+    let fields = |transaction: &Transaction| {
+        vec![
+            ("date", transaction.date.clone()),
+            (
+                "amount",
+                format!("{:+.2} EUR", transaction.amount as f64 / 1000.0),
+            ),
+            ("payee", payee(transaction)),
+            (
+                "category",
+                if transaction.subtransactions.is_empty() {
+                    category(&transaction.category_id)
+                } else {
+                    transaction
+                        .subtransactions
+                        .iter()
+                        .map(|x| category(&x.category_id))
+                        .collect::<Vec<_>>()
+                        .join(" + ")
+                },
+            ),
+            ("memo", transaction.memo.clone().unwrap_or_default()),
+            (
+                "import_id",
+                transaction.import_id.clone().unwrap_or_default(),
+            ),
+        ]
+    };
+
+    if !new_transactions.is_empty() {
+        println!("Would create:");
+        for transaction in new_transactions {
+            println!(" - |");
+            for (name, value) in fields(transaction) {
+                println!("     {}: {}", name, value);
+            }
+        }
+    }
+    if !update_transactions.is_empty() {
+        println!("Would update:");
+        for transaction in update_transactions {
+            let existing = transaction
+                .import_id
+                .as_ref()
+                .and_then(|x| existing_transactions.get(x))
+                .map(&fields)
+                .unwrap_or_default();
+            println!(" - |");
+            for (name, value) in fields(transaction) {
+                match existing.iter().find(|x| x.0 == name) {
+                    Some((_, old)) if *old != value => {
+                        println!("     {}: {} -> {}", name, old, value)
+                    }
+                    _ => println!("     {}: {}", name, value),
+                }
+            }
+        }
+    }
+    println!(
+        "Dry run: {} transactions would be created and {} updated.",
+        new_transactions.len(),
+        update_transactions.len()
+    );
+}
+
+/// Budgets of the response to `GET /budgets`.
+fn parse_budgets(res: &Response) -> Result<Vec<Budget>> {
+    let req: BudgetsRequest = parse_response(
+        res,
+        ErrorKind::YNABGetBudgets,
+        ErrorKind::YNABGetBudgetsHttp,
+        ErrorKind::YNABGetBudgetsParse,
+    )?;
+    Ok(req.data.budgets)
+}
+
+/// Payees of the response to `GET /budgets/{id}/payees`.
+fn parse_payees(res: &Response) -> Result<Vec<Payee>> {
+    let req: PayeesRequest = parse_response(
+        res,
+        ErrorKind::YNABGetPayees,
+        ErrorKind::YNABGetPayeesHttp,
+        ErrorKind::YNABGetPayeesParse,
+    )?;
+    Ok(req.data.payees)
+}
+
+/// Body of a successful response, see `response_error` for the others.
+fn parse_response<T: DeserializeOwned>(
+    res: &Response,
+    context: ErrorKind,
+    http_error: fn(u16, String) -> ErrorKind,
+    parse_error: fn(String) -> ErrorKind,
+) -> Result<T> {
+    let body = res.text();
+    if !res.status().is_success() {
+        Err(response_error(
+            res.status().as_u16(),
+            body.clone(),
+            context,
+            http_error,
+        ))?;
+    }
+    Ok(serde_json::from_str(&body).with_context(|e| parse_error(e.to_string()))?)
+}
+
+/// Date `days` days ago.
+fn days_ago(days: i64) -> NaiveDate {
+    (Utc::now() - Duration::days(days)).naive_utc().date()
+}
+
+/// Error for an unsuccessful YNAB response.
+///
+/// Errors YNAB describes in its error JSON (eg. `{"error": {"id": "401",
+/// "name": "unauthorized", ...}}`) which the user can act upon become typed
+/// `ErrorKind`s, with `context` telling which call failed. Everything else
+/// becomes `http_error` with the raw response body.
+fn response_error(
+    status: u16,
+    body: String,
+    context: ErrorKind,
+    http_error: fn(u16, String) -> ErrorKind,
+) -> failure::Error {
+    let kind = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(response) => match response.error.name.as_str() {
+            "unauthorized" | "not_authorized" => {
+                Some(ErrorKind::YNABUnauthorized(response.error.detail))
+            }
+            "subscription_lapsed" | "trial_expired" => {
+                Some(ErrorKind::YNABSubscriptionLapsed(response.error.detail))
+            }
+            "not_found" | "resource_not_found" => {
+                Some(ErrorKind::YNABNotFound(response.error.detail))
+            }
+            "too_many_requests" => Some(ErrorKind::YNABRateLimited(response.error.detail)),
+            _ => None,
+        },
+        Err(_) => None,
+    };
+    match kind {
+        Some(kind) => failure::Error::from(kind).context(context).into(),
+        None => http_error(status, body).into(),
+    }
+}
+
+/// Id of the (not deleted) account of `accounts` with id or name `account`.
+fn find_account_id(account: &str, accounts: Vec<Account>) -> Result<String> {
+    let accounts: Vec<Account> = accounts.into_iter().filter(|x| !x.deleted).collect();
+    match find_by_id_or_name(account, &accounts, |x| &x.id, |x| &x.name).as_slice() {
+        [account] => Ok(account.id.clone()),
+        [] => Err(ErrorKind::WrongAccountId(account.to_string()))?,
+        _ => Err(ErrorKind::AmbiguousAccount(account.to_string()))?,
+    }
+}
+
+/// Whether `value` looks like a YNAB id (a UUID) rather than a name.
+fn is_id(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Items whose id is `value` or, when none, whose name is `value` (exact
+/// matches win over case insensitive ones).
+fn find_by_id_or_name<'a, T>(
+    value: &str,
+    items: &'a [T],
+    id: fn(&T) -> &String,
+    name: fn(&T) -> &String,
+) -> Vec<&'a T> {
+    let by_id: Vec<&T> = items.iter().filter(|x| id(x) == value).collect();
+    if !by_id.is_empty() {
+        return by_id;
+    }
+    let by_name: Vec<&T> = items.iter().filter(|x| name(x) == value).collect();
+    if !by_name.is_empty() {
+        return by_name;
     }
+    items
+        .iter()
+        .filter(|x| name(x).to_lowercase() == value.to_lowercase())
+        .collect()
 }
@@ -0,0 +1,118 @@
+// The `async fn` getters of the YNAB and N26 clients against the mock APIs of
+// `test_util` (run with `cargo test --features async,test-util`).
+#![cfg(all(feature = "async", feature = "test-util"))]
+
+use chrono::{Duration, NaiveDate, Utc};
+use tokio::runtime::Runtime;
+use ynab_sync::error::find_kind;
+use ynab_sync::n26::TRANSACTIONS_PAGE_SIZE;
+use ynab_sync::test_util::{self, MockApi, N26Mock, YNABMock};
+use ynab_sync::ErrorKind;
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    Runtime::new().unwrap().block_on(future)
+}
+
+#[test]
+fn account_data_is_fetched_at_once() {
+    let checking = test_util::account("Checking");
+    let savings = test_util::account("Savings");
+    let mut mock = YNABMock::new(test_util::budget("My Budget"))
+        .account(checking.clone())
+        .account(savings.clone())
+        .category_group(test_util::category_group("Bills", &["Rent", "Power"]))
+        .payee(test_util::payee("Landlord"));
+    for (i, (account, date, amount)) in [
+        (&checking, "2019-12-31", -500),
+        (&checking, "2020-01-01", -1000),
+        (&checking, "2020-01-02", -2000),
+        (&savings, "2020-01-03", 3000),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let mut transaction = test_util::transaction(account, date, *amount);
+        transaction.id = Some(format!("transaction-{}", i));
+        mock = mock.transaction(transaction);
+    }
+    let api = mock.start();
+    let ynab = api.ynab();
+    let budget_id = api.budget_id();
+    let since_date = NaiveDate::from_ymd(2020, 1, 1);
+
+    let data = block_on(ynab.get_account_data_async(&budget_id, &checking.id, since_date)).unwrap();
+
+    let accounts: Vec<&str> = data.accounts.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(accounts, ["Checking", "Savings"]);
+    assert_eq!(data.category_groups.len(), 1);
+    assert_eq!(data.category_groups[0].categories.len(), 2);
+    let payees: Vec<&str> = data.payees.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(payees, ["Landlord"]);
+    let amounts: Vec<i32> = data.transactions.iter().map(|x| x.amount).collect();
+    assert_eq!(amounts, [-1000, -2000]);
+
+    // the same as the blocking getters
+    let transactions = ynab
+        .get_account_transactions_since(&budget_id, &checking.id, since_date, None)
+        .unwrap();
+    assert_eq!(transactions.len(), data.transactions.len());
+    assert_eq!(
+        block_on(ynab.get_budgets_async()).unwrap()[0].id,
+        ynab.get_budgets().unwrap()[0].id
+    );
+}
+
+#[test]
+fn n26_transactions_are_fetched_in_pages() {
+    let count = TRANSACTIONS_PAGE_SIZE + 1;
+    let now = Utc::now();
+    let mut n26_mock = N26Mock::new().category("micro-v2-food", "Food");
+    for i in 0..count {
+        let visible = now - Duration::minutes(i as i64);
+        n26_mock = n26_mock.transaction(test_util::n26_transaction(
+            &format!("n26-{}", i),
+            visible,
+            -1.0,
+            "Shop",
+        ));
+    }
+    let api = n26_mock.start();
+    let n26 = api.n26();
+
+    let transactions = block_on(n26.get_transactions_async(7)).unwrap();
+    assert_eq!(transactions.len(), count);
+    assert_eq!(transactions[count - 1].id, format!("n26-{}", count - 1));
+    let pages = api
+        .received_requests()
+        .into_iter()
+        .filter(|x| x.url.path() == "/api/smrt/transactions")
+        .count();
+    assert_eq!(pages, 2);
+
+    let categories = block_on(n26.get_categories_async()).unwrap();
+    assert_eq!(
+        categories.get("micro-v2-food").map(String::as_str),
+        Some("Food")
+    );
+    let balance = block_on(n26.get_balance_async()).unwrap();
+    assert_eq!(balance.available_balance, -1000 * count as i32);
+}
+
+#[test]
+fn http_errors_are_the_same_as_blocking() {
+    let api = MockApi::start();
+    let n26 = api.n26();
+    let error = block_on(n26.get_balance_async()).unwrap_err();
+    assert!(matches!(
+        find_kind(&error),
+        Some(ErrorKind::N26GetBalanceHttp(404, _))
+    ));
+
+    let ynab = api.ynab();
+    let error = block_on(ynab.get_payees_async("missing")).unwrap_err();
+    let blocking = ynab.get_payees("missing").unwrap_err();
+    assert_eq!(
+        find_kind(&error).map(ToString::to_string),
+        find_kind(&blocking).map(ToString::to_string)
+    );
+}
@@ -0,0 +1,173 @@
+// Syncing N26 transactions into YNAB end to end, against the mock APIs of
+// `test_util` (run with `cargo test --features test-util`).
+#![cfg(feature = "test-util")]
+
+use chrono::{Duration, Local, Utc};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env::{set_var, temp_dir};
+use std::sync::Once;
+use structopt::StructOpt;
+use ynab_sync::balance::BalanceGuard;
+use ynab_sync::memo::MemoTemplate;
+use ynab_sync::n26::{self, TRANSACTIONS_PAGE_SIZE};
+use ynab_sync::sync::{sync_source_with, Window};
+use ynab_sync::test_util::{self, MockApi, N26Mock, YNABMock, YNAB_TOKEN};
+use ynab_sync::ynab::{self, Transaction, TransactionCleared};
+
+static SETUP: Once = Once::new();
+
+/// Keep the sync state and the audit log of the tests out of the user's.
+fn setup() {
+    SETUP.call_once(|| {
+        let dir = temp_dir().join(format!("ynab-sync-tests-{}", std::process::id()));
+        set_var("XDG_DATA_HOME", dir.join("data"));
+        set_var("XDG_CACHE_HOME", dir.join("cache"));
+    });
+}
+
+fn ynab_cli(account: &str) -> ynab::Cli {
+    ynab::Cli::from_iter(&[
+        "sync-with-n26",
+        &format!("--ynab-token={}", YNAB_TOKEN),
+        "--ynab-budget=My Budget",
+        &format!("--ynab-account={}", account),
+    ])
+}
+
+/// Sync the last 7 days of `n26_api` into `account` of `ynab_api`.
+fn sync(n26_api: &MockApi, ynab_api: &MockApi, account: &str, batch_size: usize) -> bool {
+    let ynab = ynab_api
+        .ynab()
+        .with_assume_yes(true)
+        .with_batch_size(batch_size);
+    let mut n26 = n26_api.n26();
+    let memo_template = MemoTemplate::parse(n26::DEFAULT_MEMO_TEMPLATE, n26::MEMO_FIELDS).unwrap();
+    sync_source_with(
+        &ynab,
+        &mut n26,
+        Window::Days(7),
+        ynab_cli(account),
+        &memo_template,
+        &BalanceGuard::off(),
+    )
+    .unwrap()
+}
+
+fn requests(api: &MockApi, method: &str, path: &str) -> Vec<wiremock::Request> {
+    api.received_requests()
+        .into_iter()
+        .filter(|x| x.method.to_string() == method && x.url.path() == path)
+        .collect()
+}
+
+fn query(request: &wiremock::Request, name: &str) -> Option<String> {
+    request
+        .url
+        .query_pairs()
+        .find(|x| x.0 == name)
+        .map(|x| x.1.to_string())
+}
+
+fn sent_transactions(request: &wiremock::Request) -> Vec<Transaction> {
+    let body: Value = serde_json::from_slice(&request.body).unwrap();
+    serde_json::from_value(body["transactions"].clone()).unwrap()
+}
+
+#[test]
+fn n26_pages_are_synced_in_batches() {
+    setup();
+    let count = TRANSACTIONS_PAGE_SIZE + 50;
+    let now = Utc::now();
+    let mut n26_mock = N26Mock::new();
+    for i in 0..count {
+        // newest first, like the API
+        let visible = now - Duration::minutes(i as i64);
+        n26_mock = n26_mock.transaction(test_util::n26_transaction(
+            &format!("n26-{}", i),
+            visible,
+            -1.0 - i as f64,
+            "Shop",
+        ));
+    }
+    let n26_api = n26_mock.start();
+    let account = test_util::account("Batches");
+    let ynab_api = YNABMock::new(test_util::budget("My Budget"))
+        .account(account.clone())
+        .start();
+
+    assert!(sync(&n26_api, &ynab_api, &account.name, 100));
+
+    // the second page continues after the last transaction of the first one,
+    // both from the start of the first day
+    let pages = requests(&n26_api, "GET", "/api/smrt/transactions");
+    assert_eq!(pages.len(), 2);
+    assert_eq!(query(&pages[0], "lastId"), None);
+    assert_eq!(
+        query(&pages[1], "lastId"),
+        Some(format!("n26-{}", TRANSACTIONS_PAGE_SIZE - 1))
+    );
+    let first_day = (Local::today() - Duration::days(7))
+        .and_hms(0, 0, 0)
+        .timestamp_millis()
+        .to_string();
+    for page in &pages {
+        assert_eq!(query(page, "from").as_ref(), Some(&first_day));
+    }
+
+    let path = format!("/budgets/{}/transactions", ynab_api.budget_id());
+    let batches: Vec<Vec<Transaction>> = requests(&ynab_api, "POST", &path)
+        .iter()
+        .map(sent_transactions)
+        .collect();
+    let sizes: Vec<usize> = batches.iter().map(|x| x.len()).collect();
+    assert_eq!(sizes, [100, 100, 50]);
+    let import_ids: HashSet<String> = batches
+        .iter()
+        .flatten()
+        .filter_map(|x| x.import_id.clone())
+        .collect();
+    assert_eq!(import_ids.len(), count);
+    assert!(batches.iter().flatten().all(|x| x.account_id == account.id));
+}
+
+#[test]
+fn settled_n26_transactions_are_cleared() {
+    setup();
+    let visible = Utc::now() - Duration::hours(1);
+    let mut pending = test_util::n26_transaction("n26-pending", visible, -12.5, "Cafe");
+    pending["pending"] = Value::Bool(true);
+    let account = test_util::account("Pending");
+
+    // pending, created as uncleared
+    let n26_api = N26Mock::new().transaction(pending.clone()).start();
+    let ynab_api = YNABMock::new(test_util::budget("My Budget"))
+        .account(account.clone())
+        .start();
+    assert!(sync(&n26_api, &ynab_api, &account.name, 100));
+    let path = format!("/budgets/{}/transactions", ynab_api.budget_id());
+    let created = requests(&ynab_api, "POST", &path);
+    assert_eq!(created.len(), 1);
+    let mut created = sent_transactions(&created[0]).remove(0);
+    assert!(matches!(created.cleared, TransactionCleared::Uncleared));
+
+    // settled with another amount, updated and cleared
+    let mut settled = pending;
+    settled["pending"] = Value::Bool(false);
+    settled["amount"] = serde_json::json!(-13.0);
+    let n26_api = N26Mock::new().transaction(settled).start();
+    created.id = Some("ynab-transaction".to_string());
+    let ynab_api = YNABMock::new(test_util::budget("My Budget"))
+        .account(account.clone())
+        .transaction(created.clone())
+        .start();
+    assert!(sync(&n26_api, &ynab_api, &account.name, 100));
+    assert!(requests(&ynab_api, "POST", &path).is_empty());
+    let updated = requests(&ynab_api, "PATCH", &path);
+    assert_eq!(updated.len(), 1);
+    let updated = sent_transactions(&updated[0]);
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].import_id, created.import_id);
+    assert_eq!(updated[0].amount, -13000);
+    assert!(matches!(updated[0].cleared, TransactionCleared::Cleared));
+}